@@ -0,0 +1,618 @@
+//! Linux AF_PACKET capture using a TPACKET_V3 memory-mapped ring.
+//!
+//! The kernel fills fixed-size blocks with variable-length frames and hands a
+//! whole block to user space at once, either when it is full or when the block
+//! retire timeout expires. Each block becomes one batch: its frames are copied
+//! into a single shared buffer and exposed as `Bytes` slices of it, so there is
+//! one allocation per block rather than one per packet. With
+//! [`AfPacketSource::with_buffers`] each frame is copied into memory from the
+//! given [`PacketBuffers`] instead.
+
+use crate::buffers::PacketBuffers;
+use crate::filter::socket_filter;
+use crate::packet::Packet;
+use crate::source::{CaptureError, CaptureSource, CaptureStats};
+use bytes::{Bytes, BytesMut};
+use std::ffi::CString;
+use std::io;
+use std::mem::{offset_of, size_of};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::Arc;
+
+/// Largest block handed to the kernel.
+const MAX_BLOCK_SIZE: usize = 4 << 20;
+/// Smallest block (one page).
+const MIN_BLOCK_SIZE: usize = 4096;
+/// Minimum number of blocks, so the kernel can fill one while we drain another.
+const MIN_BLOCKS: usize = 2;
+/// Nominal frame size; V3 frames are variable length, this only sizes `tp_frame_nr`.
+const FRAME_SIZE: usize = 2048;
+
+/// Block and frame layout of the receive ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingGeometry {
+    pub block_size: usize,
+    pub block_count: usize,
+    pub frame_size: usize,
+}
+
+impl RingGeometry {
+    /// Splits a total ring budget of `buffer_size` bytes into power-of-two blocks.
+    pub fn from_buffer_size(buffer_size: usize) -> Self {
+        let target = (buffer_size / MIN_BLOCKS).max(1);
+        let block_size = prev_power_of_two(target).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+        let block_count = (buffer_size / block_size).max(MIN_BLOCKS);
+        Self {
+            block_size,
+            block_count,
+            frame_size: FRAME_SIZE,
+        }
+    }
+
+    /// Total mapped size of the ring in bytes.
+    pub fn ring_size(&self) -> usize {
+        self.block_size * self.block_count
+    }
+
+    fn frame_count(&self) -> usize {
+        self.ring_size() / self.frame_size
+    }
+}
+
+fn prev_power_of_two(value: usize) -> usize {
+    1 << (usize::BITS - 1 - value.leading_zeros())
+}
+
+/// AF_PACKET TPACKET_V3 capture on a single interface.
+pub struct AfPacketSource {
+    interface: String,
+    geometry: RingGeometry,
+    retire_timeout_ms: u32,
+    promiscuous: bool,
+    filter: Option<String>,
+    buffers: Option<Arc<dyn PacketBuffers>>,
+    socket: Option<OwnedFd>,
+    ring: Option<Ring>,
+    current_block: usize,
+    stats: CaptureStats,
+}
+
+impl AfPacketSource {
+    /// Creates a ring source for `interface`.
+    ///
+    /// * `buffer_size` - Total ring size in bytes.
+    /// * `max_latency_ms` - Block retire timeout: the longest a partially
+    ///   filled block is held back before being handed to user space.
+    pub fn new(
+        interface: &str,
+        buffer_size: usize,
+        max_latency_ms: u32,
+        promiscuous: bool,
+    ) -> Self {
+        Self {
+            interface: interface.to_string(),
+            geometry: RingGeometry::from_buffer_size(buffer_size),
+            retire_timeout_ms: max_latency_ms.max(1),
+            promiscuous,
+            filter: None,
+            buffers: None,
+            socket: None,
+            ring: None,
+            current_block: 0,
+            stats: CaptureStats::default(),
+        }
+    }
+
+    /// Restricts capture to packets matching a BPF `filter` expression.
+    pub fn with_filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    /// Copies each frame into memory from `buffers` instead of one shared
+    /// allocation per block.
+    pub fn with_buffers(mut self, buffers: Arc<dyn PacketBuffers>) -> Self {
+        self.buffers = Some(buffers);
+        self
+    }
+
+    /// Returns the ring layout derived from the configured buffer size.
+    pub fn geometry(&self) -> RingGeometry {
+        self.geometry
+    }
+
+    fn block_ptr(&self, ring: &Ring, index: usize) -> *mut u8 {
+        // SAFETY: index < block_count, so the offset stays inside the mapping.
+        unsafe { ring.ptr.as_ptr().add(index * self.geometry.block_size) }
+    }
+
+    fn wait_readable(&self, fd: i32) -> Result<(), CaptureError> {
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN | libc::POLLERR,
+            revents: 0,
+        };
+        // SAFETY: pfd is a valid pollfd for the duration of the call.
+        let rc = unsafe { libc::poll(&mut pfd, 1, self.retire_timeout_ms as i32) };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CaptureSource for AfPacketSource {
+    fn open(&mut self) -> Result<(), CaptureError> {
+        let name = CString::new(self.interface.as_str())
+            .map_err(|_| CaptureError::DeviceNotFound(self.interface.clone()))?;
+        // SAFETY: name is a valid NUL-terminated string.
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(CaptureError::DeviceNotFound(self.interface.clone()));
+        }
+
+        // Compile before creating the socket so a bad filter fails early.
+        let program = self.filter.as_deref().map(socket_filter).transpose()?;
+
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        // SAFETY: plain socket(2) call; the descriptor is owned below.
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, i32::from(protocol)) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // SAFETY: fd is a freshly created descriptor nobody else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Attach the filter before the ring exists so no unfiltered packet is queued.
+        if let Some(program) = &program {
+            let fprog = libc::sock_fprog {
+                len: program.len() as u16,
+                filter: program.as_ptr() as *mut libc::sock_filter,
+            };
+            set_option(&socket, libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &fprog)?;
+        }
+
+        set_option(
+            &socket,
+            libc::SOL_PACKET,
+            libc::PACKET_VERSION,
+            &(libc::tpacket_versions::TPACKET_V3 as libc::c_int),
+        )?;
+
+        let geometry = self.geometry;
+        let req = libc::tpacket_req3 {
+            tp_block_size: geometry.block_size as u32,
+            tp_block_nr: geometry.block_count as u32,
+            tp_frame_size: geometry.frame_size as u32,
+            tp_frame_nr: geometry.frame_count() as u32,
+            tp_retire_blk_tov: self.retire_timeout_ms,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        set_option(&socket, libc::SOL_PACKET, libc::PACKET_RX_RING, &req)?;
+
+        let ring = Ring::map(&socket, geometry.ring_size())?;
+
+        // SAFETY: sockaddr_ll is plain old data; all-zero is a valid value.
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = ifindex as i32;
+        // SAFETY: addr is a fully initialised sockaddr_ll of the size passed.
+        let rc = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                size_of::<libc::sockaddr_ll>() as u32,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        if self.promiscuous {
+            let mreq = libc::packet_mreq {
+                mr_ifindex: ifindex as i32,
+                mr_type: libc::PACKET_MR_PROMISC as u16,
+                mr_alen: 0,
+                mr_address: [0; 8],
+            };
+            set_option(
+                &socket,
+                libc::SOL_PACKET,
+                libc::PACKET_ADD_MEMBERSHIP,
+                &mreq,
+            )?;
+        }
+
+        self.socket = Some(socket);
+        self.ring = Some(ring);
+        self.current_block = 0;
+        Ok(())
+    }
+
+    fn next_batch(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        let (Some(socket), Some(ring)) = (self.socket.as_ref(), self.ring.as_ref()) else {
+            return Err(CaptureError::NotOpen);
+        };
+
+        let block = self.block_ptr(ring, self.current_block);
+        if !block_ready(block) {
+            self.wait_readable(socket.as_raw_fd())?;
+            if !block_ready(block) {
+                return Ok(Some(0));
+            }
+        }
+
+        // SAFETY: the kernel handed this block to user space (TP_STATUS_USER)
+        // and will not touch it until we give it back below.
+        let data = unsafe { std::slice::from_raw_parts(block, self.geometry.block_size) };
+        let appended = parse_block(data, self.buffers.as_deref(), batch);
+
+        release_block(block);
+        self.current_block = (self.current_block + 1) % self.geometry.block_count;
+        Ok(Some(appended))
+    }
+
+    fn stats(&mut self) -> Result<CaptureStats, CaptureError> {
+        let socket = self.socket.as_ref().ok_or(CaptureError::NotOpen)?;
+        // SAFETY: tpacket_stats_v3 is plain old data; all-zero is a valid value.
+        let mut kstats: libc::tpacket_stats_v3 = unsafe { std::mem::zeroed() };
+        let mut len = size_of::<libc::tpacket_stats_v3>() as libc::socklen_t;
+        // SAFETY: kstats and len describe a writable buffer of the right size.
+        let rc = unsafe {
+            libc::getsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                &mut kstats as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // The kernel resets its counters on every read, so accumulate them.
+        // tp_packets counts every packet seen, including those dropped.
+        self.stats.received += u64::from(kstats.tp_packets.saturating_sub(kstats.tp_drops));
+        self.stats.dropped += u64::from(kstats.tp_drops);
+        Ok(self.stats)
+    }
+
+    fn close(&mut self) {
+        // Unmap before closing the socket that owns the ring.
+        self.ring = None;
+        self.socket = None;
+    }
+}
+
+impl Drop for AfPacketSource {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The mmap'ed receive ring.
+struct Ring {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl Ring {
+    fn map(socket: &OwnedFd, len: usize) -> Result<Self, CaptureError> {
+        // SAFETY: mapping a ring configured on this socket via PACKET_RX_RING.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                socket.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+        let ptr = NonNull::new(ptr as *mut u8).ok_or(CaptureError::NotOpen)?;
+        Ok(Self { ptr, len })
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // SAFETY: ptr/len come from a successful mmap and are unmapped once.
+        unsafe {
+            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+// SAFETY: the mapping is only accessed through `&mut AfPacketSource`.
+unsafe impl Send for Ring {}
+
+fn set_option<T>(
+    socket: &OwnedFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: &T,
+) -> Result<(), CaptureError> {
+    // SAFETY: value points to a live T of the size passed.
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+const BLOCK_STATUS_OFFSET: usize =
+    offset_of!(libc::tpacket_block_desc, hdr) + offset_of!(libc::tpacket_hdr_v1, block_status);
+
+fn block_status(block: *mut u8) -> *mut u32 {
+    // SAFETY: block_status lies within the block descriptor.
+    unsafe { block.add(BLOCK_STATUS_OFFSET) as *mut u32 }
+}
+
+fn block_ready(block: *mut u8) -> bool {
+    // SAFETY: the status word is shared with the kernel, so read it volatile.
+    let status = unsafe { std::ptr::read_volatile(block_status(block)) };
+    fence(Ordering::Acquire);
+    status & libc::TP_STATUS_USER != 0
+}
+
+fn release_block(block: *mut u8) {
+    fence(Ordering::Release);
+    // SAFETY: we own the block until this store hands it back to the kernel.
+    unsafe { std::ptr::write_volatile(block_status(block), libc::TP_STATUS_KERNEL) };
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+}
+
+/// Copies every frame of a retired block into `batch`.
+///
+/// Frames are copied into `buffers` if given; otherwise all frames share one
+/// backing allocation. Returns the number of frames appended. Stops early on a
+/// malformed frame header rather than reading past the block.
+fn parse_block(
+    block: &[u8],
+    buffers: Option<&dyn PacketBuffers>,
+    batch: &mut Vec<Packet>,
+) -> usize {
+    let hdr = offset_of!(libc::tpacket_block_desc, hdr);
+    let (Some(num_pkts), Some(first)) = (
+        read_u32(block, hdr + offset_of!(libc::tpacket_hdr_v1, num_pkts)),
+        read_u32(
+            block,
+            hdr + offset_of!(libc::tpacket_hdr_v1, offset_to_first_pkt),
+        ),
+    ) else {
+        return 0;
+    };
+
+    let mut frames = Vec::with_capacity(num_pkts as usize);
+    let mut total = 0;
+    let mut offset = first as usize;
+    for _ in 0..num_pkts {
+        let Some(frame) = read_frame(block, offset) else {
+            break;
+        };
+        total += frame.data.len();
+        frames.push(frame);
+        if frame.next_offset == 0 {
+            break;
+        }
+        offset += frame.next_offset as usize;
+    }
+
+    if let Some(buffers) = buffers {
+        for frame in &frames {
+            batch.push(Packet::with_timestamp(
+                frame.timestamp,
+                buffers.copy(frame.data),
+            ));
+        }
+        return frames.len();
+    }
+
+    let mut buffer = BytesMut::with_capacity(total);
+    for frame in &frames {
+        buffer.extend_from_slice(frame.data);
+    }
+    let mut shared: Bytes = buffer.freeze();
+    for frame in &frames {
+        let data = shared.split_to(frame.data.len());
+        batch.push(Packet::with_timestamp(frame.timestamp, data));
+    }
+    frames.len()
+}
+
+#[derive(Clone, Copy)]
+struct Frame<'a> {
+    next_offset: u32,
+    timestamp: u64,
+    data: &'a [u8],
+}
+
+fn read_frame(block: &[u8], offset: usize) -> Option<Frame<'_>> {
+    use libc::tpacket3_hdr as H;
+    let next_offset = read_u32(block, offset + offset_of!(H, tp_next_offset))?;
+    let sec = read_u32(block, offset + offset_of!(H, tp_sec))?;
+    let nsec = read_u32(block, offset + offset_of!(H, tp_nsec))?;
+    let snaplen = read_u32(block, offset + offset_of!(H, tp_snaplen))? as usize;
+    let mac = read_u16(block, offset + offset_of!(H, tp_mac))? as usize;
+    let start = offset + mac;
+    let data = block.get(start..start + snaplen)?;
+    Some(Frame {
+        next_offset,
+        timestamp: u64::from(sec) * 1_000_000_000 + u64::from(nsec),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_splits_buffer_into_blocks() {
+        let geometry = RingGeometry::from_buffer_size(1 << 20);
+        assert_eq!(geometry.block_size, 1 << 19);
+        assert_eq!(geometry.block_count, 2);
+        assert_eq!(geometry.ring_size(), 1 << 20);
+    }
+
+    #[test]
+    fn geometry_caps_block_size() {
+        let geometry = RingGeometry::from_buffer_size(64 << 20);
+        assert_eq!(geometry.block_size, MAX_BLOCK_SIZE);
+        assert_eq!(geometry.block_count, 16);
+    }
+
+    #[test]
+    fn geometry_keeps_minimum_blocks() {
+        let geometry = RingGeometry::from_buffer_size(4096);
+        assert_eq!(geometry.block_size, MIN_BLOCK_SIZE);
+        assert_eq!(geometry.block_count, MIN_BLOCKS);
+    }
+
+    fn put_u32(block: &mut [u8], offset: usize, value: u32) {
+        block[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    fn put_u16(block: &mut [u8], offset: usize, value: u16) {
+        block[offset..offset + 2].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Builds a block the way the kernel lays it out, one frame per payload.
+    fn build_block(payloads: &[&[u8]]) -> Vec<u8> {
+        use libc::tpacket3_hdr as H;
+        let mut block = vec![0u8; 4096];
+        let hdr = offset_of!(libc::tpacket_block_desc, hdr);
+        let first = 48;
+        put_u32(
+            &mut block,
+            hdr + offset_of!(libc::tpacket_hdr_v1, num_pkts),
+            payloads.len() as u32,
+        );
+        put_u32(
+            &mut block,
+            hdr + offset_of!(libc::tpacket_hdr_v1, offset_to_first_pkt),
+            first as u32,
+        );
+
+        let mut offset = first;
+        for (i, payload) in payloads.iter().enumerate() {
+            let mac = 80;
+            let frame_len = (mac + payload.len()).next_multiple_of(16);
+            let next = if i + 1 == payloads.len() {
+                0
+            } else {
+                frame_len
+            };
+            put_u32(
+                &mut block,
+                offset + offset_of!(H, tp_next_offset),
+                next as u32,
+            );
+            put_u32(&mut block, offset + offset_of!(H, tp_sec), 10 + i as u32);
+            put_u32(&mut block, offset + offset_of!(H, tp_nsec), 500);
+            put_u32(
+                &mut block,
+                offset + offset_of!(H, tp_snaplen),
+                payload.len() as u32,
+            );
+            put_u32(
+                &mut block,
+                offset + offset_of!(H, tp_len),
+                payload.len() as u32,
+            );
+            put_u16(&mut block, offset + offset_of!(H, tp_mac), mac as u16);
+            block[offset + mac..offset + mac + payload.len()].copy_from_slice(payload);
+            offset += frame_len;
+        }
+        block
+    }
+
+    #[test]
+    fn parses_all_frames_in_block() {
+        let block = build_block(&[b"first", b"second frame", b"3"]);
+        let mut batch = Vec::new();
+
+        assert_eq!(parse_block(&block, None, &mut batch), 3);
+        assert_eq!(&batch[0].data[..], b"first");
+        assert_eq!(&batch[1].data[..], b"second frame");
+        assert_eq!(&batch[2].data[..], b"3");
+        assert_eq!(batch[0].timestamp, 10_000_000_500);
+        assert_eq!(batch[2].timestamp, 12_000_000_500);
+    }
+
+    #[test]
+    fn copies_frames_into_given_buffers() {
+        let block = build_block(&[b"first", b"second frame"]);
+        let mut batch = Vec::new();
+
+        assert_eq!(
+            parse_block(&block, Some(&crate::HeapBuffers), &mut batch),
+            2
+        );
+        assert_eq!(&batch[0].data[..], b"first");
+        assert_eq!(&batch[1].data[..], b"second frame");
+        assert_eq!(batch[1].timestamp, 11_000_000_500);
+    }
+
+    #[test]
+    fn stops_at_truncated_frame() {
+        let mut block = build_block(&[b"ok", b"truncated"]);
+        block.truncate(48 + 96 + 80 + 2);
+        let mut batch = Vec::new();
+
+        assert_eq!(parse_block(&block, None, &mut batch), 1);
+        assert_eq!(&batch[0].data[..], b"ok");
+    }
+
+    /// Needs CAP_NET_RAW: `cargo test -p vakthund-capture -- --ignored`.
+    #[test]
+    #[ignore]
+    fn captures_on_loopback() {
+        let mut source = AfPacketSource::new("lo", 1 << 20, 10, false);
+        source.open().expect("AF_PACKET needs CAP_NET_RAW");
+
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let marker = b"vakthund-af-packet-test";
+
+        let mut batch = Vec::new();
+        for _ in 0..100 {
+            sender
+                .send_to(marker, target.local_addr().unwrap())
+                .unwrap();
+            source.next_batch(&mut batch).unwrap();
+            if batch.iter().any(|p| p.data.ends_with(marker)) {
+                assert!(source.stats().unwrap().received > 0);
+                return;
+            }
+        }
+        panic!("No packet captured on loopback");
+    }
+}
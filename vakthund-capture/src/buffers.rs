@@ -0,0 +1,23 @@
+//! Storage for captured frames.
+//!
+//! Live and file sources copy every frame out of the kernel ring or libpcap
+//! buffer before handing it on. [`PacketBuffers`] decides where that copy
+//! lives, so a preallocated pool can back packet data instead of the heap.
+
+use bytes::Bytes;
+
+/// Provides the memory captured frames are copied into.
+pub trait PacketBuffers: Send + Sync {
+    /// Copies `frame` into a buffer owned by the returned `Bytes`.
+    fn copy(&self, frame: &[u8]) -> Bytes;
+}
+
+/// Copies each frame into its own heap allocation.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeapBuffers;
+
+impl PacketBuffers for HeapBuffers {
+    fn copy(&self, frame: &[u8]) -> Bytes {
+        Bytes::copy_from_slice(frame)
+    }
+}
@@ -1,54 +1,112 @@
+//! Live capture from a network interface using libpcap.
+
+use crate::buffers::{HeapBuffers, PacketBuffers};
+use crate::decode::LinkType;
+use crate::file::{header_timestamp_ns, link_type_of};
 use crate::packet::Packet;
-use pcap::{Capture, Device};
-use std::sync::atomic::{AtomicBool, Ordering}; // Use crate-local Packet
+use crate::source::{CaptureError, CaptureSource, CaptureStats};
+use pcap::{Active, Capture, Device, Precision};
+use std::sync::Arc;
 
 /// The type for the callback function: it will receive a reference to a Packet.
 pub type PacketCallback = dyn FnMut(&Packet) + Send;
 
-/// Run a live capture loop on the specified interface.
-/// This function will block until `terminate` is set to true.
-pub fn run_capture_loop<F>(
-    interface: &str,
-    buffer_size: usize,
+/// Read timeout for the live handle, in milliseconds.
+const READ_TIMEOUT_MS: i32 = 1000;
+
+/// Live pcap capture on a single interface.
+///
+/// libpcap hands out one packet per read, so each batch holds at most one packet.
+pub struct PcapSource {
+    interface: String,
+    snaplen: i32,
     promiscuous: bool,
-    terminate: &AtomicBool,
-    mut callback: F, // Use generic callback to avoid dyn FnMut cost if possible
-) where
-    F: FnMut(&Packet) + Send,
-{
-    // List available devices and select the one matching the interface name.
-    let device = Device::list()
-        .expect("Failed to list devices")
-        .into_iter()
-        .find(|d| d.name == interface)
-        .unwrap_or_else(|| panic!("Device '{}' not found", interface)); // More informative panic
-
-    // Open the capture on the selected device.
-    let mut cap = Capture::from_device(device)
-        .expect("Failed to open device")
-        .promisc(promiscuous)
-        .snaplen(buffer_size as i32)
-        .timeout(1000) // timeout in ms (adjust as needed)
-        .open()
-        .expect("Failed to open capture");
-
-    // Capture loop
-    while !terminate.load(Ordering::Relaxed) {
-        match cap.next_packet() {
+    filter: Option<String>,
+    buffers: Arc<dyn PacketBuffers>,
+    handle: Option<Capture<Active>>,
+    link_type: LinkType,
+}
+
+impl PcapSource {
+    /// Creates a live source for `interface`. Nothing is opened until [`CaptureSource::open`].
+    pub fn new(interface: &str, buffer_size: usize, promiscuous: bool) -> Self {
+        Self {
+            interface: interface.to_string(),
+            snaplen: buffer_size.min(i32::MAX as usize) as i32,
+            promiscuous,
+            filter: None,
+            buffers: Arc::new(HeapBuffers),
+            handle: None,
+            link_type: LinkType::Ethernet,
+        }
+    }
+
+    /// Restricts capture to packets matching a BPF `filter` expression.
+    pub fn with_filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    /// Copies captured frames into memory from `buffers` instead of the heap.
+    pub fn with_buffers(mut self, buffers: Arc<dyn PacketBuffers>) -> Self {
+        self.buffers = buffers;
+        self
+    }
+}
+
+impl CaptureSource for PcapSource {
+    fn open(&mut self) -> Result<(), CaptureError> {
+        // List available devices and select the one matching the interface name.
+        let device = Device::list()?
+            .into_iter()
+            .find(|d| d.name == self.interface)
+            .ok_or_else(|| CaptureError::DeviceNotFound(self.interface.clone()))?;
+
+        let mut handle = Capture::from_device(device)?
+            .promisc(self.promiscuous)
+            .snaplen(self.snaplen)
+            .timeout(READ_TIMEOUT_MS)
+            .open()?;
+        self.link_type = link_type_of(handle.get_datalink())?;
+        if let Some(filter) = &self.filter {
+            handle
+                .filter(filter, true)
+                .map_err(|e| CaptureError::InvalidFilter(format!("{filter}: {e}")))?;
+        }
+
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    fn next_batch(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        let handle = self.handle.as_mut().ok_or(CaptureError::NotOpen)?;
+        match handle.next_packet() {
             Ok(packet) => {
-                let pkt = Packet {
-                    data: packet.data.to_vec().into(),
-                };
-                callback(&pkt);
-            }
-            Err(pcap::Error::TimeoutExpired) => {
-                // No packet received in this timeout window; just continue.
-                continue;
-            }
-            Err(e) => {
-                eprintln!("Error capturing packet: {:?}", e);
-                break;
+                let timestamp = header_timestamp_ns(packet.header, Precision::Micro);
+                batch.push(
+                    Packet::with_timestamp(timestamp, self.buffers.copy(packet.data))
+                        .with_link_type(self.link_type),
+                );
+                Ok(Some(1))
             }
+            // No packet received in this timeout window.
+            Err(pcap::Error::TimeoutExpired) => Ok(Some(0)),
+            Err(pcap::Error::NoMorePackets) => Ok(None),
+            Err(e) => Err(e.into()),
         }
     }
+
+    fn stats(&mut self) -> Result<CaptureStats, CaptureError> {
+        let handle = self.handle.as_mut().ok_or(CaptureError::NotOpen)?;
+        let stat = handle.stats()?;
+        Ok(CaptureStats {
+            received: u64::from(stat.received),
+            dropped: u64::from(stat.dropped),
+            if_dropped: u64::from(stat.if_dropped),
+        })
+    }
+
+    fn close(&mut self) {
+        self.handle = None;
+    }
 }
@@ -0,0 +1,629 @@
+//! L2–L4 packet decoding.
+//!
+//! Walks the link layer (Ethernet with 802.1Q / 802.1ad QinQ tags, Linux
+//! cooked capture or none for raw IP), IPv4 / IPv6 (including IPv6 extension
+//! headers) and TCP / UDP headers. The result records the
+//! addressing found along the way and the byte range of the application
+//! payload, so callers can hand `data.slice(payload)` to protocol parsers
+//! without copying.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+use thiserror::Error;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+pub const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const LINUX_SLL_HEADER_LEN: usize = 16;
+const LINUX_SLL2_HEADER_LEN: usize = 20;
+const VLAN_TAG_LEN: usize = 4;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Packet decoding errors.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum DecodeError {
+    /// A header extends past the end of the captured data.
+    #[error("Truncated {0} header")]
+    Truncated(&'static str),
+    /// A header field holds a value that cannot be decoded.
+    #[error("Malformed {0} header")]
+    Malformed(&'static str),
+}
+
+/// IP fragmentation fields, present when the packet is one fragment of a datagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FragmentInfo {
+    /// Datagram identification (16 bits for IPv4, 32 bits for IPv6).
+    pub id: u32,
+    /// Offset of this fragment's data within the datagram, in bytes.
+    pub offset: u16,
+    /// Whether more fragments follow.
+    pub more_fragments: bool,
+}
+
+/// Network layer addressing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpInfo {
+    pub source: IpAddr,
+    pub destination: IpAddr,
+    /// Upper-layer protocol number (after any IPv6 extension headers).
+    pub protocol: u8,
+    /// Set when the packet is a fragment.
+    pub fragment: Option<FragmentInfo>,
+    /// Byte range of the IP payload (after extension headers) within the frame.
+    pub payload: Range<usize>,
+}
+
+/// TCP header fields used for flow tracking and reassembly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpInfo {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: u8,
+}
+
+impl TcpInfo {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+}
+
+/// UDP header fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UdpInfo {
+    pub source_port: u16,
+    pub destination_port: u16,
+}
+
+/// Transport layer decoded from the IP payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    Tcp(TcpInfo),
+    Udp(UdpInfo),
+}
+
+impl Transport {
+    pub fn source_port(&self) -> u16 {
+        match self {
+            Transport::Tcp(tcp) => tcp.source_port,
+            Transport::Udp(udp) => udp.source_port,
+        }
+    }
+
+    pub fn destination_port(&self) -> u16 {
+        match self {
+            Transport::Tcp(tcp) => tcp.destination_port,
+            Transport::Udp(udp) => udp.destination_port,
+        }
+    }
+}
+
+/// Result of decoding one frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedPacket {
+    /// Innermost 802.1Q VLAN id.
+    pub vlan: Option<u16>,
+    /// Outer (service) VLAN id for QinQ frames.
+    pub outer_vlan: Option<u16>,
+    /// EtherType after any VLAN tags.
+    pub ethertype: u16,
+    /// IP layer, if the frame carries IPv4 or IPv6.
+    pub ip: Option<IpInfo>,
+    /// TCP/UDP layer. `None` for other protocols and for IP fragments,
+    /// whose transport header cannot be trusted until reassembly.
+    pub transport: Option<Transport>,
+    /// Byte range of the innermost payload decoded (application data for
+    /// TCP/UDP, the IP payload otherwise).
+    pub payload: Range<usize>,
+}
+
+/// Link-layer header type of captured frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LinkType {
+    #[default]
+    Ethernet,
+    /// No link-layer header; frames start with the IPv4 or IPv6 header.
+    RawIp,
+    /// Linux cooked capture, as used for the `any` device.
+    LinuxSll,
+    /// Linux cooked capture, version 2.
+    LinuxSll2,
+}
+
+impl LinkType {
+    /// Maps a libpcap `DLT_*` value. Returns `None` for link types that
+    /// cannot be decoded.
+    pub fn from_dlt(dlt: i32) -> Option<Self> {
+        match dlt {
+            1 => Some(LinkType::Ethernet),
+            // DLT_RAW differs between platforms; 228 and 229 are IPv4 and IPv6 only.
+            12 | 14 | 101 | 228 | 229 => Some(LinkType::RawIp),
+            113 => Some(LinkType::LinuxSll),
+            276 => Some(LinkType::LinuxSll2),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a frame that starts with a `link` header.
+pub fn decode_frame(link: LinkType, data: &[u8]) -> Result<DecodedPacket, DecodeError> {
+    match link {
+        LinkType::Ethernet => decode_ethernet(data),
+        LinkType::RawIp => decode_ip(data),
+        LinkType::LinuxSll => {
+            if data.len() < LINUX_SLL_HEADER_LEN {
+                return Err(DecodeError::Truncated("Linux cooked"));
+            }
+            decode_tagged(data, read_u16(data, 14), LINUX_SLL_HEADER_LEN)
+        }
+        LinkType::LinuxSll2 => {
+            if data.len() < LINUX_SLL2_HEADER_LEN {
+                return Err(DecodeError::Truncated("Linux cooked"));
+            }
+            decode_tagged(data, read_u16(data, 0), LINUX_SLL2_HEADER_LEN)
+        }
+    }
+}
+
+/// Decodes an Ethernet frame.
+pub fn decode_ethernet(data: &[u8]) -> Result<DecodedPacket, DecodeError> {
+    decode_ethernet_at(data, 0)
+}
+
+/// Decodes an Ethernet frame starting at `offset`. Ranges in the result are
+/// relative to the start of `data`.
+pub(crate) fn decode_ethernet_at(data: &[u8], offset: usize) -> Result<DecodedPacket, DecodeError> {
+    if data.len() < offset + ETHERNET_HEADER_LEN {
+        return Err(DecodeError::Truncated("Ethernet"));
+    }
+    decode_tagged(
+        data,
+        read_u16(data, offset + 12),
+        offset + ETHERNET_HEADER_LEN,
+    )
+}
+
+/// Decodes the VLAN tags following a link-layer header that announced
+/// `ethertype`, then the network layer.
+fn decode_tagged(
+    data: &[u8],
+    mut ethertype: u16,
+    mut offset: usize,
+) -> Result<DecodedPacket, DecodeError> {
+    // Collect up to two VLAN tags (outermost first).
+    let mut tags = [None; 2];
+    let mut depth = 0;
+    while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+        if data.len() < offset + VLAN_TAG_LEN {
+            return Err(DecodeError::Truncated("802.1Q"));
+        }
+        if depth == tags.len() {
+            return Err(DecodeError::Malformed("802.1Q"));
+        }
+        tags[depth] = Some(read_u16(data, offset) & 0x0FFF);
+        depth += 1;
+        ethertype = read_u16(data, offset + 2);
+        offset += VLAN_TAG_LEN;
+    }
+    let (outer_vlan, vlan) = match depth {
+        2 => (tags[0], tags[1]),
+        _ => (None, tags[0]),
+    };
+
+    let mut decoded = decode_l3(data, ethertype, offset)?;
+    decoded.vlan = vlan;
+    decoded.outer_vlan = outer_vlan;
+    Ok(decoded)
+}
+
+/// Decodes a packet that starts with an IP header (raw IP link type).
+pub fn decode_ip(data: &[u8]) -> Result<DecodedPacket, DecodeError> {
+    let ethertype = match data.first().map(|b| b >> 4) {
+        Some(4) => ETHERTYPE_IPV4,
+        Some(6) => ETHERTYPE_IPV6,
+        Some(_) => return Err(DecodeError::Malformed("IP")),
+        None => return Err(DecodeError::Truncated("IP")),
+    };
+    decode_l3(data, ethertype, 0)
+}
+
+/// Decodes the network layer at `offset`, then the transport layer.
+pub(crate) fn decode_l3(
+    data: &[u8],
+    ethertype: u16,
+    offset: usize,
+) -> Result<DecodedPacket, DecodeError> {
+    let ip = match ethertype {
+        ETHERTYPE_IPV4 => Some(decode_ipv4(data, offset)?),
+        ETHERTYPE_IPV6 => Some(decode_ipv6(data, offset)?),
+        _ => None,
+    };
+
+    let (transport, payload) = match &ip {
+        Some(ip) if ip.fragment.is_none() => decode_transport(data, ip)?,
+        Some(ip) => (None, ip.payload.clone()),
+        None => (None, offset..data.len()),
+    };
+
+    Ok(DecodedPacket {
+        vlan: None,
+        outer_vlan: None,
+        ethertype,
+        ip,
+        transport,
+        payload,
+    })
+}
+
+fn decode_ipv4(data: &[u8], offset: usize) -> Result<IpInfo, DecodeError> {
+    let header = data.get(offset..).ok_or(DecodeError::Truncated("IPv4"))?;
+    if header.len() < 20 {
+        return Err(DecodeError::Truncated("IPv4"));
+    }
+    if header[0] >> 4 != 4 {
+        return Err(DecodeError::Malformed("IPv4"));
+    }
+    let header_len = usize::from(header[0] & 0x0F) * 4;
+    let total_len = usize::from(read_u16(header, 2));
+    if header_len < 20 || total_len < header_len {
+        return Err(DecodeError::Malformed("IPv4"));
+    }
+    if header.len() < header_len {
+        return Err(DecodeError::Truncated("IPv4"));
+    }
+
+    let flags_fragment = read_u16(header, 6);
+    let more_fragments = flags_fragment & 0x2000 != 0;
+    let fragment_offset = (flags_fragment & 0x1FFF) * 8;
+    let fragment = (more_fragments || fragment_offset != 0).then(|| FragmentInfo {
+        id: u32::from(read_u16(header, 4)),
+        offset: fragment_offset,
+        more_fragments,
+    });
+
+    // Ignore Ethernet padding past the IP total length, but tolerate a
+    // snaplen-truncated capture.
+    let end = offset + total_len.min(header.len());
+    Ok(IpInfo {
+        source: IpAddr::V4(Ipv4Addr::new(
+            header[12], header[13], header[14], header[15],
+        )),
+        destination: IpAddr::V4(Ipv4Addr::new(
+            header[16], header[17], header[18], header[19],
+        )),
+        protocol: header[9],
+        fragment,
+        payload: offset + header_len..end,
+    })
+}
+
+fn decode_ipv6(data: &[u8], offset: usize) -> Result<IpInfo, DecodeError> {
+    let header = data.get(offset..).ok_or(DecodeError::Truncated("IPv6"))?;
+    if header.len() < IPV6_HEADER_LEN {
+        return Err(DecodeError::Truncated("IPv6"));
+    }
+    if header[0] >> 4 != 6 {
+        return Err(DecodeError::Malformed("IPv6"));
+    }
+    let payload_len = usize::from(read_u16(header, 4));
+    let end = offset + (IPV6_HEADER_LEN + payload_len).min(header.len());
+    let source = Ipv6Addr::from(read_array::<16>(header, 8));
+    let destination = Ipv6Addr::from(read_array::<16>(header, 24));
+
+    // Walk the extension header chain to the upper-layer protocol.
+    let mut next_header = header[6];
+    let mut cursor = offset + IPV6_HEADER_LEN;
+    let mut fragment = None;
+    loop {
+        match next_header {
+            // Hop-by-hop, routing, destination options.
+            0 | 43 | 60 => {
+                let ext = data
+                    .get(cursor..cursor + 8)
+                    .ok_or(DecodeError::Truncated("IPv6 extension"))?;
+                next_header = ext[0];
+                cursor += (usize::from(ext[1]) + 1) * 8;
+            }
+            // Fragment header.
+            44 => {
+                let ext = data
+                    .get(cursor..cursor + 8)
+                    .ok_or(DecodeError::Truncated("IPv6 fragment"))?;
+                next_header = ext[0];
+                let offset_flags = read_u16(ext, 2);
+                let more_fragments = offset_flags & 0x0001 != 0;
+                let fragment_offset = offset_flags & 0xFFF8;
+                // An atomic fragment (offset 0, no more fragments) is a whole datagram.
+                if more_fragments || fragment_offset != 0 {
+                    fragment = Some(FragmentInfo {
+                        id: u32::from_be_bytes(read_array::<4>(ext, 4)),
+                        offset: fragment_offset,
+                        more_fragments,
+                    });
+                }
+                cursor += 8;
+            }
+            // Authentication header: length in 4-byte units, minus 2.
+            51 => {
+                let ext = data
+                    .get(cursor..cursor + 8)
+                    .ok_or(DecodeError::Truncated("IPv6 AH"))?;
+                next_header = ext[0];
+                cursor += (usize::from(ext[1]) + 2) * 4;
+            }
+            _ => break,
+        }
+        if cursor > end {
+            return Err(DecodeError::Truncated("IPv6 extension"));
+        }
+    }
+
+    Ok(IpInfo {
+        source: IpAddr::V6(source),
+        destination: IpAddr::V6(destination),
+        protocol: next_header,
+        fragment,
+        payload: cursor..end,
+    })
+}
+
+/// Decodes the TCP/UDP header found in `ip.payload`.
+pub fn decode_transport(
+    data: &[u8],
+    ip: &IpInfo,
+) -> Result<(Option<Transport>, Range<usize>), DecodeError> {
+    let segment = &data[ip.payload.clone()];
+    let start = ip.payload.start;
+    match ip.protocol {
+        IPPROTO_TCP => {
+            if segment.len() < 20 {
+                return Err(DecodeError::Truncated("TCP"));
+            }
+            let header_len = usize::from(segment[12] >> 4) * 4;
+            if header_len < 20 {
+                return Err(DecodeError::Malformed("TCP"));
+            }
+            if segment.len() < header_len {
+                return Err(DecodeError::Truncated("TCP"));
+            }
+            let tcp = TcpInfo {
+                source_port: read_u16(segment, 0),
+                destination_port: read_u16(segment, 2),
+                sequence: u32::from_be_bytes(read_array::<4>(segment, 4)),
+                acknowledgement: u32::from_be_bytes(read_array::<4>(segment, 8)),
+                flags: segment[13],
+            };
+            Ok((
+                Some(Transport::Tcp(tcp)),
+                start + header_len..ip.payload.end,
+            ))
+        }
+        IPPROTO_UDP => {
+            if segment.len() < UDP_HEADER_LEN {
+                return Err(DecodeError::Truncated("UDP"));
+            }
+            let udp = UdpInfo {
+                source_port: read_u16(segment, 0),
+                destination_port: read_u16(segment, 2),
+            };
+            let length = usize::from(read_u16(segment, 4));
+            if length < UDP_HEADER_LEN {
+                return Err(DecodeError::Malformed("UDP"));
+            }
+            let end = start + length.min(segment.len());
+            Ok((Some(Transport::Udp(udp)), start + UDP_HEADER_LEN..end))
+        }
+        _ => Ok((None, ip.payload.clone())),
+    }
+}
+
+#[inline]
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+#[inline]
+fn read_array<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    let mut out = [0; N];
+    out.copy_from_slice(&data[offset..offset + N]);
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn ethernet(ethertype: u16, vlans: &[u16], body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xAA; 6];
+        frame.extend_from_slice(&[0xBB; 6]);
+        for (i, vlan) in vlans.iter().enumerate() {
+            let tpid = if i == 0 && vlans.len() > 1 {
+                ETHERTYPE_QINQ
+            } else {
+                ETHERTYPE_VLAN
+            };
+            frame.extend_from_slice(&tpid.to_be_bytes());
+            frame.extend_from_slice(&vlan.to_be_bytes());
+        }
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    pub(crate) fn ipv4(protocol: u8, fragment: u16, id: u16, body: &[u8]) -> Vec<u8> {
+        let total = (20 + body.len()) as u16;
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&total.to_be_bytes());
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&fragment.to_be_bytes());
+        packet.extend_from_slice(&[64, protocol, 0, 0]);
+        packet.extend_from_slice(&[10, 0, 0, 1]);
+        packet.extend_from_slice(&[10, 0, 0, 2]);
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    pub(crate) fn tcp(src: u16, dst: u16, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = Vec::new();
+        segment.extend_from_slice(&src.to_be_bytes());
+        segment.extend_from_slice(&dst.to_be_bytes());
+        segment.extend_from_slice(&seq.to_be_bytes());
+        segment.extend_from_slice(&0u32.to_be_bytes());
+        segment.extend_from_slice(&[0x50, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    pub(crate) fn udp(src: u16, dst: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::new();
+        datagram.extend_from_slice(&src.to_be_bytes());
+        datagram.extend_from_slice(&dst.to_be_bytes());
+        datagram.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    #[test]
+    fn decodes_ipv4_tcp() {
+        let segment = tcp(49152, 1883, 1000, TcpInfo::PSH | TcpInfo::ACK, b"mqtt");
+        let frame = ethernet(ETHERTYPE_IPV4, &[], &ipv4(IPPROTO_TCP, 0, 1, &segment));
+
+        let decoded = decode_ethernet(&frame).unwrap();
+        let ip = decoded.ip.unwrap();
+        assert_eq!(ip.source, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(ip.destination, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+        let Some(Transport::Tcp(tcp)) = decoded.transport else {
+            panic!("expected TCP");
+        };
+        assert_eq!(tcp.destination_port, 1883);
+        assert_eq!(tcp.sequence, 1000);
+        assert_eq!(&frame[decoded.payload], b"mqtt");
+    }
+
+    #[test]
+    fn decodes_qinq_udp() {
+        let datagram = udp(5683, 40000, b"coap");
+        let frame = ethernet(
+            ETHERTYPE_IPV4,
+            &[100, 200],
+            &ipv4(IPPROTO_UDP, 0, 1, &datagram),
+        );
+
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(decoded.outer_vlan, Some(100));
+        assert_eq!(decoded.vlan, Some(200));
+        assert_eq!(decoded.transport.unwrap().source_port(), 5683);
+        assert_eq!(&frame[decoded.payload], b"coap");
+    }
+
+    #[test]
+    fn ignores_ethernet_padding() {
+        let datagram = udp(1, 2, b"x");
+        let mut frame = ethernet(ETHERTYPE_IPV4, &[7], &ipv4(IPPROTO_UDP, 0, 1, &datagram));
+        frame.extend_from_slice(&[0; 16]);
+
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(decoded.vlan, Some(7));
+        assert_eq!(&frame[decoded.payload], b"x");
+    }
+
+    #[test]
+    fn decodes_ipv6_with_extension_header() {
+        let datagram = udp(1000, 5683, b"v6");
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&((8 + datagram.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 64]); // hop-by-hop next, hop limit
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&[IPPROTO_UDP, 0, 0, 0, 0, 0, 0, 0]);
+        packet.extend_from_slice(&datagram);
+        let frame = ethernet(ETHERTYPE_IPV6, &[], &packet);
+
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(decoded.ip.unwrap().protocol, IPPROTO_UDP);
+        assert_eq!(decoded.transport.unwrap().destination_port(), 5683);
+        assert_eq!(&frame[decoded.payload], b"v6");
+    }
+
+    #[test]
+    fn fragments_skip_transport_decoding() {
+        let frame = ethernet(
+            ETHERTYPE_IPV4,
+            &[],
+            &ipv4(IPPROTO_UDP, 0x2000, 42, &udp(1, 2, b"part")),
+        );
+
+        let decoded = decode_ethernet(&frame).unwrap();
+        let fragment = decoded.ip.unwrap().fragment.unwrap();
+        assert_eq!(fragment.id, 42);
+        assert!(fragment.more_fragments);
+        assert!(decoded.transport.is_none());
+    }
+
+    #[test]
+    fn non_ip_frames_have_no_ip_layer() {
+        let frame = ethernet(0x0806, &[], &[0; 28]);
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert!(decoded.ip.is_none());
+        assert_eq!(decoded.payload, 14..frame.len());
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        assert_eq!(
+            decode_ethernet(&[0; 10]),
+            Err(DecodeError::Truncated("Ethernet"))
+        );
+        let frame = ethernet(ETHERTYPE_IPV4, &[], &[0x45, 0, 0, 20]);
+        assert_eq!(decode_ethernet(&frame), Err(DecodeError::Truncated("IPv4")));
+        let frame = ethernet(ETHERTYPE_IPV4, &[], &ipv4(IPPROTO_TCP, 0, 1, &[0; 10]));
+        assert_eq!(decode_ethernet(&frame), Err(DecodeError::Truncated("TCP")));
+    }
+
+    #[test]
+    fn decodes_raw_ip() {
+        let packet = ipv4(IPPROTO_UDP, 0, 1, &udp(1, 2, b"raw"));
+        let decoded = decode_ip(&packet).unwrap();
+        assert_eq!(&packet[decoded.payload], b"raw");
+    }
+
+    #[test]
+    fn decodes_by_link_type() {
+        let packet = ipv4(IPPROTO_UDP, 0, 1, &udp(1, 2, b"link"));
+
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0, 0];
+        sll.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        sll.extend_from_slice(&packet);
+        let mut sll2 = ETHERTYPE_IPV4.to_be_bytes().to_vec();
+        sll2.extend_from_slice(&[0; 18]);
+        sll2.extend_from_slice(&packet);
+        let ethernet = ethernet(ETHERTYPE_IPV4, &[7], &packet);
+
+        for (link, frame) in [
+            (LinkType::from_dlt(1), &ethernet),
+            (LinkType::from_dlt(12), &packet),
+            (LinkType::from_dlt(113), &sll),
+            (LinkType::from_dlt(276), &sll2),
+        ] {
+            let decoded = decode_frame(link.unwrap(), frame).unwrap();
+            assert_eq!(&frame[decoded.payload], b"link", "{link:?}");
+        }
+        assert_eq!(
+            decode_frame(LinkType::LinuxSll, &sll[..10]),
+            Err(DecodeError::Truncated("Linux cooked"))
+        );
+        assert_eq!(LinkType::from_dlt(105), None);
+    }
+}
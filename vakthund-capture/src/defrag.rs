@@ -0,0 +1,529 @@
+//! IPv4/IPv6 fragment reassembly.
+//!
+//! Fragments are held per datagram (addresses, protocol, identification)
+//! until the datagram is complete, times out or is evicted to respect the
+//! memory limits. Overlapping bytes are resolved by a target-based
+//! [`OverlapPolicy`] so the reassembled payload matches what the protected
+//! host would see, and suspicious fragmentation is reported as a
+//! [`FragmentAnomaly`].
+
+use crate::decode::IpInfo;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+
+/// Largest possible reassembled IP payload.
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// How overlapping fragment data is resolved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// The first copy of a byte wins (Windows, Solaris).
+    #[default]
+    First,
+    /// The most recent copy of a byte wins (some routers and printers).
+    Last,
+    /// Original data wins unless the new fragment starts at a lower offset.
+    Bsd,
+    /// Like BSD, but a new fragment at the same offset also wins if it is longer.
+    Linux,
+}
+
+impl OverlapPolicy {
+    /// Whether `new` overwrites bytes already provided by `old`.
+    fn new_wins(self, new: &Fragment, old: &Fragment) -> bool {
+        match self {
+            OverlapPolicy::First => false,
+            OverlapPolicy::Last => true,
+            OverlapPolicy::Bsd => new.offset < old.offset,
+            OverlapPolicy::Linux => {
+                new.offset < old.offset || (new.offset == old.offset && new.end() > old.end())
+            }
+        }
+    }
+}
+
+/// Limits and policy for the defragmenter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefragLimits {
+    pub policy: OverlapPolicy,
+    /// Maximum number of datagrams being reassembled at once.
+    pub max_datagrams: usize,
+    /// Maximum fragment bytes held across all datagrams.
+    pub max_total_bytes: usize,
+    /// Maximum number of fragments held for one datagram.
+    pub max_fragments: usize,
+    /// Incomplete datagrams older than this (nanoseconds) are discarded.
+    pub timeout_ns: u64,
+    /// Non-final fragments carrying fewer bytes than this are reported as tiny.
+    pub min_fragment_size: usize,
+}
+
+impl Default for DefragLimits {
+    fn default() -> Self {
+        Self {
+            policy: OverlapPolicy::default(),
+            max_datagrams: 4096,
+            max_total_bytes: 16 * 1024 * 1024,
+            max_fragments: 128,
+            timeout_ns: 30_000_000_000,
+            min_fragment_size: 64,
+        }
+    }
+}
+
+/// Suspicious fragmentation observed while reassembling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentAnomaly {
+    /// A fragment overlaps previously received data with different content.
+    Overlap,
+    /// A non-final fragment is smaller than `min_fragment_size`.
+    TinyFragment,
+    /// Fragments extend past the maximum datagram size.
+    Oversized,
+    /// A datagram is split into more than `max_fragments` fragments.
+    TooManyFragments,
+}
+
+/// Defragmentation counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DefragStats {
+    pub fragments: u64,
+    pub reassembled: u64,
+    pub overlaps: u64,
+    pub tiny_fragments: u64,
+    pub oversized: u64,
+    pub too_many_fragments: u64,
+    pub timed_out: u64,
+    pub evicted: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct DatagramKey {
+    source: IpAddr,
+    destination: IpAddr,
+    protocol: u8,
+    id: u32,
+}
+
+#[derive(Debug)]
+struct Fragment {
+    offset: usize,
+    /// Position in arrival order, for the overlap policy.
+    arrival: usize,
+    data: Bytes,
+}
+
+impl Fragment {
+    fn end(&self) -> usize {
+        self.offset + self.data.len()
+    }
+}
+
+#[derive(Debug)]
+struct Datagram {
+    /// Fragments ordered by offset.
+    fragments: Vec<Fragment>,
+    /// Total payload length, known once the final fragment arrives.
+    total_len: Option<usize>,
+    bytes: usize,
+    first_seen: u64,
+}
+
+impl Datagram {
+    /// Whether `[0, total_len)` is fully covered.
+    fn is_complete(&self) -> bool {
+        let Some(total) = self.total_len else {
+            return false;
+        };
+        let mut covered = 0;
+        for fragment in &self.fragments {
+            if fragment.offset > covered {
+                return false;
+            }
+            covered = covered.max(fragment.end());
+        }
+        covered >= total
+    }
+
+    /// Whether `new` overlaps earlier data with different content.
+    fn conflicts_with(&self, new: &Fragment) -> bool {
+        let candidates = self.fragments.partition_point(|old| old.offset < new.end());
+        self.fragments[..candidates].iter().any(|old| {
+            let start = new.offset.max(old.offset);
+            let end = new.end().min(old.end());
+            start < end
+                && new.data[start - new.offset..end - new.offset]
+                    != old.data[start - old.offset..end - old.offset]
+        })
+    }
+
+    /// Builds the payload, resolving overlaps with `policy`.
+    fn assemble(&self, policy: OverlapPolicy, total: usize) -> Bytes {
+        let mut arrivals: Vec<&Fragment> = self.fragments.iter().collect();
+        arrivals.sort_unstable_by_key(|fragment| fragment.arrival);
+
+        let mut buffer = vec![0u8; total];
+        let mut owner: Vec<Option<&Fragment>> = vec![None; total];
+        for fragment in arrivals {
+            let end = fragment.end().min(total);
+            for position in fragment.offset..end {
+                let wins = match owner[position] {
+                    None => true,
+                    Some(previous) => policy.new_wins(fragment, previous),
+                };
+                if wins {
+                    buffer[position] = fragment.data[position - fragment.offset];
+                    owner[position] = Some(fragment);
+                }
+            }
+        }
+        Bytes::from(buffer)
+    }
+}
+
+/// Reassembles fragmented IPv4 and IPv6 datagrams.
+#[derive(Debug)]
+pub struct Defragmenter {
+    limits: DefragLimits,
+    datagrams: HashMap<DatagramKey, Datagram>,
+    /// Datagrams by `first_seen`; the first entry is the oldest.
+    arrival: BTreeSet<(u64, DatagramKey)>,
+    buffered: usize,
+    stats: DefragStats,
+}
+
+impl Defragmenter {
+    pub fn new(limits: DefragLimits) -> Self {
+        Self {
+            limits,
+            datagrams: HashMap::new(),
+            arrival: BTreeSet::new(),
+            buffered: 0,
+            stats: DefragStats::default(),
+        }
+    }
+
+    /// Adds the fragment described by `ip` carrying `payload` (the bytes
+    /// after the IP and fragment headers).
+    ///
+    /// Returns the reassembled IP payload once every fragment has arrived.
+    /// Anomalies seen along the way are appended to `anomalies`.
+    pub fn process(
+        &mut self,
+        ip: &IpInfo,
+        payload: Bytes,
+        timestamp: u64,
+        anomalies: &mut Vec<FragmentAnomaly>,
+    ) -> Option<Bytes> {
+        let info = ip.fragment?;
+        self.stats.fragments += 1;
+
+        let key = DatagramKey {
+            source: ip.source,
+            destination: ip.destination,
+            protocol: ip.protocol,
+            id: info.id,
+        };
+        let offset = usize::from(info.offset);
+
+        if offset + payload.len() > MAX_DATAGRAM_LEN {
+            self.stats.oversized += 1;
+            anomalies.push(FragmentAnomaly::Oversized);
+            self.remove(&key);
+            return None;
+        }
+        if info.more_fragments && payload.len() < self.limits.min_fragment_size {
+            self.stats.tiny_fragments += 1;
+            anomalies.push(FragmentAnomaly::TinyFragment);
+            // An empty non-final fragment adds nothing but work.
+            if payload.is_empty() {
+                return None;
+            }
+        }
+
+        if !self.datagrams.contains_key(&key) {
+            if self.datagrams.len() >= self.limits.max_datagrams {
+                self.evict_oldest();
+            }
+            self.arrival.insert((timestamp, key));
+        }
+        let datagram = self.datagrams.entry(key).or_insert_with(|| Datagram {
+            fragments: Vec::new(),
+            total_len: None,
+            bytes: 0,
+            first_seen: timestamp,
+        });
+
+        if datagram.fragments.len() >= self.limits.max_fragments {
+            self.stats.too_many_fragments += 1;
+            anomalies.push(FragmentAnomaly::TooManyFragments);
+            self.remove(&key);
+            return None;
+        }
+
+        let fragment = Fragment {
+            offset,
+            arrival: datagram.fragments.len(),
+            data: payload,
+        };
+        if datagram.conflicts_with(&fragment) {
+            self.stats.overlaps += 1;
+            anomalies.push(FragmentAnomaly::Overlap);
+        }
+        if !info.more_fragments {
+            datagram.total_len = Some(fragment.end());
+        }
+        datagram.bytes += fragment.data.len();
+        self.buffered += fragment.data.len();
+        let index = datagram
+            .fragments
+            .partition_point(|other| other.offset <= fragment.offset);
+        datagram.fragments.insert(index, fragment);
+
+        if datagram.is_complete() {
+            let total = datagram.total_len.unwrap_or_default();
+            let payload = datagram.assemble(self.limits.policy, total);
+            self.remove(&key);
+            self.stats.reassembled += 1;
+            return Some(payload);
+        }
+
+        while self.buffered > self.limits.max_total_bytes && !self.datagrams.is_empty() {
+            self.evict_oldest();
+        }
+        None
+    }
+
+    /// Discards incomplete datagrams first seen before `now - timeout`.
+    pub fn expire(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(self.limits.timeout_ns);
+        while let Some(&(first_seen, key)) = self.arrival.first() {
+            if first_seen >= cutoff {
+                break;
+            }
+            self.remove(&key);
+            self.stats.timed_out += 1;
+        }
+    }
+
+    /// Number of datagrams awaiting fragments.
+    pub fn len(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    /// Fragment bytes currently held.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    pub fn stats(&self) -> DefragStats {
+        self.stats
+    }
+
+    fn remove(&mut self, key: &DatagramKey) {
+        if let Some(datagram) = self.datagrams.remove(key) {
+            self.arrival.remove(&(datagram.first_seen, *key));
+            self.buffered -= datagram.bytes;
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(&(_, key)) = self.arrival.first() {
+            self.remove(&key);
+            self.stats.evicted += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::FragmentInfo;
+    use std::net::Ipv4Addr;
+
+    fn ip(id: u32, offset: u16, more_fragments: bool) -> IpInfo {
+        IpInfo {
+            source: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            destination: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            protocol: 17,
+            fragment: Some(FragmentInfo {
+                id,
+                offset,
+                more_fragments,
+            }),
+            payload: 0..0,
+        }
+    }
+
+    fn limits(policy: OverlapPolicy) -> DefragLimits {
+        DefragLimits {
+            policy,
+            min_fragment_size: 8,
+            ..DefragLimits::default()
+        }
+    }
+
+    /// Sends an overlapping sequence and returns the reassembled payload.
+    ///
+    /// Arrival order: B=[8,24), A=[0,16), C=[0,24) (same offset as A, longer),
+    /// then the final fragment D=[16,24).
+    fn overlapping(policy: OverlapPolicy) -> (Bytes, Vec<FragmentAnomaly>) {
+        let mut defrag = Defragmenter::new(limits(policy));
+        let mut anomalies = Vec::new();
+        let sends: [(u16, bool, &'static [u8]); 4] = [
+            (8, true, b"BBBBBBBBBBBBBBBB"),
+            (0, true, b"AAAAAAAAAAAAAAAA"),
+            (0, true, b"CCCCCCCCCCCCCCCCCCCCCCCC"),
+            (16, false, b"DDDDDDDD"),
+        ];
+        let mut result = None;
+        for (offset, more, data) in sends {
+            result = defrag.process(
+                &ip(1, offset, more),
+                Bytes::from_static(data),
+                0,
+                &mut anomalies,
+            );
+        }
+        (result.expect("datagram should complete"), anomalies)
+    }
+
+    #[test]
+    fn reassembles_in_any_order() {
+        let mut defrag = Defragmenter::new(limits(OverlapPolicy::First));
+        let mut anomalies = Vec::new();
+        assert!(defrag
+            .process(
+                &ip(7, 8, false),
+                Bytes::from_static(b"world"),
+                0,
+                &mut anomalies
+            )
+            .is_none());
+        let payload = defrag
+            .process(
+                &ip(7, 0, true),
+                Bytes::from_static(b"hello, w"),
+                0,
+                &mut anomalies,
+            )
+            .unwrap();
+        assert_eq!(payload, Bytes::from_static(b"hello, wworld"));
+        assert!(anomalies.is_empty());
+        assert!(defrag.is_empty());
+        assert_eq!(defrag.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn applies_overlap_policies() {
+        let (first, anomalies) = overlapping(OverlapPolicy::First);
+        assert_eq!(first, Bytes::from_static(b"AAAAAAAABBBBBBBBBBBBBBBB"));
+        assert!(anomalies.contains(&FragmentAnomaly::Overlap));
+
+        let (last, _) = overlapping(OverlapPolicy::Last);
+        assert_eq!(last, Bytes::from_static(b"CCCCCCCCCCCCCCCCDDDDDDDD"));
+
+        // A and C start below B and take its bytes; C does not replace A.
+        let (bsd, _) = overlapping(OverlapPolicy::Bsd);
+        assert_eq!(bsd, Bytes::from_static(b"AAAAAAAAAAAAAAAACCCCCCCC"));
+
+        // C shares A's offset but is longer, so it replaces A and B.
+        let (linux, _) = overlapping(OverlapPolicy::Linux);
+        assert_eq!(linux, Bytes::from_static(b"CCCCCCCCCCCCCCCCCCCCCCCC"));
+    }
+
+    #[test]
+    fn identical_duplicates_are_not_overlaps() {
+        let mut defrag = Defragmenter::new(limits(OverlapPolicy::First));
+        let mut anomalies = Vec::new();
+        defrag.process(
+            &ip(1, 0, true),
+            Bytes::from_static(b"12345678"),
+            0,
+            &mut anomalies,
+        );
+        defrag.process(
+            &ip(1, 0, true),
+            Bytes::from_static(b"12345678"),
+            0,
+            &mut anomalies,
+        );
+        assert!(anomalies.is_empty());
+    }
+
+    #[test]
+    fn reports_tiny_and_oversized_fragments() {
+        let mut defrag = Defragmenter::new(limits(OverlapPolicy::First));
+        let mut anomalies = Vec::new();
+        defrag.process(
+            &ip(1, 0, true),
+            Bytes::from_static(b"tiny"),
+            0,
+            &mut anomalies,
+        );
+        assert_eq!(anomalies, vec![FragmentAnomaly::TinyFragment]);
+
+        anomalies.clear();
+        let data = Bytes::from(vec![0; 100]);
+        assert!(defrag
+            .process(&ip(1, 65528, false), data, 0, &mut anomalies)
+            .is_none());
+        assert_eq!(anomalies, vec![FragmentAnomaly::Oversized]);
+        assert!(defrag.is_empty());
+
+        // Empty non-final fragments are reported and not held.
+        anomalies.clear();
+        defrag.process(&ip(2, 0, true), Bytes::new(), 0, &mut anomalies);
+        assert_eq!(anomalies, vec![FragmentAnomaly::TinyFragment]);
+        assert!(defrag.is_empty());
+    }
+
+    #[test]
+    fn drops_datagrams_with_too_many_fragments() {
+        let mut defrag = Defragmenter::new(DefragLimits {
+            max_fragments: 4,
+            ..limits(OverlapPolicy::First)
+        });
+        let mut anomalies = Vec::new();
+        for index in 0..5 {
+            defrag.process(
+                &ip(1, index * 8, true),
+                Bytes::from(vec![0; 8]),
+                0,
+                &mut anomalies,
+            );
+        }
+        assert_eq!(anomalies, vec![FragmentAnomaly::TooManyFragments]);
+        assert!(defrag.is_empty());
+        assert_eq!(defrag.buffered_bytes(), 0);
+        assert_eq!(defrag.stats().too_many_fragments, 1);
+    }
+
+    #[test]
+    fn enforces_timeout_and_memory_limits() {
+        let mut defrag = Defragmenter::new(DefragLimits {
+            max_total_bytes: 16,
+            ..limits(OverlapPolicy::First)
+        });
+        let mut anomalies = Vec::new();
+        defrag.process(&ip(1, 0, true), Bytes::from(vec![0; 8]), 0, &mut anomalies);
+        defrag.process(&ip(2, 0, true), Bytes::from(vec![0; 8]), 1, &mut anomalies);
+        defrag.process(&ip(3, 0, true), Bytes::from(vec![0; 8]), 2, &mut anomalies);
+        assert_eq!(defrag.len(), 2);
+        assert_eq!(defrag.stats().evicted, 1);
+        // The datagram seen first is the one evicted.
+        assert!(defrag.datagrams.keys().all(|key| key.id != 1));
+        assert_eq!(defrag.arrival.len(), 2);
+
+        defrag.expire(DefragLimits::default().timeout_ns + 3);
+        assert!(defrag.is_empty());
+        assert!(defrag.arrival.is_empty());
+        assert_eq!(defrag.stats().timed_out, 2);
+        assert_eq!(defrag.buffered_bytes(), 0);
+    }
+}
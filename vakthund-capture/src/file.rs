@@ -0,0 +1,380 @@
+//! Offline capture from pcap/pcapng files.
+//!
+//! Replays previously recorded traffic through the same [`CaptureSource`]
+//! interface as live capture. Per-packet timestamps are taken from the file, and replay can
+//! optionally be paced at real or accelerated speed.
+
+use crate::buffers::{HeapBuffers, PacketBuffers};
+use crate::decode::LinkType;
+use crate::packet::Packet;
+use crate::source::{CaptureError, CaptureSource, CaptureStats, DEFAULT_BATCH_SIZE};
+use pcap::{Capture, Linktype, Offline, PacketHeader, Precision};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Paces replay so that packets are released with the same relative spacing
+/// they were captured with, scaled by a speed multiplier.
+#[derive(Debug, Clone)]
+pub struct ReplayPacer {
+    speed: f64,
+    origin: Option<(u64, Instant)>,
+}
+
+impl ReplayPacer {
+    /// Creates a pacer. A `speed` of 1.0 is real time, 2.0 is twice as fast.
+    ///
+    /// # Panics
+    /// If `speed` is not a positive, finite number.
+    pub fn new(speed: f64) -> Self {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "Replay speed must be positive"
+        );
+        Self {
+            speed,
+            origin: None,
+        }
+    }
+
+    /// Returns how long to wait at `now` before releasing a packet captured at
+    /// `timestamp` (nanoseconds). The first packet seen anchors the timeline.
+    pub fn delay_for(&mut self, timestamp: u64, now: Instant) -> Duration {
+        let (first_ts, started) = *self.origin.get_or_insert((timestamp, now));
+        let offset_ns = timestamp.saturating_sub(first_ts) as f64 / self.speed;
+        let due = started + Duration::from_nanos(offset_ns as u64);
+        due.saturating_duration_since(now)
+    }
+}
+
+/// Longest single pacing sleep, so a large gap in the file does not stall
+/// shutdown.
+const MAX_PACING_WAIT: Duration = Duration::from_millis(100);
+
+/// Replays a pcap or pcapng file.
+///
+/// Packets keep their original capture timestamps. When `speed` is `None` the
+/// file is read as fast as possible; otherwise replay is paced by [`ReplayPacer`].
+pub struct FileSource {
+    path: PathBuf,
+    pacer: Option<ReplayPacer>,
+    filter: Option<String>,
+    buffers: Arc<dyn PacketBuffers>,
+    handle: Option<Capture<Offline>>,
+    link_type: LinkType,
+    pending: Option<Packet>,
+    received: u64,
+}
+
+impl FileSource {
+    /// Creates a replay source for the file at `path`.
+    pub fn new(path: impl Into<PathBuf>, speed: Option<f64>) -> Self {
+        Self {
+            path: path.into(),
+            pacer: speed.map(ReplayPacer::new),
+            filter: None,
+            buffers: Arc::new(HeapBuffers),
+            handle: None,
+            link_type: LinkType::Ethernet,
+            pending: None,
+            received: 0,
+        }
+    }
+
+    /// Replays only packets matching a BPF `filter` expression.
+    pub fn with_filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    /// Copies captured frames into memory from `buffers` instead of the heap.
+    pub fn with_buffers(mut self, buffers: Arc<dyn PacketBuffers>) -> Self {
+        self.buffers = buffers;
+        self
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Packet>, CaptureError> {
+        if let Some(packet) = self.pending.take() {
+            return Ok(Some(packet));
+        }
+        let handle = self.handle.as_mut().ok_or(CaptureError::NotOpen)?;
+        match handle.next_packet() {
+            Ok(packet) => {
+                let timestamp = header_timestamp_ns(packet.header, Precision::Nano);
+                Ok(Some(
+                    Packet::with_timestamp(timestamp, self.buffers.copy(packet.data))
+                        .with_link_type(self.link_type),
+                ))
+            }
+            Err(pcap::Error::NoMorePackets) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Hands out as many packets as fit in a batch.
+    fn next_unpaced(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        let mut appended = 0;
+        while appended < DEFAULT_BATCH_SIZE {
+            match self.read_packet()? {
+                Some(packet) => {
+                    batch.push(packet);
+                    appended += 1;
+                }
+                None if appended == 0 => return Ok(None),
+                None => break,
+            }
+        }
+        Ok(Some(appended))
+    }
+
+    /// Releases one packet at a time once it is due.
+    fn next_paced(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        let Some(packet) = self.read_packet()? else {
+            return Ok(None);
+        };
+        let delay = self.pacer.as_mut().map_or(Duration::ZERO, |pacer| {
+            pacer.delay_for(packet.timestamp, Instant::now())
+        });
+        if delay > MAX_PACING_WAIT {
+            std::thread::sleep(MAX_PACING_WAIT);
+            self.pending = Some(packet);
+            return Ok(Some(0));
+        }
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+        batch.push(packet);
+        Ok(Some(1))
+    }
+}
+
+impl CaptureSource for FileSource {
+    fn open(&mut self) -> Result<(), CaptureError> {
+        // libpcap reads both pcap and pcapng; request nanosecond timestamps so
+        // nanosecond-resolution files are not truncated.
+        let mut handle = Capture::from_file_with_precision(&self.path, Precision::Nano)?;
+        self.link_type = link_type_of(handle.get_datalink())?;
+        if let Some(filter) = &self.filter {
+            handle
+                .filter(filter, true)
+                .map_err(|e| CaptureError::InvalidFilter(format!("{filter}: {e}")))?;
+        }
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    fn next_batch(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        let appended = if self.pacer.is_some() {
+            self.next_paced(batch)?
+        } else {
+            self.next_unpaced(batch)?
+        };
+        if let Some(count) = appended {
+            self.received += count as u64;
+        }
+        Ok(appended)
+    }
+
+    fn stats(&mut self) -> Result<CaptureStats, CaptureError> {
+        // Nothing is dropped when reading from a file.
+        Ok(CaptureStats {
+            received: self.received,
+            ..CaptureStats::default()
+        })
+    }
+
+    fn close(&mut self) {
+        self.handle = None;
+        self.pending = None;
+    }
+}
+
+/// Converts a pcap header timestamp to nanoseconds since the Unix epoch.
+///
+/// With [`Precision::Nano`] libpcap stores nanoseconds in the `tv_usec` field.
+pub(crate) fn header_timestamp_ns(header: &PacketHeader, precision: Precision) -> u64 {
+    let sub_second = match precision {
+        Precision::Micro => header.ts.tv_usec as u64 * 1_000,
+        Precision::Nano => header.ts.tv_usec as u64,
+    };
+    (header.ts.tv_sec as u64) * 1_000_000_000 + sub_second
+}
+
+/// Maps the datalink of an open handle, rejecting link types that cannot be
+/// decoded rather than misreading every frame.
+pub(crate) fn link_type_of(datalink: Linktype) -> Result<LinkType, CaptureError> {
+    LinkType::from_dlt(datalink.0).ok_or_else(|| {
+        let name = datalink
+            .get_name()
+            .unwrap_or_else(|_| format!("DLT {}", datalink.0));
+        CaptureError::UnsupportedLinkType(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_packet_is_released_immediately() {
+        let mut pacer = ReplayPacer::new(1.0);
+        let now = Instant::now();
+        assert_eq!(pacer.delay_for(5_000_000_000, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn keeps_relative_spacing() {
+        let mut pacer = ReplayPacer::new(1.0);
+        let now = Instant::now();
+        pacer.delay_for(1_000_000_000, now);
+        assert_eq!(
+            pacer.delay_for(1_250_000_000, now),
+            Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn accelerates_replay() {
+        let mut pacer = ReplayPacer::new(4.0);
+        let now = Instant::now();
+        pacer.delay_for(0, now);
+        assert_eq!(
+            pacer.delay_for(1_000_000_000, now),
+            Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn out_of_order_timestamps_are_not_delayed() {
+        let mut pacer = ReplayPacer::new(1.0);
+        let now = Instant::now();
+        pacer.delay_for(2_000_000_000, now);
+        assert_eq!(pacer.delay_for(1_000_000_000, now), Duration::ZERO);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_speed() {
+        ReplayPacer::new(0.0);
+    }
+
+    const FRAMES: [(u64, u8); 3] = [
+        (1_700_000_000_000_000_001, 0xa0),
+        (1_700_000_000_123_456_789, 0xa1),
+        (1_700_000_001_999_999_999, 0xa2),
+    ];
+
+    /// Ethernet frame of 60 `fill` bytes.
+    fn frame(fill: u8) -> Vec<u8> {
+        vec![fill; 60]
+    }
+
+    /// Classic pcap file with nanosecond timestamps.
+    fn pcap_file() -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&0xa1b2_3c4d_u32.to_le_bytes());
+        file.extend_from_slice(&2_u16.to_le_bytes());
+        file.extend_from_slice(&4_u16.to_le_bytes());
+        file.extend_from_slice(&[0; 8]); // thiszone, sigfigs
+        file.extend_from_slice(&65_535_u32.to_le_bytes());
+        file.extend_from_slice(&1_u32.to_le_bytes()); // Ethernet
+        for (timestamp, fill) in FRAMES {
+            let data = frame(fill);
+            file.extend_from_slice(&((timestamp / 1_000_000_000) as u32).to_le_bytes());
+            file.extend_from_slice(&((timestamp % 1_000_000_000) as u32).to_le_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            file.extend_from_slice(&data);
+        }
+        file
+    }
+
+    /// Appends a pcapng block of `block_type` around `body`.
+    fn pcapng_block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+        let length = (12 + body.len()) as u32;
+        file.extend_from_slice(&block_type.to_le_bytes());
+        file.extend_from_slice(&length.to_le_bytes());
+        file.extend_from_slice(body);
+        file.extend_from_slice(&length.to_le_bytes());
+    }
+
+    /// pcapng file with one Ethernet interface at nanosecond resolution.
+    fn pcapng_file() -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut section = Vec::new();
+        section.extend_from_slice(&0x1a2b_3c4d_u32.to_le_bytes());
+        section.extend_from_slice(&1_u16.to_le_bytes());
+        section.extend_from_slice(&0_u16.to_le_bytes());
+        section.extend_from_slice(&(-1_i64).to_le_bytes());
+        pcapng_block(&mut file, 0x0a0d_0d0a, &section);
+
+        let mut interface = Vec::new();
+        interface.extend_from_slice(&1_u16.to_le_bytes()); // Ethernet
+        interface.extend_from_slice(&0_u16.to_le_bytes());
+        interface.extend_from_slice(&65_535_u32.to_le_bytes());
+        // if_tsresol = 10^-9, then opt_endofopt.
+        interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+        pcapng_block(&mut file, 1, &interface);
+
+        for (timestamp, fill) in FRAMES {
+            let data = frame(fill);
+            let mut packet = Vec::new();
+            packet.extend_from_slice(&0_u32.to_le_bytes());
+            packet.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+            packet.extend_from_slice(&(timestamp as u32).to_le_bytes());
+            packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            packet.extend_from_slice(&data);
+            pcapng_block(&mut file, 6, &packet);
+        }
+        file
+    }
+
+    /// Replays `contents` from a temporary file until the source reports the
+    /// end of it, which it must keep doing.
+    fn replay(name: &str, contents: &[u8]) -> Vec<Packet> {
+        let path = std::env::temp_dir().join(format!("vakthund-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let mut source = FileSource::new(&path, None);
+        source.open().unwrap();
+        let mut packets = Vec::new();
+        while let Some(count) = source.next_batch(&mut packets).unwrap() {
+            assert!(count > 0);
+        }
+        assert!(source.next_batch(&mut packets).unwrap().is_none());
+        assert_eq!(source.stats().unwrap().received, packets.len() as u64);
+        source.close();
+        std::fs::remove_file(&path).unwrap();
+        packets
+    }
+
+    fn assert_replayed(packets: &[Packet]) {
+        assert_eq!(packets.len(), FRAMES.len());
+        for (packet, (timestamp, fill)) in packets.iter().zip(FRAMES) {
+            assert_eq!(packet.timestamp, timestamp);
+            assert_eq!(packet.data, frame(fill));
+            assert_eq!(packet.link_type, LinkType::Ethernet);
+        }
+    }
+
+    #[test]
+    fn replays_pcap_with_nanosecond_timestamps() {
+        assert_replayed(&replay("replay.pcap", &pcap_file()));
+    }
+
+    #[test]
+    fn replays_pcapng_with_nanosecond_timestamps() {
+        assert_replayed(&replay("replay.pcapng", &pcapng_file()));
+    }
+
+    #[test]
+    fn missing_file_fails_to_open() {
+        let path = std::env::temp_dir().join("vakthund-no-such-capture.pcap");
+        let mut source = FileSource::new(path, None);
+        assert!(matches!(source.open(), Err(CaptureError::Pcap(_))));
+        assert!(matches!(
+            source.next_batch(&mut Vec::new()),
+            Err(CaptureError::NotOpen)
+        ));
+    }
+}
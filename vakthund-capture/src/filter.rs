@@ -0,0 +1,56 @@
+//! BPF capture filters.
+//!
+//! Filters use tcpdump expression syntax and are compiled by libpcap. pcap
+//! handles apply them directly; AF_PACKET sockets get the compiled classic
+//! BPF program attached with `SO_ATTACH_FILTER`.
+
+use crate::source::CaptureError;
+use pcap::{BpfProgram, Capture, Linktype};
+
+/// Compiles `expression` for Ethernet frames.
+pub fn compile_filter(expression: &str) -> Result<BpfProgram, CaptureError> {
+    Capture::dead(Linktype::ETHERNET)?
+        .compile(expression, true)
+        .map_err(|e| CaptureError::InvalidFilter(format!("{expression}: {e}")))
+}
+
+/// Compiles `expression` into classic BPF instructions for a packet socket.
+#[cfg(target_os = "linux")]
+pub(crate) fn socket_filter(expression: &str) -> Result<Vec<libc::sock_filter>, CaptureError> {
+    compile_filter(expression)?
+        .get_instructions()
+        .iter()
+        .map(|instruction| parse_instruction(&instruction.to_string()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| CaptureError::InvalidFilter(format!("{expression}: unexpected BPF output")))
+}
+
+/// Parses libpcap's "code jt jf k" instruction rendering.
+#[cfg(target_os = "linux")]
+fn parse_instruction(text: &str) -> Option<libc::sock_filter> {
+    let mut fields = text.split_whitespace();
+    let instruction = libc::sock_filter {
+        code: fields.next()?.parse().ok()?,
+        jt: fields.next()?.parse().ok()?,
+        jf: fields.next()?.parse().ok()?,
+        k: fields.next()?.parse().ok()?,
+    };
+    fields.next().is_none().then_some(instruction)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rendered_instructions() {
+        let instruction = parse_instruction("40 0 0 12").unwrap();
+        assert_eq!(instruction.code, 40);
+        assert_eq!(instruction.k, 12);
+        let instruction = parse_instruction("21 1 5 2048").unwrap();
+        assert_eq!((instruction.jt, instruction.jf), (1, 5));
+        assert!(parse_instruction("21 1 5").is_none());
+        assert!(parse_instruction("21 1 5 2048 9").is_none());
+        assert!(parse_instruction("21 x 5 2048").is_none());
+    }
+}
@@ -0,0 +1,599 @@
+//! Bidirectional flow tracking.
+//!
+//! Flows are keyed on the 5-tuple plus VLAN and capture interface, with both
+//! directions of a conversation mapping to the same [`FlowKey`]. Each [`Flow`]
+//! counts packets and bytes per direction, records first/last-seen timestamps
+//! (capture or virtual time, whichever the caller supplies), follows the TCP
+//! connection state and carries the application protocol once known.
+//!
+//! The table holds at most `max_flows` entries; when full, the least recently
+//! seen flow is evicted and kept for [`FlowTable::drain_evicted`]. Recency is
+//! kept in an ordered index, so eviction does not scan the table.
+//! [`FlowTable::expire`] ends flows that have been idle or active for too long,
+//! walking the recency and start-time indexes from their oldest entries.
+
+use crate::decode::TcpInfo;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Size and time bounds for the flow table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowLimits {
+    /// Maximum number of tracked flows.
+    pub max_flows: usize,
+    /// Flows without packets for longer than this (nanoseconds) are ended.
+    pub idle_timeout_ns: u64,
+    /// Flows older than this (nanoseconds) are ended even while active.
+    pub active_timeout_ns: u64,
+}
+
+impl Default for FlowLimits {
+    fn default() -> Self {
+        Self {
+            max_flows: 262144,
+            idle_timeout_ns: 120_000_000_000,
+            active_timeout_ns: 1_800_000_000_000,
+        }
+    }
+}
+
+/// Identifies a conversation regardless of direction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub interface: Arc<str>,
+    pub vlan: Option<u16>,
+    /// IP protocol number.
+    pub protocol: u8,
+    lower: SocketAddr,
+    upper: SocketAddr,
+}
+
+impl FlowKey {
+    /// Creates the key for traffic between `source` and `destination`. Both
+    /// directions produce the same key.
+    pub fn new(
+        interface: Arc<str>,
+        vlan: Option<u16>,
+        protocol: u8,
+        source: SocketAddr,
+        destination: SocketAddr,
+    ) -> Self {
+        let (lower, upper) = if source <= destination {
+            (source, destination)
+        } else {
+            (destination, source)
+        };
+        Self {
+            interface,
+            vlan,
+            protocol,
+            lower,
+            upper,
+        }
+    }
+
+    /// The two endpoints, in canonical order.
+    pub fn endpoints(&self) -> (SocketAddr, SocketAddr) {
+        (self.lower, self.upper)
+    }
+}
+
+/// Connection state of a TCP flow, as seen on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    /// SYN seen from the initiator.
+    SynSent,
+    /// SYN+ACK seen from the responder.
+    SynReceived,
+    /// Handshake completed, or data seen on a connection picked up mid-stream.
+    Established,
+    /// FIN seen in one direction.
+    Closing,
+    /// FIN seen in both directions.
+    Closed,
+    /// Connection reset.
+    Reset,
+}
+
+/// Application protocol carried by a flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppProtocol {
+    Mqtt,
+    ModbusTcp,
+    Coap,
+}
+
+/// Packet and byte counts for one direction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlowCounters {
+    pub packets: u64,
+    /// IP payload bytes.
+    pub bytes: u64,
+}
+
+/// State of one tracked flow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flow {
+    pub key: FlowKey,
+    /// Endpoint that opened the conversation (the first sender seen, or the
+    /// SYN sender).
+    pub initiator: SocketAddr,
+    /// Traffic from the initiator.
+    pub forward: FlowCounters,
+    /// Traffic towards the initiator.
+    pub reverse: FlowCounters,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    /// `None` for non-TCP flows.
+    pub tcp_state: Option<TcpState>,
+    pub app_protocol: Option<AppProtocol>,
+    fin_forward: bool,
+    fin_reverse: bool,
+    /// Position in the table's recency index.
+    recency: u64,
+    /// Creation number, unique within the table; orders flows that started
+    /// at the same time in the start-time index.
+    serial: u64,
+}
+
+impl Flow {
+    fn new(key: FlowKey, initiator: SocketAddr, timestamp: u64, recency: u64) -> Self {
+        Self {
+            key,
+            initiator,
+            forward: FlowCounters::default(),
+            reverse: FlowCounters::default(),
+            first_seen: timestamp,
+            last_seen: timestamp,
+            tcp_state: None,
+            app_protocol: None,
+            fin_forward: false,
+            fin_reverse: false,
+            recency,
+            serial: recency,
+        }
+    }
+
+    /// Duration between the first and last packet (nanoseconds).
+    pub fn duration(&self) -> u64 {
+        self.last_seen - self.first_seen
+    }
+
+    fn observe_tcp(&mut self, flags: u8, forward: bool) {
+        let syn = flags & TcpInfo::SYN != 0;
+        let ack = flags & TcpInfo::ACK != 0;
+        let state = self.tcp_state;
+        if matches!(state, Some(TcpState::Closed | TcpState::Reset)) {
+            return;
+        }
+        if flags & TcpInfo::RST != 0 {
+            self.tcp_state = Some(TcpState::Reset);
+            return;
+        }
+        if flags & TcpInfo::FIN != 0 {
+            if forward {
+                self.fin_forward = true;
+            } else {
+                self.fin_reverse = true;
+            }
+            self.tcp_state = Some(if self.fin_forward && self.fin_reverse {
+                TcpState::Closed
+            } else {
+                TcpState::Closing
+            });
+            return;
+        }
+        self.tcp_state = match (syn, ack, state) {
+            (true, false, None) => Some(TcpState::SynSent),
+            (true, true, None | Some(TcpState::SynSent)) => Some(TcpState::SynReceived),
+            (false, _, None | Some(TcpState::SynSent | TcpState::SynReceived)) => {
+                Some(TcpState::Established)
+            }
+            (_, _, state) => state,
+        };
+    }
+}
+
+/// Flow table counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlowStats {
+    /// Flows created.
+    pub created: u64,
+    /// Flows ended by the idle or active timeout.
+    pub expired: u64,
+    /// Flows evicted to stay within `max_flows`.
+    pub evicted: u64,
+}
+
+/// Memory-bounded table of active flows.
+#[derive(Debug)]
+pub struct FlowTable {
+    limits: FlowLimits,
+    flows: HashMap<FlowKey, Flow>,
+    /// Flow keys by recency; the first entry is the least recently seen.
+    recency: BTreeMap<u64, FlowKey>,
+    next_recency: u64,
+    /// Flow keys by `(first_seen, serial)`; the first entry started earliest.
+    started: BTreeMap<(u64, u64), FlowKey>,
+    /// Evicted flows not yet taken by [`FlowTable::drain_evicted`].
+    evicted: Vec<Flow>,
+    stats: FlowStats,
+}
+
+impl FlowTable {
+    pub fn new(limits: FlowLimits) -> Self {
+        Self {
+            limits,
+            flows: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_recency: 0,
+            started: BTreeMap::new(),
+            evicted: Vec::new(),
+            stats: FlowStats::default(),
+        }
+    }
+
+    /// Accounts one packet of `bytes` IP payload bytes sent by `source` on the
+    /// flow `key`, creating the flow if needed, and returns it.
+    pub fn update(
+        &mut self,
+        key: FlowKey,
+        source: SocketAddr,
+        tcp: Option<&TcpInfo>,
+        bytes: usize,
+        timestamp: u64,
+    ) -> &mut Flow {
+        if !self.flows.contains_key(&key) {
+            if self.flows.len() >= self.limits.max_flows {
+                self.evict_oldest();
+            }
+            let (lower, upper) = key.endpoints();
+            let destination = if source == lower { upper } else { lower };
+            // A SYN+ACK as the first packet means the handshake started before
+            // the capture did: the receiver is the initiator. Without either
+            // half of the handshake the client is taken to be the endpoint on
+            // the higher, ephemeral port.
+            let handshake = tcp.map_or(0, |tcp| tcp.flags & (TcpInfo::SYN | TcpInfo::ACK));
+            let initiator = if handshake == TcpInfo::SYN {
+                source
+            } else if handshake == TcpInfo::SYN | TcpInfo::ACK || destination.port() > source.port()
+            {
+                destination
+            } else {
+                source
+            };
+            self.stats.created += 1;
+            self.flows.insert(
+                key.clone(),
+                Flow::new(key.clone(), initiator, timestamp, self.next_recency),
+            );
+            self.recency.insert(self.next_recency, key.clone());
+            self.started
+                .insert((timestamp, self.next_recency), key.clone());
+            self.next_recency += 1;
+        }
+
+        let flow = self.flows.get_mut(&key).expect("flow was just inserted");
+        if flow.recency + 1 != self.next_recency {
+            let key = self
+                .recency
+                .remove(&flow.recency)
+                .expect("flow is in the recency index");
+            flow.recency = self.next_recency;
+            self.recency.insert(flow.recency, key);
+            self.next_recency += 1;
+        }
+        let forward = source == flow.initiator;
+        let counters = if forward {
+            &mut flow.forward
+        } else {
+            &mut flow.reverse
+        };
+        counters.packets += 1;
+        counters.bytes += bytes as u64;
+        flow.last_seen = flow.last_seen.max(timestamp);
+        if let Some(tcp) = tcp {
+            flow.observe_tcp(tcp.flags, forward);
+        }
+        flow
+    }
+
+    pub fn get(&self, key: &FlowKey) -> Option<&Flow> {
+        self.flows.get(key)
+    }
+
+    /// Iterates over all tracked flows in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &Flow> {
+        self.flows.values()
+    }
+
+    /// Ends flows idle since before `now - idle_timeout` or started before
+    /// `now - active_timeout`, and returns them.
+    ///
+    /// Only the expired flows and one live flow per index are visited. Idle
+    /// flows are found in recency order, so one seen with a timestamp older
+    /// than a flow updated before it waits until that flow expires too.
+    pub fn expire(&mut self, now: u64) -> Vec<Flow> {
+        let idle_cutoff = now.saturating_sub(self.limits.idle_timeout_ns);
+        let active_cutoff = now.saturating_sub(self.limits.active_timeout_ns);
+        let mut expired = Vec::new();
+        while let Some((_, key)) = self.recency.first_key_value() {
+            if self
+                .flows
+                .get(key)
+                .is_some_and(|flow| flow.last_seen >= idle_cutoff)
+            {
+                break;
+            }
+            let key = key.clone();
+            expired.extend(self.remove(&key));
+        }
+        while let Some((&(first_seen, _), key)) = self.started.first_key_value() {
+            if first_seen >= active_cutoff {
+                break;
+            }
+            let key = key.clone();
+            expired.extend(self.remove(&key));
+        }
+        self.stats.expired += expired.len() as u64;
+        expired
+    }
+
+    /// Takes the flows evicted since the last call.
+    pub fn drain_evicted(&mut self) -> impl Iterator<Item = Flow> + '_ {
+        self.evicted.drain(..)
+    }
+
+    /// Number of tracked flows.
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    pub fn stats(&self) -> FlowStats {
+        self.stats
+    }
+
+    fn remove(&mut self, key: &FlowKey) -> Option<Flow> {
+        let flow = self.flows.remove(key)?;
+        self.recency.remove(&flow.recency);
+        self.started.remove(&(flow.first_seen, flow.serial));
+        Some(flow)
+    }
+
+    fn evict_oldest(&mut self) {
+        let Some(key) = self.recency.first_key_value().map(|(_, key)| key.clone()) else {
+            return;
+        };
+        if let Some(flow) = self.remove(&key) {
+            self.evicted.push(flow);
+            self.stats.evicted += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::IPPROTO_TCP;
+
+    const CLIENT: &str = "10.0.0.1:50000";
+    const SERVER: &str = "10.0.0.2:1883";
+
+    fn key(source: &str, destination: &str) -> FlowKey {
+        FlowKey::new(
+            "eth0".into(),
+            Some(10),
+            IPPROTO_TCP,
+            source.parse().unwrap(),
+            destination.parse().unwrap(),
+        )
+    }
+
+    fn tcp(flags: u8) -> TcpInfo {
+        TcpInfo {
+            source_port: 0,
+            destination_port: 0,
+            sequence: 0,
+            acknowledgement: 0,
+            flags,
+        }
+    }
+
+    fn send(table: &mut FlowTable, from: &str, to: &str, flags: u8, bytes: usize, ts: u64) {
+        table.update(
+            key(from, to),
+            from.parse().unwrap(),
+            Some(&tcp(flags)),
+            bytes,
+            ts,
+        );
+    }
+
+    #[test]
+    fn both_directions_share_a_flow() {
+        assert_eq!(key(CLIENT, SERVER), key(SERVER, CLIENT));
+        let other_vlan = FlowKey {
+            vlan: Some(11),
+            ..key(CLIENT, SERVER)
+        };
+        assert_ne!(other_vlan, key(CLIENT, SERVER));
+    }
+
+    #[test]
+    fn counts_per_direction_and_tracks_handshake() {
+        let mut table = FlowTable::new(FlowLimits::default());
+        send(&mut table, CLIENT, SERVER, TcpInfo::SYN, 20, 1);
+        assert_eq!(
+            table.get(&key(CLIENT, SERVER)).unwrap().tcp_state,
+            Some(TcpState::SynSent)
+        );
+        send(
+            &mut table,
+            SERVER,
+            CLIENT,
+            TcpInfo::SYN | TcpInfo::ACK,
+            20,
+            2,
+        );
+        send(&mut table, CLIENT, SERVER, TcpInfo::ACK, 20, 3);
+        send(
+            &mut table,
+            CLIENT,
+            SERVER,
+            TcpInfo::PSH | TcpInfo::ACK,
+            120,
+            4,
+        );
+
+        let flow = table.get(&key(SERVER, CLIENT)).unwrap();
+        assert_eq!(flow.initiator, CLIENT.parse().unwrap());
+        assert_eq!(
+            flow.forward,
+            FlowCounters {
+                packets: 3,
+                bytes: 160
+            }
+        );
+        assert_eq!(
+            flow.reverse,
+            FlowCounters {
+                packets: 1,
+                bytes: 20
+            }
+        );
+        assert_eq!((flow.first_seen, flow.last_seen), (1, 4));
+        assert_eq!(flow.tcp_state, Some(TcpState::Established));
+
+        send(
+            &mut table,
+            CLIENT,
+            SERVER,
+            TcpInfo::FIN | TcpInfo::ACK,
+            20,
+            5,
+        );
+        assert_eq!(
+            table.get(&key(CLIENT, SERVER)).unwrap().tcp_state,
+            Some(TcpState::Closing)
+        );
+        send(
+            &mut table,
+            SERVER,
+            CLIENT,
+            TcpInfo::FIN | TcpInfo::ACK,
+            20,
+            6,
+        );
+        assert_eq!(
+            table.get(&key(CLIENT, SERVER)).unwrap().tcp_state,
+            Some(TcpState::Closed)
+        );
+    }
+
+    #[test]
+    fn mid_stream_pickup() {
+        let mut table = FlowTable::new(FlowLimits::default());
+        send(
+            &mut table,
+            SERVER,
+            CLIENT,
+            TcpInfo::SYN | TcpInfo::ACK,
+            20,
+            1,
+        );
+        let flow = table.get(&key(CLIENT, SERVER)).unwrap();
+        assert_eq!(flow.initiator, CLIENT.parse().unwrap());
+        assert_eq!(flow.reverse.packets, 1);
+
+        // Without a handshake the client is the endpoint on the higher port,
+        // even when the server speaks first.
+        let mut table = FlowTable::new(FlowLimits::default());
+        send(&mut table, SERVER, CLIENT, TcpInfo::ACK, 100, 1);
+        let flow = table.get(&key(CLIENT, SERVER)).unwrap();
+        assert_eq!(flow.initiator, CLIENT.parse().unwrap());
+
+        let mut table = FlowTable::new(FlowLimits::default());
+        send(&mut table, CLIENT, SERVER, TcpInfo::ACK, 100, 1);
+        send(&mut table, CLIENT, SERVER, TcpInfo::RST, 20, 2);
+        assert_eq!(
+            table.get(&key(CLIENT, SERVER)).unwrap().tcp_state,
+            Some(TcpState::Reset)
+        );
+    }
+
+    #[test]
+    fn expires_idle_and_long_lived_flows() {
+        let mut table = FlowTable::new(FlowLimits {
+            max_flows: 16,
+            idle_timeout_ns: 100,
+            active_timeout_ns: 1_000,
+        });
+        send(&mut table, CLIENT, SERVER, TcpInfo::ACK, 1, 0);
+        send(&mut table, "10.0.0.3:50001", SERVER, TcpInfo::ACK, 1, 50);
+        for ts in (100..=1_000).step_by(50) {
+            send(&mut table, "10.0.0.4:50001", SERVER, TcpInfo::ACK, 1, ts);
+        }
+
+        let expired = table.expire(1_000);
+        assert_eq!(expired.len(), 2);
+        assert_eq!(table.len(), 1);
+
+        let expired = table.expire(1_101);
+        assert_eq!(expired[0].forward.packets, 19);
+        assert!(table.is_empty());
+        assert_eq!(table.stats().expired, 3);
+        assert!(table.recency.is_empty() && table.started.is_empty());
+    }
+
+    #[test]
+    fn expires_long_lived_flows_by_start_time() {
+        let mut table = FlowTable::new(FlowLimits {
+            max_flows: 16,
+            idle_timeout_ns: 100,
+            active_timeout_ns: 200,
+        });
+        // The long-lived flow is the most recently seen one.
+        send(&mut table, CLIENT, SERVER, TcpInfo::ACK, 1, 0);
+        send(&mut table, "10.0.0.3:50001", SERVER, TcpInfo::ACK, 1, 150);
+        send(&mut table, CLIENT, SERVER, TcpInfo::ACK, 1, 190);
+
+        let expired = table.expire(210);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].key, key(CLIENT, SERVER));
+        assert_eq!(table.len(), 1);
+        assert_eq!((table.recency.len(), table.started.len()), (1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_seen_at_capacity() {
+        let mut table = FlowTable::new(FlowLimits {
+            max_flows: 2,
+            ..FlowLimits::default()
+        });
+        send(&mut table, "10.0.0.3:1", SERVER, TcpInfo::ACK, 1, 1);
+        send(&mut table, "10.0.0.4:1", SERVER, TcpInfo::ACK, 1, 2);
+        send(&mut table, "10.0.0.3:1", SERVER, TcpInfo::ACK, 1, 3);
+        send(&mut table, "10.0.0.5:1", SERVER, TcpInfo::ACK, 1, 4);
+
+        assert_eq!(table.len(), 2);
+        assert!(table.get(&key("10.0.0.4:1", SERVER)).is_none());
+        assert_eq!(table.stats().evicted, 1);
+        assert_eq!(table.stats().created, 3);
+        let evicted: Vec<_> = table.drain_evicted().collect();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].key, key("10.0.0.4:1", SERVER));
+        assert_eq!(table.drain_evicted().count(), 0);
+
+        // Expiry keeps the recency index in step with the table.
+        table.expire(u64::MAX);
+        send(&mut table, "10.0.0.6:1", SERVER, TcpInfo::ACK, 1, 5);
+        send(&mut table, "10.0.0.7:1", SERVER, TcpInfo::ACK, 1, 6);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.stats().evicted, 1);
+    }
+}
@@ -1,10 +1,49 @@
 //! vakthund‑capture
 //!
 //! Provides a unified capture interface for Vakthund.
-//! Currently, only live capture (using pcap) is implemented.
+//! Packets are read through the [`CaptureSource`] trait, with backends for
+//! live capture (using pcap or, on Linux, an AF_PACKET TPACKET_V3 ring),
+//! offline replay of pcap/pcapng files and in-memory packet lists. Sources
+//! can be narrowed with a BPF [`filter`] applied in the kernel, and copy
+//! frames into memory provided through [`buffers`].
+//! [`decode`] walks captured frames down to the application payload,
+//! [`tunnel`] unwraps GRE/ERSPAN/VXLAN/GENEVE encapsulation, [`defrag`]
+//! reassembles IP fragments, [`reassembly`] rebuilds TCP byte streams from
+//! segments and [`flow`] keeps per-conversation state.
 
+#[cfg(target_os = "linux")]
+pub mod af_packet;
+pub mod buffers;
 pub mod capture;
+pub mod decode;
+pub mod defrag;
+pub mod file;
+pub mod filter;
+pub mod flow;
+pub mod memory;
 pub mod packet;
+pub mod reassembly;
+pub mod source;
+pub mod tunnel;
 
-pub use capture::run_capture_loop;
+#[cfg(target_os = "linux")]
+pub use af_packet::AfPacketSource;
+pub use buffers::{HeapBuffers, PacketBuffers};
+pub use capture::PcapSource;
+pub use decode::{
+    decode_ethernet, decode_frame, decode_ip, DecodeError, DecodedPacket, LinkType, Transport,
+};
+pub use defrag::{DefragLimits, DefragStats, Defragmenter, FragmentAnomaly, OverlapPolicy};
+pub use file::{FileSource, ReplayPacer};
+pub use filter::compile_filter;
+pub use flow::{
+    AppProtocol, Flow, FlowCounters, FlowKey, FlowLimits, FlowStats, FlowTable, TcpState,
+};
+pub use memory::MemorySource;
 pub use packet::Packet;
+pub use reassembly::{Frame, ReassemblyLimits, ReassemblyStats, StreamKey, TcpReassembler};
+pub use source::{
+    run_capture_loop, run_capture_loop_batched, run_capture_loop_with_stats, CaptureError,
+    CaptureSource, CaptureStats,
+};
+pub use tunnel::{decode_tunnel, TunnelHeader, TunnelKind};
@@ -1,9 +1,14 @@
 /// A simple packet type used for capture.
+use crate::decode::LinkType;
 use bytes::Bytes;
 
 #[derive(Debug, Clone)]
 pub struct Packet {
+    /// Capture timestamp in nanoseconds since the Unix epoch (0 if unknown).
+    pub timestamp: u64,
     pub data: Bytes,
+    /// Link-layer header `data` starts with.
+    pub link_type: LinkType,
 }
 
 impl Packet {
@@ -11,7 +16,24 @@ impl Packet {
     pub fn new(data: Vec<u8>) -> Self {
         // `Bytes::from` will take ownership of the Vec<u8>
         Packet {
+            timestamp: 0,
             data: Bytes::from(data),
+            link_type: LinkType::Ethernet,
         }
     }
+
+    /// Creates a new Packet carrying the capture timestamp from the source.
+    pub fn with_timestamp(timestamp: u64, data: Bytes) -> Self {
+        Packet {
+            timestamp,
+            data,
+            link_type: LinkType::Ethernet,
+        }
+    }
+
+    /// Marks the packet as starting with a `link_type` header instead of Ethernet.
+    pub fn with_link_type(mut self, link_type: LinkType) -> Self {
+        self.link_type = link_type;
+        self
+    }
 }
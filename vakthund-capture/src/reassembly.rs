@@ -0,0 +1,690 @@
+//! TCP stream reassembly.
+//!
+//! Tracks each direction of a TCP connection separately, orders segments by
+//! sequence number, drops retransmitted bytes (first copy wins) and buffers
+//! out-of-order data up to per-stream and global memory caps. In-order bytes
+//! are handed to a caller-supplied framing function, and every complete
+//! application message is returned as its own buffer.
+//!
+//! A gap left by a segment that never arrives is skipped once it has been open
+//! for `gap_timeout_ns`: the partial message before it is discarded and
+//! framing resumes at the first buffered segment.
+
+use crate::decode::TcpInfo;
+use bytes::{Bytes, BytesMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::SocketAddr;
+
+/// Memory and time bounds for the reassembler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyLimits {
+    /// Maximum number of tracked stream directions.
+    pub max_streams: usize,
+    /// Maximum bytes buffered for one stream (out-of-order plus unframed data).
+    pub max_stream_bytes: usize,
+    /// Maximum bytes buffered across all streams.
+    pub max_total_bytes: usize,
+    /// Streams idle for longer than this (nanoseconds) are dropped by [`TcpReassembler::expire`].
+    /// Only segments that advance the stream count as activity.
+    pub idle_timeout_ns: u64,
+    /// A gap in the sequence space open for longer than this (nanoseconds) is skipped.
+    pub gap_timeout_ns: u64,
+}
+
+impl Default for ReassemblyLimits {
+    fn default() -> Self {
+        Self {
+            max_streams: 16384,
+            max_stream_bytes: 1024 * 1024,
+            max_total_bytes: 64 * 1024 * 1024,
+            idle_timeout_ns: 120_000_000_000,
+            gap_timeout_ns: 10_000_000_000,
+        }
+    }
+}
+
+/// One direction of a TCP connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamKey {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+/// Result of asking a framing function for the next message in a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// More data is needed before a message can be delimited.
+    Incomplete,
+    /// The first `n` bytes form a complete message.
+    Complete(usize),
+    /// The stream does not carry the expected protocol.
+    Invalid,
+}
+
+/// Reassembly counters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyStats {
+    /// Segments carrying payload or control flags that were processed.
+    pub segments: u64,
+    /// Segments whose bytes had all been seen before.
+    pub retransmissions: u64,
+    /// Segments that arrived ahead of a gap and were buffered.
+    pub out_of_order: u64,
+    /// Segments discarded because a memory cap was reached.
+    pub dropped_segments: u64,
+    /// Streams evicted to stay within `max_streams` or `max_total_bytes`.
+    pub evicted_streams: u64,
+    /// Streams abandoned because the framing function rejected their data.
+    pub desyncs: u64,
+    /// Gaps skipped after waiting `gap_timeout_ns` for the missing bytes.
+    pub skipped_gaps: u64,
+}
+
+#[derive(Debug)]
+struct StreamState {
+    /// Sequence number at relative offset 0.
+    base_seq: u32,
+    /// Relative offset of the next expected byte.
+    next: u64,
+    /// Out-of-order segments keyed by relative offset. Segments never overlap.
+    segments: BTreeMap<u64, Bytes>,
+    /// In-order bytes not yet consumed by framing.
+    pending: BytesMut,
+    /// Bytes held in `segments` and `pending`.
+    buffered: usize,
+    /// Relative offset of the FIN, once seen.
+    fin_at: Option<u64>,
+    /// Set after a framing error; further data is ignored.
+    desynced: bool,
+    /// When the stream last advanced; out-of-order data does not count.
+    last_progress: u64,
+    /// When the current gap opened, while segments are buffered past it.
+    gap_since: Option<u64>,
+}
+
+enum Insert {
+    Retransmission,
+    InOrder,
+    OutOfOrder,
+    Dropped,
+}
+
+impl StreamState {
+    fn new(base_seq: u32, timestamp: u64) -> Self {
+        Self {
+            base_seq,
+            next: 0,
+            segments: BTreeMap::new(),
+            pending: BytesMut::new(),
+            buffered: 0,
+            fin_at: None,
+            desynced: false,
+            last_progress: timestamp,
+            gap_since: None,
+        }
+    }
+
+    /// Maps a sequence number to a relative offset, using the window around
+    /// `next` to resolve 32-bit wraparound.
+    fn offset_of(&self, seq: u32) -> i64 {
+        let next_seq = self.base_seq.wrapping_add(self.next as u32);
+        self.next as i64 + i64::from(seq.wrapping_sub(next_seq) as i32)
+    }
+
+    /// Adds the bytes of a segment not already held. The first copy of every
+    /// byte wins: data overlapping delivered or buffered bytes is trimmed.
+    fn insert(&mut self, start: i64, mut data: Bytes, max_bytes: usize) -> Insert {
+        let next = self.next as i64;
+        let end = start + data.len() as i64;
+        if end <= next {
+            return Insert::Retransmission;
+        }
+        if start < next {
+            data = data.slice((next - start) as usize..);
+        }
+        let start = start.max(next) as u64;
+
+        if start == self.next && self.segments.is_empty() {
+            self.append(data);
+            return Insert::InOrder;
+        }
+
+        let pieces = self.uncovered(start, start + data.len() as u64);
+        let new_bytes: u64 = pieces.iter().map(|(from, to)| to - from).sum();
+        if new_bytes == 0 {
+            return Insert::Retransmission;
+        }
+        if start > self.next && self.buffered + new_bytes as usize > max_bytes {
+            return Insert::Dropped;
+        }
+        for (from, to) in pieces {
+            let piece = data.slice((from - start) as usize..(to - start) as usize);
+            self.buffered += piece.len();
+            self.segments.insert(from, piece);
+        }
+        if start > self.next {
+            return Insert::OutOfOrder;
+        }
+        self.drain();
+        Insert::InOrder
+    }
+
+    /// Ranges within `start..end` not covered by a buffered segment.
+    fn uncovered(&self, start: u64, end: u64) -> Vec<(u64, u64)> {
+        let mut cursor = start;
+        if let Some((offset, segment)) = self.segments.range(..=start).next_back() {
+            cursor = cursor.max(offset + segment.len() as u64);
+        }
+        let mut pieces = Vec::new();
+        for (&offset, segment) in self.segments.range(start + 1..end) {
+            if offset > cursor {
+                pieces.push((cursor, offset));
+            }
+            cursor = cursor.max(offset + segment.len() as u64);
+        }
+        if cursor < end {
+            pieces.push((cursor, end));
+        }
+        pieces
+    }
+
+    /// Moves buffered segments that continue the stream into `pending`.
+    fn drain(&mut self) {
+        while let Some(entry) = self.segments.first_entry() {
+            if *entry.key() != self.next {
+                break;
+            }
+            let segment = entry.remove();
+            self.buffered -= segment.len();
+            self.append(segment);
+        }
+    }
+
+    /// Gives up on the bytes missing before the first buffered segment. The
+    /// unframed data before the gap can no longer complete and is discarded.
+    fn skip_gap(&mut self) {
+        if let Some((&offset, _)) = self.segments.first_key_value() {
+            self.buffered -= self.pending.len();
+            self.pending.clear();
+            self.next = offset;
+            self.drain();
+        }
+    }
+
+    fn append(&mut self, data: Bytes) {
+        self.next += data.len() as u64;
+        self.buffered += data.len();
+        self.pending.extend_from_slice(&data);
+    }
+
+    /// Splits complete messages off `pending`. Returns `false` if framing failed.
+    fn frame<F>(&mut self, frame: &mut F, out: &mut Vec<Bytes>) -> bool
+    where
+        F: FnMut(&[u8]) -> Frame,
+    {
+        while !self.pending.is_empty() {
+            match frame(&self.pending) {
+                Frame::Incomplete => break,
+                Frame::Complete(n) if n > 0 && n <= self.pending.len() => {
+                    self.buffered -= n;
+                    out.push(self.pending.split_to(n).freeze());
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn desync(&mut self) {
+        self.desynced = true;
+        self.segments.clear();
+        self.pending.clear();
+        self.buffered = 0;
+        self.gap_since = None;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.fin_at.is_some_and(|fin| self.next >= fin)
+    }
+}
+
+/// Per-flow TCP reassembler.
+#[derive(Debug)]
+pub struct TcpReassembler {
+    limits: ReassemblyLimits,
+    streams: HashMap<StreamKey, StreamState>,
+    /// Streams by `last_progress`; the first entry is the stalest.
+    recency: BTreeSet<(u64, StreamKey)>,
+    buffered: usize,
+    stats: ReassemblyStats,
+}
+
+impl TcpReassembler {
+    pub fn new(limits: ReassemblyLimits) -> Self {
+        Self {
+            limits,
+            streams: HashMap::new(),
+            recency: BTreeSet::new(),
+            buffered: 0,
+            stats: ReassemblyStats::default(),
+        }
+    }
+
+    /// Adds one segment to its stream and appends every message completed by
+    /// it to `out`, as delimited by `frame`.
+    ///
+    /// Streams picked up mid-connection start at the first data segment seen.
+    /// RST drops the stream immediately; FIN drops it once all data before the
+    /// FIN has been delivered. A gap open for longer than `gap_timeout_ns` is
+    /// skipped when the next segment of the stream arrives.
+    pub fn process<F>(
+        &mut self,
+        key: StreamKey,
+        tcp: &TcpInfo,
+        payload: Bytes,
+        timestamp: u64,
+        mut frame: F,
+        out: &mut Vec<Bytes>,
+    ) where
+        F: FnMut(&[u8]) -> Frame,
+    {
+        let syn = tcp.flags & TcpInfo::SYN != 0;
+        let fin = tcp.flags & TcpInfo::FIN != 0;
+        if tcp.flags & TcpInfo::RST != 0 {
+            self.remove(&key);
+            return;
+        }
+        if payload.is_empty() && !syn && !fin {
+            return;
+        }
+        self.stats.segments += 1;
+
+        // Sequence number of the first payload byte.
+        let data_seq = if syn {
+            tcp.sequence.wrapping_add(1)
+        } else {
+            tcp.sequence
+        };
+        if !self.streams.contains_key(&key) {
+            if payload.is_empty() && !syn {
+                return;
+            }
+            if self.streams.len() >= self.limits.max_streams {
+                self.evict_oldest();
+            }
+            self.streams
+                .insert(key, StreamState::new(data_seq, timestamp));
+            self.recency.insert((timestamp, key));
+        }
+
+        let Some(stream) = self.streams.get_mut(&key) else {
+            return;
+        };
+        if stream.desynced {
+            return;
+        }
+        if syn && stream.next == 0 && stream.buffered == 0 {
+            stream.base_seq = data_seq;
+        }
+
+        let before = stream.buffered;
+        let progress = stream.next;
+        let start = stream.offset_of(data_seq);
+        let length = payload.len() as u64;
+        if !payload.is_empty() {
+            match stream.insert(start, payload, self.limits.max_stream_bytes) {
+                Insert::Retransmission => self.stats.retransmissions += 1,
+                Insert::OutOfOrder => self.stats.out_of_order += 1,
+                Insert::Dropped => self.stats.dropped_segments += 1,
+                Insert::InOrder => {}
+            }
+        }
+        if fin && start >= 0 {
+            stream.fin_at = Some(start as u64 + length);
+        }
+        if stream.segments.is_empty() {
+            stream.gap_since = None;
+        } else {
+            let since = *stream.gap_since.get_or_insert(timestamp);
+            if timestamp.saturating_sub(since) >= self.limits.gap_timeout_ns {
+                stream.skip_gap();
+                stream.gap_since = (!stream.segments.is_empty()).then_some(timestamp);
+                self.stats.skipped_gaps += 1;
+            }
+        }
+        if (stream.next > progress || fin) && stream.last_progress != timestamp {
+            self.recency.remove(&(stream.last_progress, key));
+            self.recency.insert((timestamp, key));
+            stream.last_progress = timestamp;
+        }
+
+        if !stream.frame(&mut frame, out) || stream.pending.len() > self.limits.max_stream_bytes {
+            // Either not the expected protocol or a message too large to buffer.
+            stream.desync();
+            self.stats.desyncs += 1;
+        }
+        let after = stream.buffered;
+        let finished = stream.is_finished();
+        self.buffered = self.buffered + after - before;
+
+        if finished {
+            self.remove(&key);
+        }
+        while self.buffered > self.limits.max_total_bytes && !self.streams.is_empty() {
+            self.evict_oldest();
+        }
+    }
+
+    /// Drops streams that have not advanced since before `now - idle_timeout`.
+    pub fn expire(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(self.limits.idle_timeout_ns);
+        while let Some(&(last_progress, key)) = self.recency.first() {
+            if last_progress >= cutoff {
+                break;
+            }
+            self.remove(&key);
+        }
+    }
+
+    /// Number of tracked stream directions.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Bytes currently buffered across all streams.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    pub fn stats(&self) -> ReassemblyStats {
+        self.stats
+    }
+
+    fn remove(&mut self, key: &StreamKey) {
+        if let Some(stream) = self.streams.remove(key) {
+            self.recency.remove(&(stream.last_progress, *key));
+            self.buffered -= stream.buffered;
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(&(_, key)) = self.recency.first() {
+            self.remove(&key);
+            self.stats.evicted_streams += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames messages as a one-byte length prefix followed by that many bytes.
+    fn length_prefixed(data: &[u8]) -> Frame {
+        match data.first() {
+            Some(0) => Frame::Invalid,
+            Some(&len) if data.len() > usize::from(len) => Frame::Complete(usize::from(len) + 1),
+            _ => Frame::Incomplete,
+        }
+    }
+
+    fn key() -> StreamKey {
+        StreamKey {
+            source: "10.0.0.1:40000".parse().unwrap(),
+            destination: "10.0.0.2:1883".parse().unwrap(),
+        }
+    }
+
+    fn tcp(sequence: u32, flags: u8) -> TcpInfo {
+        TcpInfo {
+            source_port: 40000,
+            destination_port: 1883,
+            sequence,
+            acknowledgement: 0,
+            flags,
+        }
+    }
+
+    fn feed(
+        reassembler: &mut TcpReassembler,
+        sequence: u32,
+        flags: u8,
+        payload: &'static [u8],
+    ) -> Vec<Bytes> {
+        feed_at(reassembler, 0, sequence, flags, payload)
+    }
+
+    fn feed_at(
+        reassembler: &mut TcpReassembler,
+        timestamp: u64,
+        sequence: u32,
+        flags: u8,
+        payload: &'static [u8],
+    ) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        reassembler.process(
+            key(),
+            &tcp(sequence, flags),
+            Bytes::from_static(payload),
+            timestamp,
+            length_prefixed,
+            &mut out,
+        );
+        out
+    }
+
+    #[test]
+    fn splits_and_joins_messages_across_segments() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        assert!(feed(&mut reassembler, 99, TcpInfo::SYN, b"").is_empty());
+        assert!(feed(&mut reassembler, 100, TcpInfo::ACK, b"\x03ab").is_empty());
+        let out = feed(&mut reassembler, 103, TcpInfo::ACK, b"c\x01x\x02y");
+        assert_eq!(out, vec![Bytes::from("\x03abc"), Bytes::from("\x01x")]);
+        let out = feed(&mut reassembler, 108, TcpInfo::ACK, b"z");
+        assert_eq!(out, vec![Bytes::from("\x02yz")]);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn reorders_out_of_order_segments() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        assert!(feed(&mut reassembler, 102, TcpInfo::ACK, b"cd").is_empty());
+        assert_eq!(reassembler.stats().out_of_order, 1);
+        let out = feed(&mut reassembler, 100, TcpInfo::ACK, b"\x03b");
+        assert_eq!(out, vec![Bytes::from("\x03bcd")]);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn ignores_retransmitted_bytes() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        feed(&mut reassembler, 100, TcpInfo::ACK, b"\x02a");
+        assert!(feed(&mut reassembler, 100, TcpInfo::ACK, b"\x02a").is_empty());
+        assert_eq!(reassembler.stats().retransmissions, 1);
+        // Partial overlap: only the new byte is used.
+        let out = feed(&mut reassembler, 101, TcpInfo::ACK, b"ab");
+        assert_eq!(out, vec![Bytes::from("\x02ab")]);
+    }
+
+    #[test]
+    fn keeps_first_copy_of_overlapping_bytes() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        feed(&mut reassembler, 102, TcpInfo::ACK, b"c");
+        // Same offset, longer: only the bytes past the buffered copy are kept.
+        feed(&mut reassembler, 102, TcpInfo::ACK, b"XY\x01z");
+        assert_eq!(reassembler.stats().out_of_order, 2);
+        assert!(feed(&mut reassembler, 102, TcpInfo::ACK, b"c").is_empty());
+        assert_eq!(reassembler.stats().retransmissions, 1);
+        // In-order data overlapping buffered bytes does not replace them.
+        let out = feed(&mut reassembler, 100, TcpInfo::ACK, b"\x03bQR");
+        assert_eq!(out, vec![Bytes::from("\x03bcY"), Bytes::from("\x01z")]);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn skips_gap_after_timeout() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        let timeout = ReassemblyLimits::default().gap_timeout_ns;
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        feed(&mut reassembler, 100, TcpInfo::ACK, b"\x01a\x05");
+        // Bytes 103..110 are lost.
+        assert!(feed_at(&mut reassembler, 1, 110, TcpInfo::ACK, b"\x01b").is_empty());
+        assert!(feed_at(&mut reassembler, timeout, 112, TcpInfo::ACK, b"\x01c").is_empty());
+        let out = feed_at(&mut reassembler, timeout + 1, 114, TcpInfo::ACK, b"\x01d");
+        assert_eq!(
+            out,
+            vec![
+                Bytes::from("\x01b"),
+                Bytes::from("\x01c"),
+                Bytes::from("\x01d")
+            ]
+        );
+        assert_eq!(reassembler.stats().skipped_gaps, 1);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn out_of_order_data_does_not_keep_stream_alive() {
+        let limits = ReassemblyLimits {
+            gap_timeout_ns: u64::MAX,
+            ..ReassemblyLimits::default()
+        };
+        let mut reassembler = TcpReassembler::new(limits);
+        feed(&mut reassembler, 100, TcpInfo::ACK, b"\x05ab");
+        feed_at(
+            &mut reassembler,
+            limits.idle_timeout_ns,
+            200,
+            TcpInfo::ACK,
+            b"x",
+        );
+        reassembler.expire(limits.idle_timeout_ns + 1);
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn handles_sequence_wraparound() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, u32::MAX - 1, TcpInfo::SYN, b"");
+        feed(&mut reassembler, u32::MAX, TcpInfo::ACK, b"\x02");
+        let out = feed(&mut reassembler, 0, TcpInfo::ACK, b"ab");
+        assert_eq!(out, vec![Bytes::from("\x02ab")]);
+    }
+
+    #[test]
+    fn enforces_stream_memory_cap() {
+        let limits = ReassemblyLimits {
+            max_stream_bytes: 4,
+            ..ReassemblyLimits::default()
+        };
+        let mut reassembler = TcpReassembler::new(limits);
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        feed(&mut reassembler, 110, TcpInfo::ACK, b"abc");
+        feed(&mut reassembler, 120, TcpInfo::ACK, b"def");
+        assert_eq!(reassembler.stats().dropped_segments, 1);
+        assert_eq!(reassembler.buffered_bytes(), 3);
+    }
+
+    #[test]
+    fn abandons_stream_on_framing_error() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, 99, TcpInfo::SYN, b"");
+        assert!(feed(&mut reassembler, 100, TcpInfo::ACK, b"\x00junk").is_empty());
+        assert_eq!(reassembler.stats().desyncs, 1);
+        assert!(feed(&mut reassembler, 105, TcpInfo::ACK, b"\x01a").is_empty());
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn releases_streams_on_fin_rst_and_timeout() {
+        let mut reassembler = TcpReassembler::new(ReassemblyLimits::default());
+        feed(&mut reassembler, 100, TcpInfo::ACK, b"\x05ab");
+        assert_eq!(reassembler.len(), 1);
+        feed(&mut reassembler, 103, TcpInfo::RST, b"");
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.buffered_bytes(), 0);
+
+        let out = feed(&mut reassembler, 200, TcpInfo::ACK | TcpInfo::FIN, b"\x01a");
+        assert_eq!(out.len(), 1);
+        assert!(reassembler.is_empty());
+
+        feed(&mut reassembler, 300, TcpInfo::ACK, b"\x05ab");
+        reassembler.expire(ReassemblyLimits::default().idle_timeout_ns + 1);
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn evicts_oldest_stream_at_capacity() {
+        let limits = ReassemblyLimits {
+            max_streams: 1,
+            ..ReassemblyLimits::default()
+        };
+        let mut reassembler = TcpReassembler::new(limits);
+        feed(&mut reassembler, 100, TcpInfo::ACK, b"\x05ab");
+
+        let other = StreamKey {
+            source: "10.0.0.3:40000".parse().unwrap(),
+            ..key()
+        };
+        let mut out = Vec::new();
+        reassembler.process(
+            other,
+            &tcp(1, TcpInfo::ACK),
+            Bytes::from_static(b"\x05"),
+            1,
+            length_prefixed,
+            &mut out,
+        );
+        assert_eq!(reassembler.len(), 1);
+        assert_eq!(reassembler.stats().evicted_streams, 1);
+        assert_eq!(reassembler.buffered_bytes(), 1);
+    }
+
+    #[test]
+    fn evicts_the_stream_that_progressed_least_recently() {
+        let limits = ReassemblyLimits {
+            max_streams: 2,
+            ..ReassemblyLimits::default()
+        };
+        let mut reassembler = TcpReassembler::new(limits);
+        let stream = |port: u16| StreamKey {
+            source: SocketAddr::new("10.0.0.3".parse().unwrap(), port),
+            ..key()
+        };
+        let mut send = |key, sequence, timestamp| {
+            let mut out = Vec::new();
+            reassembler.process(
+                key,
+                &tcp(sequence, TcpInfo::ACK),
+                Bytes::from_static(b"\x05"),
+                timestamp,
+                length_prefixed,
+                &mut out,
+            );
+        };
+        send(stream(1), 1, 0);
+        send(stream(2), 1, 1);
+        // The first stream advances, so the second is now the stalest.
+        send(stream(1), 2, 2);
+        send(stream(3), 1, 3);
+
+        assert_eq!(reassembler.len(), 2);
+        assert!(reassembler.streams.contains_key(&stream(1)));
+        assert!(!reassembler.streams.contains_key(&stream(2)));
+        assert_eq!(reassembler.recency.len(), 2);
+
+        reassembler.expire(ReassemblyLimits::default().idle_timeout_ns + 3);
+        assert_eq!(reassembler.len(), 1);
+        assert!(reassembler.streams.contains_key(&stream(3)));
+        assert_eq!(reassembler.buffered_bytes(), 1);
+    }
+}
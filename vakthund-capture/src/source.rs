@@ -0,0 +1,165 @@
+//! Pluggable capture backends.
+//!
+//! A [`CaptureSource`] hides where packets come from (a live interface, a
+//! capture file, an in-memory list) behind open / next-batch / stats / close.
+//! [`run_capture_loop`] drives any source until it is exhausted or asked to stop.
+
+use crate::packet::Packet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Upper bound on packets returned by a single `next_batch` call.
+pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// Default interval between capture statistics samples.
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Capture backend error conditions.
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("Capture device '{0}' not found")]
+    DeviceNotFound(String),
+    #[error("Capture source is not open")]
+    NotOpen,
+    #[error("Unsupported capture mode: {0}")]
+    UnsupportedMode(String),
+    #[error("Invalid capture filter: {0}")]
+    InvalidFilter(String),
+    #[error("Unsupported link type: {0}")]
+    UnsupportedLinkType(String),
+    #[error("pcap error: {0}")]
+    Pcap(#[from] pcap::Error),
+    #[error("Capture I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Packet counters reported by a capture source.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Packets delivered by the source.
+    pub received: u64,
+    /// Packets dropped because the capture buffer was full.
+    pub dropped: u64,
+    /// Packets dropped by the network interface or its driver.
+    pub if_dropped: u64,
+}
+
+/// A source of captured packets.
+pub trait CaptureSource: Send {
+    /// Opens the underlying handle. Must be called before `next_batch`.
+    fn open(&mut self) -> Result<(), CaptureError>;
+
+    /// Appends the next batch of packets to `batch`.
+    ///
+    /// Returns `Ok(Some(n))` with the number of packets appended (0 if the read
+    /// timed out), or `Ok(None)` once the source is exhausted.
+    fn next_batch(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError>;
+
+    /// Returns the current packet counters.
+    fn stats(&mut self) -> Result<CaptureStats, CaptureError>;
+
+    /// Releases the underlying handle.
+    fn close(&mut self);
+}
+
+impl<S: CaptureSource + ?Sized> CaptureSource for Box<S> {
+    fn open(&mut self) -> Result<(), CaptureError> {
+        (**self).open()
+    }
+
+    fn next_batch(&mut self, batch: &mut Vec<Packet>) -> Result<Option<usize>, CaptureError> {
+        (**self).next_batch(batch)
+    }
+
+    fn stats(&mut self) -> Result<CaptureStats, CaptureError> {
+        (**self).stats()
+    }
+
+    fn close(&mut self) {
+        (**self).close()
+    }
+}
+
+/// Opens `source` and feeds every packet to `callback` until the source is
+/// exhausted or `terminate` is set. The source is closed before returning.
+///
+/// Returns the final capture statistics.
+pub fn run_capture_loop<S, F>(
+    source: &mut S,
+    terminate: &AtomicBool,
+    callback: F,
+) -> Result<CaptureStats, CaptureError>
+where
+    S: CaptureSource + ?Sized,
+    F: FnMut(&Packet) + Send,
+{
+    run_capture_loop_with_stats(source, terminate, STATS_INTERVAL, |_| {}, callback)
+}
+
+/// Like [`run_capture_loop`], but also samples the source counters every
+/// `interval` and passes them to `on_stats`. The final counters are reported
+/// too, so `on_stats` sees every drop the source knows about.
+pub fn run_capture_loop_with_stats<S, F, G>(
+    source: &mut S,
+    terminate: &AtomicBool,
+    interval: Duration,
+    on_stats: G,
+    mut callback: F,
+) -> Result<CaptureStats, CaptureError>
+where
+    S: CaptureSource + ?Sized,
+    F: FnMut(&Packet) + Send,
+    G: FnMut(&CaptureStats),
+{
+    run_capture_loop_batched(source, terminate, interval, on_stats, |batch| {
+        batch.iter().for_each(&mut callback)
+    })
+}
+
+/// Like [`run_capture_loop_with_stats`], but hands each batch read from the
+/// source to `on_batch` at once, so per-batch work (such as publishing events)
+/// is paid once per burst rather than once per packet.
+pub fn run_capture_loop_batched<S, F, G>(
+    source: &mut S,
+    terminate: &AtomicBool,
+    interval: Duration,
+    mut on_stats: G,
+    mut on_batch: F,
+) -> Result<CaptureStats, CaptureError>
+where
+    S: CaptureSource + ?Sized,
+    F: FnMut(&[Packet]) + Send,
+    G: FnMut(&CaptureStats),
+{
+    source.open()?;
+
+    let mut batch = Vec::with_capacity(DEFAULT_BATCH_SIZE);
+    let mut last_sample = Instant::now();
+    let result = loop {
+        if terminate.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        batch.clear();
+        match source.next_batch(&mut batch) {
+            Ok(Some(_)) if batch.is_empty() => {}
+            Ok(Some(_)) => on_batch(&batch),
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        }
+        if last_sample.elapsed() >= interval {
+            // A failed sample is not fatal; the next one may succeed.
+            if let Ok(stats) = source.stats() {
+                on_stats(&stats);
+            }
+            last_sample = Instant::now();
+        }
+    };
+
+    let stats = result.and_then(|_| source.stats());
+    if let Ok(stats) = &stats {
+        on_stats(stats);
+    }
+    source.close();
+    stats
+}
@@ -0,0 +1,417 @@
+//! Tunnel decapsulation.
+//!
+//! Mirrored traffic from remote SPAN sources usually arrives wrapped in GRE
+//! (optionally carrying ERSPAN type II/III), VXLAN or GENEVE. [`decode_tunnel`]
+//! recognises one encapsulation layer on a decoded packet and decodes the
+//! inner packet in place. Callers repeat it, up to [`MAX_TUNNEL_DEPTH`]
+//! layers, reassembling fragments between layers as needed. Ranges in the
+//! inner [`DecodedPacket`] stay relative to the original buffer, so payloads
+//! can still be sliced without copying.
+
+use std::net::IpAddr;
+
+use crate::decode::{
+    decode_ethernet_at, decode_l3, read_u16, DecodeError, DecodedPacket, Transport, ETHERTYPE_IPV4,
+    ETHERTYPE_IPV6,
+};
+
+pub const IPPROTO_GRE: u8 = 47;
+pub const VXLAN_PORT: u16 = 4789;
+pub const GENEVE_PORT: u16 = 6081;
+
+/// GRE protocol type for transparent Ethernet bridging.
+const ETHERTYPE_TEB: u16 = 0x6558;
+/// GRE protocol type for ERSPAN type I/II.
+const ETHERTYPE_ERSPAN_II: u16 = 0x88BE;
+/// GRE protocol type for ERSPAN type III.
+const ETHERTYPE_ERSPAN_III: u16 = 0x22EB;
+
+const GRE_HEADER_LEN: usize = 4;
+const ERSPAN_II_HEADER_LEN: usize = 8;
+const ERSPAN_III_HEADER_LEN: usize = 12;
+const ERSPAN_III_SUBHEADER_LEN: usize = 8;
+const VXLAN_HEADER_LEN: usize = 8;
+const GENEVE_HEADER_LEN: usize = 8;
+
+/// Encapsulation layers unwrapped before giving up. Deeper nesting is left
+/// encapsulated rather than decoded without bound.
+pub const MAX_TUNNEL_DEPTH: usize = 4;
+
+/// Supported encapsulations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TunnelKind {
+    Gre,
+    ErspanII,
+    ErspanIII,
+    Vxlan,
+    Geneve,
+}
+
+/// One unwrapped encapsulation layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TunnelHeader {
+    pub kind: TunnelKind,
+    /// Outer (tunnel endpoint) source address.
+    pub source: IpAddr,
+    /// Outer (tunnel endpoint) destination address.
+    pub destination: IpAddr,
+    /// GRE key, ERSPAN session id or VXLAN/GENEVE network identifier.
+    pub id: Option<u32>,
+}
+
+/// Unwraps one encapsulation layer from `packet`, decoded from `data`.
+///
+/// Returns `Ok(None)` when `packet` is not a supported tunnel. IP fragments
+/// are never unwrapped; reassemble them first.
+pub fn decode_tunnel(
+    data: &[u8],
+    packet: &DecodedPacket,
+) -> Result<Option<(TunnelHeader, DecodedPacket)>, DecodeError> {
+    let Some(ip) = packet.ip.as_ref().filter(|ip| ip.fragment.is_none()) else {
+        return Ok(None);
+    };
+    // Ignore anything past the end of the outer payload, such as Ethernet padding.
+    let data = &data[..packet.payload.end];
+    let inner = match (ip.protocol, packet.transport) {
+        (IPPROTO_GRE, _) => decode_gre(data, ip.payload.start)?,
+        (_, Some(Transport::Udp(udp))) if udp.destination_port == VXLAN_PORT => {
+            decode_vxlan(data, packet.payload.start)?
+        }
+        (_, Some(Transport::Udp(udp))) if udp.destination_port == GENEVE_PORT => {
+            decode_geneve(data, packet.payload.start)?
+        }
+        _ => None,
+    };
+    Ok(inner.map(|(kind, id, inner)| {
+        let header = TunnelHeader {
+            kind,
+            source: ip.source,
+            destination: ip.destination,
+            id,
+        };
+        (header, inner)
+    }))
+}
+
+type Inner = Option<(TunnelKind, Option<u32>, DecodedPacket)>;
+
+/// GRE (RFC 2784 / RFC 2890), dispatching ERSPAN by protocol type.
+fn decode_gre(data: &[u8], offset: usize) -> Result<Inner, DecodeError> {
+    let header = data
+        .get(offset..offset + GRE_HEADER_LEN)
+        .ok_or(DecodeError::Truncated("GRE"))?;
+    let flags = read_u16(header, 0);
+    // Version 1 is PPTP's enhanced GRE, which carries PPP rather than frames.
+    if flags & 0x0007 != 0 {
+        return Ok(None);
+    }
+    let protocol = read_u16(header, 2);
+    let has_checksum = flags & 0x8000 != 0;
+    let has_key = flags & 0x2000 != 0;
+    let has_sequence = flags & 0x1000 != 0;
+
+    let mut cursor = offset + GRE_HEADER_LEN;
+    if has_checksum {
+        cursor += 4;
+    }
+    let key = if has_key {
+        let key = data
+            .get(cursor..cursor + 4)
+            .ok_or(DecodeError::Truncated("GRE"))?;
+        cursor += 4;
+        Some(u32::from_be_bytes([key[0], key[1], key[2], key[3]]))
+    } else {
+        None
+    };
+    if has_sequence {
+        cursor += 4;
+    }
+    if cursor > data.len() {
+        return Err(DecodeError::Truncated("GRE"));
+    }
+
+    match protocol {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => Ok(Some((
+            TunnelKind::Gre,
+            key,
+            decode_l3(data, protocol, cursor)?,
+        ))),
+        ETHERTYPE_TEB => Ok(Some((
+            TunnelKind::Gre,
+            key,
+            decode_ethernet_at(data, cursor)?,
+        ))),
+        // ERSPAN type I has no sequence number and no ERSPAN header.
+        ETHERTYPE_ERSPAN_II if !has_sequence => Ok(Some((
+            TunnelKind::ErspanII,
+            None,
+            decode_ethernet_at(data, cursor)?,
+        ))),
+        ETHERTYPE_ERSPAN_II => decode_erspan_ii(data, cursor),
+        ETHERTYPE_ERSPAN_III => decode_erspan_iii(data, cursor),
+        _ => Ok(None),
+    }
+}
+
+fn decode_erspan_ii(data: &[u8], offset: usize) -> Result<Inner, DecodeError> {
+    let header = data
+        .get(offset..offset + ERSPAN_II_HEADER_LEN)
+        .ok_or(DecodeError::Truncated("ERSPAN"))?;
+    if header[0] >> 4 != 1 {
+        return Err(DecodeError::Malformed("ERSPAN"));
+    }
+    let session = u32::from(read_u16(header, 2) & 0x03FF);
+    let inner = decode_ethernet_at(data, offset + ERSPAN_II_HEADER_LEN)?;
+    Ok(Some((TunnelKind::ErspanII, Some(session), inner)))
+}
+
+fn decode_erspan_iii(data: &[u8], offset: usize) -> Result<Inner, DecodeError> {
+    let header = data
+        .get(offset..offset + ERSPAN_III_HEADER_LEN)
+        .ok_or(DecodeError::Truncated("ERSPAN"))?;
+    if header[0] >> 4 != 2 {
+        return Err(DecodeError::Malformed("ERSPAN"));
+    }
+    let session = u32::from(read_u16(header, 2) & 0x03FF);
+    let frame_type = (header[10] >> 2) & 0x1F;
+    let has_subheader = header[11] & 0x01 != 0;
+    let mut cursor = offset + ERSPAN_III_HEADER_LEN;
+    if has_subheader {
+        cursor += ERSPAN_III_SUBHEADER_LEN;
+    }
+    // Only Ethernet frames are mirrored in practice; other frame types are
+    // left encapsulated.
+    if frame_type != 0 {
+        return Ok(None);
+    }
+    let inner = decode_ethernet_at(data, cursor)?;
+    Ok(Some((TunnelKind::ErspanIII, Some(session), inner)))
+}
+
+/// VXLAN (RFC 7348), always carrying Ethernet.
+fn decode_vxlan(data: &[u8], offset: usize) -> Result<Inner, DecodeError> {
+    let header = data
+        .get(offset..offset + VXLAN_HEADER_LEN)
+        .ok_or(DecodeError::Truncated("VXLAN"))?;
+    if header[0] & 0x08 == 0 {
+        return Err(DecodeError::Malformed("VXLAN"));
+    }
+    let vni = u32::from_be_bytes([0, header[4], header[5], header[6]]);
+    let inner = decode_ethernet_at(data, offset + VXLAN_HEADER_LEN)?;
+    Ok(Some((TunnelKind::Vxlan, Some(vni), inner)))
+}
+
+/// GENEVE (RFC 8926), skipping any options.
+fn decode_geneve(data: &[u8], offset: usize) -> Result<Inner, DecodeError> {
+    let header = data
+        .get(offset..offset + GENEVE_HEADER_LEN)
+        .ok_or(DecodeError::Truncated("GENEVE"))?;
+    if header[0] >> 6 != 0 {
+        return Err(DecodeError::Malformed("GENEVE"));
+    }
+    let options_len = usize::from(header[0] & 0x3F) * 4;
+    let protocol = read_u16(header, 2);
+    let vni = u32::from_be_bytes([0, header[4], header[5], header[6]]);
+    let cursor = offset + GENEVE_HEADER_LEN + options_len;
+    if cursor > data.len() {
+        return Err(DecodeError::Truncated("GENEVE"));
+    }
+    let inner = match protocol {
+        ETHERTYPE_TEB => decode_ethernet_at(data, cursor)?,
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => decode_l3(data, protocol, cursor)?,
+        _ => return Ok(None),
+    };
+    Ok(Some((TunnelKind::Geneve, Some(vni), inner)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::tests::{ethernet, ipv4, tcp, udp};
+    use crate::decode::{decode_ethernet, IPPROTO_TCP, IPPROTO_UDP};
+    use std::net::Ipv4Addr;
+
+    /// Unwraps every layer of `packet`, returning them outermost first
+    /// followed by the innermost packet.
+    fn decapsulate(
+        data: &[u8],
+        packet: DecodedPacket,
+    ) -> Result<(Vec<TunnelHeader>, DecodedPacket), DecodeError> {
+        let mut tunnels = Vec::new();
+        let mut packet = packet;
+        while tunnels.len() < MAX_TUNNEL_DEPTH {
+            match decode_tunnel(data, &packet)? {
+                Some((header, inner)) => {
+                    tunnels.push(header);
+                    packet = inner;
+                }
+                None => break,
+            }
+        }
+        Ok((tunnels, packet))
+    }
+
+    /// An IPv4 packet between the given addresses.
+    fn ipv4_between(source: [u8; 4], destination: [u8; 4], protocol: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = ipv4(protocol, 0, 1, body);
+        packet[12..16].copy_from_slice(&source);
+        packet[16..20].copy_from_slice(&destination);
+        packet
+    }
+
+    fn inner_frame() -> Vec<u8> {
+        let segment = tcp(40000, 1883, 1, 0x18, b"mqtt");
+        ethernet(
+            ETHERTYPE_IPV4,
+            &[30],
+            &ipv4_between([192, 168, 0, 5], [192, 168, 0, 6], IPPROTO_TCP, &segment),
+        )
+    }
+
+    fn gre(flags: u16, protocol: u16, extra: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut header = flags.to_be_bytes().to_vec();
+        header.extend_from_slice(&protocol.to_be_bytes());
+        header.extend_from_slice(extra);
+        header.extend_from_slice(body);
+        ethernet(ETHERTYPE_IPV4, &[], &ipv4(IPPROTO_GRE, 0, 1, &header))
+    }
+
+    fn assert_inner_mqtt(frame: &[u8], inner: &DecodedPacket) {
+        let ip = inner.ip.as_ref().unwrap();
+        assert_eq!(ip.source, IpAddr::V4(Ipv4Addr::new(192, 168, 0, 5)));
+        assert_eq!(inner.transport.unwrap().destination_port(), 1883);
+        assert_eq!(&frame[inner.payload.clone()], b"mqtt");
+    }
+
+    #[test]
+    fn unwraps_gre_with_key() {
+        let frame = gre(0x2000, ETHERTYPE_TEB, &7u32.to_be_bytes(), &inner_frame());
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+
+        assert_eq!(
+            tunnels,
+            [TunnelHeader {
+                kind: TunnelKind::Gre,
+                source: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                destination: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                id: Some(7),
+            }]
+        );
+        assert_eq!(inner.vlan, Some(30));
+        assert_inner_mqtt(&frame, &inner);
+    }
+
+    #[test]
+    fn unwraps_gre_carrying_ip() {
+        let segment = tcp(40000, 1883, 1, 0x18, b"mqtt");
+        let packet = ipv4_between([192, 168, 0, 5], [192, 168, 0, 6], IPPROTO_TCP, &segment);
+        let frame = gre(0, ETHERTYPE_IPV4, &[], &packet);
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+
+        assert_eq!(tunnels[0].id, None);
+        assert_inner_mqtt(&frame, &inner);
+    }
+
+    #[test]
+    fn unwraps_erspan_ii() {
+        let mut erspan = vec![0x10, 0x00, 0x00, 0x2A, 0, 0, 0, 0];
+        erspan.extend_from_slice(&inner_frame());
+        let frame = gre(0x1000, ETHERTYPE_ERSPAN_II, &[0, 0, 0, 1], &erspan);
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+
+        assert_eq!(tunnels[0].kind, TunnelKind::ErspanII);
+        assert_eq!(tunnels[0].id, Some(42));
+        assert_inner_mqtt(&frame, &inner);
+    }
+
+    #[test]
+    fn unwraps_erspan_iii_with_subheader() {
+        let mut erspan = vec![0x20, 0x00, 0x00, 0x05];
+        erspan.extend_from_slice(&[0; 6]); // timestamp, SGT
+        erspan.extend_from_slice(&[0x00, 0x01]); // Ethernet frame, O bit
+        erspan.extend_from_slice(&[0; ERSPAN_III_SUBHEADER_LEN]);
+        erspan.extend_from_slice(&inner_frame());
+        let frame = gre(0x1000, ETHERTYPE_ERSPAN_III, &[0, 0, 0, 1], &erspan);
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+
+        assert_eq!(tunnels[0].kind, TunnelKind::ErspanIII);
+        assert_eq!(tunnels[0].id, Some(5));
+        assert_inner_mqtt(&frame, &inner);
+    }
+
+    #[test]
+    fn unwraps_nested_vxlan_in_geneve() {
+        let mut vxlan = vec![0x08, 0, 0, 0, 0x00, 0x10, 0x00, 0];
+        vxlan.extend_from_slice(&inner_frame());
+        let vxlan_frame = ethernet(
+            ETHERTYPE_IPV4,
+            &[],
+            &ipv4_between(
+                [172, 16, 0, 1],
+                [172, 16, 0, 2],
+                IPPROTO_UDP,
+                &udp(50000, VXLAN_PORT, &vxlan),
+            ),
+        );
+
+        // GENEVE with one 4-byte option word.
+        let mut geneve = vec![0x01, 0x00];
+        geneve.extend_from_slice(&ETHERTYPE_TEB.to_be_bytes());
+        geneve.extend_from_slice(&[0x00, 0x00, 0x09, 0]);
+        geneve.extend_from_slice(&[0xFF; 4]);
+        geneve.extend_from_slice(&vxlan_frame);
+        let frame = ethernet(
+            ETHERTYPE_IPV4,
+            &[],
+            &ipv4(IPPROTO_UDP, 0, 1, &udp(50001, GENEVE_PORT, &geneve)),
+        );
+
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+        assert_eq!(tunnels.len(), 2);
+        assert_eq!(tunnels[0].kind, TunnelKind::Geneve);
+        assert_eq!(tunnels[0].id, Some(9));
+        assert_eq!(tunnels[1].kind, TunnelKind::Vxlan);
+        assert_eq!(tunnels[1].id, Some(4096));
+        assert_eq!(tunnels[1].source, IpAddr::V4(Ipv4Addr::new(172, 16, 0, 1)));
+        assert_inner_mqtt(&frame, &inner);
+    }
+
+    #[test]
+    fn stops_at_max_depth() {
+        let mut frame = inner_frame();
+        for _ in 0..=MAX_TUNNEL_DEPTH {
+            frame = gre(0, ETHERTYPE_TEB, &[], &frame);
+        }
+        let (tunnels, inner) = decapsulate(&frame, decode_ethernet(&frame).unwrap()).unwrap();
+        assert_eq!(tunnels.len(), MAX_TUNNEL_DEPTH);
+        assert_eq!(inner.ip.unwrap().protocol, IPPROTO_GRE);
+    }
+
+    #[test]
+    fn leaves_plain_traffic_alone() {
+        let frame = inner_frame();
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(decode_tunnel(&frame, &decoded), Ok(None));
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let frame = ethernet(
+            ETHERTYPE_IPV4,
+            &[],
+            &ipv4(IPPROTO_UDP, 0, 1, &udp(1, VXLAN_PORT, &[0; 8])),
+        );
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(
+            decode_tunnel(&frame, &decoded),
+            Err(DecodeError::Malformed("VXLAN"))
+        );
+
+        let frame = gre(0x2000, ETHERTYPE_TEB, &[0, 0], &[]);
+        let decoded = decode_ethernet(&frame).unwrap();
+        assert_eq!(
+            decode_tunnel(&frame, &decoded),
+            Err(DecodeError::Truncated("GRE"))
+        );
+    }
+}
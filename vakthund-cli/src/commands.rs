@@ -22,9 +22,10 @@ pub enum Commands {
 
 #[derive(Args, Debug, Clone)]
 pub struct RunArgs {
-    /// Network interface to monitor
+    /// Network interface to monitor; repeat to capture from several at once.
+    /// Defaults to the interfaces in the capture config.
     #[arg(short, long)]
-    pub interface: String,
+    pub interface: Vec<String>,
     /// Validate config before running
     #[arg(long, default_value_t = true)]
     pub validate: bool,
@@ -5,36 +5,253 @@
 //! - File‑based replay
 //! - Simulated traffic generation
 
+use std::path::PathBuf;
+
 use serde::{Deserialize, Deserializer, Serialize};
-use validator::{self, Validate};
+use validator::{self, Validate, ValidationError};
 
 use crate::validation;
 
 /// Packet capture configuration.
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+#[validate(schema(function = validate_capture))]
 pub struct CaptureConfig {
-    /// Capture mode (xdp, pcap, simulated).
+    /// Capture mode (xdp, pcap, af_packet, file, simulated). `xdp` captures
+    /// with libpcap until an XDP backend exists.
     #[validate(custom(function = validation::validate_mode))]
     pub mode: String,
 
-    /// Network interface for live capture.
+    /// Network interface for live capture. Ignored when `interfaces` is set.
     #[validate(custom(function = validation::validate_interface))]
     #[serde(default = "default_interface")]
     pub interface: String,
 
+    /// Interfaces captured concurrently, each optionally overriding the
+    /// capture settings below.
+    #[validate(nested)]
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceConfig>,
+
     /// Run in promiscuous mode?
     #[serde(default = "default_promiscuous")]
     pub promiscuous: bool,
 
-    /// Capture buffer size in bytes.
+    /// Capture buffer size in bytes (total ring size for `af_packet`).
     #[validate(range(min = 4096, max = 1073741824))]
     #[serde(default = "default_buffer_size", deserialize_with = "deserialize_size")]
     pub buffer_size: usize,
 
-    /// Maximum capture latency (milliseconds).
+    /// Maximum capture latency (milliseconds). Used as the block retire
+    /// timeout for `af_packet`.
     #[validate(range(min = 1, max = 5000))]
     #[serde(default = "default_latency")]
     pub max_latency_ms: u32,
+
+    /// Path to a pcap/pcapng file replayed in `file` mode.
+    #[serde(default)]
+    pub file_path: Option<PathBuf>,
+
+    /// Replay speed multiplier for `file` mode (1.0 = real time).
+    /// Unset replays as fast as possible.
+    #[validate(range(min = 0.01, max = 1000.0))]
+    #[serde(default)]
+    pub replay_speed: Option<f64>,
+
+    /// BPF filter expression (tcpdump syntax) applied before packets reach
+    /// user space, e.g. `tcp port 1883 or udp port 5683`.
+    #[validate(custom(function = validation::validate_bpf_filter))]
+    #[serde(default)]
+    pub bpf_filter: Option<String>,
+
+    /// TCP stream reassembly limits (MQTT, Modbus/TCP).
+    #[validate(nested)]
+    #[serde(default)]
+    pub tcp_reassembly: TcpReassemblyConfig,
+
+    /// IPv4/IPv6 fragment reassembly.
+    #[validate(nested)]
+    #[serde(default)]
+    pub defrag: DefragConfig,
+
+    /// Flow table size and timeouts.
+    #[validate(nested)]
+    #[serde(default)]
+    pub flows: FlowConfig,
+}
+
+/// Memory and time bounds for TCP stream reassembly.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct TcpReassemblyConfig {
+    /// Maximum number of tracked stream directions.
+    #[validate(range(min = 1, max = 4194304))]
+    #[serde(default = "default_max_streams")]
+    pub max_streams: usize,
+
+    /// Maximum bytes buffered per stream direction.
+    #[validate(range(min = 1024, max = 67108864))]
+    #[serde(
+        default = "default_max_stream_buffer",
+        deserialize_with = "deserialize_size"
+    )]
+    pub max_stream_buffer: usize,
+
+    /// Maximum bytes buffered across all streams.
+    #[validate(range(min = 65536, max = 1073741824))]
+    #[serde(
+        default = "default_max_total_buffer",
+        deserialize_with = "deserialize_size"
+    )]
+    pub max_total_buffer: usize,
+
+    /// Idle time after which a stream is dropped (seconds).
+    #[validate(range(min = 1, max = 86400))]
+    #[serde(default = "default_stream_timeout")]
+    pub idle_timeout_secs: u64,
+
+    /// Time a gap left by a lost segment is waited on before it is skipped (seconds).
+    #[validate(range(min = 1, max = 3600))]
+    #[serde(default = "default_gap_timeout")]
+    pub gap_timeout_secs: u64,
+}
+
+impl Default for TcpReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            max_streams: default_max_streams(),
+            max_stream_buffer: default_max_stream_buffer(),
+            max_total_buffer: default_max_total_buffer(),
+            idle_timeout_secs: default_stream_timeout(),
+            gap_timeout_secs: default_gap_timeout(),
+        }
+    }
+}
+
+/// Per-interface capture settings. Unset fields fall back to the values of
+/// the enclosing [`CaptureConfig`].
+#[derive(Debug, Serialize, Deserialize, Validate, Clone, PartialEq)]
+pub struct InterfaceConfig {
+    /// Interface name, e.g. `eth0`.
+    #[validate(custom(function = validation::validate_interface))]
+    pub name: String,
+
+    /// Run this interface in promiscuous mode?
+    #[serde(default)]
+    pub promiscuous: Option<bool>,
+
+    /// Capture buffer size for this interface in bytes.
+    #[validate(range(min = 4096, max = 1073741824))]
+    #[serde(default, deserialize_with = "deserialize_optional_size")]
+    pub buffer_size: Option<usize>,
+
+    /// BPF filter expression for this interface.
+    #[validate(custom(function = validation::validate_bpf_filter))]
+    #[serde(default)]
+    pub bpf_filter: Option<String>,
+}
+
+impl InterfaceConfig {
+    /// Creates an entry for `name` that inherits every setting.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            promiscuous: None,
+            buffer_size: None,
+            bpf_filter: None,
+        }
+    }
+}
+
+impl CaptureConfig {
+    /// Names of the interfaces to capture from: the `interfaces` list, or the
+    /// single `interface` when the list is empty.
+    pub fn interface_names(&self) -> Vec<String> {
+        if self.interfaces.is_empty() {
+            vec![self.interface.clone()]
+        } else {
+            self.interfaces.iter().map(|i| i.name.clone()).collect()
+        }
+    }
+
+    /// Resolves the effective settings for capturing on `name`.
+    ///
+    /// The returned config has `interface` set to `name`, any overrides from
+    /// the matching `interfaces` entry applied and `interfaces` cleared.
+    pub fn for_interface(&self, name: &str) -> CaptureConfig {
+        let mut config = CaptureConfig {
+            interface: name.to_string(),
+            interfaces: Vec::new(),
+            ..self.clone()
+        };
+        if let Some(overrides) = self.interfaces.iter().find(|i| i.name == name) {
+            if let Some(promiscuous) = overrides.promiscuous {
+                config.promiscuous = promiscuous;
+            }
+            if let Some(buffer_size) = overrides.buffer_size {
+                config.buffer_size = buffer_size;
+            }
+            if let Some(filter) = &overrides.bpf_filter {
+                config.bpf_filter = Some(filter.clone());
+            }
+        }
+        config
+    }
+}
+
+/// Flow table bounds.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+#[validate(schema(function = validate_flow_timeouts))]
+pub struct FlowConfig {
+    /// Maximum number of tracked flows, split evenly over the table's
+    /// shards. A full shard evicts its least recently seen flow.
+    #[validate(range(min = 1, max = 16777216))]
+    #[serde(default = "default_max_flows")]
+    pub max_flows: usize,
+
+    /// Time without packets after which a flow ends (seconds).
+    #[validate(range(min = 1, max = 86400))]
+    #[serde(default = "default_flow_idle_timeout")]
+    pub idle_timeout_secs: u64,
+
+    /// Time after which a long-lived flow ends even while active (seconds).
+    #[validate(range(min = 1, max = 604800))]
+    #[serde(default = "default_flow_active_timeout")]
+    pub active_timeout_secs: u64,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            max_flows: default_max_flows(),
+            idle_timeout_secs: default_flow_idle_timeout(),
+            active_timeout_secs: default_flow_active_timeout(),
+        }
+    }
+}
+
+/// An active timeout below the idle timeout would end every flow early.
+fn validate_flow_timeouts(config: &FlowConfig) -> Result<(), ValidationError> {
+    if config.active_timeout_secs < config.idle_timeout_secs {
+        return Err(ValidationError::new("active_timeout_below_idle_timeout"));
+    }
+    Ok(())
+}
+
+/// `file` mode needs a file to replay, and each interface may only be listed once.
+fn validate_capture(config: &CaptureConfig) -> Result<(), ValidationError> {
+    if config.mode == "file" && config.file_path.is_none() {
+        return Err(ValidationError::new("missing_file_path"));
+    }
+    for (i, interface) in config.interfaces.iter().enumerate() {
+        if config.interfaces[..i]
+            .iter()
+            .any(|other| other.name == interface.name)
+        {
+            let mut error = ValidationError::new("duplicate_interface");
+            error.message = Some(format!("Interface '{}' is listed twice", interface.name).into());
+            return Err(error);
+        }
+    }
+    Ok(())
 }
 
 fn default_interface() -> String {
@@ -53,6 +270,124 @@ fn default_latency() -> u32 {
     100
 }
 
+/// IP fragment reassembly settings.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct DefragConfig {
+    /// How overlapping fragment data is resolved. Should match the
+    /// operating system of the protected hosts.
+    #[serde(default)]
+    pub overlap_policy: OverlapPolicy,
+
+    /// Maximum number of datagrams being reassembled at once.
+    #[validate(range(min = 1, max = 1048576))]
+    #[serde(default = "default_max_datagrams")]
+    pub max_datagrams: usize,
+
+    /// Maximum fragment bytes held across all datagrams.
+    #[validate(range(min = 65536, max = 1073741824))]
+    #[serde(
+        default = "default_max_fragment_buffer",
+        deserialize_with = "deserialize_size"
+    )]
+    pub max_buffer: usize,
+
+    /// Time to wait for missing fragments (seconds).
+    #[validate(range(min = 1, max = 120))]
+    #[serde(default = "default_fragment_timeout")]
+    pub timeout_secs: u64,
+
+    /// Non-final fragments smaller than this (bytes) raise a tiny-fragment alert.
+    #[validate(range(max = 1280))]
+    #[serde(default = "default_min_fragment_size")]
+    pub min_fragment_size: usize,
+
+    /// Maximum fragments held for one datagram; more drop the datagram
+    /// and raise an alert.
+    #[validate(range(min = 2, max = 8192))]
+    #[serde(default = "default_max_fragments")]
+    pub max_fragments: usize,
+}
+
+/// How overlapping IP fragment data is resolved.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapPolicy {
+    /// The first copy of a byte wins (Windows, Solaris).
+    #[default]
+    First,
+    /// The most recent copy of a byte wins.
+    Last,
+    /// Original data wins unless the new fragment starts at a lower offset.
+    Bsd,
+    /// Like BSD, but a new fragment at the same offset also wins if longer.
+    Linux,
+}
+
+impl Default for DefragConfig {
+    fn default() -> Self {
+        Self {
+            overlap_policy: OverlapPolicy::default(),
+            max_datagrams: default_max_datagrams(),
+            max_buffer: default_max_fragment_buffer(),
+            timeout_secs: default_fragment_timeout(),
+            min_fragment_size: default_min_fragment_size(),
+            max_fragments: default_max_fragments(),
+        }
+    }
+}
+
+fn default_max_streams() -> usize {
+    16384
+}
+
+fn default_max_stream_buffer() -> usize {
+    1048576
+}
+
+fn default_max_total_buffer() -> usize {
+    67108864
+}
+
+fn default_stream_timeout() -> u64 {
+    120
+}
+
+fn default_gap_timeout() -> u64 {
+    10
+}
+
+fn default_max_flows() -> usize {
+    262144
+}
+
+fn default_flow_idle_timeout() -> u64 {
+    120
+}
+
+fn default_flow_active_timeout() -> u64 {
+    1800
+}
+
+fn default_max_datagrams() -> usize {
+    4096
+}
+
+fn default_max_fragment_buffer() -> usize {
+    16777216
+}
+
+fn default_fragment_timeout() -> u64 {
+    30
+}
+
+fn default_min_fragment_size() -> usize {
+    64
+}
+
+fn default_max_fragments() -> usize {
+    128
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum SizeValue {
@@ -65,7 +400,20 @@ fn deserialize_size<'de, D>(deserializer: D) -> Result<usize, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let sv = SizeValue::deserialize(deserializer)?;
+    parse_size(SizeValue::deserialize(deserializer)?)
+}
+
+/// [`deserialize_size`] for optional fields.
+fn deserialize_optional_size<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<SizeValue>::deserialize(deserializer)?
+        .map(parse_size)
+        .transpose()
+}
+
+fn parse_size<E: serde::de::Error>(sv: SizeValue) -> Result<usize, E> {
     match sv {
         SizeValue::Num(n) => Ok(n),
         SizeValue::Str(s) => {
@@ -79,13 +427,13 @@ where
                     unit_part.push(c);
                 }
             }
-            let number: f64 = num_part.parse().map_err(serde::de::Error::custom)?;
+            let number: f64 = num_part.parse().map_err(E::custom)?;
             let multiplier = match unit_part.to_lowercase().as_str() {
                 "kb" | "kib" => 1024.0,
                 "mb" | "mib" => 1024.0 * 1024.0,
                 "gb" | "gib" => 1024.0 * 1024.0 * 1024.0,
                 "" => 1.0,
-                _ => return Err(serde::de::Error::custom("Unknown size unit")),
+                _ => return Err(E::custom("Unknown size unit")),
             };
             Ok((number * multiplier) as usize)
         }
@@ -96,9 +444,135 @@ impl Default for CaptureConfig {
         Self {
             mode: "xdp".into(),
             interface: default_interface(),
+            interfaces: Vec::new(),
             promiscuous: default_promiscuous(),
             buffer_size: default_buffer_size(),
             max_latency_ms: default_latency(),
+            file_path: None,
+            replay_speed: None,
+            bpf_filter: None,
+            tcp_reassembly: TcpReassemblyConfig::default(),
+            defrag: DefragConfig::default(),
+            flows: FlowConfig::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use figment::providers::{Format, Yaml};
+    use figment::Figment;
+
+    #[test]
+    fn file_mode_requires_path() {
+        let mut config = CaptureConfig {
+            mode: "file".into(),
+            ..CaptureConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        config.file_path = Some("capture.pcapng".into());
+        config
+            .validate()
+            .expect("File mode with a path should be valid");
+    }
+
+    #[test]
+    fn rejects_non_positive_replay_speed() {
+        let config = CaptureConfig {
+            mode: "file".into(),
+            file_path: Some("capture.pcap".into()),
+            replay_speed: Some(0.0),
+            ..CaptureConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn reassembly_limits_accept_sizes() {
+        let config: TcpReassemblyConfig = Figment::from(Yaml::string(
+            "max_stream_buffer: \"256KiB\"\nmax_total_buffer: 8MiB",
+        ))
+        .extract()
+        .unwrap();
+        assert_eq!(config.max_stream_buffer, 262144);
+        assert_eq!(config.max_total_buffer, 8388608);
+        assert_eq!(config.max_streams, default_max_streams());
+        config
+            .validate()
+            .expect("Default reassembly limits should be valid");
+    }
+
+    #[test]
+    fn libpcap_validates_bpf_filter() {
+        let mut config = CaptureConfig {
+            bpf_filter: Some(
+                "tcp port 1883 or tcp port 8883 or udp port 5683 or tcp port 502".into(),
+            ),
+            ..CaptureConfig::default()
+        };
+        config.validate().expect("IoT port filter should compile");
+
+        config.bpf_filter = Some("tcp port banana".into());
+        let error = crate::ConfigError::from(config.validate().unwrap_err());
+        assert!(error
+            .to_string()
+            .contains("Invalid BPF filter 'tcp port banana'"));
+    }
+
+    #[test]
+    fn interface_overrides_fall_back_to_defaults() {
+        let config: CaptureConfig = Figment::from(Yaml::string(
+            "mode: af_packet\nbuffer_size: 4MiB\ninterfaces:\n  - name: eth0\n  - name: wlan0\n    promiscuous: false\n    buffer_size: 512KiB\n",
+        ))
+        .extract()
+        .unwrap();
+        config.validate().expect("Interface list should be valid");
+        assert_eq!(config.interface_names(), ["eth0", "wlan0"]);
+
+        let eth0 = config.for_interface("eth0");
+        assert_eq!(eth0.interface, "eth0");
+        assert!(eth0.promiscuous);
+        assert_eq!(eth0.buffer_size, 4194304);
+        assert!(eth0.interfaces.is_empty());
+
+        let wlan0 = config.for_interface("wlan0");
+        assert!(!wlan0.promiscuous);
+        assert_eq!(wlan0.buffer_size, 524288);
+    }
+
+    #[test]
+    fn rejects_duplicate_interfaces() {
+        let mut config = CaptureConfig::default();
+        assert_eq!(config.interface_names(), ["eth0"]);
+        config.interfaces = vec![InterfaceConfig::new("eth1"), InterfaceConfig::new("eth1")];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn active_timeout_must_cover_idle_timeout() {
+        let mut config = FlowConfig::default();
+        config
+            .validate()
+            .expect("Default flow config should be valid");
+        config.active_timeout_secs = 60;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_overlap_policy() {
+        use serde::de::value::{Error, StrDeserializer};
+
+        let policy = |name| OverlapPolicy::deserialize(StrDeserializer::<Error>::new(name));
+        assert_eq!(policy("bsd").unwrap(), OverlapPolicy::Bsd);
+        assert!(policy("windows").is_err());
+
+        let mut config = DefragConfig::default();
+        config
+            .validate()
+            .expect("Default defrag config should be valid");
+        config.max_fragments = 1;
+        assert!(config.validate().is_err());
+    }
+}
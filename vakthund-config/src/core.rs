@@ -34,13 +34,35 @@ pub struct EventBusConfig {
     #[serde(default = "default_true")]
     pub require_power_of_two: bool,
 
-    /// Number of consumers.
+    /// Number of flow-affine event processing workers.
+    #[validate(range(min = 1, max = 1024))]
     #[serde(default = "default_consumers")]
     pub num_consumers: u32,
 
-    /// Spin strategy for full queue (yield, spin_loop, or block).
+    /// Ring buffer concurrency model. Only `mpmc` is accepted: the bus is
+    /// shared between capture threads, workers and the simulator, where an
+    /// `spsc` ring would be unsound.
+    #[validate(custom(function = validation::validate_bus_mode))]
+    #[serde(default = "default_mode")]
+    pub mode: String,
+
+    /// How blocking producers wait on a full queue (yield, spin_loop, or block).
+    #[validate(custom(function = validation::validate_full_queue_strategy))]
     #[serde(default = "default_spin_strategy")]
     pub full_queue_strategy: String,
+
+    /// Capacity of the priority lane for control and alert messages (must be
+    /// a power of two).
+    #[serde(default = "default_priority_capacity")]
+    #[validate(range(min = 16, max = 65536))]
+    #[validate(custom(function = validation::validate_power_of_two))]
+    pub priority_capacity: usize,
+
+    /// Consecutive priority messages delivered before a waiting packet event
+    /// gets a turn.
+    #[serde(default = "default_starvation_limit")]
+    #[validate(range(min = 1, max = 65536))]
+    pub starvation_limit: u32,
 }
 
 fn default_capacity() -> usize {
@@ -55,17 +77,32 @@ fn default_consumers() -> u32 {
     num_cpus::get() as u32
 }
 
+fn default_mode() -> String {
+    "mpmc".into()
+}
+
 fn default_spin_strategy() -> String {
     "yield".into()
 }
 
+fn default_priority_capacity() -> usize {
+    256
+}
+
+fn default_starvation_limit() -> u32 {
+    32
+}
+
 impl Default for EventBusConfig {
     fn default() -> Self {
         Self {
             capacity: default_capacity(),
             require_power_of_two: default_true(),
             num_consumers: default_consumers(),
+            mode: default_mode(),
             full_queue_strategy: default_spin_strategy(),
+            priority_capacity: default_priority_capacity(),
+            starvation_limit: default_starvation_limit(),
         }
     }
 }
@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 use thiserror::Error;
-use validator::ValidationErrors;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
 /// Unified configuration error type.
 #[derive(Debug, Error)]
@@ -17,7 +17,7 @@ pub enum ConfigError {
 
     /// Figment parsing error.
     #[error("Configuration parsing error: {0}")]
-    Parsing(#[from] figment::Error),
+    Parsing(#[from] Box<figment::Error>),
 
     /// I/O error.
     #[error("Configuration I/O error: {0}")]
@@ -25,20 +25,49 @@ pub enum ConfigError {
 }
 
 fn format_validation_errors(errors: &ValidationErrors) -> String {
+    let mut output = String::new();
+    write_validation_errors(&mut output, "", errors);
+    output
+}
+
+/// Writes field errors, descending into nested sections with dotted paths
+/// (e.g. `capture.bpf_filter`).
+fn write_validation_errors(output: &mut String, prefix: &str, errors: &ValidationErrors) {
     use std::fmt::Write;
 
-    let mut output = String::new();
-    for (field, errors) in errors.field_errors() {
-        let _ = writeln!(output, "Field '{}':", field);
-        for error in errors {
-            let message = match &error.message {
-                Some(msg) => msg.to_string(),
-                None => error.code.to_string(),
-            };
-            let _ = writeln!(output, "  - {}", message);
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{prefix}.{field}")
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let _ = writeln!(output, "Field '{}':", path);
+                for error in errors {
+                    let message = match &error.message {
+                        Some(msg) => msg.to_string(),
+                        None => error.code.to_string(),
+                    };
+                    let _ = writeln!(output, "  - {}", message);
+                }
+            }
+            ValidationErrorsKind::Struct(nested) => {
+                write_validation_errors(output, &path, nested);
+            }
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    write_validation_errors(output, &format!("{path}[{index}]"), nested);
+                }
+            }
         }
     }
-    output
+}
+
+impl From<figment::Error> for ConfigError {
+    fn from(error: figment::Error) -> Self {
+        ConfigError::Parsing(Box::new(error))
+    }
 }
 
 impl From<ValidationErrors> for ConfigError {
@@ -31,13 +31,15 @@ mod simulator;
 mod telemetry;
 mod validation; // Add the new module
 
-pub use capture::CaptureConfig;
+pub use capture::{
+    CaptureConfig, DefragConfig, FlowConfig, InterfaceConfig, OverlapPolicy, TcpReassemblyConfig,
+};
 pub use core::CoreConfig;
 pub use core::EventBusConfig;
 pub use error::ConfigError;
-pub use monitor::MonitorConfig;
-pub use prevention::FirewallConfig;
+pub use monitor::{CoapExchangeConfig, MonitorConfig, MqttSessionConfig};
 pub use prevention::PreventionConfig;
+pub use prevention::{AutoBlockConfig, FirewallConfig};
 pub use provider::ConfigProvider;
 pub use simulator::ChaosConfig;
 pub use simulator::NetworkModelConfig;
@@ -132,7 +134,6 @@ impl VakthundConfig {
     pub fn load_with_provider(provider: &dyn ConfigProvider) -> Result<Self, ConfigError> {
         provider
             .load()
-            .map_err(ConfigError::from)
             .and_then(|figment| figment.extract().map_err(ConfigError::from))
             .and_then(|config: Self| {
                 config.validate()?;
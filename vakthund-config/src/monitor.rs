@@ -23,6 +23,16 @@ pub struct MonitorConfig {
     /// Alert destination configuration.
     #[validate(nested)]
     pub alerts: AlertConfig,
+
+    /// Stateful MQTT session tracking.
+    #[validate(nested)]
+    #[serde(default)]
+    pub mqtt_sessions: MqttSessionConfig,
+
+    /// CoAP exchange tracking and block-wise reassembly.
+    #[validate(nested)]
+    #[serde(default)]
+    pub coap_exchanges: CoapExchangeConfig,
 }
 
 /// Anomaly detection thresholds.
@@ -138,6 +148,124 @@ impl Default for AlertConfig {
     }
 }
 
+/// Bounds for MQTT session tracking and its protocol anomaly alerts.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct MqttSessionConfig {
+    /// Maximum number of tracked MQTT connections.
+    #[validate(range(min = 1, max = 1_000_000))]
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+
+    /// Unacknowledged publications per direction before alerting.
+    #[validate(range(min = 1, max = 65535))]
+    #[serde(default = "default_max_inflight")]
+    pub max_inflight: usize,
+
+    /// Time for a QoS 1 or 2 exchange to complete (seconds); stalled QoS 2
+    /// exchanges raise an alert.
+    #[validate(range(min = 1, max = 3600))]
+    #[serde(default = "default_ack_timeout")]
+    pub ack_timeout_secs: u64,
+
+    /// Time after which a silent connection without keep alive is
+    /// forgotten (seconds).
+    #[validate(range(min = 1, max = 86400))]
+    #[serde(default = "default_session_idle_timeout")]
+    pub idle_timeout_secs: u64,
+}
+
+fn default_max_sessions() -> usize {
+    10_000
+}
+fn default_max_inflight() -> usize {
+    1024
+}
+fn default_ack_timeout() -> u64 {
+    30
+}
+fn default_session_idle_timeout() -> u64 {
+    300
+}
+
+impl Default for MqttSessionConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: default_max_sessions(),
+            max_inflight: default_max_inflight(),
+            ack_timeout_secs: default_ack_timeout(),
+            idle_timeout_secs: default_session_idle_timeout(),
+        }
+    }
+}
+
+/// Bounds for CoAP exchange tracking and block-wise body reassembly.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct CoapExchangeConfig {
+    /// Maximum number of tracked CoAP endpoint pairs.
+    #[validate(range(min = 1, max = 1_000_000))]
+    #[serde(default = "default_max_endpoints")]
+    pub max_endpoints: usize,
+
+    /// Outstanding requests, observations and transfers per endpoint pair.
+    #[validate(range(min = 1, max = 65535))]
+    #[serde(default = "default_max_exchanges")]
+    pub max_exchanges: usize,
+
+    /// Largest block-wise body reassembled (bytes).
+    #[validate(range(min = 1024, max = 67_108_864))]
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+
+    /// Bytes held by block-wise transfers in progress, across all endpoint
+    /// pairs.
+    #[validate(range(min = 65536, max = 1_073_741_824))]
+    #[serde(default = "default_max_total_body_buffer")]
+    pub max_total_buffer: usize,
+
+    /// Time for a request to be answered or a transfer to continue (seconds).
+    #[validate(range(min = 1, max = 3600))]
+    #[serde(default = "default_exchange_timeout")]
+    pub exchange_timeout_secs: u64,
+
+    /// Time after which a silent endpoint pair and its observations are
+    /// forgotten (seconds).
+    #[validate(range(min = 1, max = 86400))]
+    #[serde(default = "default_endpoint_idle_timeout")]
+    pub idle_timeout_secs: u64,
+}
+
+fn default_max_endpoints() -> usize {
+    10_000
+}
+fn default_max_exchanges() -> usize {
+    64
+}
+fn default_max_body_size() -> usize {
+    1_048_576
+}
+fn default_max_total_body_buffer() -> usize {
+    16_777_216
+}
+fn default_exchange_timeout() -> u64 {
+    247
+}
+fn default_endpoint_idle_timeout() -> u64 {
+    3600
+}
+
+impl Default for CoapExchangeConfig {
+    fn default() -> Self {
+        Self {
+            max_endpoints: default_max_endpoints(),
+            max_exchanges: default_max_exchanges(),
+            max_body_size: default_max_body_size(),
+            max_total_buffer: default_max_total_body_buffer(),
+            exchange_timeout_secs: default_exchange_timeout(),
+            idle_timeout_secs: default_endpoint_idle_timeout(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
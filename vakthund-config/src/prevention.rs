@@ -29,6 +29,11 @@ pub struct PreventionConfig {
     /// Quarantine parameters.
     #[validate(nested)]
     pub quarantine: QuarantineConfig,
+
+    /// Which alerts block their client without operator confirmation.
+    #[validate(nested)]
+    #[serde(default)]
+    pub auto_block: AutoBlockConfig,
 }
 
 /// eBPF firewall configuration.
@@ -44,7 +49,7 @@ pub struct FirewallConfig {
     #[serde(default = "default_max_rules")]
     pub max_rules: usize,
 
-    /// Whitelisted IP ranges.
+    /// Whitelisted IP ranges, never blocked automatically.
     #[serde(default)]
     pub whitelist: Vec<IpNetwork>,
 }
@@ -56,6 +61,45 @@ fn default_max_rules() -> usize {
     10000
 }
 
+/// Automatic blocking in response to alerts. Off by default: a false
+/// positive would otherwise cut off a legitimate device.
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct AutoBlockConfig {
+    /// Block the client of alerts matching the conditions below.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Minimum severity of an alert that blocks.
+    #[validate(custom(function = validation::validate_severity))]
+    #[serde(default = "default_block_severity")]
+    pub min_severity: String,
+
+    /// Rule ids allowed to block; empty allows every rule.
+    #[serde(default)]
+    pub rules: Vec<u32>,
+
+    /// Also block UDP clients. Their source addresses are trivially
+    /// spoofed, so by default they are only blocked on an operator's
+    /// `Block` command.
+    #[serde(default)]
+    pub block_udp: bool,
+}
+
+fn default_block_severity() -> String {
+    "critical".into()
+}
+
+impl Default for AutoBlockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_severity: default_block_severity(),
+            rules: Vec::new(),
+            block_udp: false,
+        }
+    }
+}
+
 impl Default for FirewallConfig {
     fn default() -> Self {
         Self {
@@ -53,7 +53,7 @@ pub fn validate_severity(level: &str) -> Result<(), ValidationError> {
 
 /// Validate capture mode.
 pub fn validate_mode(mode: &str) -> Result<(), ValidationError> {
-    let re = regex::Regex::new("^(xdp|pcap|simulated)$")
+    let re = regex::Regex::new("^(xdp|pcap|af_packet|file|simulated)$")
         .map_err(|_| ValidationError::new("invalid_regex"))?;
     if re.is_match(mode) {
         Ok(())
@@ -61,3 +61,33 @@ pub fn validate_mode(mode: &str) -> Result<(), ValidationError> {
         Err(ValidationError::new("invalid_capture_mode"))
     }
 }
+
+/// Validate event bus full queue strategy.
+pub fn validate_full_queue_strategy(strategy: &str) -> Result<(), ValidationError> {
+    if ["yield", "spin_loop", "block"].contains(&strategy) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_full_queue_strategy"))
+    }
+}
+
+/// Validate event bus mode. The engine shares its bus between capture
+/// threads, workers and the simulator, so `spsc` is rejected.
+pub fn validate_bus_mode(mode: &str) -> Result<(), ValidationError> {
+    match mode {
+        "mpmc" => Ok(()),
+        "spsc" => Err(ValidationError::new("spsc_bus_is_shared")),
+        _ => Err(ValidationError::new("invalid_bus_mode")),
+    }
+}
+
+/// Validate a BPF capture filter by compiling it for Ethernet with libpcap.
+pub fn validate_bpf_filter(filter: &str) -> Result<(), ValidationError> {
+    pcap::Capture::dead(pcap::Linktype::ETHERNET)
+        .and_then(|capture| capture.compile(filter, true))
+        .map(|_| ())
+        .map_err(|e| {
+            ValidationError::new("invalid_bpf_filter")
+                .with_message(format!("Invalid BPF filter '{filter}': {e}").into())
+        })
+}
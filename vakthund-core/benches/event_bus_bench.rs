@@ -3,30 +3,73 @@ extern crate criterion;
 
 use bytes::Bytes;
 use criterion::{black_box, Criterion};
-use vakthund_core::events::{bus::EventBus, network::NetworkEvent};
+use vakthund_core::events::{
+    bus::{BusMode, BusOptions, EventBus},
+    network::NetworkEvent,
+};
 
 fn benchmark_event_bus_throughput(c: &mut Criterion) {
     let mut group = c.benchmark_group("event_bus_throughput");
 
-    for capacity in [128, 1024, 16384] {
-        group.throughput(criterion::Throughput::Elements(capacity as u64));
-        group.bench_function(format!("capacity_{}", capacity), |b| {
-            let event_bus = EventBus::with_capacity(capacity).unwrap();
-            let event = NetworkEvent {
-                timestamp: 0,
-                payload: Bytes::from_static(b"test_payload"),
-                source: None,
-                destination: None,
-            };
+    for (mode, name) in [(BusMode::Spsc, "spsc"), (BusMode::Mpmc, "mpmc")] {
+        for capacity in [128, 1024, 16384] {
+            group.throughput(criterion::Throughput::Elements(capacity as u64));
+            group.bench_function(format!("{}_capacity_{}", name, capacity), |b| {
+                let options = BusOptions {
+                    capacity,
+                    mode,
+                    ..BusOptions::default()
+                };
+                // SAFETY: the benchmark sends and receives on one thread.
+                let event_bus = unsafe { EventBus::with_options_unchecked(options) }.unwrap();
+                let event = NetworkEvent::new(0, Bytes::from_static(b"test_payload"));
+                b.iter(|| {
+                    // Use black_box to prevent over‑optimization.
+                    event_bus.send(black_box(event.clone())).unwrap();
+                    black_box(event_bus.recv().unwrap());
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Moves bursts of events through the bus one at a time and in batches.
+fn benchmark_event_bus_batching(c: &mut Criterion) {
+    let mut group = c.benchmark_group("event_bus_batching");
+    let event = NetworkEvent::new(0, Bytes::from_static(b"test_payload"));
+
+    for burst in [16, 64, 256] {
+        group.throughput(criterion::Throughput::Elements(burst as u64));
+        group.bench_function(format!("single_{}", burst), |b| {
+            let event_bus = EventBus::with_capacity(1024).unwrap();
+            b.iter(|| {
+                for _ in 0..burst {
+                    event_bus.send(black_box(event.clone())).unwrap();
+                }
+                for _ in 0..burst {
+                    black_box(event_bus.recv().unwrap());
+                }
+            });
+        });
+        group.bench_function(format!("batch_{}", burst), |b| {
+            let event_bus = EventBus::with_capacity(1024).unwrap();
+            let mut outgoing = Vec::with_capacity(burst);
+            let mut incoming = Vec::with_capacity(burst);
             b.iter(|| {
-                // Use black_box to prevent over‑optimization.
-                black_box(event_bus.send(event.clone()).unwrap());
-                black_box(event_bus.recv().unwrap());
+                outgoing.extend((0..burst).map(|_| black_box(event.clone())));
+                event_bus.send_batch(&mut outgoing).unwrap();
+                event_bus.recv_batch(&mut incoming, burst);
+                black_box(incoming.drain(..).count());
             });
         });
     }
     group.finish();
 }
 
-criterion_group!(benches, benchmark_event_bus_throughput);
+criterion_group!(
+    benches,
+    benchmark_event_bus_throughput,
+    benchmark_event_bus_batching
+);
 criterion_main!(benches);
@@ -7,7 +7,8 @@
 //! - Memory safety and deterministic behavior
 //!
 //! ### Key Submodules:
-//! - `pool/`: Fixed-size memory pools for common data structures
+//! - `pool/`: Fixed-size, lock-free memory pools for common data structures
+//! - `packet/`: Pooled packet buffers exposed as `Bytes`
 //! - `arena/`: Arena allocators using `bumpalo` for larger, temporary allocations
 //! - `stats/`: Memory usage tracking and statistics
 //!
@@ -16,5 +17,8 @@
 //! - Integration with hardware memory management units (MMUs)
 
 pub mod arena;
+pub mod packet;
 pub mod pool;
 pub mod stats;
+
+pub use packet::PacketPool;
@@ -0,0 +1,213 @@
+//! ## vakthund-core::alloc::packet
+//! **Pooled packet buffers**
+//!
+//! Fixed-size slabs for captured frames, preallocated in one block and
+//! recycled through the same lock-free free list as [`MemoryPool`]. A frame is
+//! copied into a free slab and handed out as `Bytes` that return the slab to
+//! the pool when the last clone is dropped, so frame data never touches the
+//! heap allocator once the pool is warm. Frames larger than a slab, or copied
+//! while every slab is in use, fall back to a heap copy and are counted.
+//!
+//! [`MemoryPool`]: super::pool::MemoryPool
+
+use super::pool::FreeList;
+use bytes::Bytes;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use vakthund_capture::PacketBuffers;
+
+/// Slab storage shared by a pool and every buffer handed out from it.
+struct Slabs {
+    memory: Box<[UnsafeCell<u8>]>,
+    slab_size: usize,
+    free: FreeList,
+    in_use: AtomicUsize,
+    fallbacks: AtomicU64,
+}
+
+// SAFETY: a slab is written only by the thread that popped it from the free
+// list, before any `Bytes` for it exists, and is read-only until it is pushed
+// back when that `Bytes` is dropped.
+unsafe impl Send for Slabs {}
+unsafe impl Sync for Slabs {}
+
+impl Slabs {
+    #[inline]
+    fn slab_ptr(&self, index: usize) -> *mut u8 {
+        // `UnsafeCell<u8>` has the layout of `u8`, and the pointer covers the
+        // whole allocation.
+        let base = self.memory.as_ptr() as *mut u8;
+        // SAFETY: `index` is below the slab count, so the offset is in bounds.
+        unsafe { base.add(index * self.slab_size) }
+    }
+}
+
+/// One occupied slab, owned by the `Bytes` exposing it.
+struct Slab {
+    slabs: Arc<Slabs>,
+    index: usize,
+    len: usize,
+}
+
+impl AsRef<[u8]> for Slab {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: the first `len` bytes of the slab were initialised before this
+        // `Slab` was created, and nothing writes to them until it is dropped.
+        unsafe { std::slice::from_raw_parts(self.slabs.slab_ptr(self.index), self.len) }
+    }
+}
+
+impl Drop for Slab {
+    fn drop(&mut self) {
+        self.slabs.free.push(self.index);
+        self.slabs.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A lock-free pool of fixed-size packet buffers.
+///
+/// Cloning the pool is cheap; clones share the same slabs.
+#[derive(Clone)]
+pub struct PacketPool {
+    slabs: Arc<Slabs>,
+}
+
+impl PacketPool {
+    /// Preallocates `capacity` slabs of `slab_size` bytes each.
+    ///
+    /// # Panics
+    /// If `slab_size` or `capacity` is zero.
+    pub fn new(slab_size: usize, capacity: usize) -> Self {
+        assert!(slab_size > 0, "Slab size must be greater than zero");
+        assert!(capacity > 0, "Capacity must be greater than zero");
+
+        let memory = vec![0u8; slab_size * capacity].into_boxed_slice();
+        // SAFETY: `UnsafeCell<u8>` is `repr(transparent)` over `u8`.
+        let memory = unsafe { Box::from_raw(Box::into_raw(memory) as *mut [UnsafeCell<u8>]) };
+        Self {
+            slabs: Arc::new(Slabs {
+                memory,
+                slab_size,
+                free: FreeList::new(capacity),
+                in_use: AtomicUsize::new(0),
+                fallbacks: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Copies `data` into a pooled slab, or onto the heap if it does not fit or
+    /// the pool is exhausted.
+    pub fn copy(&self, data: &[u8]) -> Bytes {
+        let slab = if data.len() <= self.slabs.slab_size {
+            self.slabs.free.pop()
+        } else {
+            None
+        };
+        let Some(index) = slab else {
+            self.slabs.fallbacks.fetch_add(1, Ordering::Relaxed);
+            return Bytes::copy_from_slice(data);
+        };
+
+        self.slabs.in_use.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: the slab was just popped from the free list, so no `Bytes`
+        // refers to it and no other thread can write to it.
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.slabs.slab_ptr(index), data.len());
+        }
+        Bytes::from_owner(Slab {
+            slabs: self.slabs.clone(),
+            index,
+            len: data.len(),
+        })
+    }
+
+    /// Size of one slab in bytes.
+    pub fn slab_size(&self) -> usize {
+        self.slabs.slab_size
+    }
+
+    /// Total number of slabs.
+    pub fn capacity(&self) -> usize {
+        self.slabs.memory.len() / self.slabs.slab_size
+    }
+
+    /// Number of slabs currently held by live `Bytes`.
+    pub fn in_use(&self) -> usize {
+        self.slabs.in_use.load(Ordering::Relaxed)
+    }
+
+    /// Number of copies that fell back to the heap.
+    pub fn fallbacks(&self) -> u64 {
+        self.slabs.fallbacks.load(Ordering::Relaxed)
+    }
+}
+
+impl PacketBuffers for PacketPool {
+    fn copy(&self, frame: &[u8]) -> Bytes {
+        PacketPool::copy(self, frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slabs_return_to_pool_on_drop() {
+        let pool = PacketPool::new(16, 2);
+        let first = pool.copy(b"first frame");
+        let clone = first.slice(0..5);
+        let second = pool.copy(b"second");
+        assert_eq!(pool.in_use(), 2);
+        assert_eq!(&first[..], b"first frame");
+        assert_eq!(&clone[..], b"first");
+        assert_eq!(&second[..], b"second");
+
+        drop(first);
+        assert_eq!(pool.in_use(), 2, "slice still holds the slab");
+        drop(clone);
+        drop(second);
+        assert_eq!(pool.in_use(), 0);
+        assert_eq!(pool.fallbacks(), 0);
+    }
+
+    #[test]
+    fn falls_back_to_heap_when_exhausted_or_oversized() {
+        let pool = PacketPool::new(4, 1);
+        let held = pool.copy(b"abcd");
+        let overflow = pool.copy(b"efgh");
+        let oversized = pool.copy(b"too long");
+        assert_eq!(&overflow[..], b"efgh");
+        assert_eq!(&oversized[..], b"too long");
+        assert_eq!(pool.fallbacks(), 2);
+        assert_eq!(pool.in_use(), 1);
+
+        drop(held);
+        let reused = pool.copy(b"ijkl");
+        assert_eq!(&reused[..], b"ijkl");
+        assert_eq!(pool.fallbacks(), 2);
+    }
+
+    #[test]
+    fn buffers_can_be_released_on_other_threads() {
+        let pool = PacketPool::new(64, 32);
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200u32 {
+                        let frame = [thread as u8; 8];
+                        let data = pool.copy(&frame);
+                        let sent = std::thread::spawn(move || data);
+                        assert_eq!(&sent.join().unwrap()[..], &frame, "iteration {i}");
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(pool.in_use(), 0);
+    }
+}
@@ -2,60 +2,130 @@
 //! **Fixed-size memory pools**
 //!
 //! This module implements fixed-size memory pools for efficient allocation
-//! and deallocation of objects of the same size.
+//! and deallocation of objects of the same size. All slots are allocated up
+//! front; free slots are tracked in a lock-free [`FreeList`], so allocation and
+//! deallocation never take a lock.
+use std::cell::UnsafeCell;
 use std::mem::MaybeUninit;
 use std::ptr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+/// Index marking the end of the free list.
+const NIL: u32 = u32::MAX;
+
+/// Lock-free stack of free slot indices (a Treiber stack).
+///
+/// The head packs a modification tag into its upper 32 bits, so a pop racing
+/// with a pop/push pair of the same index (ABA) fails its compare-exchange.
+pub(crate) struct FreeList {
+    head: AtomicU64,
+    next: Box<[AtomicU32]>,
+}
+
+#[inline]
+fn pack(tag: u32, index: u32) -> u64 {
+    (u64::from(tag) << 32) | u64::from(index)
+}
+
+#[inline]
+fn unpack(head: u64) -> (u32, u32) {
+    ((head >> 32) as u32, head as u32)
+}
+
+impl FreeList {
+    /// Creates a list holding every index in `0..capacity`, lowest on top.
+    ///
+    /// # Panics
+    /// If `capacity` does not fit in a `u32` index.
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity < NIL as usize, "Capacity exceeds pool index range");
+        let next = (0..capacity)
+            .map(|index| {
+                let next = index + 1;
+                AtomicU32::new(if next == capacity { NIL } else { next as u32 })
+            })
+            .collect();
+        let first = if capacity == 0 { NIL } else { 0 };
+        Self {
+            head: AtomicU64::new(pack(0, first)),
+            next,
+        }
+    }
+
+    /// Takes a free index, or `None` if every index is in use.
+    pub(crate) fn pop(&self) -> Option<usize> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let (tag, index) = unpack(head);
+            if index == NIL {
+                return None;
+            }
+            let next = self.next[index as usize].load(Ordering::Relaxed);
+            match self.head.compare_exchange_weak(
+                head,
+                pack(tag.wrapping_add(1), next),
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(index as usize),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Returns `index` to the list. The caller must own it (from [`Self::pop`]).
+    pub(crate) fn push(&self, index: usize) {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let (tag, top) = unpack(head);
+            self.next[index].store(top, Ordering::Relaxed);
+            match self.head.compare_exchange_weak(
+                head,
+                pack(tag.wrapping_add(1), index as u32),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// A contiguous run of `chunk_size` slots.
+type Chunk<T> = Box<[UnsafeCell<MaybeUninit<T>>]>;
 
 pub struct MemoryPool<T> {
     chunk_size: usize,
-    chunks: Mutex<Vec<Box<[MaybeUninit<T>]>>>,
-    free_indices: Mutex<Vec<usize>>,
+    chunks: Box<[Chunk<T>]>,
+    free: FreeList,
     allocated_count: AtomicUsize,
     capacity: usize,
 }
 
+// SAFETY: a slot is only accessed through the `PoolPtr` that popped its index
+// from the free list, so slots are never shared between threads.
+unsafe impl<T: Send> Send for MemoryPool<T> {}
+unsafe impl<T: Send> Sync for MemoryPool<T> {}
+
 impl<T> MemoryPool<T> {
     pub fn new(chunk_size: usize, capacity: usize) -> Self {
         assert!(chunk_size > 0, "Chunk size must be greater than zero");
         assert!(capacity > 0, "Capacity must be greater than zero");
 
-        println!(
-            "MemoryPool::new: chunk_size={}, capacity={}",
-            chunk_size, capacity
-        ); // Debug Print
-
-        let num_chunks = (capacity + chunk_size - 1) / chunk_size;
-        println!("MemoryPool::new: num_chunks={}", num_chunks); // Debug Print
-        let mut chunks = Vec::with_capacity(num_chunks);
-        let mut free_indices = Vec::with_capacity(capacity);
-
-        println!(
-            "MemoryPool::new: Initial chunks.capacity()={}, free_indices.capacity()={}",
-            chunks.capacity(),
-            free_indices.capacity()
-        ); // Debug Print
-
-        for _ in 0..num_chunks {
-            let mut vec = Vec::with_capacity(chunk_size);
-            vec.resize_with(chunk_size, || MaybeUninit::uninit());
-            chunks.push(vec.into_boxed_slice());
-        }
-        println!("MemoryPool::new: After chunk resize, chunks.len()={}, chunks[0].len() (if chunks not empty)={}", chunks.len(), chunks.first().map_or(0, |c| c.len()));
-
-        for i in 0..capacity {
-            free_indices.push(i);
-        }
-        println!(
-            "MemoryPool::new: After free_indices push, free_indices.len()={}",
-            free_indices.len()
-        ); // Debug Print
+        let num_chunks = capacity.div_ceil(chunk_size);
+        let chunks = (0..num_chunks)
+            .map(|_| {
+                (0..chunk_size)
+                    .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                    .collect()
+            })
+            .collect();
 
         Self {
             chunk_size,
-            chunks: Mutex::new(chunks),
-            free_indices: Mutex::new(free_indices),
+            chunks,
+            free: FreeList::new(capacity),
             allocated_count: AtomicUsize::new(0),
             capacity,
         }
@@ -63,26 +133,21 @@ impl<T> MemoryPool<T> {
 
     /// Allocates an object from the memory pool.
     /// Returns `None` if the pool is full.
-    pub fn allocate(&self) -> Option<PoolPtr<T>> {
-        let mut free_indices_lock = self.free_indices.lock().unwrap();
-        if let Some(index) = free_indices_lock.pop() {
-            self.allocated_count.fetch_add(1, Ordering::Relaxed);
-            Some(PoolPtr::new(self, index))
-        } else {
-            None // Pool is full
-        }
+    pub fn allocate(&self) -> Option<PoolPtr<'_, T>> {
+        let index = self.free.pop()?;
+        self.allocated_count.fetch_add(1, Ordering::Relaxed);
+        Some(PoolPtr::new(self, index))
     }
 
-    /// Deallocates an object back to the memory pool.
+    /// Deallocates an object back to the memory pool. Equivalent to dropping
+    /// the `PoolPtr`.
     ///
     /// # Safety
     ///
     /// The `PoolPtr` must be valid and associated with this `MemoryPool`.
     pub unsafe fn deallocate(&self, ptr: PoolPtr<T>) {
-        let index = ptr.index;
-        // Simplified lock acquisition and usage:
-        self.free_indices.lock().unwrap().push(index);
-        self.allocated_count.fetch_sub(1, Ordering::Relaxed);
+        debug_assert!(ptr::eq(ptr.pool, self), "PoolPtr from another pool");
+        drop(ptr);
     }
 
     /// Returns the current number of allocated objects in the pool.
@@ -100,14 +165,17 @@ impl<T> MemoryPool<T> {
         self.chunk_size
     }
 
-    // Helper function to get a mutable reference to the memory location for a given index
+    // Helper function to get a mutable pointer to the memory location for a given index
     #[inline]
     fn get_memory_location_mut(&self, index: usize) -> *mut T {
-        let chunk_index = index / self.chunk_size;
-        let offset_in_chunk = index % self.chunk_size;
-        let mut chunks_lock = self.chunks.lock().unwrap();
-        let chunk = &mut chunks_lock[chunk_index];
-        chunk[offset_in_chunk].as_mut_ptr() as *mut T // Cast MaybeUninit<T>* to T*
+        let chunk = &self.chunks[index / self.chunk_size];
+        chunk[index % self.chunk_size].get().cast()
+    }
+
+    #[inline]
+    fn release(&self, index: usize) {
+        self.free.push(index);
+        self.allocated_count.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -162,19 +230,25 @@ impl<'pool, T> PoolPtr<'pool, T> {
 
 impl<'pool, T> Drop for PoolPtr<'pool, T> {
     fn drop(&mut self) {
-        // Directly deallocate using the pool's internals
-        self.pool.free_indices.lock().unwrap().push(self.index);
-        self.pool.allocated_count.fetch_sub(1, Ordering::Relaxed);
+        self.pool.release(self.index);
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
 
     #[test]
     fn test_memory_pool_allocate_deallocate() {
         let pool: MemoryPool<u32> = MemoryPool::new(10, 20);
-        let _ptr1 = pool.allocate().unwrap();
+        let ptr1 = pool.allocate().unwrap();
+        unsafe {
+            ptr1.write(7);
+            assert_eq!(ptr1.read(), 7);
+            pool.deallocate(ptr1);
+        }
+        assert_eq!(pool.allocated_count(), 0);
     }
 
     #[test]
@@ -191,6 +265,9 @@ mod tests {
         assert!(pool.allocate().is_none()); // Pool is full
 
         // allocations are dropped here, triggering deallocation.
+        allocations.clear();
+        assert_eq!(pool.allocated_count(), 0);
+        assert!(pool.allocate().is_some());
     }
 
     #[test]
@@ -204,4 +281,38 @@ mod tests {
     fn test_memory_pool_zero_capacity() {
         MemoryPool::<u32>::new(10, 0);
     }
+
+    #[test]
+    fn test_memory_pool_concurrent_slots_are_exclusive() {
+        const THREADS: usize = 4;
+        const CAPACITY: usize = 8;
+        let pool = Arc::new(MemoryPool::<usize>::new(4, CAPACITY));
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        let Some(slot) = pool.allocate() else {
+                            continue;
+                        };
+                        // A slot shared with another thread would be overwritten.
+                        unsafe {
+                            slot.write(thread);
+                            std::hint::spin_loop();
+                            assert_eq!(slot.read(), thread);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(pool.allocated_count(), 0);
+        let slots: Vec<_> = (0..CAPACITY).map(|_| pool.allocate().unwrap()).collect();
+        let distinct: HashSet<_> = slots.iter().map(|slot| slot.index).collect();
+        assert_eq!(distinct.len(), CAPACITY);
+    }
 }
@@ -11,6 +11,9 @@ pub enum SimulationError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Capture error: {0}")]
+    Capture(#[from] vakthund_capture::CaptureError),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
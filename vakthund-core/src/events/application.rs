@@ -0,0 +1,121 @@
+//! Parsed application-layer messages.
+
+use super::network::{five_tuple, NetworkEvent, TransportProtocol};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use vakthund_detection::alert::ScanContext;
+
+/// Application protocols Vakthund parses.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ApplicationProtocol {
+    Mqtt,
+    Coap,
+    ModbusTcp,
+}
+
+impl ApplicationProtocol {
+    /// Protocol name as used in logs and metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            ApplicationProtocol::Mqtt => "MQTT",
+            ApplicationProtocol::Coap => "CoAP",
+            ApplicationProtocol::ModbusTcp => "Modbus",
+        }
+    }
+}
+
+/// One application message parsed from a network event.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApplicationEvent {
+    /// Timestamp of the packet the message was parsed from
+    pub timestamp: u64,
+    pub protocol: ApplicationProtocol,
+    /// MQTT control packet type, CoAP code or Modbus function code
+    pub message_type: u8,
+    /// Message body (MQTT payload, CoAP payload or Modbus data), sharing the
+    /// packet's buffer
+    #[serde(with = "Bytes")]
+    pub body: Bytes,
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+    #[serde(default)]
+    pub transport: Option<TransportProtocol>,
+    #[serde(default)]
+    pub interface: Option<Arc<str>>,
+    /// Endpoint that opened the connection, if known
+    #[serde(default)]
+    pub initiator: Option<SocketAddr>,
+}
+
+impl ApplicationEvent {
+    /// Creates the message parsed from `event`, sharing its buffer. Returns
+    /// `None` if `body` is not part of `event.payload`.
+    pub fn parsed_from(
+        event: &NetworkEvent,
+        protocol: ApplicationProtocol,
+        message_type: u8,
+        body: &[u8],
+    ) -> Option<Self> {
+        let payload = event.payload.as_ptr_range();
+        let range = body.as_ptr_range();
+        let body = if body.is_empty() {
+            Bytes::new()
+        } else if payload.start <= range.start && range.end <= payload.end {
+            event.payload.slice_ref(body)
+        } else {
+            return None;
+        };
+        Some(Self {
+            timestamp: event.timestamp,
+            protocol,
+            message_type,
+            body,
+            source: event.source,
+            destination: event.destination,
+            transport: event.protocol,
+            interface: event.interface.clone(),
+            initiator: event.initiator,
+        })
+    }
+
+    /// Context for inspecting the message body, so alerts carry its
+    /// addressing.
+    pub fn scan_context(&self) -> ScanContext {
+        ScanContext {
+            timestamp: self.timestamp,
+            protocol: self.protocol.name(),
+            flow: five_tuple(self.source, self.destination, self.transport),
+            initiator: self.initiator,
+            interface: self.interface.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_the_payload_or_rejects_foreign_bodies() {
+        let event = NetworkEvent::new(1, Bytes::from_static(b"\x30\x05topic"));
+        let message = ApplicationEvent::parsed_from(
+            &event,
+            ApplicationProtocol::Mqtt,
+            3,
+            &event.payload[2..],
+        )
+        .unwrap();
+        assert_eq!(message.body, "topic");
+        assert_eq!(message.body.as_ptr(), event.payload[2..].as_ptr());
+
+        let copy = event.payload.to_vec();
+        assert!(
+            ApplicationEvent::parsed_from(&event, ApplicationProtocol::Mqtt, 3, &copy[2..])
+                .is_none()
+        );
+        let empty = ApplicationEvent::parsed_from(&event, ApplicationProtocol::Mqtt, 12, &[]);
+        assert!(empty.unwrap().body.is_empty());
+    }
+}
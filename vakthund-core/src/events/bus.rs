@@ -1,47 +1,156 @@
 //! Thread-safe event bus implementation for high-frequency messaging.
 //!
-//! This module provides a lock-free, single-producer single-consumer (SPSC) event bus
-//! using a circular buffer and atomic operations.
+//! This module provides a lock-free event bus using a circular buffer and atomic
+//! operations. The ring is chosen per bus with [`BusMode`]: a multi-producer
+//! multi-consumer (MPMC) ring with per-slot sequence numbers, safe to share between
+//! any number of threads, or a single-producer single-consumer (SPSC) ring for
+//! pipelines that guarantee one thread on each side. Only the MPMC ring can be
+//! built safely; an SPSC bus comes from the `unsafe`
+//! [`EventBus::with_options_unchecked`], whose caller upholds that guarantee.
 //!
 //! Inspired by LMAX Disruptor pattern with optimizations for:
-//! - Single Producer Single Consumer (SPSC) workloads
 //! - Cache-line aware data layout
 //! - Backpressure signaling
+//!
+//! Besides packet events the bus carries control commands and alerts as an
+//! [`Envelope`]. These travel on a separate priority lane that consumers of
+//! [`EventBus::recv_envelope`] drain first, so a packet flood cannot delay them. A
+//! starvation guard still hands out one packet event after every
+//! `starvation_limit` consecutive priority messages.
+//!
+//! Producers that must not drop events use [`EventBus::send_blocking`], which waits
+//! for space according to the bus's [`FullQueueStrategy`]. Consumers can poll with
+//! [`EventBus::recv`] or await [`EventBus::recv_async`], which is woken as soon as an
+//! event is published.
 
+use super::envelope::{Envelope, Priority};
 use super::network::NetworkEvent;
-use once_cell::sync::Lazy;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use super::ring::{MpmcRing, Ring, SpscRing};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
 use thiserror::Error;
-use tracing::error;
+use tokio::sync::Notify;
+use tracing::{debug, error};
 
 /// Event bus error conditions.
 #[derive(Error, Debug)]
 pub enum EventError {
     #[error("Event queue capacity exceeded")]
     QueueFull,
+    #[error("Event bus is closed")]
+    Closed,
+    #[error("{0} events were not processed")]
+    Unprocessed(u64),
     #[error("Invalid capacity (must be a power of two)")]
     InvalidCapacity,
+    #[error("SPSC event buses can only be created with EventBus::with_options_unchecked")]
+    UncheckedMode,
 }
 
-/// Cache-line aligned atomic counter to prevent false sharing
-#[repr(align(64))]
-#[derive(Debug)]
-struct AlignedCounter(AtomicU64);
+/// Concurrency model of the bus's ring buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BusMode {
+    /// One producer and one consumer at a time. Sharing the bus between
+    /// several senders or receivers is undefined behaviour, so this mode is
+    /// only available through [`EventBus::with_options_unchecked`].
+    Spsc,
+    /// Any number of concurrent producers and consumers.
+    #[default]
+    Mpmc,
+}
 
-impl AlignedCounter {
-    #[inline]
-    fn new(value: u64) -> Self {
-        Self(AtomicU64::new(value))
+impl FromStr for BusMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spsc" => Ok(BusMode::Spsc),
+            "mpmc" => Ok(BusMode::Mpmc),
+            other => Err(format!("Unknown event bus mode '{other}'")),
+        }
+    }
+}
+
+/// How a blocking producer waits for space when the queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FullQueueStrategy {
+    /// Yield the thread to the scheduler between attempts.
+    #[default]
+    Yield,
+    /// Busy-spin with a CPU pause hint. Lowest latency, burns a core.
+    SpinLoop,
+    /// Sleep until a consumer frees a slot.
+    Block,
+}
+
+impl FromStr for FullQueueStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yield" => Ok(FullQueueStrategy::Yield),
+            "spin_loop" => Ok(FullQueueStrategy::SpinLoop),
+            "block" => Ok(FullQueueStrategy::Block),
+            other => Err(format!("Unknown full queue strategy '{other}'")),
+        }
+    }
+}
+
+/// Upper bound on one `Block` wait, so a missed wakeup only costs latency.
+const BLOCK_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Construction parameters for [`EventBus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusOptions {
+    /// Slots in the packet lane (power of two).
+    pub capacity: usize,
+    pub mode: BusMode,
+    pub strategy: FullQueueStrategy,
+    /// Slots in the priority lane (power of two).
+    pub priority_capacity: usize,
+    /// Consecutive priority messages delivered before a waiting packet event
+    /// gets a turn.
+    pub starvation_limit: u32,
+}
+
+impl Default for BusOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            mode: BusMode::default(),
+            strategy: FullQueueStrategy::default(),
+            priority_capacity: 256,
+            starvation_limit: 32,
+        }
+    }
+}
+
+fn new_ring<T>(mode: BusMode, capacity: usize) -> Ring<T> {
+    match mode {
+        BusMode::Spsc => Ring::Spsc(SpscRing::new(capacity)),
+        BusMode::Mpmc => Ring::Mpmc(MpmcRing::new(capacity)),
     }
 }
 
 #[derive(Debug)]
 struct InnerBus {
-    buffer: Box<[std::cell::UnsafeCell<Option<NetworkEvent>>]>,
-    head: AlignedCounter,
-    tail: AlignedCounter,
-    mask: usize,
+    ring: Ring<NetworkEvent>,
+    /// Control and alert messages, drained before `ring`.
+    priority: Ring<Envelope>,
+    starvation_limit: u32,
+    /// Priority messages delivered since the last packet event.
+    priority_streak: AtomicU32,
+    mode: BusMode,
+    closed: AtomicBool,
+    strategy: FullQueueStrategy,
+    /// Wakes consumers waiting in `recv_async`.
+    published: Notify,
+    /// Producers sleeping in `send_blocking` under the `Block` strategy.
+    blocked_producers: AtomicUsize,
+    space_lock: Mutex<()>,
+    space_available: Condvar,
 }
 
 /// Thread-safe event bus for high-frequency messaging
@@ -57,21 +166,66 @@ impl EventBus {
     ///
     /// * `capacity` - Must be a power of two for efficient modulo operations.
     pub fn with_capacity(capacity: usize) -> Result<Self, EventError> {
-        if !capacity.is_power_of_two() {
-            return Err(EventError::InvalidCapacity);
+        Self::with_strategy(capacity, FullQueueStrategy::default())
+    }
+
+    /// Creates new event bus whose blocking producers wait using `strategy`.
+    pub fn with_strategy(capacity: usize, strategy: FullQueueStrategy) -> Result<Self, EventError> {
+        Self::with_mode(capacity, BusMode::default(), strategy)
+    }
+
+    /// Creates new event bus built on the ring selected by `mode`.
+    ///
+    /// Fails with [`EventError::UncheckedMode`] for [`BusMode::Spsc`].
+    pub fn with_mode(
+        capacity: usize,
+        mode: BusMode,
+        strategy: FullQueueStrategy,
+    ) -> Result<Self, EventError> {
+        Self::with_options(BusOptions {
+            capacity,
+            mode,
+            strategy,
+            ..BusOptions::default()
+        })
+    }
+
+    /// Creates new event bus from `options`.
+    ///
+    /// Fails with [`EventError::UncheckedMode`] for [`BusMode::Spsc`].
+    pub fn with_options(options: BusOptions) -> Result<Self, EventError> {
+        if options.mode == BusMode::Spsc {
+            return Err(EventError::UncheckedMode);
         }
+        // SAFETY: an MPMC bus may be shared between any number of threads.
+        unsafe { Self::with_options_unchecked(options) }
+    }
 
-        let buffer = (0..capacity)
-            .map(|_| std::cell::UnsafeCell::new(None))
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+    /// Creates new event bus from `options`, including SPSC buses.
+    ///
+    /// # Safety
+    ///
+    /// With [`BusMode::Spsc`], at most one thread may send on the bus and at
+    /// most one thread may receive from it at any time, counting every handle
+    /// obtained through [`Self::share`] or an `Arc`.
+    pub unsafe fn with_options_unchecked(options: BusOptions) -> Result<Self, EventError> {
+        if !options.capacity.is_power_of_two() || !options.priority_capacity.is_power_of_two() {
+            return Err(EventError::InvalidCapacity);
+        }
 
         Ok(Self {
             inner: Arc::new(InnerBus {
-                buffer,
-                head: AlignedCounter::new(0),
-                tail: AlignedCounter::new(0),
-                mask: capacity - 1,
+                ring: new_ring(options.mode, options.capacity),
+                priority: new_ring(options.mode, options.priority_capacity),
+                starvation_limit: options.starvation_limit.max(1),
+                priority_streak: AtomicU32::new(0),
+                mode: options.mode,
+                closed: AtomicBool::new(false),
+                strategy: options.strategy,
+                published: Notify::new(),
+                blocked_producers: AtomicUsize::new(0),
+                space_lock: Mutex::new(()),
+                space_available: Condvar::new(),
             }),
         })
     }
@@ -84,43 +238,39 @@ impl EventBus {
         }
     }
 
+    /// Ring buffer this bus was built on.
+    #[inline]
+    pub fn mode(&self) -> BusMode {
+        self.inner.mode
+    }
+
     /// Attempts to send event onto the bus.
-    ///
-    /// # Safety
-    ///
-    /// Uses unsafe code for interior mutability guarded by atomic counters.
     #[inline]
     pub fn send(&self, event: NetworkEvent) -> Result<(), EventError> {
-        // Check if bus is closed
-        if Self::closed().load(Ordering::Acquire) {
-            return Err(EventError::QueueFull);
+        if self.is_closed() {
+            return Err(EventError::Closed);
         }
 
-        let head = self.inner.head.0.load(Ordering::Relaxed);
-        let tail = self.inner.tail.0.load(Ordering::Acquire);
-
-        if head - tail >= self.inner.buffer.len() as u64 {
+        if !self.inner.ring.push(event) {
             return Err(EventError::QueueFull);
         }
-
-        // SAFETY: Exclusive write access ensured by atomic counters
-        unsafe {
-            let index = head & self.inner.mask as u64;
-            *self.inner.buffer[index as usize].get() = Some(event);
-        }
-
-        self.inner.head.0.store(head + 1, Ordering::Release);
+        self.inner.published.notify_one();
         Ok(())
     }
 
-    /// Send event to event bus, blocks if queue is full.
+    /// Send event to event bus, waiting according to the bus's
+    /// [`FullQueueStrategy`] while the queue is full.
+    ///
+    /// Gives up, dropping the event, once the bus is closed.
     #[inline]
     pub fn send_blocking(&self, event: NetworkEvent) {
         loop {
             match self.send(event.clone()) {
                 Ok(_) => break,
-                Err(EventError::QueueFull) => {
-                    std::thread::yield_now();
+                Err(EventError::QueueFull) => self.wait_for_space(),
+                Err(EventError::Closed) => {
+                    debug!("Event bus closed, dropping event");
+                    break;
                 }
                 Err(e) => {
                     error!("Unexpected error during blocking push: {:?}", e);
@@ -130,84 +280,228 @@ impl EventBus {
         }
     }
 
+    fn wait_for_space(&self) {
+        match self.inner.strategy {
+            FullQueueStrategy::Yield => std::thread::yield_now(),
+            FullQueueStrategy::SpinLoop => std::hint::spin_loop(),
+            FullQueueStrategy::Block => {
+                self.inner.blocked_producers.fetch_add(1, Ordering::SeqCst);
+                let guard = self
+                    .inner
+                    .space_lock
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                // Re-check under the lock; consumers notify while holding it.
+                if self.is_full() && !self.is_closed() {
+                    let _ = self
+                        .inner
+                        .space_available
+                        .wait_timeout(guard, BLOCK_TIMEOUT);
+                }
+                self.inner.blocked_producers.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner.ring.len() >= self.inner.ring.capacity() as u64
+    }
+
     /// Attempts to receive a event from the bus.
     ///
     /// Returns `None` if the queue is empty.
     #[inline]
     pub fn recv(&self) -> Option<NetworkEvent> {
-        let tail = self.inner.tail.0.load(Ordering::Relaxed);
-        let head = self.inner.head.0.load(Ordering::Acquire);
+        let event = self.inner.ring.pop()?;
+        self.wake_blocked_producers();
+        Some(event)
+    }
 
-        if head == tail {
-            return None;
+    /// Publishes events from the front of `events` with a single index update and
+    /// removes them from the vector. Events that do not fit stay in `events`.
+    ///
+    /// Returns how many events were queued.
+    pub fn send_batch(&self, events: &mut Vec<NetworkEvent>) -> Result<usize, EventError> {
+        if self.is_closed() {
+            return Err(EventError::Closed);
         }
 
-        // SAFETY: Exclusive read access ensured by atomic counters
-        let event = unsafe {
-            let idx = (tail as usize) & self.inner.mask;
-            (*self.inner.buffer[idx].get()).take()
-        };
+        let count = self.inner.ring.push_batch(events);
+        if count > 0 {
+            self.inner.published.notify_one();
+        }
+        Ok(count)
+    }
 
-        self.inner.tail.0.store(tail + 1, Ordering::Release);
-        event
+    /// Moves up to `max` queued events into `out` with a single index update.
+    ///
+    /// Returns how many events were received.
+    pub fn recv_batch(&self, out: &mut Vec<NetworkEvent>, max: usize) -> usize {
+        let count = self.inner.ring.pop_batch(out, max);
+        if count > 0 {
+            self.wake_blocked_producers();
+        }
+        count
     }
 
-    /// Closes the event bus, preventing new events from being sent.
-    pub fn close(&self) {
-        Self::closed().store(true, Ordering::Release);
+    fn wake_blocked_producers(&self) {
+        if self.inner.blocked_producers.load(Ordering::SeqCst) > 0 {
+            let _guard = self
+                .inner
+                .space_lock
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            self.inner.space_available.notify_all();
+        }
     }
 
-    /// Verifies that all events have been processed.
-    pub fn verify_completion(&self) -> Result<(), EventError> {
-        // Check if bus is closed
-        if Self::closed().load(Ordering::Acquire) {
+    /// Receives the next event, waiting until one is published.
+    ///
+    /// Returns `None` once the bus is closed and drained.
+    pub async fn recv_async(&self) -> Option<NetworkEvent> {
+        self.wait_for(Self::recv).await
+    }
+
+    /// Queues `envelope` on the lane for its [`Priority`].
+    pub fn send_envelope(&self, envelope: Envelope) -> Result<(), EventError> {
+        let envelope = match envelope {
+            Envelope::Network(event) => return self.send(event),
+            other => other,
+        };
+        debug_assert_eq!(envelope.priority(), Priority::High);
+        if self.is_closed() {
+            return Err(EventError::Closed);
+        }
+        // Parsed messages arrive at packet rate; keep room for the rest.
+        if matches!(envelope, Envelope::Application(_))
+            && self.inner.priority.len() as usize >= self.inner.priority.capacity() / 2
+        {
+            return Err(EventError::QueueFull);
+        }
+
+        if !self.inner.priority.push(envelope) {
             return Err(EventError::QueueFull);
         }
+        self.inner.published.notify_one();
+        Ok(())
+    }
 
-        // Get current head and tail
-        let head = self.inner.head.0.load(Ordering::Acquire);
-        let tail = self.inner.tail.0.load(Ordering::Acquire);
+    /// Receives the next message from either lane, priority lane first.
+    ///
+    /// Returns `None` if both lanes are empty.
+    pub fn recv_envelope(&self) -> Option<Envelope> {
+        // Counted up front, so concurrent consumers cannot both read the same
+        // streak and let the priority lane run past the limit.
+        let streak = self.inner.priority_streak.fetch_add(1, Ordering::Relaxed);
+        if streak < self.inner.starvation_limit || self.inner.ring.len() == 0 {
+            if let Some(envelope) = self.inner.priority.pop() {
+                return Some(envelope);
+            }
+        }
+
+        // Packet events have waited long enough, or no priority message is queued.
+        self.inner.priority_streak.swap(0, Ordering::Relaxed);
+        self.recv()
+            .map(Envelope::Network)
+            .or_else(|| self.inner.priority.pop())
+    }
+
+    /// Receives the next message from either lane, waiting until one is
+    /// published.
+    ///
+    /// Returns `None` once the bus is closed and drained.
+    pub async fn recv_envelope_async(&self) -> Option<Envelope> {
+        self.wait_for(Self::recv_envelope).await
+    }
+
+    async fn wait_for<T>(&self, recv: impl Fn(&Self) -> Option<T>) -> Option<T> {
+        loop {
+            // Registered before the checks below, so the `notify_waiters` in
+            // `close` cannot slip in between them and the wait.
+            let notified = self.inner.published.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(item) = recv(self) {
+                return Some(item);
+            }
+            if self.is_closed() {
+                // Catch a message published just before the close.
+                return recv(self);
+            }
+            notified.await;
+        }
+    }
+
+    /// Closes the event bus. Further sends fail with [`EventError::Closed`];
+    /// events already queued can still be received.
+    pub fn close(&self) {
+        self.inner.closed.store(true, Ordering::Release);
+        // Every waiting consumer has to see the close, not just one of them;
+        // the stored permit covers a consumer about to wait.
+        self.inner.published.notify_waiters();
+        self.inner.published.notify_one();
+        let _guard = self
+            .inner
+            .space_lock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        self.inner.space_available.notify_all();
+    }
+
+    /// Returns true once the bus has been closed.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::Acquire)
+    }
+
+    /// Number of events and priority messages waiting to be received.
+    #[inline]
+    pub fn len(&self) -> usize {
+        (self.inner.ring.len() + self.inner.priority.len()) as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
+    /// Verifies that all events have been processed, typically after the bus
+    /// was closed and drained.
+    pub fn verify_completion(&self) -> Result<(), EventError> {
         // Calculate number of unprocessed events
-        let unprocessed = head.wrapping_sub(tail);
+        let unprocessed = self.len() as u64;
         if unprocessed > 0 {
             error!(
                 "Event bus verification failed: {} unprocessed events",
                 unprocessed
             );
-            return Err(EventError::QueueFull);
+            return Err(EventError::Unprocessed(unprocessed));
         }
 
         // Verify all slots are empty
-        for i in 0..self.inner.buffer.len() {
-            unsafe {
-                if let Some(_) = *self.inner.buffer[i].get() {
-                    error!(
-                        "Event bus verification failed: Slot {} still contains event",
-                        i
-                    );
-                    return Err(EventError::QueueFull);
-                }
-            }
+        if let Some(i) = self.inner.ring.occupied_slot() {
+            error!(
+                "Event bus verification failed: Slot {} still contains event",
+                i
+            );
+            return Err(EventError::Unprocessed(1));
+        }
+        if let Some(i) = self.inner.priority.occupied_slot() {
+            error!(
+                "Event bus verification failed: Priority slot {} still contains message",
+                i
+            );
+            return Err(EventError::Unprocessed(1));
         }
 
         Ok(())
     }
-
-    // Thread-safe singleton for closed state
-    fn closed() -> &'static AtomicBool {
-        static CLOSED: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
-        &CLOSED
-    }
 }
 
-// SAFETY: Thread safety ensured by atomic counters and Arc
-unsafe impl Send for InnerBus {}
-unsafe impl Sync for InnerBus {}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::events::control::ControlEvent;
     use bytes::Bytes;
 
     fn test_event(seq: u64) -> NetworkEvent {
@@ -251,15 +545,386 @@ mod tests {
     }
 
     #[test]
-    fn wraps_buffer_correctly() {
+    fn parses_full_queue_strategies() {
+        assert_eq!("yield".parse(), Ok(FullQueueStrategy::Yield));
+        assert_eq!("spin_loop".parse(), Ok(FullQueueStrategy::SpinLoop));
+        assert_eq!("block".parse(), Ok(FullQueueStrategy::Block));
+        assert!("sleep".parse::<FullQueueStrategy>().is_err());
+    }
+
+    #[test]
+    fn blocked_producer_resumes_when_space_frees() {
+        for strategy in [
+            FullQueueStrategy::Yield,
+            FullQueueStrategy::SpinLoop,
+            FullQueueStrategy::Block,
+        ] {
+            let bus = Arc::new(EventBus::with_strategy(2, strategy).unwrap());
+            bus.send(test_event(1)).unwrap();
+            bus.send(test_event(2)).unwrap();
+
+            let producer = {
+                let bus = bus.clone();
+                std::thread::spawn(move || bus.send_blocking(test_event(3)))
+            };
+            std::thread::sleep(Duration::from_millis(20));
+            assert_eq!(bus.recv().unwrap().timestamp, 1);
+            producer.join().unwrap();
+            assert_eq!(bus.recv().unwrap().timestamp, 2);
+            assert_eq!(bus.recv().unwrap().timestamp, 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_async_wakes_on_publish() {
+        let bus = Arc::new(EventBus::with_capacity(4).unwrap());
+        let consumer = tokio::spawn({
+            let bus = bus.clone();
+            async move { bus.recv_async().await.map(|e| e.timestamp) }
+        });
+        tokio::task::yield_now().await;
+        bus.send(test_event(9)).unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), consumer)
+            .await
+            .expect("consumer should wake on publish")
+            .unwrap();
+        assert_eq!(received, Some(9));
+    }
+
+    #[test]
+    fn close_rejects_sends_and_allows_drain() {
         let bus = EventBus::with_capacity(4).unwrap();
-        for cycle in 0..2 {
-            for i in 0..4 {
-                bus.send(test_event(i + cycle * 4)).unwrap();
-            }
-            for i in 0..4 {
-                assert_eq!(bus.recv().unwrap().timestamp, i + cycle * 4);
+        let other = EventBus::with_capacity(4).unwrap();
+        bus.send(test_event(1)).unwrap();
+        bus.send(test_event(2)).unwrap();
+        bus.close();
+
+        assert!(matches!(bus.send(test_event(3)), Err(EventError::Closed)));
+        bus.send_blocking(test_event(4));
+        assert!(
+            matches!(bus.verify_completion(), Err(EventError::Unprocessed(2))),
+            "queued events are still pending"
+        );
+        assert_eq!(bus.recv().unwrap().timestamp, 1);
+        assert_eq!(bus.recv().unwrap().timestamp, 2);
+        assert!(bus.recv().is_none());
+        bus.verify_completion()
+            .expect("A closed, drained bus is complete");
+
+        // Closing one bus leaves others open.
+        assert!(!other.is_closed());
+        other.send(test_event(5)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recv_async_drains_then_ends_after_close() {
+        let bus = EventBus::with_capacity(4).unwrap();
+        bus.send(test_event(1)).unwrap();
+        bus.close();
+        assert_eq!(bus.recv_async().await.unwrap().timestamp, 1);
+        assert!(bus.recv_async().await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn close_wakes_every_waiting_consumer() {
+        let bus = Arc::new(EventBus::with_capacity(4).unwrap());
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let bus = bus.clone();
+                tokio::spawn(async move { bus.recv_envelope_async().await.is_none() })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        bus.close();
+        for consumer in consumers {
+            let ended = tokio::time::timeout(Duration::from_secs(1), consumer)
+                .await
+                .expect("consumer should wake on close");
+            assert!(ended.unwrap());
+        }
+    }
+
+    #[test]
+    fn batches_keep_order_and_respect_capacity() {
+        let bus = EventBus::with_capacity(4).unwrap();
+        bus.send(test_event(0)).unwrap();
+        assert_eq!(bus.recv().unwrap().timestamp, 0);
+
+        // Wraps around the end of the ring.
+        let mut events: Vec<_> = (1..=6).map(test_event).collect();
+        assert_eq!(bus.send_batch(&mut events).unwrap(), 4);
+        assert_eq!(
+            events.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+            [5, 6]
+        );
+        assert_eq!(bus.send_batch(&mut events).unwrap(), 0);
+
+        let mut received = Vec::new();
+        assert_eq!(bus.recv_batch(&mut received, 3), 3);
+        assert_eq!(bus.send_batch(&mut events).unwrap(), 2);
+        assert_eq!(bus.recv_batch(&mut received, 16), 3);
+        assert_eq!(
+            received.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(bus.recv_batch(&mut received, 16), 0);
+
+        bus.close();
+        assert!(matches!(
+            bus.send_batch(&mut vec![test_event(7)]),
+            Err(EventError::Closed)
+        ));
+    }
+
+    #[test]
+    fn wraps_buffer_correctly() {
+        for mode in [BusMode::Spsc, BusMode::Mpmc] {
+            let options = BusOptions {
+                capacity: 4,
+                mode,
+                ..BusOptions::default()
+            };
+            // SAFETY: the bus is only used from this thread.
+            let bus = unsafe { EventBus::with_options_unchecked(options) }.unwrap();
+            assert_eq!(bus.mode(), mode);
+            for cycle in 0..2 {
+                for i in 0..4 {
+                    bus.send(test_event(i + cycle * 4)).unwrap();
+                }
+                assert!(matches!(
+                    bus.send(test_event(99)),
+                    Err(EventError::QueueFull)
+                ));
+                for i in 0..4 {
+                    assert_eq!(bus.recv().unwrap().timestamp, i + cycle * 4);
+                }
             }
+            bus.verify_completion().unwrap();
         }
     }
+
+    /// Runs `producers` threads publishing disjoint ranges of `per_producer`
+    /// timestamps through a small MPMC bus while `consumers` threads drain it,
+    /// and returns every timestamp received.
+    fn run_contended(
+        producers: u64,
+        consumers: usize,
+        per_producer: u64,
+        batched: bool,
+    ) -> Vec<u64> {
+        let bus =
+            Arc::new(EventBus::with_mode(64, BusMode::Mpmc, FullQueueStrategy::Yield).unwrap());
+
+        let receivers: Vec<_> = (0..consumers)
+            .map(|_| {
+                let bus = bus.clone();
+                std::thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    let mut batch = Vec::new();
+                    loop {
+                        let received = if batched {
+                            bus.recv_batch(&mut batch, 8)
+                        } else {
+                            bus.recv().map(|e| batch.push(e)).map_or(0, |_| 1)
+                        };
+                        if received == 0 {
+                            if bus.is_closed() && bus.is_empty() {
+                                break;
+                            }
+                            std::thread::yield_now();
+                        }
+                        seen.extend(batch.drain(..).map(|e| e.timestamp));
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        let senders: Vec<_> = (0..producers)
+            .map(|producer| {
+                let bus = bus.clone();
+                std::thread::spawn(move || {
+                    let range = producer * per_producer..(producer + 1) * per_producer;
+                    if batched {
+                        let mut pending: Vec<_> = range.map(test_event).collect();
+                        while !pending.is_empty() {
+                            let mut chunk: Vec<_> = pending.drain(..pending.len().min(5)).collect();
+                            while !chunk.is_empty() {
+                                if bus.send_batch(&mut chunk).unwrap() == 0 {
+                                    std::thread::yield_now();
+                                }
+                            }
+                        }
+                    } else {
+                        range.for_each(|ts| bus.send_blocking(test_event(ts)));
+                    }
+                })
+            })
+            .collect();
+
+        senders.into_iter().for_each(|s| s.join().unwrap());
+        bus.close();
+        let mut seen: Vec<u64> = receivers
+            .into_iter()
+            .flat_map(|r| r.join().unwrap())
+            .collect();
+        bus.verify_completion().unwrap();
+        seen.sort_unstable();
+        seen
+    }
+
+    #[test]
+    fn mpmc_delivers_every_event_once_under_contention() {
+        let seen = run_contended(4, 4, 20_000, false);
+        assert_eq!(seen, (0..80_000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn mpmc_batches_deliver_every_event_once_under_contention() {
+        let seen = run_contended(4, 3, 20_000, true);
+        assert_eq!(seen, (0..80_000).collect::<Vec<_>>());
+    }
+
+    fn shutdown() -> Envelope {
+        Envelope::Control(ControlEvent::Shutdown)
+    }
+
+    fn timestamp(envelope: Envelope) -> Option<u64> {
+        match envelope {
+            Envelope::Network(event) => Some(event.timestamp),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn priority_lane_skips_ahead_of_packets() {
+        let bus = EventBus::with_capacity(4).unwrap();
+        bus.send(test_event(1)).unwrap();
+        bus.send_envelope(test_event(2).into()).unwrap();
+        bus.send_envelope(shutdown()).unwrap();
+        assert_eq!(bus.len(), 3);
+
+        assert!(matches!(
+            bus.recv_envelope(),
+            Some(Envelope::Control(ControlEvent::Shutdown))
+        ));
+        assert_eq!(bus.recv_envelope().and_then(timestamp), Some(1));
+        // Plain receivers only see packet events.
+        assert_eq!(bus.recv().unwrap().timestamp, 2);
+        assert!(bus.recv_envelope().is_none());
+
+        bus.close();
+        assert!(matches!(
+            bus.send_envelope(shutdown()),
+            Err(EventError::Closed)
+        ));
+    }
+
+    #[test]
+    fn starvation_guard_lets_packets_through() {
+        let bus = EventBus::with_options(BusOptions {
+            capacity: 8,
+            priority_capacity: 8,
+            starvation_limit: 3,
+            ..BusOptions::default()
+        })
+        .unwrap();
+        for ts in 0..2 {
+            bus.send(test_event(ts)).unwrap();
+        }
+        for _ in 0..8 {
+            bus.send_envelope(shutdown()).unwrap();
+        }
+        assert!(matches!(
+            bus.send_envelope(shutdown()),
+            Err(EventError::QueueFull)
+        ));
+
+        let order: Vec<_> = std::iter::from_fn(|| bus.recv_envelope())
+            .map(timestamp)
+            .collect();
+        let control = None;
+        assert_eq!(
+            order,
+            [
+                control,
+                control,
+                control,
+                Some(0),
+                control,
+                control,
+                control,
+                Some(1),
+                control,
+                control
+            ]
+        );
+        bus.verify_completion().unwrap();
+    }
+
+    #[test]
+    fn application_messages_leave_room_for_control() {
+        use crate::events::application::{ApplicationEvent, ApplicationProtocol};
+
+        let bus = EventBus::with_options(BusOptions {
+            priority_capacity: 4,
+            ..BusOptions::default()
+        })
+        .unwrap();
+        let packet = test_event(1);
+        let message =
+            ApplicationEvent::parsed_from(&packet, ApplicationProtocol::Mqtt, 3, &packet.payload)
+                .unwrap();
+        for _ in 0..2 {
+            bus.send_envelope(message.clone().into()).unwrap();
+        }
+        assert!(matches!(
+            bus.send_envelope(message.into()),
+            Err(EventError::QueueFull)
+        ));
+        for _ in 0..2 {
+            bus.send_envelope(shutdown()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn recv_envelope_async_wakes_on_control() {
+        let bus = Arc::new(EventBus::with_capacity(4).unwrap());
+        let consumer = tokio::spawn({
+            let bus = bus.clone();
+            async move { bus.recv_envelope_async().await }
+        });
+        tokio::task::yield_now().await;
+        bus.send_envelope(shutdown()).unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), consumer)
+            .await
+            .expect("consumer should wake on control message")
+            .unwrap();
+        assert!(matches!(received, Some(Envelope::Control(_))));
+        bus.close();
+        assert!(bus.recv_envelope_async().await.is_none());
+    }
+
+    #[test]
+    fn safe_constructors_reject_spsc() {
+        assert!(matches!(
+            EventBus::with_mode(4, BusMode::Spsc, FullQueueStrategy::default()),
+            Err(EventError::UncheckedMode)
+        ));
+        assert!(matches!(
+            EventBus::with_options(BusOptions {
+                mode: BusMode::Spsc,
+                ..BusOptions::default()
+            }),
+            Err(EventError::UncheckedMode)
+        ));
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("spsc".parse(), Ok(BusMode::Spsc));
+        assert_eq!("mpmc".parse(), Ok(BusMode::Mpmc));
+        assert!("ring".parse::<BusMode>().is_err());
+        assert_eq!(BusMode::default(), BusMode::Mpmc);
+    }
 }
@@ -0,0 +1,17 @@
+//! Control commands.
+
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Command changing how the engine runs.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum ControlEvent {
+    /// Reload detection rules.
+    ReloadRules,
+    /// Stop capturing and shut down once queued events are processed.
+    Shutdown,
+    /// Block traffic from an address.
+    Block(IpAddr),
+    /// Lift a block on an address.
+    Unblock(IpAddr),
+}
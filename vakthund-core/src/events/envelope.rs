@@ -0,0 +1,164 @@
+//! Typed messages carried on the event bus.
+//!
+//! Packet events travel on the bus's bulk lane. Every other event (parsed
+//! messages, alerts, prevention actions, flow records and control commands)
+//! travels on the priority lane, so it is delivered ahead of any packets still
+//! queued rather than waiting behind a flood. Parsed messages are as frequent
+//! as packets, so they may fill only half of that lane.
+
+use super::application::ApplicationEvent;
+use super::control::ControlEvent;
+use super::flow::FlowEvent;
+use super::network::NetworkEvent;
+use super::prevention::PreventionEvent;
+use serde::{Deserialize, Serialize};
+use vakthund_detection::alert::Alert;
+
+/// Bus lane a message is queued on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk packet traffic.
+    Normal,
+    /// Everything derived from or steering packet processing, delivered first.
+    High,
+}
+
+/// A message on the event bus.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum Envelope {
+    Network(NetworkEvent),
+    Application(ApplicationEvent),
+    Alert(Alert),
+    Prevention(PreventionEvent),
+    Flow(FlowEvent),
+    Control(ControlEvent),
+}
+
+impl Envelope {
+    /// Lane the message is queued on.
+    pub fn priority(&self) -> Priority {
+        match self {
+            Envelope::Network(_) => Priority::Normal,
+            _ => Priority::High,
+        }
+    }
+
+    /// Short name of the event type, as used for telemetry.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Envelope::Network(_) => "packet",
+            Envelope::Application(_) => "application",
+            Envelope::Alert(_) => "alert",
+            Envelope::Prevention(_) => "prevention",
+            Envelope::Flow(_) => "flow",
+            Envelope::Control(_) => "control",
+        }
+    }
+}
+
+macro_rules! impl_from_event {
+    ($($event:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$event> for Envelope {
+                fn from(event: $event) -> Self {
+                    Envelope::$variant(event)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_event! {
+    NetworkEvent => Network,
+    ApplicationEvent => Application,
+    Alert => Alert,
+    PreventionEvent => Prevention,
+    FlowEvent => Flow,
+    ControlEvent => Control,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::application::ApplicationProtocol;
+    use crate::events::flow::FlowRecord;
+    use crate::events::network::TransportProtocol;
+    use crate::events::prevention::{PreventionAction, PreventionOutcome};
+    use bytes::Bytes;
+    use vakthund_detection::SignatureEngine;
+
+    #[test]
+    fn events_round_trip_through_serde() {
+        let packet = NetworkEvent {
+            source: Some("10.0.0.1:50000".parse().unwrap()),
+            destination: Some("10.0.0.2:1883".parse().unwrap()),
+            protocol: Some(TransportProtocol::Tcp),
+            ..NetworkEvent::new(42, Bytes::from_static(b"\x30\x05topic"))
+        };
+        let message = ApplicationEvent::parsed_from(
+            &packet,
+            ApplicationProtocol::Mqtt,
+            3,
+            &packet.payload[2..],
+        )
+        .unwrap();
+        let engine = SignatureEngine::new();
+        engine.add_pattern("topic").unwrap();
+        let alert = engine
+            .detect(&message.body, &message.scan_context())
+            .remove(0);
+
+        let prevention = PreventionEvent {
+            timestamp: 43,
+            action: PreventionAction::Block,
+            target: "10.0.0.1".parse().unwrap(),
+            interface: "eth0".into(),
+            outcome: PreventionOutcome::Failed("permission denied".into()),
+        };
+        let flow = FlowEvent::Ended(FlowRecord {
+            interface: Some("eth0".into()),
+            vlan: Some(10),
+            protocol: TransportProtocol::Tcp,
+            initiator: "10.0.0.1:50000".parse().unwrap(),
+            responder: "10.0.0.2:1883".parse().unwrap(),
+            forward_packets: 3,
+            forward_bytes: 160,
+            reverse_packets: 1,
+            reverse_bytes: 20,
+            first_seen: 1,
+            last_seen: 44,
+            application: Some(ApplicationProtocol::Mqtt),
+        });
+
+        for envelope in [
+            Envelope::from(packet),
+            message.into(),
+            alert.clone().into(),
+            prevention.into(),
+            flow.into(),
+            ControlEvent::Shutdown.into(),
+        ] {
+            let json = serde_json::to_string(&envelope).unwrap();
+            let decoded: Envelope = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, envelope);
+        }
+
+        let flow = alert.flow.expect("alert should carry the 5-tuple");
+        assert_eq!(flow.source, "10.0.0.1:50000".parse().unwrap());
+        assert_eq!(flow.protocol, 6);
+        assert_eq!(alert.protocol, "MQTT");
+        assert_eq!(alert.timestamp, 42);
+    }
+
+    #[test]
+    fn only_packets_use_the_bulk_lane() {
+        assert_eq!(
+            Envelope::from(NetworkEvent::new(0, Bytes::new())).priority(),
+            Priority::Normal
+        );
+        assert_eq!(
+            Envelope::from(ControlEvent::ReloadRules).priority(),
+            Priority::High
+        );
+    }
+}
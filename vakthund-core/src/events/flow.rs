@@ -0,0 +1,37 @@
+//! Flow lifecycle events.
+
+use super::application::ApplicationProtocol;
+use super::network::TransportProtocol;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Summary of a bidirectional flow.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FlowRecord {
+    #[serde(default)]
+    pub interface: Option<Arc<str>>,
+    pub vlan: Option<u16>,
+    pub protocol: TransportProtocol,
+    /// Endpoint that opened the flow
+    pub initiator: SocketAddr,
+    pub responder: SocketAddr,
+    /// Packets and bytes sent by the initiator
+    pub forward_packets: u64,
+    pub forward_bytes: u64,
+    /// Packets and bytes sent by the responder
+    pub reverse_packets: u64,
+    pub reverse_bytes: u64,
+    /// Timestamps of the first and last packet in nanoseconds
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub application: Option<ApplicationProtocol>,
+}
+
+/// A flow starting or ending.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum FlowEvent {
+    Started(FlowRecord),
+    /// The flow closed, timed out or was evicted.
+    Ended(FlowRecord),
+}
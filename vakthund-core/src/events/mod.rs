@@ -3,11 +3,25 @@
 //! Provides protocol-agnostic event handling with:
 //! - Zero-copy payloads
 //! - Thread-safe lock-free queues
-//! - Network-specific event types
+//! - A typed event model: packets, parsed application messages, alerts,
+//!   prevention actions, flow lifecycle and control commands, all
+//!   serde-serializable and carried on the bus as an [`Envelope`]
 
+pub mod application;
 pub mod bus;
+pub mod control;
+pub mod envelope;
+pub mod flow;
 pub mod network;
+pub mod prevention;
+mod ring;
 
 // Re-export primary components
-pub use bus::{EventBus, EventError};
-pub use network::NetworkEvent;
+pub use application::{ApplicationEvent, ApplicationProtocol};
+pub use bus::{BusMode, BusOptions, EventBus, EventError, FullQueueStrategy};
+pub use control::ControlEvent;
+pub use envelope::{Envelope, Priority};
+pub use flow::{FlowEvent, FlowRecord};
+pub use network::{Encapsulation, NetworkEvent, TransportProtocol, TunnelProtocol};
+pub use prevention::{PreventionAction, PreventionEvent, PreventionOutcome};
+pub use vakthund_detection::alert::{Alert, FiveTuple, Severity};
@@ -3,15 +3,69 @@
 use bytes::Bytes;
 use serde::Deserialize;
 use serde::Serialize;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use vakthund_detection::alert::FiveTuple;
+
+/// Transport protocol carried by a network event.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransportProtocol {
+    Tcp,
+    Udp,
+    /// Any other IP protocol, by protocol number.
+    Other(u8),
+}
+
+impl From<u8> for TransportProtocol {
+    fn from(number: u8) -> Self {
+        match number {
+            6 => TransportProtocol::Tcp,
+            17 => TransportProtocol::Udp,
+            other => TransportProtocol::Other(other),
+        }
+    }
+}
+
+impl From<TransportProtocol> for u8 {
+    fn from(protocol: TransportProtocol) -> Self {
+        match protocol {
+            TransportProtocol::Tcp => 6,
+            TransportProtocol::Udp => 17,
+            TransportProtocol::Other(number) => number,
+        }
+    }
+}
+
+/// Tunnel protocol the packet was encapsulated in.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TunnelProtocol {
+    Gre,
+    ErspanII,
+    ErspanIII,
+    Vxlan,
+    Geneve,
+}
+
+/// Outer addressing of one encapsulation layer that was stripped before inspection.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encapsulation {
+    pub protocol: TunnelProtocol,
+    /// Tunnel endpoint source address
+    pub source: IpAddr,
+    /// Tunnel endpoint destination address
+    pub destination: IpAddr,
+    /// GRE key, ERSPAN session id or VXLAN/GENEVE network identifier
+    pub id: Option<u32>,
+}
 
 /// Protocol-agnostic network event with metadata
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub struct NetworkEvent {
     /// Monotonic timestamp in nanoseconds from system/clock
     pub timestamp: u64,
 
-    /// Immutable payload buffer using zero-copy semantics
+    /// Immutable payload buffer using zero-copy semantics.
+    /// For captured traffic this is the application payload, not the raw frame.
     #[serde(with = "Bytes")]
     pub payload: Bytes,
 
@@ -20,6 +74,27 @@ pub struct NetworkEvent {
 
     /// Optional destination address for network context
     pub destination: Option<SocketAddr>,
+
+    /// Transport protocol, if the packet was decoded down to the IP layer
+    #[serde(default)]
+    pub protocol: Option<TransportProtocol>,
+
+    /// Innermost 802.1Q VLAN id, if the frame was tagged
+    #[serde(default)]
+    pub vlan: Option<u16>,
+
+    /// Interface the packet was captured on
+    #[serde(default)]
+    pub interface: Option<Arc<str>>,
+
+    /// Endpoint that opened the connection, as seen by the flow table
+    #[serde(default)]
+    pub initiator: Option<SocketAddr>,
+
+    /// Encapsulation layers stripped to reach the inner packet, outermost first.
+    /// The addressing fields above describe the inner packet.
+    #[serde(default)]
+    pub tunnels: Vec<Encapsulation>,
 }
 
 impl NetworkEvent {
@@ -31,6 +106,29 @@ impl NetworkEvent {
             payload,
             source: None,
             destination: None,
+            protocol: None,
+            vlan: None,
+            interface: None,
+            initiator: None,
+            tunnels: Vec::new(),
         }
     }
+
+    /// Addressing of the packet, if fully known.
+    pub fn five_tuple(&self) -> Option<FiveTuple> {
+        five_tuple(self.source, self.destination, self.protocol)
+    }
+}
+
+/// Builds a 5-tuple from optional addressing fields.
+pub(crate) fn five_tuple(
+    source: Option<SocketAddr>,
+    destination: Option<SocketAddr>,
+    protocol: Option<TransportProtocol>,
+) -> Option<FiveTuple> {
+    Some(FiveTuple {
+        source: source?,
+        destination: destination?,
+        protocol: protocol?.into(),
+    })
 }
@@ -0,0 +1,33 @@
+//! Prevention actions taken in response to alerts or control commands.
+
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Action applied to an address.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreventionAction {
+    Block,
+    Unblock,
+}
+
+/// Result of applying a prevention action.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum PreventionOutcome {
+    Applied,
+    Failed(String),
+    /// The firewall cannot express the action (e.g. IPv6 rules).
+    Unsupported,
+}
+
+/// A prevention action and its result.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct PreventionEvent {
+    /// Timestamp in nanoseconds
+    pub timestamp: u64,
+    pub action: PreventionAction,
+    pub target: IpAddr,
+    /// Interface the action was applied on
+    pub interface: Arc<str>,
+    pub outcome: PreventionOutcome,
+}
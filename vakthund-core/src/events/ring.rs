@@ -0,0 +1,485 @@
+//! Ring buffers backing [`EventBus`](super::bus::EventBus).
+//!
+//! [`SpscRing`] tracks a single head and tail counter. It is the fastest
+//! option but only sound while at most one thread publishes and one thread
+//! consumes at a time.
+//!
+//! [`MpmcRing`] is a bounded multi-producer multi-consumer queue with a
+//! sequence number per slot, in the style of the LMAX Disruptor. A slot whose
+//! sequence equals a position is free for the producer of that position; once
+//! written its sequence becomes `position + 1`, marking it ready for the
+//! consumer of that position, who sets it to `position + capacity` to hand it
+//! to the producer of the next lap. Producers and consumers claim positions
+//! with a compare-and-swap on their cursor, so every position is written and
+//! read by exactly one thread.
+
+use std::cell::UnsafeCell;
+use std::cmp::Ordering as Position;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Cache-line aligned atomic counter to prevent false sharing
+#[repr(align(64))]
+#[derive(Debug)]
+struct AlignedCounter(AtomicU64);
+
+impl AlignedCounter {
+    #[inline]
+    fn new(value: u64) -> Self {
+        Self(AtomicU64::new(value))
+    }
+}
+
+/// The ring an event bus was built on.
+#[derive(Debug)]
+pub(super) enum Ring<T> {
+    Spsc(SpscRing<T>),
+    Mpmc(MpmcRing<T>),
+}
+
+impl<T> Ring<T> {
+    /// Publishes `event`. Returns false, dropping it, if the ring is full.
+    #[inline]
+    pub(super) fn push(&self, event: T) -> bool {
+        match self {
+            Ring::Spsc(ring) => ring.push(event),
+            Ring::Mpmc(ring) => ring.push(event),
+        }
+    }
+
+    #[inline]
+    pub(super) fn pop(&self) -> Option<T> {
+        match self {
+            Ring::Spsc(ring) => ring.pop(),
+            Ring::Mpmc(ring) => ring.pop(),
+        }
+    }
+
+    /// Publishes as many events from the front of `events` as fit, removing
+    /// them from the vector. Returns how many were published.
+    pub(super) fn push_batch(&self, events: &mut Vec<T>) -> usize {
+        match self {
+            Ring::Spsc(ring) => ring.push_batch(events),
+            Ring::Mpmc(ring) => ring.push_batch(events),
+        }
+    }
+
+    /// Moves up to `max` events into `out`. Returns how many were moved.
+    pub(super) fn pop_batch(&self, out: &mut Vec<T>, max: usize) -> usize {
+        match self {
+            Ring::Spsc(ring) => ring.pop_batch(out, max),
+            Ring::Mpmc(ring) => ring.pop_batch(out, max),
+        }
+    }
+
+    /// Number of published (or claimed) positions not yet consumed.
+    #[inline]
+    pub(super) fn len(&self) -> u64 {
+        match self {
+            Ring::Spsc(ring) => ring.len(),
+            Ring::Mpmc(ring) => ring.len(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn capacity(&self) -> usize {
+        match self {
+            Ring::Spsc(ring) => ring.buffer.len(),
+            Ring::Mpmc(ring) => ring.slots.len(),
+        }
+    }
+
+    /// Index of a slot still holding an event, if any. Only meaningful while
+    /// no producer or consumer is active.
+    pub(super) fn occupied_slot(&self) -> Option<usize> {
+        // SAFETY: Callers only inspect quiescent rings; the read is a snapshot
+        match self {
+            Ring::Spsc(ring) => ring
+                .buffer
+                .iter()
+                .position(|slot| unsafe { (*slot.get()).is_some() }),
+            Ring::Mpmc(ring) => ring
+                .slots
+                .iter()
+                .position(|slot| unsafe { (*slot.event.get()).is_some() }),
+        }
+    }
+}
+
+/// Single-producer single-consumer ring.
+#[derive(Debug)]
+pub(super) struct SpscRing<T> {
+    buffer: Box<[UnsafeCell<Option<T>>]>,
+    head: AlignedCounter,
+    tail: AlignedCounter,
+    mask: usize,
+}
+
+impl<T> SpscRing<T> {
+    /// Creates a ring of `capacity` slots, which must be a power of two.
+    pub(super) fn new(capacity: usize) -> Self {
+        let buffer = (0..capacity)
+            .map(|_| UnsafeCell::new(None))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Self {
+            buffer,
+            head: AlignedCounter::new(0),
+            tail: AlignedCounter::new(0),
+            mask: capacity - 1,
+        }
+    }
+
+    #[inline]
+    fn push(&self, event: T) -> bool {
+        let head = self.head.0.load(Ordering::Relaxed);
+        let tail = self.tail.0.load(Ordering::Acquire);
+
+        if head - tail >= self.buffer.len() as u64 {
+            return false;
+        }
+
+        // SAFETY: Exclusive write access ensured by atomic counters
+        unsafe {
+            let index = head & self.mask as u64;
+            *self.buffer[index as usize].get() = Some(event);
+        }
+
+        self.head.0.store(head + 1, Ordering::Release);
+        true
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<T> {
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        let head = self.head.0.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: Exclusive read access ensured by atomic counters
+        let event = unsafe {
+            let idx = (tail as usize) & self.mask;
+            (*self.buffer[idx].get()).take()
+        };
+
+        self.tail.0.store(tail + 1, Ordering::Release);
+        event
+    }
+
+    fn push_batch(&self, events: &mut Vec<T>) -> usize {
+        let head = self.head.0.load(Ordering::Relaxed);
+        let tail = self.tail.0.load(Ordering::Acquire);
+        let free = self.buffer.len() - (head - tail) as usize;
+        let count = free.min(events.len());
+        if count == 0 {
+            return 0;
+        }
+
+        for (offset, event) in events.drain(..count).enumerate() {
+            // SAFETY: Slots between head and tail + capacity belong to the producer
+            unsafe {
+                let index = (head as usize + offset) & self.mask;
+                *self.buffer[index].get() = Some(event);
+            }
+        }
+
+        self.head.0.store(head + count as u64, Ordering::Release);
+        count
+    }
+
+    fn pop_batch(&self, out: &mut Vec<T>, max: usize) -> usize {
+        let tail = self.tail.0.load(Ordering::Relaxed);
+        let head = self.head.0.load(Ordering::Acquire);
+        let count = ((head - tail) as usize).min(max);
+        if count == 0 {
+            return 0;
+        }
+
+        out.reserve(count);
+        for offset in 0..count {
+            // SAFETY: Slots between tail and head belong to the consumer
+            let event = unsafe {
+                let index = (tail as usize + offset) & self.mask;
+                (*self.buffer[index].get()).take()
+            };
+            out.extend(event);
+        }
+
+        self.tail.0.store(tail + count as u64, Ordering::Release);
+        count
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        let head = self.head.0.load(Ordering::Acquire);
+        let tail = self.tail.0.load(Ordering::Acquire);
+        head.wrapping_sub(tail)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    sequence: AtomicU64,
+    event: UnsafeCell<Option<T>>,
+}
+
+/// Multi-producer multi-consumer ring with per-slot sequence numbers.
+#[derive(Debug)]
+pub(super) struct MpmcRing<T> {
+    slots: Box<[Slot<T>]>,
+    /// Next position to publish.
+    enqueue: AlignedCounter,
+    /// Next position to consume.
+    dequeue: AlignedCounter,
+    mask: usize,
+}
+
+impl<T> MpmcRing<T> {
+    /// Creates a ring of `capacity` slots, which must be a power of two.
+    pub(super) fn new(capacity: usize) -> Self {
+        let slots = (0..capacity as u64)
+            .map(|position| Slot {
+                sequence: AtomicU64::new(position),
+                event: UnsafeCell::new(None),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Self {
+            slots,
+            enqueue: AlignedCounter::new(0),
+            dequeue: AlignedCounter::new(0),
+            mask: capacity - 1,
+        }
+    }
+
+    #[inline]
+    fn slot(&self, position: u64) -> &Slot<T> {
+        &self.slots[position as usize & self.mask]
+    }
+
+    fn push(&self, event: T) -> bool {
+        let mut position = self.enqueue.0.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(position);
+            match slot.sequence.load(Ordering::Acquire).cmp(&position) {
+                Position::Equal => {
+                    match self.enqueue.0.compare_exchange_weak(
+                        position,
+                        position + 1,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            // SAFETY: Winning the CAS makes this thread the only
+                            // writer of `position`
+                            unsafe { *slot.event.get() = Some(event) };
+                            slot.sequence.store(position + 1, Ordering::Release);
+                            return true;
+                        }
+                        Err(current) => position = current,
+                    }
+                }
+                // The slot still holds the event from one lap earlier.
+                Position::Less => return false,
+                // Another producer claimed `position` first.
+                Position::Greater => position = self.enqueue.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut position = self.dequeue.0.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(position);
+            match slot.sequence.load(Ordering::Acquire).cmp(&(position + 1)) {
+                Position::Equal => {
+                    match self.dequeue.0.compare_exchange_weak(
+                        position,
+                        position + 1,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    ) {
+                        Ok(_) => {
+                            // SAFETY: Winning the CAS makes this thread the only
+                            // reader of `position`
+                            let event = unsafe { (*slot.event.get()).take() };
+                            slot.sequence
+                                .store(position + self.slots.len() as u64, Ordering::Release);
+                            return event;
+                        }
+                        Err(current) => position = current,
+                    }
+                }
+                // Nothing published at `position` yet.
+                Position::Less => return None,
+                // Another consumer claimed `position` first.
+                Position::Greater => position = self.dequeue.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Claims a run of consecutive free positions with one CAS on the cursor.
+    fn push_batch(&self, events: &mut Vec<T>) -> usize {
+        let wanted = events.len().min(self.slots.len()) as u64;
+        if wanted == 0 {
+            return 0;
+        }
+
+        loop {
+            let position = self.enqueue.0.load(Ordering::Relaxed);
+            let count = (0..wanted)
+                .take_while(|&offset| {
+                    self.slot(position + offset)
+                        .sequence
+                        .load(Ordering::Acquire)
+                        == position + offset
+                })
+                .count() as u64;
+            if count == 0 {
+                if self.slot(position).sequence.load(Ordering::Acquire) < position {
+                    return 0;
+                }
+                continue;
+            }
+            if self
+                .enqueue
+                .0
+                .compare_exchange(
+                    position,
+                    position + count,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                continue;
+            }
+
+            for (offset, event) in (0..count).zip(events.drain(..count as usize)) {
+                let slot = self.slot(position + offset);
+                // SAFETY: The CAS above made this thread the only writer of
+                // every claimed position
+                unsafe { *slot.event.get() = Some(event) };
+                slot.sequence
+                    .store(position + offset + 1, Ordering::Release);
+            }
+            return count as usize;
+        }
+    }
+
+    /// Claims a run of consecutive published positions with one CAS on the
+    /// cursor.
+    fn pop_batch(&self, out: &mut Vec<T>, max: usize) -> usize {
+        let wanted = max.min(self.slots.len()) as u64;
+        if wanted == 0 {
+            return 0;
+        }
+
+        loop {
+            let position = self.dequeue.0.load(Ordering::Relaxed);
+            let count = (0..wanted)
+                .take_while(|&offset| {
+                    self.slot(position + offset)
+                        .sequence
+                        .load(Ordering::Acquire)
+                        == position + offset + 1
+                })
+                .count() as u64;
+            if count == 0 {
+                if self.slot(position).sequence.load(Ordering::Acquire) < position + 1 {
+                    return 0;
+                }
+                continue;
+            }
+            if self
+                .dequeue
+                .0
+                .compare_exchange(
+                    position,
+                    position + count,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                continue;
+            }
+
+            out.reserve(count as usize);
+            let lap = self.slots.len() as u64;
+            for offset in 0..count {
+                let slot = self.slot(position + offset);
+                // SAFETY: The CAS above made this thread the only reader of
+                // every claimed position
+                out.extend(unsafe { (*slot.event.get()).take() });
+                slot.sequence
+                    .store(position + offset + lap, Ordering::Release);
+            }
+            return count as usize;
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        // Load the consumer cursor first so the difference never underflows.
+        let dequeue = self.dequeue.0.load(Ordering::Acquire);
+        let enqueue = self.enqueue.0.load(Ordering::Acquire);
+        enqueue.saturating_sub(dequeue)
+    }
+}
+
+// SAFETY: Slot access is handed between threads through the atomic counters
+// (SPSC) or the per-slot sequence numbers (MPMC)
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::network::NetworkEvent;
+    use bytes::Bytes;
+
+    fn event(seq: u64) -> NetworkEvent {
+        NetworkEvent::new(seq, Bytes::new())
+    }
+
+    #[test]
+    fn mpmc_sequences_advance_by_lap() {
+        let ring = MpmcRing::new(2);
+        for lap in 0..3u64 {
+            assert!(ring.push(event(lap * 2)));
+            assert!(ring.push(event(lap * 2 + 1)));
+            assert!(!ring.push(event(99)), "ring is full");
+            assert_eq!(ring.len(), 2);
+            assert_eq!(ring.pop().unwrap().timestamp, lap * 2);
+            assert_eq!(ring.pop().unwrap().timestamp, lap * 2 + 1);
+            assert!(ring.pop().is_none());
+        }
+        let sequences: Vec<_> = ring
+            .slots
+            .iter()
+            .map(|slot| slot.sequence.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(sequences, [6, 7]);
+    }
+
+    #[test]
+    fn mpmc_batches_stop_at_busy_slots() {
+        let ring = MpmcRing::new(4);
+        let mut events: Vec<_> = (0..3).map(event).collect();
+        assert_eq!(ring.push_batch(&mut events), 3);
+
+        let mut out = Vec::new();
+        assert_eq!(ring.pop_batch(&mut out, 2), 2);
+        let mut events: Vec<_> = (3..8).map(event).collect();
+        assert_eq!(ring.push_batch(&mut events), 3);
+        assert_eq!(events.len(), 2);
+        assert_eq!(ring.push_batch(&mut events), 0);
+
+        assert_eq!(ring.pop_batch(&mut out, 10), 4);
+        let order: Vec<_> = out.iter().map(|e| e.timestamp).collect();
+        assert_eq!(order, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(ring.pop_batch(&mut out, 10), 0);
+    }
+}
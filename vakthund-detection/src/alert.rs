@@ -0,0 +1,147 @@
+//! ## vakthund-detection::alert
+//! **Structured alerts raised by detection**
+//!
+//! Every detection produces an [`Alert`] carrying the rule that fired, its
+//! severity, the traffic it fired on and where in the inspected buffer it
+//! matched. Alerts are serde-serializable so prevention, telemetry and
+//! scenario recording all see the same record.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Bytes of the inspected buffer kept before the first match in an excerpt.
+pub const EXCERPT_LEAD: usize = 16;
+
+/// Maximum length of a payload excerpt.
+pub const EXCERPT_LEN: usize = 64;
+
+/// Alert severity, ordered from least to most severe. Matches the levels
+/// accepted by `AlertConfig::min_severity`.
+#[derive(
+    Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Lowercase name, as used in configuration and metric labels.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("Unknown alert severity '{s}'")),
+        }
+    }
+}
+
+/// Addressing of the traffic an alert fired on.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FiveTuple {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// IP protocol number
+    pub protocol: u8,
+}
+
+/// Where and on what a buffer is being inspected.
+#[derive(Clone, Debug, Default)]
+pub struct ScanContext {
+    /// Timestamp of the packet the buffer came from, in nanoseconds
+    pub timestamp: u64,
+    /// Application protocol name, e.g. `MQTT`
+    pub protocol: &'static str,
+    pub flow: Option<FiveTuple>,
+    /// Endpoint that opened the connection, if known
+    pub initiator: Option<SocketAddr>,
+    pub interface: Option<Arc<str>>,
+}
+
+/// A detection rule firing on inspected traffic.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Alert {
+    /// Timestamp of the offending packet in nanoseconds
+    pub timestamp: u64,
+    pub rule_id: u32,
+    pub rule_revision: u32,
+    pub message: String,
+    pub severity: Severity,
+    /// Application protocol name, e.g. `MQTT`
+    pub protocol: String,
+    pub flow: Option<FiveTuple>,
+    /// Endpoint that opened the connection, if known. Unlike `flow.source`
+    /// this is the client whichever side sent the offending packet.
+    #[serde(default)]
+    pub initiator: Option<SocketAddr>,
+    #[serde(default)]
+    pub interface: Option<Arc<str>>,
+    /// Byte ranges of every match within the inspected buffer
+    pub offsets: Vec<Range<usize>>,
+    /// Up to [`EXCERPT_LEN`] bytes of the inspected buffer around the first match
+    #[serde(with = "serde_bytes")]
+    pub excerpt: Vec<u8>,
+}
+
+/// Copies the part of `data` around the first of `offsets` into an excerpt.
+pub fn excerpt(data: &[u8], offsets: &[Range<usize>]) -> Vec<u8> {
+    let start = offsets
+        .first()
+        .map_or(0, |first| first.start.saturating_sub(EXCERPT_LEAD))
+        .min(data.len());
+    let end = (start + EXCERPT_LEN).min(data.len());
+    data[start..end].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severities_are_ordered_and_parsed() {
+        assert!(Severity::Low < Severity::Medium);
+        assert!(Severity::High < Severity::Critical);
+        assert_eq!("CRITICAL".parse(), Ok(Severity::Critical));
+        assert_eq!(Severity::High.to_string(), "high");
+        assert!("urgent".parse::<Severity>().is_err());
+    }
+
+    #[test]
+    fn excerpt_is_bounded_around_first_match() {
+        let data: Vec<u8> = (0..200).collect();
+        let excerpt = excerpt(&data, &[100..104, 150..154]);
+        assert_eq!(excerpt.len(), EXCERPT_LEN);
+        assert_eq!(excerpt[0], 100 - EXCERPT_LEAD as u8);
+
+        assert_eq!(super::excerpt(b"short", &[1..3, 4..5]), b"short");
+    }
+}
@@ -0,0 +1,1043 @@
+//! ## vakthund-detection::coap_exchange
+//! **CoAP exchange tracking and block-wise reassembly**
+//!
+//! Bodies too large for one datagram, such as firmware images, travel as
+//! Block1 (request) or Block2 (response) sequences (RFC 7959), so a signature
+//! spanning two blocks never matches a single message. The tracker follows
+//! each pair of endpoints and:
+//! - pairs responses with their requests by token, and piggybacked
+//!   responses by message id as well
+//! - follows Observe registrations (RFC 7641), so notifications are
+//!   attributed to the observed resource until the client deregisters,
+//!   resets a notification or the server ends the observation
+//! - reassembles block-wise bodies in order, discarding transfers with gaps
+//!
+//! Completed bodies are returned as [`CoapBody`] to be scanned as a whole,
+//! e.g. with [`SignatureEngine::buffer_scan`](crate::SignatureEngine::buffer_scan).
+//! A transfer discarded for exceeding a limit would never be scanned whole,
+//! so it raises a [`CoapAnomaly`] alert.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use vakthund_protocols::coap::{Block, CoapOptions};
+use vakthund_protocols::CoapPacket;
+
+use crate::alert::{excerpt, Alert, FiveTuple, ScanContext, Severity};
+
+/// CoAP anomaly alerts use rule ids from here up, after the MQTT anomaly and
+/// IP fragmentation rules.
+pub const COAP_ANOMALY_RULE_BASE: u32 = 1_002_000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Minimum time between timeout sweeps, in nanoseconds.
+const SWEEP_INTERVAL: u64 = NANOS_PER_SEC;
+
+/// Notifications older than this are fresh whatever their sequence number
+/// (RFC 7641 section 3.4), in nanoseconds.
+const OBSERVE_REORDER_WINDOW: u64 = 128 * NANOS_PER_SEC;
+
+/// A block-wise transfer discarded before it completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoapAnomaly {
+    /// The body grew past `max_body_size`.
+    BodyTooLarge,
+    /// The endpoint pair had `max_exchanges` transfers in progress, so its
+    /// oldest transfer was evicted.
+    TransferEvicted,
+    /// Transfers in progress hold `max_total_buffer` bytes.
+    BufferExhausted,
+}
+
+impl CoapAnomaly {
+    pub fn rule_id(self) -> u32 {
+        COAP_ANOMALY_RULE_BASE + self as u32
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            CoapAnomaly::BodyTooLarge | CoapAnomaly::BufferExhausted => Severity::Medium,
+            CoapAnomaly::TransferEvicted => Severity::Low,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            CoapAnomaly::BodyTooLarge => "CoAP block-wise body exceeds the reassembly limit",
+            CoapAnomaly::TransferEvicted => "Too many concurrent CoAP block-wise transfers",
+            CoapAnomaly::BufferExhausted => "CoAP reassembly buffer exhausted",
+        }
+    }
+}
+
+/// Which body of an exchange a transfer carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoapBodyKind {
+    /// Request body, sent as Block1
+    Request,
+    /// Response or notification body, sent as Block2
+    Response,
+}
+
+/// A body reassembled from a completed block-wise transfer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoapBody {
+    pub kind: CoapBodyKind,
+    /// Method code of the request
+    pub method: u8,
+    /// Uri-Path of the request, e.g. `/fw/image`
+    pub uri: String,
+    /// Observe sequence number, if the body is a notification
+    pub observe: Option<u32>,
+    /// Number of blocks the body was assembled from
+    pub blocks: u32,
+    pub data: Vec<u8>,
+}
+
+/// Bounds for the exchange tracker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoapExchangeLimits {
+    /// Endpoint pairs tracked at once; further pairs are not tracked.
+    pub max_endpoints: usize,
+    /// Outstanding requests, observations and transfers, each, per endpoint
+    /// pair.
+    pub max_exchanges: usize,
+    /// Largest body reassembled; longer transfers are discarded.
+    pub max_body_size: usize,
+    /// Bytes held by transfers in progress across all endpoint pairs.
+    pub max_total_buffer: usize,
+    /// Time for a request to be answered or a transfer to continue, in
+    /// nanoseconds.
+    pub exchange_timeout_ns: u64,
+    /// Time after which a silent endpoint pair is forgotten, with its
+    /// observations, in nanoseconds.
+    pub idle_timeout_ns: u64,
+}
+
+impl Default for CoapExchangeLimits {
+    fn default() -> Self {
+        Self {
+            max_endpoints: 10_000,
+            max_exchanges: 64,
+            max_body_size: 1 << 20,
+            max_total_buffer: 16 << 20,
+            // EXCHANGE_LIFETIME with the default transmission parameters.
+            exchange_timeout_ns: 247 * NANOS_PER_SEC,
+            idle_timeout_ns: 3600 * NANOS_PER_SEC,
+        }
+    }
+}
+
+/// An endpoint pair, as the unordered pair of its addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct EndpointKey(SocketAddr, SocketAddr);
+
+impl EndpointKey {
+    fn new(flow: &FiveTuple) -> Self {
+        if flow.source <= flow.destination {
+            Self(flow.source, flow.destination)
+        } else {
+            Self(flow.destination, flow.source)
+        }
+    }
+}
+
+/// A token, as chosen by the requester.
+type TokenKey = (SocketAddr, Vec<u8>);
+
+/// A transfer, by requester, resource and direction. Later blocks may use
+/// other tokens, so the token is not part of the key.
+type TransferKey = (SocketAddr, String, CoapBodyKind);
+
+/// A request awaiting its response.
+#[derive(Clone, Debug)]
+struct Request {
+    method: u8,
+    uri: String,
+    message_id: u16,
+    /// Observe registration
+    register: bool,
+    since: u64,
+}
+
+/// An established Observe registration.
+#[derive(Clone, Debug)]
+struct Observation {
+    method: u8,
+    uri: String,
+    sequence: u32,
+    /// Message id of the latest notification, for matching resets
+    message_id: u16,
+    since: u64,
+}
+
+/// A block-wise transfer in progress.
+#[derive(Clone, Debug)]
+struct Transfer {
+    method: u8,
+    observe: Option<u32>,
+    blocks: u32,
+    data: Vec<u8>,
+    /// Message id of the latest block, for ignoring retransmissions
+    message_id: u16,
+    since: u64,
+}
+
+/// Limits, and the reassembly memory shared by every endpoint pair.
+#[derive(Debug)]
+struct Budget {
+    limits: CoapExchangeLimits,
+    /// Bytes held by transfers in progress
+    buffered: usize,
+    /// Transfers discarded while handling the current packet, with their
+    /// requester
+    dropped: Vec<(CoapAnomaly, SocketAddr)>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    last_seen: u64,
+    requests: HashMap<TokenKey, Request>,
+    observations: HashMap<TokenKey, Observation>,
+    transfers: HashMap<TransferKey, Transfer>,
+}
+
+impl Endpoint {
+    fn new(now: u64) -> Self {
+        Self {
+            last_seen: now,
+            requests: HashMap::new(),
+            observations: HashMap::new(),
+            transfers: HashMap::new(),
+        }
+    }
+
+    fn request(
+        &mut self,
+        packet: &CoapPacket<'_>,
+        flow: &FiveTuple,
+        now: u64,
+        budget: &mut Budget,
+    ) -> Option<CoapBody> {
+        let token = (flow.source, packet.token.to_vec());
+        let uri = uri(&packet.options);
+        let observe = packet.options.observe();
+        if observe == Some(1) {
+            self.observations.remove(&token);
+        }
+
+        let body = packet.options.block1().and_then(|block| {
+            let key = (flow.source, uri.clone(), CoapBodyKind::Request);
+            self.reassemble(key, block, packet, None, now, budget)
+        });
+
+        // A new request on a token replaces the earlier one.
+        if self.requests.len() < budget.limits.max_exchanges || self.requests.contains_key(&token) {
+            self.requests.insert(
+                token,
+                Request {
+                    method: packet.code,
+                    uri,
+                    message_id: packet.message_id,
+                    register: observe == Some(0),
+                    since: now,
+                },
+            );
+        }
+        body
+    }
+
+    fn response(
+        &mut self,
+        packet: &CoapPacket<'_>,
+        flow: &FiveTuple,
+        now: u64,
+        budget: &mut Budget,
+    ) -> Option<CoapBody> {
+        let token = (flow.destination, packet.token.to_vec());
+        let success = packet.code_class() == 2;
+        let observe = packet.options.observe();
+
+        let (method, uri) = if let Some(request) = self.requests.get(&token) {
+            // A piggybacked response echoes the message id of its request.
+            if packet.message_type == CoapPacket::ACKNOWLEDGEMENT
+                && packet.message_id != request.message_id
+            {
+                return None;
+            }
+            let request = self.requests.remove(&token)?;
+            if request.register && success {
+                if let Some(sequence) = observe {
+                    if self.observations.len() < budget.limits.max_exchanges {
+                        self.observations.insert(
+                            token.clone(),
+                            Observation {
+                                method: request.method,
+                                uri: request.uri.clone(),
+                                sequence,
+                                message_id: packet.message_id,
+                                since: now,
+                            },
+                        );
+                    }
+                }
+            }
+            (request.method, request.uri)
+        } else if let Some(observation) = self.observations.get_mut(&token) {
+            // An error or a response without Observe ends the observation.
+            let Some(sequence) = observe.filter(|_| success) else {
+                self.observations.remove(&token);
+                return None;
+            };
+            if !is_fresh(
+                observation.sequence,
+                sequence,
+                now.saturating_sub(observation.since),
+            ) {
+                return None;
+            }
+            observation.sequence = sequence;
+            observation.message_id = packet.message_id;
+            observation.since = now;
+            (observation.method, observation.uri.clone())
+        } else {
+            return None;
+        };
+
+        if packet.code_class() >= 4 {
+            // The server gave up on the request body.
+            self.discard(&(flow.destination, uri, CoapBodyKind::Request), budget);
+            return None;
+        }
+        let block = packet.options.block2().filter(|_| success)?;
+        let key = (flow.destination, uri, CoapBodyKind::Response);
+        self.reassemble(key, block, packet, observe, now, budget)
+            .map(|body| CoapBody { method, ..body })
+    }
+
+    /// Handles an empty acknowledgement or reset.
+    fn empty(&mut self, packet: &CoapPacket<'_>, flow: &FiveTuple) {
+        if packet.message_type != CoapPacket::RESET {
+            return;
+        }
+        // Rejecting a notification cancels the observation; rejecting a
+        // request abandons it.
+        let message_id = packet.message_id;
+        self.observations.retain(|(requester, _), observation| {
+            *requester != flow.source || observation.message_id != message_id
+        });
+        self.requests.retain(|(requester, _), request| {
+            *requester != flow.destination || request.message_id != message_id
+        });
+    }
+
+    /// Appends the block `packet` carries to its transfer, returning the body
+    /// once the last block arrives.
+    fn reassemble(
+        &mut self,
+        key: TransferKey,
+        block: Block,
+        packet: &CoapPacket<'_>,
+        observe: Option<u32>,
+        now: u64,
+        budget: &mut Budget,
+    ) -> Option<CoapBody> {
+        let payload = packet.payload();
+        if let Some(transfer) = self.transfers.get(&key) {
+            if transfer.message_id == packet.message_id {
+                // Retransmission of the latest block.
+                return None;
+            }
+        }
+        if block.num == 0 {
+            if !block.more {
+                // The whole body is in this message.
+                return None;
+            }
+            // A first block always starts a new body, e.g. a new notification.
+            self.discard(&key, budget);
+            if self.transfers.len() >= budget.limits.max_exchanges {
+                self.evict_oldest(budget);
+            }
+            self.transfers.insert(
+                key.clone(),
+                Transfer {
+                    method: packet.code,
+                    observe,
+                    blocks: 0,
+                    data: Vec::new(),
+                    message_id: packet.message_id,
+                    since: now,
+                },
+            );
+        }
+
+        let transfer = self.transfers.get_mut(&key)?;
+        let offset = block.offset();
+        if offset < transfer.data.len() {
+            // Block already received under another message id.
+            return None;
+        }
+        // Every block but the last fills its block size exactly.
+        let anomaly = if offset + payload.len() > budget.limits.max_body_size {
+            Some(CoapAnomaly::BodyTooLarge)
+        } else if budget.buffered + payload.len() > budget.limits.max_total_buffer {
+            Some(CoapAnomaly::BufferExhausted)
+        } else {
+            None
+        };
+        if anomaly.is_some()
+            || offset > transfer.data.len()
+            || (block.more && payload.len() != block.size())
+        {
+            self.discard(&key, budget);
+            if let Some(anomaly) = anomaly {
+                budget.dropped.push((anomaly, key.0));
+            }
+            return None;
+        }
+        transfer.data.extend_from_slice(payload);
+        budget.buffered += payload.len();
+        transfer.blocks += 1;
+        transfer.message_id = packet.message_id;
+        transfer.since = now;
+        if block.more {
+            return None;
+        }
+
+        let transfer = self.discard(&key, budget)?;
+        let (_, uri, kind) = key;
+        Some(CoapBody {
+            kind,
+            method: transfer.method,
+            uri,
+            observe: transfer.observe,
+            blocks: transfer.blocks,
+            data: transfer.data,
+        })
+    }
+
+    /// Removes a transfer, releasing its bytes from the budget.
+    fn discard(&mut self, key: &TransferKey, budget: &mut Budget) -> Option<Transfer> {
+        let transfer = self.transfers.remove(key)?;
+        budget.buffered -= transfer.data.len();
+        Some(transfer)
+    }
+
+    /// Evicts the transfer that progressed least recently.
+    fn evict_oldest(&mut self, budget: &mut Budget) {
+        let oldest = self
+            .transfers
+            .iter()
+            .min_by_key(|(_, transfer)| transfer.since)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.discard(&key, budget);
+            budget.dropped.push((CoapAnomaly::TransferEvicted, key.0));
+        }
+    }
+
+    /// Bytes held by this pair's transfers.
+    fn buffered(&self) -> usize {
+        self.transfers
+            .values()
+            .map(|transfer| transfer.data.len())
+            .sum()
+    }
+
+    fn is_idle(&self) -> bool {
+        self.requests.is_empty() && self.observations.is_empty() && self.transfers.is_empty()
+    }
+}
+
+/// Follows CoAP exchanges between endpoints and reassembles block-wise
+/// transfers.
+#[derive(Debug)]
+pub struct CoapExchangeTracker {
+    budget: Budget,
+    endpoints: HashMap<EndpointKey, Endpoint>,
+    last_sweep: u64,
+}
+
+impl CoapExchangeTracker {
+    pub fn new(limits: CoapExchangeLimits) -> Self {
+        Self {
+            budget: Budget {
+                limits,
+                buffered: 0,
+                dropped: Vec::new(),
+            },
+            endpoints: HashMap::new(),
+            last_sweep: 0,
+        }
+    }
+
+    /// Number of tracked endpoint pairs.
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// Bytes held by block-wise transfers in progress.
+    pub fn buffered_bytes(&self) -> usize {
+        self.budget.buffered
+    }
+
+    /// Requests on `flow`'s endpoint pair still awaiting a response.
+    pub fn pending_requests(&self, flow: &FiveTuple) -> usize {
+        self.endpoints
+            .get(&EndpointKey::new(flow))
+            .map_or(0, |endpoint| endpoint.requests.len())
+    }
+
+    /// Resources observed on `flow`'s endpoint pair, in no particular order.
+    pub fn observed(&self, flow: &FiveTuple) -> Vec<&str> {
+        self.endpoints
+            .get(&EndpointKey::new(flow))
+            .map_or_else(Vec::new, |endpoint| {
+                endpoint
+                    .observations
+                    .values()
+                    .map(|observation| observation.uri.as_str())
+                    .collect()
+            })
+    }
+
+    /// Updates the exchanges `packet` belongs to and returns the body it
+    /// completes, if it is the last block of a block-wise transfer.
+    /// Transfers discarded for exceeding a limit are reported in `alerts`.
+    pub fn track(
+        &mut self,
+        packet: &CoapPacket<'_>,
+        context: &ScanContext,
+        alerts: &mut Vec<Alert>,
+    ) -> Option<CoapBody> {
+        let now = context.timestamp;
+        self.sweep(now);
+        let flow = context.flow?;
+        let key = EndpointKey::new(&flow);
+        if !self.endpoints.contains_key(&key) {
+            // Only a request opens an exchange.
+            if !packet.is_request() || self.endpoints.len() >= self.budget.limits.max_endpoints {
+                return None;
+            }
+            self.endpoints.insert(key, Endpoint::new(now));
+        }
+
+        let endpoint = self.endpoints.get_mut(&key)?;
+        endpoint.last_seen = now;
+        let body = if packet.is_request() {
+            endpoint.request(packet, &flow, now, &mut self.budget)
+        } else if packet.is_response() {
+            endpoint.response(packet, &flow, now, &mut self.budget)
+        } else {
+            endpoint.empty(packet, &flow);
+            None
+        };
+
+        for (anomaly, requester) in self.budget.dropped.drain(..) {
+            let server = if requester == key.0 { key.1 } else { key.0 };
+            alerts.push(Alert {
+                timestamp: now,
+                rule_id: anomaly.rule_id(),
+                rule_revision: 1,
+                message: anomaly.message().to_string(),
+                severity: anomaly.severity(),
+                protocol: "CoAP".to_string(),
+                flow: Some(FiveTuple {
+                    source: requester,
+                    destination: server,
+                    protocol: flow.protocol,
+                }),
+                initiator: Some(requester),
+                interface: context.interface.clone(),
+                offsets: Vec::new(),
+                excerpt: excerpt(packet.payload(), &[]),
+            });
+        }
+        body
+    }
+
+    /// Expires unanswered requests, stalled transfers and silent endpoint
+    /// pairs, at most once per [`SWEEP_INTERVAL`].
+    fn sweep(&mut self, now: u64) {
+        if now < self.last_sweep.saturating_add(SWEEP_INTERVAL) {
+            return;
+        }
+        self.last_sweep = now;
+
+        let CoapExchangeLimits {
+            exchange_timeout_ns,
+            idle_timeout_ns,
+            ..
+        } = self.budget.limits;
+        let live = |since: u64| now.saturating_sub(since) <= exchange_timeout_ns;
+        let buffered = &mut self.budget.buffered;
+        self.endpoints.retain(|_, endpoint| {
+            endpoint.requests.retain(|_, request| live(request.since));
+            endpoint.transfers.retain(|_, transfer| {
+                let keep = live(transfer.since);
+                if !keep {
+                    *buffered -= transfer.data.len();
+                }
+                keep
+            });
+            let silent = now.saturating_sub(endpoint.last_seen);
+            let keep =
+                silent <= idle_timeout_ns && !(endpoint.is_idle() && silent > exchange_timeout_ns);
+            if !keep {
+                *buffered -= endpoint.buffered();
+            }
+            keep
+        });
+    }
+}
+
+/// The Uri-Path of a request, `/`-joined.
+fn uri(options: &CoapOptions<'_>) -> String {
+    let mut uri = String::new();
+    for segment in options.uri_path() {
+        uri.push('/');
+        uri.push_str(segment);
+    }
+    if uri.is_empty() {
+        uri.push('/');
+    }
+    uri
+}
+
+/// Whether notification `next` is newer than `previous`, received `elapsed`
+/// nanoseconds earlier (RFC 7641 section 3.4).
+fn is_fresh(previous: u32, next: u32, elapsed: u64) -> bool {
+    const WINDOW: u32 = 1 << 23;
+    (previous < next && next - previous < WINDOW)
+        || (previous > next && previous - next > WINDOW)
+        || elapsed > OBSERVE_REORDER_WINDOW
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signatures::SignatureEngine;
+    use bytes::Bytes;
+    use vakthund_protocols::CoapParser;
+
+    const CLIENT: &str = "10.0.0.1:40000";
+    const SERVER: &str = "10.0.0.2:5683";
+
+    const GET: u8 = 0x01;
+    const PUT: u8 = 0x03;
+    const CONTENT: u8 = 0x45;
+    const CONTINUE: u8 = 0x5F;
+    const CHANGED: u8 = 0x44;
+    const NOT_FOUND: u8 = 0x84;
+
+    const URI_PATH: u16 = 11;
+    const OBSERVE: u16 = 6;
+    const BLOCK2: u16 = 23;
+    const BLOCK1: u16 = 27;
+
+    /// Encodes a message; `options` must be in ascending order and short.
+    fn message(
+        message_type: u8,
+        code: u8,
+        message_id: u16,
+        token: &[u8],
+        options: &[(u16, &[u8])],
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut data = vec![0x40 | (message_type << 4) | token.len() as u8, code];
+        data.extend_from_slice(&message_id.to_be_bytes());
+        data.extend_from_slice(token);
+        let mut previous = 0;
+        for &(number, value) in options {
+            let delta = number - previous;
+            previous = number;
+            let length = value.len() as u8;
+            if delta < 13 {
+                data.push((delta as u8) << 4 | length);
+            } else {
+                data.push(0xD0 | length);
+                data.push(delta as u8 - 13);
+            }
+            data.extend_from_slice(value);
+        }
+        if !payload.is_empty() {
+            data.push(0xFF);
+            data.extend_from_slice(payload);
+        }
+        data
+    }
+
+    /// Feeds `data`, sent at `seconds` by `source` to `destination`, to the
+    /// tracker, which must not raise alerts.
+    fn send(
+        tracker: &mut CoapExchangeTracker,
+        source: &str,
+        destination: &str,
+        seconds: u64,
+        data: Vec<u8>,
+    ) -> Option<CoapBody> {
+        let (body, alerts) = exchange(tracker, source, destination, seconds, data);
+        assert!(alerts.is_empty(), "{alerts:?}");
+        body
+    }
+
+    fn exchange(
+        tracker: &mut CoapExchangeTracker,
+        source: &str,
+        destination: &str,
+        seconds: u64,
+        data: Vec<u8>,
+    ) -> (Option<CoapBody>, Vec<Alert>) {
+        let context = ScanContext {
+            timestamp: seconds * NANOS_PER_SEC,
+            protocol: "CoAP",
+            flow: Some(flow(source, destination)),
+            initiator: None,
+            interface: None,
+        };
+        let data = Bytes::from(data);
+        let packet = CoapParser::new().parse(&data).unwrap();
+        let mut alerts = Vec::new();
+        let body = tracker.track(&packet, &context, &mut alerts);
+        (body, alerts)
+    }
+
+    fn flow(source: &str, destination: &str) -> FiveTuple {
+        FiveTuple {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+            protocol: 17,
+        }
+    }
+
+    /// A Block option value with 16 byte blocks.
+    fn block(num: u8, more: bool) -> [u8; 1] {
+        [num << 4 | u8::from(more) << 3]
+    }
+
+    #[test]
+    fn reassembles_block1_request_body() {
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits::default());
+        let body = b"firmware image: version 2 payload tail";
+        let chunks: Vec<_> = body.chunks(16).collect();
+        // Both patterns straddle a block boundary.
+        let engine = SignatureEngine::new();
+        engine.add_pattern("image: version").unwrap();
+        engine.add_pattern("payload tail").unwrap();
+
+        let mut completed = None;
+        for (num, chunk) in chunks.iter().enumerate() {
+            assert!(engine.buffer_scan(chunk).is_empty());
+            let more = num + 1 < chunks.len();
+            let message_id = 0x100 + num as u16;
+            let request = message(
+                0,
+                PUT,
+                message_id,
+                &[num as u8],
+                &[(URI_PATH, b"fw"), (BLOCK1, &block(num as u8, more))],
+                chunk,
+            );
+            completed = send(&mut tracker, CLIENT, SERVER, 1, request.clone());
+            // Retransmissions are ignored.
+            assert_eq!(send(&mut tracker, CLIENT, SERVER, 1, request), None);
+            let code = if more { CONTINUE } else { CHANGED };
+            let ack = message(2, code, message_id, &[num as u8], &[], &[]);
+            assert_eq!(send(&mut tracker, SERVER, CLIENT, 1, ack), None);
+        }
+
+        let completed = completed.expect("body reassembled");
+        assert_eq!(completed.kind, CoapBodyKind::Request);
+        assert_eq!(completed.method, PUT);
+        assert_eq!(completed.uri, "/fw");
+        assert_eq!(completed.blocks, 3);
+        assert_eq!(completed.data, body);
+        assert_eq!(engine.buffer_scan(&completed.data), [0, 1]);
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 0);
+    }
+
+    #[test]
+    fn reassembles_block2_response_and_drops_gaps() {
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits::default());
+        let body = [0x5A; 40];
+
+        for (num, range) in [(0u8, 0..16), (1, 16..32), (2, 32..40)] {
+            let more = range.end < body.len();
+            let token = [0xA0 + num];
+            let request = message(
+                0,
+                GET,
+                0x200 + u16::from(num),
+                &token,
+                &[(URI_PATH, b"log"), (BLOCK2, &block(num, false))],
+                &[],
+            );
+            assert_eq!(send(&mut tracker, CLIENT, SERVER, 2, request), None);
+            let response = message(
+                2,
+                CONTENT,
+                0x200 + u16::from(num),
+                &token,
+                &[(BLOCK2, &block(num, more))],
+                &body[range],
+            );
+            let completed = send(&mut tracker, SERVER, CLIENT, 2, response);
+            assert_eq!(completed.is_some(), !more);
+            if let Some(completed) = completed {
+                assert_eq!(completed.kind, CoapBodyKind::Response);
+                assert_eq!(completed.method, GET);
+                assert_eq!(completed.uri, "/log");
+                assert_eq!(completed.data, body);
+            }
+        }
+
+        // A skipped block discards the transfer.
+        for (num, more) in [(0, true), (2, false)] {
+            let token = [0xB0 + num];
+            let request = message(
+                0,
+                GET,
+                0x300 + u16::from(num),
+                &token,
+                &[(URI_PATH, b"log")],
+                &[],
+            );
+            send(&mut tracker, CLIENT, SERVER, 3, request);
+            let response = message(
+                2,
+                CONTENT,
+                0x300 + u16::from(num),
+                &token,
+                &[(BLOCK2, &block(num, more))],
+                &body[..16],
+            );
+            assert_eq!(send(&mut tracker, SERVER, CLIENT, 3, response), None);
+        }
+    }
+
+    #[test]
+    fn pairs_piggybacked_responses_by_message_id() {
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits::default());
+        let request = message(0, GET, 0x10, b"t", &[(URI_PATH, b"a")], &[]);
+        send(&mut tracker, CLIENT, SERVER, 1, request);
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 1);
+
+        // Same token, wrong message id: not this request's response.
+        let stray = message(2, CONTENT, 0x11, b"t", &[], b"x");
+        send(&mut tracker, SERVER, CLIENT, 1, stray);
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 1);
+
+        // An empty ACK, then a separate response matched by token alone.
+        send(
+            &mut tracker,
+            SERVER,
+            CLIENT,
+            1,
+            message(2, 0, 0x10, b"", &[], &[]),
+        );
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 1);
+        let separate = message(0, CONTENT, 0x7000, b"t", &[], b"x");
+        send(&mut tracker, SERVER, CLIENT, 2, separate);
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 0);
+
+        // Unanswered requests expire.
+        let request = message(1, GET, 0x12, b"u", &[(URI_PATH, b"a")], &[]);
+        send(&mut tracker, CLIENT, SERVER, 3, request);
+        let other = message(1, GET, 0x13, b"v", &[], &[]);
+        send(&mut tracker, "10.0.0.3:40000", SERVER, 300, other);
+        assert_eq!(tracker.pending_requests(&flow(CLIENT, SERVER)), 0);
+        assert_eq!(tracker.len(), 1);
+    }
+
+    #[test]
+    fn follows_observe_registrations() {
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits::default());
+        let flow = flow(CLIENT, SERVER);
+        let register = message(
+            0,
+            GET,
+            0x20,
+            b"ob",
+            &[(OBSERVE, &[]), (URI_PATH, b"temp")],
+            &[],
+        );
+        send(&mut tracker, CLIENT, SERVER, 1, register);
+        let first = message(2, CONTENT, 0x20, b"ob", &[(OBSERVE, &[5])], b"21");
+        send(&mut tracker, SERVER, CLIENT, 1, first);
+        assert_eq!(tracker.observed(&flow), ["/temp"]);
+
+        // A block-wise notification is reassembled and attributed to the
+        // resource; a stale one is ignored.
+        let stale = message(
+            1,
+            CONTENT,
+            0x8000,
+            b"ob",
+            &[(OBSERVE, &[4]), (BLOCK2, &block(0, true))],
+            &[0; 16],
+        );
+        send(&mut tracker, SERVER, CLIENT, 2, stale);
+        let notification = message(
+            1,
+            CONTENT,
+            0x8001,
+            b"ob",
+            &[(OBSERVE, &[6]), (BLOCK2, &block(0, true))],
+            &[1; 16],
+        );
+        assert_eq!(send(&mut tracker, SERVER, CLIENT, 2, notification), None);
+        let rest = message(
+            0,
+            GET,
+            0x21,
+            b"nx",
+            &[(URI_PATH, b"temp"), (BLOCK2, &block(1, false))],
+            &[],
+        );
+        send(&mut tracker, CLIENT, SERVER, 2, rest);
+        let last = message(
+            2,
+            CONTENT,
+            0x21,
+            b"nx",
+            &[(BLOCK2, &block(1, false))],
+            &[2; 4],
+        );
+        let completed =
+            send(&mut tracker, SERVER, CLIENT, 2, last).expect("notification reassembled");
+        assert_eq!(completed.uri, "/temp");
+        assert_eq!(completed.observe, Some(6));
+        assert_eq!(completed.data.len(), 20);
+        assert_eq!(completed.data[0], 1);
+
+        // Resetting a confirmable notification cancels the observation.
+        let confirmable = message(0, CONTENT, 0x8002, b"ob", &[(OBSERVE, &[7])], b"22");
+        send(&mut tracker, SERVER, CLIENT, 3, confirmable);
+        assert_eq!(tracker.observed(&flow).len(), 1);
+        send(
+            &mut tracker,
+            CLIENT,
+            SERVER,
+            3,
+            message(3, 0, 0x8002, b"", &[], &[]),
+        );
+        assert!(tracker.observed(&flow).is_empty());
+
+        // So does an error response, or an explicit deregistration.
+        for end in [
+            message(1, NOT_FOUND, 0x8003, b"ob", &[], &[]),
+            message(
+                0,
+                GET,
+                0x22,
+                b"ob",
+                &[(OBSERVE, &[1]), (URI_PATH, b"temp")],
+                &[],
+            ),
+        ] {
+            let register = message(
+                0,
+                GET,
+                0x23,
+                b"ob",
+                &[(OBSERVE, &[]), (URI_PATH, b"temp")],
+                &[],
+            );
+            send(&mut tracker, CLIENT, SERVER, 4, register);
+            send(
+                &mut tracker,
+                SERVER,
+                CLIENT,
+                4,
+                message(2, CONTENT, 0x23, b"ob", &[(OBSERVE, &[9])], b"1"),
+            );
+            assert_eq!(tracker.observed(&flow).len(), 1);
+            let from_client = end[1] == GET;
+            let (source, destination) = if from_client {
+                (CLIENT, SERVER)
+            } else {
+                (SERVER, CLIENT)
+            };
+            send(&mut tracker, source, destination, 4, end);
+            assert!(tracker.observed(&flow).is_empty());
+        }
+    }
+
+    /// Sends the first 16 byte block of a PUT to `uri`.
+    fn first_block(tracker: &mut CoapExchangeTracker, message_id: u16, uri: &[u8]) -> Vec<Alert> {
+        let request = message(
+            0,
+            PUT,
+            message_id,
+            &message_id.to_be_bytes(),
+            &[(URI_PATH, uri), (BLOCK1, &block(0, true))],
+            &[0; 16],
+        );
+        exchange(tracker, CLIENT, SERVER, 1, request).1
+    }
+
+    #[test]
+    fn reports_transfers_dropped_at_limits() {
+        let anomalies = |alerts: Vec<Alert>| -> Vec<u32> {
+            alerts.into_iter().map(|alert| alert.rule_id).collect()
+        };
+
+        // The oldest transfer makes way for a new one.
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits {
+            max_exchanges: 2,
+            ..CoapExchangeLimits::default()
+        });
+        assert!(first_block(&mut tracker, 1, b"a").is_empty());
+        assert!(first_block(&mut tracker, 2, b"b").is_empty());
+        let alerts = first_block(&mut tracker, 3, b"c");
+        assert_eq!(
+            anomalies(alerts.clone()),
+            [CoapAnomaly::TransferEvicted.rule_id()]
+        );
+        assert_eq!(alerts[0].initiator, Some(CLIENT.parse().unwrap()));
+        assert_eq!(alerts[0].flow, Some(flow(CLIENT, SERVER)));
+        assert_eq!(tracker.buffered_bytes(), 32);
+
+        // A body over the size limit.
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits {
+            max_body_size: 16,
+            ..CoapExchangeLimits::default()
+        });
+        assert!(first_block(&mut tracker, 1, b"a").is_empty());
+        let second = message(
+            0,
+            PUT,
+            2,
+            b"x",
+            &[(URI_PATH, b"a"), (BLOCK1, &block(1, false))],
+            &[0; 4],
+        );
+        let (body, alerts) = exchange(&mut tracker, CLIENT, SERVER, 1, second);
+        assert_eq!(body, None);
+        assert_eq!(anomalies(alerts), [CoapAnomaly::BodyTooLarge.rule_id()]);
+        assert_eq!(tracker.buffered_bytes(), 0);
+
+        // Every transfer draws on one shared buffer.
+        let mut tracker = CoapExchangeTracker::new(CoapExchangeLimits {
+            max_total_buffer: 24,
+            ..CoapExchangeLimits::default()
+        });
+        assert!(first_block(&mut tracker, 1, b"a").is_empty());
+        assert_eq!(
+            anomalies(first_block(&mut tracker, 2, b"b")),
+            [CoapAnomaly::BufferExhausted.rule_id()]
+        );
+        assert_eq!(tracker.buffered_bytes(), 16);
+
+        // Expired transfers release their bytes.
+        let ping = message(0, GET, 9, b"p", &[], &[]);
+        send(&mut tracker, CLIENT, SERVER, 1000, ping);
+        assert_eq!(tracker.buffered_bytes(), 0);
+    }
+}
@@ -1,5 +1,13 @@
 //! Crate for signature-based and anomaly-based detection functionalities.
 
+pub mod alert;
+pub mod coap_exchange;
+pub mod mqtt_session;
 pub mod signatures;
 
-pub use signatures::SignatureEngine;
+pub use alert::{Alert, FiveTuple, ScanContext, Severity};
+pub use coap_exchange::{
+    CoapAnomaly, CoapBody, CoapBodyKind, CoapExchangeLimits, CoapExchangeTracker,
+};
+pub use mqtt_session::{MqttAnomaly, MqttSessionLimits, MqttSessionTracker};
+pub use signatures::{SignatureEngine, SignatureRule};
@@ -0,0 +1,1014 @@
+//! ## vakthund-detection::mqtt_session
+//! **Stateful MQTT session tracking**
+//!
+//! Per-packet parsing cannot see abuse that spans packets. The tracker follows
+//! each MQTT connection from CONNECT to DISCONNECT (keyed by its address pair)
+//! and raises protocol-anomaly [`Alert`]s for:
+//! - client packets after the broker refused the CONNECT, or a second
+//!   CONNECT on one connection
+//! - a client id taken over by another connection while the connection
+//!   that held it keeps sending
+//! - topic aliases that are zero, above the receiver's maximum or never mapped
+//! - reused packet ids, too many unacknowledged publications and
+//!   acknowledgements for unknown packet ids
+//! - publications the client never subscribed to (clean sessions only)
+//! - QoS 2 exchanges that never complete and clients silent for longer than
+//!   1.5 times their keep alive
+//!
+//! Connections first seen after their CONNECT (sensor restarts, expired
+//! sessions) are followed mid-stream without lifecycle alerts. Each anomaly
+//! is raised at most once per connection. Anomaly alerts use the
+//! rule ids from [`MQTT_ANOMALY_RULE_BASE`] up, clear of signature rule ids.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use vakthund_protocols::framing::MQTT_PORT;
+use vakthund_protocols::mqtt::{ControlPacket, Properties, Property, QoS};
+use vakthund_protocols::{MqttPacket, ProtocolVersion};
+
+use crate::alert::{excerpt, Alert, FiveTuple, ScanContext, Severity};
+
+/// Rule id of the first MQTT anomaly.
+pub const MQTT_ANOMALY_RULE_BASE: u32 = 1_000_000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Minimum time between timeout sweeps, in nanoseconds.
+const SWEEP_INTERVAL: u64 = NANOS_PER_SEC;
+
+/// Subscriptions remembered per connection.
+const MAX_SUBSCRIPTIONS: usize = 1024;
+
+/// A protocol anomaly detected across the packets of one connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MqttAnomaly {
+    PacketBeforeConnect,
+    DuplicateConnect,
+    ClientIdTakeover,
+    TopicAliasAbuse,
+    PacketIdReuse,
+    InflightExceeded,
+    UnknownPacketId,
+    UnsolicitedPublish,
+    Qos2Incomplete,
+    KeepAliveExpired,
+}
+
+impl MqttAnomaly {
+    pub fn rule_id(self) -> u32 {
+        MQTT_ANOMALY_RULE_BASE + self as u32
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            MqttAnomaly::ClientIdTakeover | MqttAnomaly::InflightExceeded => Severity::High,
+            MqttAnomaly::PacketBeforeConnect
+            | MqttAnomaly::DuplicateConnect
+            | MqttAnomaly::TopicAliasAbuse
+            | MqttAnomaly::UnsolicitedPublish => Severity::Medium,
+            MqttAnomaly::PacketIdReuse
+            | MqttAnomaly::UnknownPacketId
+            | MqttAnomaly::Qos2Incomplete
+            | MqttAnomaly::KeepAliveExpired => Severity::Low,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            MqttAnomaly::PacketBeforeConnect => "MQTT client packet without an accepted CONNECT",
+            MqttAnomaly::DuplicateConnect => "Second MQTT CONNECT on one connection",
+            MqttAnomaly::ClientIdTakeover => "MQTT client id in use by two live connections",
+            MqttAnomaly::TopicAliasAbuse => "Invalid or unmapped MQTT topic alias",
+            MqttAnomaly::PacketIdReuse => "MQTT packet id reused while in flight",
+            MqttAnomaly::InflightExceeded => "Too many unacknowledged MQTT publications",
+            MqttAnomaly::UnknownPacketId => "MQTT acknowledgement for unknown packet id",
+            MqttAnomaly::UnsolicitedPublish => "MQTT publication without matching subscription",
+            MqttAnomaly::Qos2Incomplete => "MQTT QoS 2 exchange never completed",
+            MqttAnomaly::KeepAliveExpired => "MQTT client exceeded its keep alive",
+        }
+    }
+}
+
+/// Bounds for the session tracker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttSessionLimits {
+    /// Connections tracked at once; further connections are not tracked.
+    pub max_sessions: usize,
+    /// Unacknowledged publications per direction before
+    /// [`MqttAnomaly::InflightExceeded`], unless the receiver announced a
+    /// lower receive maximum.
+    pub max_inflight: usize,
+    /// Time for a QoS 1 or 2 exchange to complete, in nanoseconds. Expired
+    /// QoS 2 exchanges raise [`MqttAnomaly::Qos2Incomplete`].
+    pub ack_timeout_ns: u64,
+    /// Time after which a silent connection without keep alive is dropped,
+    /// in nanoseconds.
+    pub idle_timeout_ns: u64,
+}
+
+impl Default for MqttSessionLimits {
+    fn default() -> Self {
+        Self {
+            max_sessions: 10_000,
+            max_inflight: 1024,
+            ack_timeout_ns: 30 * NANOS_PER_SEC,
+            idle_timeout_ns: 300 * NANOS_PER_SEC,
+        }
+    }
+}
+
+/// A connection, as the unordered pair of its endpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct SessionKey(SocketAddr, SocketAddr);
+
+impl SessionKey {
+    fn new(flow: &FiveTuple) -> Self {
+        if flow.source <= flow.destination {
+            Self(flow.source, flow.destination)
+        } else {
+            Self(flow.destination, flow.source)
+        }
+    }
+}
+
+/// An unacknowledged QoS 1 or 2 publication.
+#[derive(Clone, Copy, Debug)]
+struct Inflight {
+    qos: QoS,
+    /// PUBREC seen (QoS 2)
+    received: bool,
+    since: u64,
+}
+
+/// State of one side of a connection as a publisher.
+#[derive(Debug, Default)]
+struct Direction {
+    inflight: HashMap<u16, Inflight>,
+    /// Receive maximum announced by the other side
+    receive_maximum: Option<u16>,
+    /// Topic alias maximum announced by the other side (0 forbids aliases)
+    alias_maximum: u16,
+    aliases: HashSet<u16>,
+}
+
+/// Where a connection is in its lifecycle, as far as the tracker saw it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SessionState {
+    /// First seen after its CONNECT; lifecycle anomalies are not raised.
+    MidStream,
+    Connected,
+    /// The broker refused the CONNECT.
+    Refused,
+}
+
+#[derive(Debug)]
+struct Session {
+    client: SocketAddr,
+    broker: SocketAddr,
+    interface: Option<Arc<str>>,
+    state: SessionState,
+    /// Another connection claimed this connection's client id.
+    superseded: bool,
+    client_id: Option<String>,
+    version: ProtocolVersion,
+    clean_session: bool,
+    /// Keep alive in nanoseconds (0 disables it)
+    keep_alive: u64,
+    last_client_activity: u64,
+    last_seen: u64,
+    subscriptions: Vec<String>,
+    from_client: Direction,
+    from_broker: Direction,
+    /// Anomalies already raised, one bit per [`MqttAnomaly`]
+    raised: u32,
+}
+
+impl Session {
+    fn new(client: SocketAddr, broker: SocketAddr, context: &ScanContext) -> Self {
+        Self {
+            client,
+            broker,
+            interface: context.interface.clone(),
+            state: SessionState::MidStream,
+            superseded: false,
+            client_id: None,
+            version: ProtocolVersion::default(),
+            clean_session: false,
+            keep_alive: 0,
+            last_client_activity: context.timestamp,
+            last_seen: context.timestamp,
+            subscriptions: Vec::new(),
+            from_client: Direction::default(),
+            from_broker: Direction::default(),
+            raised: 0,
+        }
+    }
+
+    fn direction(&mut self, from_client: bool) -> &mut Direction {
+        if from_client {
+            &mut self.from_client
+        } else {
+            &mut self.from_broker
+        }
+    }
+
+    /// Records `anomaly`, returning `false` if it was already raised.
+    fn raise(&mut self, anomaly: MqttAnomaly) -> bool {
+        let bit = 1 << anomaly as u32;
+        let first = self.raised & bit == 0;
+        self.raised |= bit;
+        first
+    }
+
+    /// Addressing from the client to the broker.
+    fn flow(&self) -> FiveTuple {
+        FiveTuple {
+            source: self.client,
+            destination: self.broker,
+            protocol: 6,
+        }
+    }
+}
+
+/// Follows MQTT connections and reports protocol anomalies.
+#[derive(Debug)]
+pub struct MqttSessionTracker {
+    limits: MqttSessionLimits,
+    sessions: HashMap<SessionKey, Session>,
+    /// Connection currently holding each client id
+    client_ids: HashMap<String, SessionKey>,
+    last_sweep: u64,
+}
+
+impl MqttSessionTracker {
+    pub fn new(limits: MqttSessionLimits) -> Self {
+        Self {
+            limits,
+            sessions: HashMap::new(),
+            client_ids: HashMap::new(),
+            last_sweep: 0,
+        }
+    }
+
+    /// Number of tracked connections.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Protocol version negotiated on `flow`'s connection, for decoding its
+    /// packets other than CONNECT.
+    pub fn version(&self, flow: &FiveTuple) -> ProtocolVersion {
+        self.sessions
+            .get(&SessionKey::new(flow))
+            .map_or_else(ProtocolVersion::default, |session| session.version)
+    }
+
+    /// Updates the connection `packet` was sent on and returns the anomalies
+    /// it reveals, together with any timeouts that have expired since.
+    pub fn observe(&mut self, packet: &MqttPacket<'_>, context: &ScanContext) -> Vec<Alert> {
+        let mut alerts = self.sweep(context.timestamp);
+        let Some(flow) = context.flow else {
+            return alerts;
+        };
+        let key = SessionKey::new(&flow);
+        if !self.sessions.contains_key(&key) {
+            if self.sessions.len() >= self.limits.max_sessions {
+                return alerts;
+            }
+            let (client, broker) = if is_client_packet(packet, &flow) {
+                (flow.source, flow.destination)
+            } else {
+                (flow.destination, flow.source)
+            };
+            self.sessions
+                .insert(key, Session::new(client, broker, context));
+        }
+
+        // Anomalies are the client's doing even when the broker's packet
+        // reveals them, so alerts always point from client to broker.
+        let client_flow = self.sessions.get(&key).map(Session::flow);
+        alerts.extend(
+            self.update(key, packet, &flow, context.timestamp)
+                .into_iter()
+                .map(|anomaly| {
+                    anomaly_alert(
+                        anomaly,
+                        context.timestamp,
+                        client_flow,
+                        context.interface.clone(),
+                        packet.payload,
+                    )
+                }),
+        );
+        alerts
+    }
+
+    /// Applies `packet` to its connection and returns the anomalies not yet
+    /// raised on it.
+    fn update(
+        &mut self,
+        key: SessionKey,
+        packet: &MqttPacket<'_>,
+        flow: &FiveTuple,
+        now: u64,
+    ) -> Vec<MqttAnomaly> {
+        let Some(session) = self.sessions.get_mut(&key) else {
+            return Vec::new();
+        };
+        let from_client = flow.source == session.client;
+        session.last_seen = now;
+        if from_client {
+            session.last_client_activity = now;
+        }
+
+        let mut found = Vec::new();
+        let mut claimed_id = None;
+        let mut closed = false;
+        let is_connect = matches!(packet.control, ControlPacket::Connect(_));
+        if from_client && session.state == SessionState::Refused && !is_connect {
+            found.push(MqttAnomaly::PacketBeforeConnect);
+        }
+        // Brokers close the old connection when a client id is reused; one
+        // that keeps sending is a second device with the same id.
+        if from_client && session.superseded {
+            found.push(MqttAnomaly::ClientIdTakeover);
+        }
+        match &packet.control {
+            ControlPacket::Connect(connect) if from_client => {
+                if session.state == SessionState::Connected {
+                    found.push(MqttAnomaly::DuplicateConnect);
+                } else {
+                    session.state = SessionState::Connected;
+                    session.version = connect.version;
+                    session.clean_session = connect.clean_session;
+                    session.keep_alive = u64::from(connect.keep_alive) * NANOS_PER_SEC;
+                    // The client's limits bound what the broker may send it.
+                    session.from_broker.alias_maximum = topic_alias_maximum(&connect.properties);
+                    session.from_broker.receive_maximum = receive_maximum(&connect.properties);
+                    // Claimed once the broker accepts the connection.
+                    if !connect.client_id.is_empty() {
+                        session.client_id = Some(connect.client_id.to_string());
+                    }
+                }
+            }
+            ControlPacket::Connack {
+                reason_code,
+                properties,
+                ..
+            } if !from_client => {
+                session.from_client.alias_maximum = topic_alias_maximum(properties);
+                session.from_client.receive_maximum = receive_maximum(properties);
+                if *reason_code != 0 {
+                    session.state = SessionState::Refused;
+                } else {
+                    let assigned = properties.iter().find_map(|property| match property {
+                        Property::AssignedClientIdentifier(id) => Some(id.to_string()),
+                        _ => None,
+                    });
+                    claimed_id = assigned.or_else(|| session.client_id.clone());
+                }
+            }
+            ControlPacket::Publish(publish) => {
+                let subscribed = from_client
+                    || !session.clean_session
+                    || publish.topic.is_empty()
+                    || session
+                        .subscriptions
+                        .iter()
+                        .any(|filter| topic_matches(filter, publish.topic));
+                if !subscribed {
+                    found.push(MqttAnomaly::UnsolicitedPublish);
+                }
+
+                let max_inflight = self.limits.max_inflight;
+                let direction = session.direction(from_client);
+                if let Some(alias) = publish.properties.topic_alias() {
+                    if alias == 0 || alias > direction.alias_maximum {
+                        found.push(MqttAnomaly::TopicAliasAbuse);
+                    } else if publish.topic.is_empty() {
+                        if !direction.aliases.contains(&alias) {
+                            found.push(MqttAnomaly::TopicAliasAbuse);
+                        }
+                    } else {
+                        direction.aliases.insert(alias);
+                    }
+                }
+                if let Some(packet_id) = publish.packet_id {
+                    if direction.inflight.contains_key(&packet_id) && !publish.dup {
+                        found.push(MqttAnomaly::PacketIdReuse);
+                    }
+                    let limit = direction
+                        .receive_maximum
+                        .map_or(max_inflight, |maximum| max_inflight.min(maximum.into()));
+                    // Publications over the limit are not remembered, so the
+                    // table stays bounded.
+                    if direction.inflight.contains_key(&packet_id)
+                        || direction.inflight.len() < limit
+                    {
+                        direction.inflight.insert(
+                            packet_id,
+                            Inflight {
+                                qos: publish.qos,
+                                received: false,
+                                since: now,
+                            },
+                        );
+                    } else {
+                        found.push(MqttAnomaly::InflightExceeded);
+                    }
+                }
+            }
+            // PUBACK, PUBREC and PUBCOMP come from the receiver, PUBREL from
+            // the publisher.
+            ControlPacket::Puback(ack) => {
+                let inflight = &mut session.direction(!from_client).inflight;
+                match inflight.get(&ack.packet_id) {
+                    Some(entry) if entry.qos == QoS::AtLeastOnce => {
+                        inflight.remove(&ack.packet_id);
+                    }
+                    _ => found.push(MqttAnomaly::UnknownPacketId),
+                }
+            }
+            ControlPacket::Pubrec(ack) => {
+                let inflight = &mut session.direction(!from_client).inflight;
+                match inflight.get_mut(&ack.packet_id) {
+                    // A failure reason code ends the exchange.
+                    Some(entry) if entry.qos == QoS::ExactlyOnce && ack.reason_code >= 0x80 => {
+                        inflight.remove(&ack.packet_id);
+                    }
+                    Some(entry) if entry.qos == QoS::ExactlyOnce => entry.received = true,
+                    _ => found.push(MqttAnomaly::UnknownPacketId),
+                }
+            }
+            ControlPacket::Pubrel(ack) => {
+                let inflight = &session.direction(from_client).inflight;
+                if !inflight
+                    .get(&ack.packet_id)
+                    .is_some_and(|entry| entry.received)
+                {
+                    found.push(MqttAnomaly::UnknownPacketId);
+                }
+            }
+            ControlPacket::Pubcomp(ack) => {
+                let inflight = &mut session.direction(!from_client).inflight;
+                match inflight.get(&ack.packet_id) {
+                    Some(entry) if entry.received => {
+                        inflight.remove(&ack.packet_id);
+                    }
+                    _ => found.push(MqttAnomaly::UnknownPacketId),
+                }
+            }
+            ControlPacket::Subscribe { subscriptions, .. } if from_client => {
+                for subscription in subscriptions.iter() {
+                    if session.subscriptions.len() < MAX_SUBSCRIPTIONS
+                        && !session
+                            .subscriptions
+                            .iter()
+                            .any(|f| f == subscription.filter)
+                    {
+                        session.subscriptions.push(subscription.filter.to_string());
+                    }
+                }
+            }
+            ControlPacket::Unsubscribe { filters, .. } if from_client => {
+                session
+                    .subscriptions
+                    .retain(|subscribed| !filters.iter().any(|filter| filter == subscribed));
+            }
+            ControlPacket::Disconnect { .. } => closed = true,
+            _ => {}
+        }
+
+        if let Some(client_id) = claimed_id {
+            session.client_id = Some(client_id.clone());
+            let holder = self.client_ids.insert(client_id, key);
+            if let Some(previous) = holder
+                .filter(|&holder| holder != key)
+                .and_then(|holder| self.sessions.get_mut(&holder))
+            {
+                previous.superseded = true;
+            }
+        }
+
+        let Some(session) = self.sessions.get_mut(&key) else {
+            return Vec::new();
+        };
+        found.retain(|&anomaly| session.raise(anomaly));
+        if closed {
+            self.remove(key);
+        }
+        found
+    }
+
+    /// Expires QoS 2 exchanges and silent connections, at most once per
+    /// [`SWEEP_INTERVAL`].
+    fn sweep(&mut self, now: u64) -> Vec<Alert> {
+        if now < self.last_sweep.saturating_add(SWEEP_INTERVAL) {
+            return Vec::new();
+        }
+        self.last_sweep = now;
+
+        let MqttSessionLimits {
+            ack_timeout_ns,
+            idle_timeout_ns,
+            ..
+        } = self.limits;
+        let mut alerts = Vec::new();
+        let mut expired = Vec::new();
+        for (&key, session) in &mut self.sessions {
+            let mut stalled = false;
+            for direction in [&mut session.from_client, &mut session.from_broker] {
+                // Unacknowledged QoS 1 publications expire silently.
+                direction.inflight.retain(|_, entry| {
+                    let stale = now.saturating_sub(entry.since) > ack_timeout_ns;
+                    stalled |= stale && entry.qos == QoS::ExactlyOnce;
+                    !stale
+                });
+            }
+            let mut raised = Vec::new();
+            if stalled {
+                raised.push(MqttAnomaly::Qos2Incomplete);
+            }
+
+            // Brokers drop clients silent for 1.5 times their keep alive.
+            let silent = now.saturating_sub(session.last_client_activity);
+            if session.state == SessionState::Connected
+                && session.keep_alive > 0
+                && silent > session.keep_alive * 3 / 2
+            {
+                raised.push(MqttAnomaly::KeepAliveExpired);
+                expired.push(key);
+            } else if now.saturating_sub(session.last_seen) > idle_timeout_ns {
+                expired.push(key);
+            }
+
+            for anomaly in raised {
+                if session.raise(anomaly) {
+                    alerts.push(anomaly_alert(
+                        anomaly,
+                        now,
+                        Some(session.flow()),
+                        session.interface.clone(),
+                        &[],
+                    ));
+                }
+            }
+        }
+        for key in expired {
+            self.remove(key);
+        }
+        alerts
+    }
+
+    fn remove(&mut self, key: SessionKey) {
+        let Some(session) = self.sessions.remove(&key) else {
+            return;
+        };
+        if let Some(client_id) = session.client_id {
+            if self.client_ids.get(&client_id) == Some(&key) {
+                self.client_ids.remove(&client_id);
+            }
+        }
+    }
+}
+
+fn anomaly_alert(
+    anomaly: MqttAnomaly,
+    timestamp: u64,
+    flow: Option<FiveTuple>,
+    interface: Option<Arc<str>>,
+    data: &[u8],
+) -> Alert {
+    Alert {
+        timestamp,
+        rule_id: anomaly.rule_id(),
+        rule_revision: 1,
+        message: anomaly.message().to_string(),
+        severity: anomaly.severity(),
+        protocol: "MQTT".to_string(),
+        flow,
+        initiator: flow.map(|flow| flow.source),
+        interface,
+        offsets: Vec::new(),
+        excerpt: excerpt(data, &[]),
+    }
+}
+
+fn topic_alias_maximum(properties: &Properties<'_>) -> u16 {
+    properties
+        .iter()
+        .find_map(|property| match property {
+            Property::TopicAliasMaximum(maximum) => Some(maximum),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+fn receive_maximum(properties: &Properties<'_>) -> Option<u16> {
+    properties.iter().find_map(|property| match property {
+        Property::ReceiveMaximum(maximum) => Some(maximum),
+        _ => None,
+    })
+}
+
+/// Whether `topic` matches the subscription `filter`, including `+` and `#`
+/// wildcards and shared subscriptions (`$share/{group}/{filter}`).
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let filter = filter
+        .strip_prefix("$share/")
+        .and_then(|shared| shared.split_once('/'))
+        .map_or(filter, |(_, filter)| filter);
+    // Wildcards at the first level do not match `$SYS`-style topics.
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match part {
+            "#" => return true,
+            "+" => {
+                if levels.next().is_none() {
+                    return false;
+                }
+            }
+            _ => {
+                if levels.next() != Some(part) {
+                    return false;
+                }
+            }
+        }
+    }
+    levels.next().is_none()
+}
+
+/// Whether `packet`, the first seen on its connection, was sent by the client.
+fn is_client_packet(packet: &MqttPacket<'_>, flow: &FiveTuple) -> bool {
+    if flow.destination.port() == MQTT_PORT {
+        return true;
+    }
+    if flow.source.port() == MQTT_PORT {
+        return false;
+    }
+    !matches!(
+        packet.control,
+        ControlPacket::Connack { .. }
+            | ControlPacket::Suback { .. }
+            | ControlPacket::Unsuback { .. }
+            | ControlPacket::Pingresp
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use vakthund_protocols::MqttParser;
+
+    const CLIENT: &str = "10.0.0.1:50000";
+    const BROKER: &str = "10.0.0.2:1883";
+
+    /// Feeds `data`, sent at `seconds` by `source` to `destination`, to the tracker.
+    fn send(
+        tracker: &mut MqttSessionTracker,
+        source: &str,
+        destination: &str,
+        seconds: u64,
+        data: &[u8],
+    ) -> Vec<MqttAnomaly> {
+        let flow = FiveTuple {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+            protocol: 6,
+        };
+        let bytes = Bytes::copy_from_slice(data);
+        let packet = MqttParser::new()
+            .with_version(tracker.version(&flow))
+            .parse(&bytes)
+            .unwrap();
+        let context = ScanContext {
+            timestamp: seconds * NANOS_PER_SEC,
+            protocol: "MQTT",
+            flow: Some(flow),
+            initiator: None,
+            interface: None,
+        };
+        tracker
+            .observe(&packet, &context)
+            .into_iter()
+            .map(|alert| anomaly(alert.rule_id))
+            .collect()
+    }
+
+    fn anomaly(rule_id: u32) -> MqttAnomaly {
+        [
+            MqttAnomaly::PacketBeforeConnect,
+            MqttAnomaly::DuplicateConnect,
+            MqttAnomaly::ClientIdTakeover,
+            MqttAnomaly::TopicAliasAbuse,
+            MqttAnomaly::PacketIdReuse,
+            MqttAnomaly::InflightExceeded,
+            MqttAnomaly::UnknownPacketId,
+            MqttAnomaly::UnsolicitedPublish,
+            MqttAnomaly::Qos2Incomplete,
+            MqttAnomaly::KeepAliveExpired,
+        ]
+        .into_iter()
+        .find(|anomaly| anomaly.rule_id() == rule_id)
+        .unwrap()
+    }
+
+    /// CONNECT with clean session, the given level, keep alive and client id.
+    fn connect(level: u8, keep_alive: u16, client_id: &str) -> Vec<u8> {
+        let mut body = b"\x00\x04MQTT".to_vec();
+        body.extend_from_slice(&[level, 0x02]);
+        body.extend_from_slice(&keep_alive.to_be_bytes());
+        if level == 5 {
+            // Topic alias maximum 2.
+            body.extend_from_slice(&[0x03, 0x22, 0x00, 0x02]);
+        }
+        body.extend_from_slice(&(client_id.len() as u16).to_be_bytes());
+        body.extend_from_slice(client_id.as_bytes());
+        let mut packet = vec![0x10, body.len() as u8];
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    #[test]
+    fn flags_packets_outside_the_connection_lifecycle() {
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        // A connection first seen mid-stream is followed without alerts.
+        assert!(send(&mut tracker, CLIENT, BROKER, 1, b"\x30\x03\x00\x01a").is_empty());
+        assert!(send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 0, "a")).is_empty());
+        assert_eq!(
+            send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 0, "a")),
+            [MqttAnomaly::DuplicateConnect]
+        );
+        assert!(send(&mut tracker, CLIENT, BROKER, 2, b"\xE0\x00").is_empty());
+        assert!(tracker.is_empty());
+
+        // Publishing after the broker refused the connection.
+        send(&mut tracker, CLIENT, BROKER, 3, &connect(4, 0, "a"));
+        assert!(send(&mut tracker, BROKER, CLIENT, 3, b"\x20\x02\x00\x05").is_empty());
+        assert_eq!(
+            send(&mut tracker, CLIENT, BROKER, 3, b"\x30\x03\x00\x01a"),
+            [MqttAnomaly::PacketBeforeConnect]
+        );
+        // Raised once per connection.
+        assert!(send(&mut tracker, CLIENT, BROKER, 3, b"\x30\x03\x00\x01a").is_empty());
+    }
+
+    #[test]
+    fn detects_client_id_takeover() {
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        let intruder = "10.0.0.9:40000";
+        let accepted = b"\x20\x02\x00\x00";
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 0, "plc-1"));
+        send(&mut tracker, BROKER, CLIENT, 1, accepted);
+        // A reconnect while the old connection is still tracked, e.g. after a
+        // NAT drop, is not a takeover on its own.
+        assert!(send(&mut tracker, intruder, BROKER, 2, &connect(4, 0, "plc-1")).is_empty());
+        assert!(send(&mut tracker, BROKER, intruder, 2, accepted).is_empty());
+        // The old connection sending afterwards is.
+        assert_eq!(
+            send(&mut tracker, CLIENT, BROKER, 3, b"\xC0\x00"),
+            [MqttAnomaly::ClientIdTakeover]
+        );
+
+        // Reconnecting after a clean disconnect is not a takeover.
+        send(&mut tracker, intruder, BROKER, 4, b"\xE0\x00");
+        send(&mut tracker, CLIENT, BROKER, 4, b"\xE0\x00");
+        send(&mut tracker, intruder, BROKER, 5, &connect(4, 0, "plc-1"));
+        assert!(send(&mut tracker, BROKER, intruder, 5, accepted).is_empty());
+        assert!(send(&mut tracker, intruder, BROKER, 5, b"\xC0\x00").is_empty());
+
+        // A refused CONNECT claims nothing.
+        send(&mut tracker, CLIENT, BROKER, 6, &connect(4, 0, "plc-1"));
+        send(&mut tracker, BROKER, CLIENT, 6, b"\x20\x02\x00\x02");
+        assert!(send(&mut tracker, intruder, BROKER, 6, b"\xC0\x00").is_empty());
+    }
+
+    #[test]
+    fn tracks_topic_aliases_and_subscriptions() {
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(5, 0, "c"));
+        assert_eq!(
+            tracker.version(&FiveTuple {
+                source: BROKER.parse().unwrap(),
+                destination: CLIENT.parse().unwrap(),
+                protocol: 6,
+            }),
+            ProtocolVersion::V5
+        );
+        // The broker allows no client aliases; the client allows two.
+        assert_eq!(
+            send(
+                &mut tracker,
+                CLIENT,
+                BROKER,
+                1,
+                b"\x30\x07\x00\x01a\x03\x23\x00\x01"
+            ),
+            [MqttAnomaly::TopicAliasAbuse]
+        );
+
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(5, 0, "c"));
+        send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x82\x09\x00\x01\x00\x00\x03a/+\x00",
+        );
+        // Alias 1 for topic a/b, then used alone.
+        assert!(send(
+            &mut tracker,
+            BROKER,
+            CLIENT,
+            1,
+            b"\x30\x09\x00\x03a/b\x03\x23\x00\x01"
+        )
+        .is_empty());
+        assert!(send(
+            &mut tracker,
+            BROKER,
+            CLIENT,
+            1,
+            b"\x30\x06\x00\x00\x03\x23\x00\x01"
+        )
+        .is_empty());
+        // Alias 2 was never mapped.
+        assert_eq!(
+            send(
+                &mut tracker,
+                BROKER,
+                CLIENT,
+                1,
+                b"\x30\x06\x00\x00\x03\x23\x00\x02"
+            ),
+            [MqttAnomaly::TopicAliasAbuse]
+        );
+        assert_eq!(
+            send(&mut tracker, BROKER, CLIENT, 1, b"\x30\x06\x00\x03b/c\x00"),
+            [MqttAnomaly::UnsolicitedPublish]
+        );
+    }
+
+    #[test]
+    fn attributes_anomalies_to_the_client() {
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 0, "c"));
+        let data = Bytes::from_static(b"\x40\x02\x00\x09");
+        let packet = MqttParser::new().parse(&data).unwrap();
+        let from_broker = FiveTuple {
+            source: BROKER.parse().unwrap(),
+            destination: CLIENT.parse().unwrap(),
+            protocol: 6,
+        };
+        let context = ScanContext {
+            timestamp: NANOS_PER_SEC,
+            protocol: "MQTT",
+            flow: Some(from_broker),
+            initiator: None,
+            interface: None,
+        };
+        let alerts = tracker.observe(&packet, &context);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule_id, MqttAnomaly::UnknownPacketId.rule_id());
+        let flow = alerts[0].flow.unwrap();
+        assert_eq!(flow.source, CLIENT.parse().unwrap());
+        assert_eq!(flow.destination, BROKER.parse().unwrap());
+    }
+
+    #[test]
+    fn follows_in_flight_packet_ids() {
+        let limits = MqttSessionLimits {
+            max_inflight: 2,
+            ..MqttSessionLimits::default()
+        };
+        let mut tracker = MqttSessionTracker::new(limits);
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 0, "c"));
+
+        // QoS 1 publication and its acknowledgement.
+        assert!(send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x32\x05\x00\x01a\x00\x01"
+        )
+        .is_empty());
+        assert!(send(&mut tracker, BROKER, CLIENT, 1, b"\x40\x02\x00\x01").is_empty());
+        assert_eq!(
+            send(&mut tracker, BROKER, CLIENT, 1, b"\x40\x02\x00\x01"),
+            [MqttAnomaly::UnknownPacketId]
+        );
+
+        // A complete QoS 2 exchange.
+        for (source, destination, data) in [
+            (CLIENT, BROKER, &b"\x34\x05\x00\x01a\x00\x02"[..]),
+            (BROKER, CLIENT, b"\x50\x02\x00\x02"),
+            (CLIENT, BROKER, b"\x62\x02\x00\x02"),
+            (BROKER, CLIENT, b"\x70\x02\x00\x02"),
+        ] {
+            assert!(send(&mut tracker, source, destination, 1, data).is_empty());
+        }
+
+        assert!(send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x32\x05\x00\x01a\x00\x03"
+        )
+        .is_empty());
+        assert_eq!(
+            send(
+                &mut tracker,
+                CLIENT,
+                BROKER,
+                1,
+                b"\x32\x05\x00\x01a\x00\x03"
+            ),
+            [MqttAnomaly::PacketIdReuse]
+        );
+        // A retransmission flagged DUP is not a reuse.
+        assert!(send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x3A\x05\x00\x01a\x00\x03"
+        )
+        .is_empty());
+        send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x32\x05\x00\x01a\x00\x04",
+        );
+        assert_eq!(
+            send(
+                &mut tracker,
+                CLIENT,
+                BROKER,
+                1,
+                b"\x32\x05\x00\x01a\x00\x05"
+            ),
+            [MqttAnomaly::InflightExceeded]
+        );
+        // Publications over the limit are not remembered.
+        let session = tracker.sessions.values().next().unwrap();
+        assert_eq!(session.from_client.inflight.len(), 2);
+        assert!(!session.from_client.inflight.contains_key(&5));
+    }
+
+    #[test]
+    fn expires_stalled_exchanges_and_silent_clients() {
+        let mut tracker = MqttSessionTracker::new(MqttSessionLimits::default());
+        let other = "10.0.0.3:50001";
+        send(&mut tracker, CLIENT, BROKER, 1, &connect(4, 10, "a"));
+        send(
+            &mut tracker,
+            CLIENT,
+            BROKER,
+            1,
+            b"\x34\x05\x00\x01a\x00\x02",
+        );
+        send(&mut tracker, other, BROKER, 1, &connect(4, 0, "b"));
+        send(&mut tracker, other, BROKER, 1, b"\x32\x05\x00\x01a\x00\x07");
+
+        // Within 1.5 times the keep alive and the QoS 2 timeout.
+        assert!(send(&mut tracker, other, BROKER, 15, b"\xC0\x00").is_empty());
+        let mut expired = send(&mut tracker, other, BROKER, 40, b"\xC0\x00");
+        expired.sort_by_key(|anomaly| anomaly.rule_id());
+        assert_eq!(
+            expired,
+            [MqttAnomaly::Qos2Incomplete, MqttAnomaly::KeepAliveExpired]
+        );
+        assert_eq!(tracker.len(), 1);
+        // The unacknowledged QoS 1 publication expired silently.
+        assert_eq!(
+            send(&mut tracker, BROKER, other, 40, b"\x40\x02\x00\x07"),
+            [MqttAnomaly::UnknownPacketId]
+        );
+    }
+
+    #[test]
+    fn matches_topic_filters() {
+        assert!(topic_matches("a/+/c", "a/b/c"));
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("$share/group/a/+", "a/b"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(!topic_matches("a/b", "a"));
+        assert!(!topic_matches("#", "$SYS/uptime"));
+    }
+}
@@ -17,38 +17,71 @@ use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
 use parking_lot::RwLock;
 use thiserror::Error;
 
+use crate::alert::{excerpt, Alert, ScanContext, Severity};
+
 #[derive(Debug, Error)]
 pub enum DetectionError {
     #[error("Pattern compilation failed: {0}")]
     PatternError(String), // We'll use a generic string error for aho-corasick
 }
 
+/// A pattern and the metadata of the alert it raises.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureRule {
+    pub id: u32,
+    pub revision: u32,
+    pub pattern: String,
+    pub message: String,
+    pub severity: Severity,
+}
+
 pub struct SignatureEngine {
-    patterns: RwLock<Vec<String>>, // Store patterns as Strings
+    rules: RwLock<Vec<SignatureRule>>,
     matcher: RwLock<Option<AhoCorasick>>,
 }
 
 impl SignatureEngine {
     pub fn new() -> Self {
         Self {
-            patterns: RwLock::new(Vec::new()),
+            rules: RwLock::new(Vec::new()),
             matcher: RwLock::new(None),
         }
     }
 
-    /// Add pattern using Tigerbeetle-style *_verb
+    /// Add pattern using Tigerbeetle-style *_verb. The pattern gets the next
+    /// free rule id, revision 1 and medium severity.
     pub fn add_pattern(&self, pattern: &str) -> Result<(), DetectionError> {
+        let id = self
+            .rules
+            .read()
+            .iter()
+            .map(|rule| rule.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.add_rule(SignatureRule {
+            id,
+            revision: 1,
+            pattern: pattern.to_string(),
+            message: format!("Signature match: {pattern}"),
+            severity: Severity::default(),
+        })
+    }
+
+    /// Add a rule with its alert metadata.
+    pub fn add_rule(&self, rule: SignatureRule) -> Result<(), DetectionError> {
         {
-            let mut patterns = self.patterns.write();
-            patterns.push(pattern.to_string());
+            let mut rules = self.rules.write();
+            rules.push(rule);
         } // The write lock is dropped here.
         self.rebuild_matcher()
     }
+
     /// Rebuild Aho-Corasick matcher when patterns change
     fn rebuild_matcher(&self) -> Result<(), DetectionError> {
-        let patterns = self.patterns.read();
+        let rules = self.rules.read();
         let matcher = AhoCorasickBuilder::new()
-            .build(patterns.iter()) // Build from iterator of &String
+            .build(rules.iter().map(|rule| &rule.pattern))
             .map_err(|e| DetectionError::PatternError(e.to_string()))?; // Convert error
 
         *self.matcher.write() = Some(matcher);
@@ -67,6 +100,44 @@ impl SignatureEngine {
                 .collect()
         })
     }
+
+    /// Scan buffer and raise one alert per rule that matched, in rule order.
+    pub fn detect(&self, data: &[u8], context: &ScanContext) -> Vec<Alert> {
+        let matcher_read_guard = self.matcher.read();
+        let Some(matcher) = matcher_read_guard.as_ref() else {
+            return Vec::new();
+        };
+        let mut matched: Vec<(usize, Vec<_>)> = Vec::new();
+        for m in matcher.find_overlapping_iter(data) {
+            let index = m.pattern().as_usize();
+            match matched.iter_mut().find(|(rule, _)| *rule == index) {
+                Some((_, offsets)) => offsets.push(m.range()),
+                None => matched.push((index, vec![m.range()])),
+            }
+        }
+        matched.sort_by_key(|(rule, _)| *rule);
+
+        let rules = self.rules.read();
+        matched
+            .into_iter()
+            .map(|(index, offsets)| {
+                let rule = &rules[index];
+                Alert {
+                    timestamp: context.timestamp,
+                    rule_id: rule.id,
+                    rule_revision: rule.revision,
+                    message: rule.message.clone(),
+                    severity: rule.severity,
+                    protocol: context.protocol.to_string(),
+                    flow: context.flow,
+                    initiator: context.initiator,
+                    interface: context.interface.clone(),
+                    excerpt: excerpt(data, &offsets),
+                    offsets,
+                }
+            })
+            .collect()
+    }
 }
 
 impl Default for SignatureEngine {
@@ -108,4 +179,36 @@ mod tests {
         assert!(matches.contains(&0)); // Index 0 for "test"
         assert!(matches.contains(&1)); // Index 1 for "example"
     }
+
+    #[test]
+    fn test_detect_raises_alert_per_rule() {
+        let engine = SignatureEngine::new();
+        engine.add_pattern("test").unwrap();
+        engine
+            .add_rule(SignatureRule {
+                id: 9001,
+                revision: 3,
+                pattern: "exec".into(),
+                message: "Command injection".into(),
+                severity: Severity::Critical,
+            })
+            .unwrap();
+
+        let context = ScanContext {
+            timestamp: 42,
+            protocol: "MQTT",
+            ..ScanContext::default()
+        };
+        let alerts = engine.detect(b"exec test; exec", &context);
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].rule_id, 1);
+        assert_eq!(alerts[0].offsets.first(), Some(&(5..9)));
+        assert_eq!(alerts[1].rule_id, 9001);
+        assert_eq!(alerts[1].rule_revision, 3);
+        assert_eq!(alerts[1].severity, Severity::Critical);
+        assert_eq!(alerts[1].offsets, [0..4, 11..15]);
+        assert_eq!(alerts[1].timestamp, 42);
+        assert_eq!(alerts[1].protocol, "MQTT");
+        assert_eq!(alerts[1].excerpt, b"exec test; exec");
+    }
 }
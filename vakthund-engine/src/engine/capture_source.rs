@@ -0,0 +1,93 @@
+//! Selects the capture backend for production mode from `CaptureConfig::mode`.
+
+use std::sync::Arc;
+
+use tracing::warn;
+use vakthund_capture::{CaptureError, CaptureSource, FileSource, PacketBuffers, PcapSource};
+use vakthund_config::CaptureConfig;
+
+/// Builds the capture source configured by `config.mode`, with
+/// `config.bpf_filter` applied if set. Captured frames are copied into
+/// `buffers`.
+///
+/// `xdp` currently falls back to libpcap, with a warning, until an XDP
+/// backend is available.
+pub fn open_capture_source(
+    config: &CaptureConfig,
+    interface: &str,
+    buffers: Arc<dyn PacketBuffers>,
+) -> Result<Box<dyn CaptureSource>, CaptureError> {
+    let filter = config.bpf_filter.as_deref();
+    match config.mode.as_str() {
+        "pcap" | "xdp" => {
+            if config.mode == "xdp" {
+                warn!("XDP capture is not available yet, capturing {interface} with libpcap");
+            }
+            let source = PcapSource::new(interface, config.buffer_size, config.promiscuous)
+                .with_buffers(buffers);
+            Ok(Box::new(match filter {
+                Some(filter) => source.with_filter(filter),
+                None => source,
+            }))
+        }
+        #[cfg(target_os = "linux")]
+        "af_packet" => {
+            let source = vakthund_capture::AfPacketSource::new(
+                interface,
+                config.buffer_size,
+                config.max_latency_ms,
+                config.promiscuous,
+            )
+            .with_buffers(buffers);
+            Ok(Box::new(match filter {
+                Some(filter) => source.with_filter(filter),
+                None => source,
+            }))
+        }
+        "file" => {
+            let path = config
+                .file_path
+                .as_ref()
+                .ok_or_else(|| CaptureError::UnsupportedMode("file without file_path".into()))?;
+            let source = FileSource::new(path, config.replay_speed).with_buffers(buffers);
+            Ok(Box::new(match filter {
+                Some(filter) => source.with_filter(filter),
+                None => source,
+            }))
+        }
+        other => Err(CaptureError::UnsupportedMode(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vakthund_capture::HeapBuffers;
+
+    #[test]
+    fn rejects_simulated_mode() {
+        let config = CaptureConfig {
+            mode: "simulated".into(),
+            ..CaptureConfig::default()
+        };
+        assert!(matches!(
+            open_capture_source(&config, "eth0", Arc::new(HeapBuffers)),
+            Err(CaptureError::UnsupportedMode(_))
+        ));
+    }
+
+    #[test]
+    fn file_mode_needs_path() {
+        let config = CaptureConfig {
+            mode: "file".into(),
+            ..CaptureConfig::default()
+        };
+        assert!(open_capture_source(&config, "eth0", Arc::new(HeapBuffers)).is_err());
+
+        let config = CaptureConfig {
+            file_path: Some("capture.pcap".into()),
+            ..config
+        };
+        assert!(open_capture_source(&config, "eth0", Arc::new(HeapBuffers)).is_ok());
+    }
+}
@@ -0,0 +1,843 @@
+//! Turns captured frames into `NetworkEvent`s for the event bus.
+//!
+//! Frames are decoded down to the transport layer, with IP fragments
+//! reassembled and GRE/ERSPAN/VXLAN/GENEVE tunnels unwrapped first. MQTT and
+//! Modbus/TCP streams are reassembled and emit one event per application
+//! message; all other traffic emits one event per packet (or reassembled
+//! datagram).
+
+use std::hash::{BuildHasher, RandomState};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::{Mutex, MutexGuard};
+use tracing::trace;
+use vakthund_capture::decode::{decode_transport, IpInfo};
+use vakthund_capture::tunnel::MAX_TUNNEL_DEPTH;
+use vakthund_capture::{
+    decode_frame, decode_tunnel, AppProtocol, DecodedPacket, DefragLimits, DefragStats,
+    Defragmenter, Flow, FlowKey, FlowLimits, FlowStats, FlowTable, FragmentAnomaly, Frame,
+    OverlapPolicy, Packet, ReassemblyLimits, ReassemblyStats, StreamKey, TcpReassembler, Transport,
+    TunnelHeader, TunnelKind,
+};
+use vakthund_config::CaptureConfig;
+use vakthund_core::events::{
+    ApplicationProtocol, Encapsulation, FlowEvent, FlowRecord, NetworkEvent, TransportProtocol,
+    TunnelProtocol,
+};
+use vakthund_detection::{Alert, FiveTuple, Severity};
+use vakthund_protocols::coap::COAP_PORT;
+use vakthund_protocols::StreamProtocol;
+
+/// How often idle reassembly state is swept, in capture time (nanoseconds).
+const EXPIRY_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// Fragmentation alerts use rule ids from here up, after the MQTT anomaly
+/// rules.
+pub const FRAGMENT_ANOMALY_RULE_BASE: u32 = 1_001_000;
+
+/// Number of independently locked parts of a [`SharedFlowTable`].
+const FLOW_TABLE_SHARDS: usize = 16;
+
+/// Flow table shared between capture loops and the rest of the engine.
+///
+/// Flows are spread by a keyed hash over independently locked shards, so
+/// capture loops rarely wait on each other. Each shard holds an equal part
+/// of `max_flows` and evicts its own least recently seen flow.
+#[derive(Debug, Clone)]
+pub struct SharedFlowTable {
+    shards: Arc<[Mutex<FlowTable>]>,
+    hasher: RandomState,
+    /// Capture time of the last sweep by [`SharedFlowTable::expire_due`].
+    last_expiry: Arc<AtomicU64>,
+}
+
+impl SharedFlowTable {
+    pub fn new(limits: FlowLimits) -> Self {
+        let shards = FLOW_TABLE_SHARDS.min(limits.max_flows).max(1);
+        let limits = FlowLimits {
+            max_flows: (limits.max_flows / shards).max(1),
+            ..limits
+        };
+        Self {
+            shards: (0..shards)
+                .map(|_| Mutex::new(FlowTable::new(limits)))
+                .collect(),
+            hasher: RandomState::new(),
+            last_expiry: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Locks the shard that holds `key`.
+    pub fn shard(&self, key: &FlowKey) -> MutexGuard<'_, FlowTable> {
+        let index = self.hasher.hash_one(key) % self.shards.len() as u64;
+        self.shards[index as usize].lock()
+    }
+
+    /// Returns a copy of the flow `key`, if tracked.
+    pub fn get(&self, key: &FlowKey) -> Option<Flow> {
+        self.shard(key).get(key).cloned()
+    }
+
+    /// Returns copies of all tracked flows, in no particular order.
+    pub fn snapshot(&self) -> Vec<Flow> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.lock().iter().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Ends timed-out flows in every shard and returns them.
+    pub fn expire(&self, now: u64) -> Vec<Flow> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.lock().expire(now))
+            .collect()
+    }
+
+    /// Like [`SharedFlowTable::expire`], but only once per `interval` of
+    /// capture time however many capture loops call it: the first caller
+    /// past the interval sweeps, the others get nothing.
+    pub fn expire_due(&self, now: u64, interval: u64) -> Vec<Flow> {
+        let last = self.last_expiry.load(Ordering::Relaxed);
+        if now < last.saturating_add(interval)
+            || self
+                .last_expiry
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return Vec::new();
+        }
+        self.expire(now)
+    }
+
+    /// Number of tracked flows.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Counters summed over all shards.
+    pub fn stats(&self) -> FlowStats {
+        self.shards
+            .iter()
+            .fold(FlowStats::default(), |total, shard| {
+                let stats = shard.lock().stats();
+                FlowStats {
+                    created: total.created + stats.created,
+                    expired: total.expired + stats.expired,
+                    evicted: total.evicted + stats.evicted,
+                }
+            })
+    }
+}
+
+/// Creates an empty flow table bounded by `config.flows`.
+pub fn new_flow_table(config: &CaptureConfig) -> SharedFlowTable {
+    let flows = &config.flows;
+    SharedFlowTable::new(FlowLimits {
+        max_flows: flows.max_flows,
+        idle_timeout_ns: flows.idle_timeout_secs.saturating_mul(1_000_000_000),
+        active_timeout_ns: flows.active_timeout_secs.saturating_mul(1_000_000_000),
+    })
+}
+
+/// Stateful frame-to-event conversion for one capture loop.
+///
+/// Every event is tagged with the capture config's `interface`, and every
+/// decoded IP packet is accounted in the flow table.
+pub struct PacketIngest {
+    interface: Arc<str>,
+    flows: SharedFlowTable,
+    defragmenter: Defragmenter,
+    reassembler: TcpReassembler,
+    anomalies: Vec<FragmentAnomaly>,
+    alerts: Vec<Alert>,
+    flow_events: Vec<FlowEvent>,
+    messages: Vec<Bytes>,
+    last_expiry: u64,
+}
+
+impl PacketIngest {
+    /// Creates an ingest stage with its own flow table.
+    pub fn new(config: &CaptureConfig) -> Self {
+        Self::with_flows(config, new_flow_table(config))
+    }
+
+    /// Creates an ingest stage that records flows in a shared table.
+    pub fn with_flows(config: &CaptureConfig, flows: SharedFlowTable) -> Self {
+        let reassembly = &config.tcp_reassembly;
+        let defrag = &config.defrag;
+        let policy = match defrag.overlap_policy {
+            vakthund_config::OverlapPolicy::First => OverlapPolicy::First,
+            vakthund_config::OverlapPolicy::Last => OverlapPolicy::Last,
+            vakthund_config::OverlapPolicy::Bsd => OverlapPolicy::Bsd,
+            vakthund_config::OverlapPolicy::Linux => OverlapPolicy::Linux,
+        };
+        Self {
+            interface: config.interface.as_str().into(),
+            flows,
+            defragmenter: Defragmenter::new(DefragLimits {
+                policy,
+                max_datagrams: defrag.max_datagrams,
+                max_total_bytes: defrag.max_buffer,
+                max_fragments: defrag.max_fragments,
+                timeout_ns: defrag.timeout_secs.saturating_mul(1_000_000_000),
+                min_fragment_size: defrag.min_fragment_size,
+            }),
+            reassembler: TcpReassembler::new(ReassemblyLimits {
+                max_streams: reassembly.max_streams,
+                max_stream_bytes: reassembly.max_stream_buffer,
+                max_total_bytes: reassembly.max_total_buffer,
+                idle_timeout_ns: reassembly.idle_timeout_secs.saturating_mul(1_000_000_000),
+                gap_timeout_ns: reassembly.gap_timeout_secs.saturating_mul(1_000_000_000),
+            }),
+            anomalies: Vec::new(),
+            alerts: Vec::new(),
+            flow_events: Vec::new(),
+            messages: Vec::new(),
+            last_expiry: 0,
+        }
+    }
+
+    /// Decodes `packet` and passes each resulting event to `emit`.
+    ///
+    /// Frames that fail to decode or carry no IP layer are dropped.
+    pub fn process(&mut self, packet: &Packet, mut emit: impl FnMut(NetworkEvent)) {
+        self.expire(packet.timestamp);
+
+        let mut decoded = match decode_frame(packet.link_type, &packet.data) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                trace!("Dropping undecodable frame: {e}");
+                return;
+            }
+        };
+        let frame_vlan = decoded.vlan;
+        let mut data = packet.data.clone();
+        let mut tunnels = Vec::new();
+
+        // Reassemble and unwrap alternately: a tunnel may be carried in
+        // fragments, and may itself carry fragments.
+        loop {
+            let Some(ip) = &decoded.ip else {
+                return;
+            };
+            if ip.fragment.is_some() {
+                let Some((datagram, reassembled)) =
+                    self.reassemble(&data, &decoded, packet.timestamp)
+                else {
+                    return;
+                };
+                data = datagram;
+                decoded = reassembled;
+            }
+            if tunnels.len() == MAX_TUNNEL_DEPTH {
+                break;
+            }
+            match decode_tunnel(&data, &decoded) {
+                Ok(Some((tunnel, inner))) => {
+                    tunnels.push(encapsulation(&tunnel));
+                    decoded = inner;
+                }
+                Ok(None) => break,
+                // Traffic to a tunnel port is not necessarily a tunnel, and
+                // dropping it would hide it: inspect the outer packet instead.
+                Err(e) => {
+                    trace!("Inspecting undecodable tunnel payload as is: {e}");
+                    break;
+                }
+            }
+        }
+
+        let Some(ip) = decoded.ip else {
+            return;
+        };
+        let transport = decoded.transport;
+        let payload = data.slice(decoded.payload);
+        let vlan = decoded.vlan.or(frame_vlan);
+        let initiator = self.track_flow(&ip, transport, vlan, packet.timestamp);
+
+        let interface = &self.interface;
+        let event = |payload: Bytes| NetworkEvent {
+            timestamp: packet.timestamp,
+            payload,
+            interface: Some(interface.clone()),
+            initiator: Some(initiator),
+            tunnels: tunnels.clone(),
+            ..base_event(&ip, transport, vlan)
+        };
+
+        let Some(Transport::Tcp(tcp)) = transport else {
+            emit(event(payload));
+            return;
+        };
+        let Some(protocol) = StreamProtocol::from_ports(tcp.source_port, tcp.destination_port)
+        else {
+            emit(event(payload));
+            return;
+        };
+
+        let key = StreamKey {
+            source: SocketAddr::new(ip.source, tcp.source_port),
+            destination: SocketAddr::new(ip.destination, tcp.destination_port),
+        };
+        self.reassembler.process(
+            key,
+            &tcp,
+            payload,
+            packet.timestamp,
+            |data| match protocol.frame_len(data) {
+                Ok(Some(len)) => Frame::Complete(len),
+                Ok(None) => Frame::Incomplete,
+                Err(e) => {
+                    trace!("{protocol:?} stream {key:?} desynchronised: {e}");
+                    Frame::Invalid
+                }
+            },
+            &mut self.messages,
+        );
+        for message in self.messages.drain(..) {
+            emit(event(message));
+        }
+    }
+
+    /// Returns the flow table packets are accounted in.
+    pub fn flows(&self) -> &SharedFlowTable {
+        &self.flows
+    }
+
+    /// Takes the alerts raised while decoding since the last call.
+    pub fn drain_alerts(&mut self) -> impl Iterator<Item = Alert> + '_ {
+        self.alerts.drain(..)
+    }
+
+    /// Takes the flows started, and ended by timeout or eviction, since the
+    /// last call.
+    pub fn drain_flow_events(&mut self) -> impl Iterator<Item = FlowEvent> + '_ {
+        self.flow_events.drain(..)
+    }
+
+    /// Returns the IP defragmentation counters.
+    pub fn defrag_stats(&self) -> DefragStats {
+        self.defragmenter.stats()
+    }
+
+    /// Returns the TCP reassembly counters.
+    pub fn reassembly_stats(&self) -> ReassemblyStats {
+        self.reassembler.stats()
+    }
+
+    /// Feeds a fragment to the defragmenter. Once the datagram is complete,
+    /// returns it along with the packet re-decoded against it.
+    fn reassemble(
+        &mut self,
+        data: &Bytes,
+        decoded: &DecodedPacket,
+        timestamp: u64,
+    ) -> Option<(Bytes, DecodedPacket)> {
+        let ip = decoded.ip.as_ref()?;
+        let fragment = data.slice(ip.payload.clone());
+        let reassembled = self
+            .defragmenter
+            .process(ip, fragment, timestamp, &mut self.anomalies);
+        for anomaly in self.anomalies.drain(..) {
+            self.alerts
+                .push(fragment_alert(anomaly, ip, &self.interface, timestamp));
+        }
+        let datagram = reassembled?;
+        let ip = IpInfo {
+            fragment: None,
+            payload: 0..datagram.len(),
+            ..ip.clone()
+        };
+        match decode_transport(&datagram, &ip) {
+            Ok((transport, payload)) => {
+                let decoded = DecodedPacket {
+                    ip: Some(ip),
+                    transport,
+                    payload,
+                    ..decoded.clone()
+                };
+                Some((datagram, decoded))
+            }
+            Err(e) => {
+                trace!("Dropping reassembled datagram: {e}");
+                None
+            }
+        }
+    }
+
+    /// Accounts the packet in its flow and returns the flow's initiator.
+    fn track_flow(
+        &mut self,
+        ip: &IpInfo,
+        transport: Option<Transport>,
+        vlan: Option<u16>,
+        now: u64,
+    ) -> SocketAddr {
+        let (source_port, destination_port) =
+            transport.map_or((0, 0), |t| (t.source_port(), t.destination_port()));
+        let source = SocketAddr::new(ip.source, source_port);
+        let destination = SocketAddr::new(ip.destination, destination_port);
+        let (tcp, app_protocol) = match transport {
+            Some(Transport::Tcp(tcp)) => {
+                let protocol =
+                    StreamProtocol::from_ports(source_port, destination_port).map(|protocol| {
+                        match protocol {
+                            StreamProtocol::Mqtt => AppProtocol::Mqtt,
+                            StreamProtocol::ModbusTcp => AppProtocol::ModbusTcp,
+                        }
+                    });
+                (Some(tcp), protocol)
+            }
+            Some(Transport::Udp(_))
+                if source_port == COAP_PORT || destination_port == COAP_PORT =>
+            {
+                (None, Some(AppProtocol::Coap))
+            }
+            _ => (None, None),
+        };
+
+        let key = FlowKey::new(
+            self.interface.clone(),
+            vlan,
+            ip.protocol,
+            source,
+            destination,
+        );
+        let mut flows = self.flows.shard(&key);
+        let flow = flows.update(key, source, tcp.as_ref(), ip.payload.len(), now);
+        if flow.app_protocol.is_none() {
+            flow.app_protocol = app_protocol;
+        }
+        let initiator = flow.initiator;
+        if flow.forward.packets + flow.reverse.packets == 1 {
+            self.flow_events.push(FlowEvent::Started(flow_record(flow)));
+        }
+        self.flow_events.extend(
+            flows
+                .drain_evicted()
+                .map(|flow| FlowEvent::Ended(flow_record(&flow))),
+        );
+        initiator
+    }
+
+    fn expire(&mut self, now: u64) {
+        if now >= self.last_expiry + EXPIRY_INTERVAL_NS {
+            self.defragmenter.expire(now);
+            self.reassembler.expire(now);
+            self.flow_events.extend(
+                self.flows
+                    .expire_due(now, EXPIRY_INTERVAL_NS)
+                    .iter()
+                    .map(|flow| FlowEvent::Ended(flow_record(flow))),
+            );
+            self.last_expiry = now;
+        }
+    }
+}
+
+/// Reports fragmentation used to evade inspection. Raised before the
+/// reassembled payload is queued for detection. Fragments carry no ports,
+/// and their sources are as easily spoofed as any, so the alert names no
+/// initiator to block.
+fn fragment_alert(
+    anomaly: FragmentAnomaly,
+    ip: &IpInfo,
+    interface: &Arc<str>,
+    timestamp: u64,
+) -> Alert {
+    let (rule, severity, message) = match anomaly {
+        FragmentAnomaly::Overlap => (0, Severity::High, "Overlapping IP fragments"),
+        FragmentAnomaly::TinyFragment => (1, Severity::Low, "Tiny non-final IP fragment"),
+        FragmentAnomaly::Oversized => (2, Severity::High, "IP fragments exceed 65535 bytes"),
+        FragmentAnomaly::TooManyFragments => (
+            3,
+            Severity::Medium,
+            "IP datagram split into too many fragments",
+        ),
+    };
+    Alert {
+        timestamp,
+        rule_id: FRAGMENT_ANOMALY_RULE_BASE + rule,
+        rule_revision: 1,
+        message: message.to_string(),
+        severity,
+        protocol: "IP".to_string(),
+        flow: Some(FiveTuple {
+            source: SocketAddr::new(ip.source, 0),
+            destination: SocketAddr::new(ip.destination, 0),
+            protocol: ip.protocol,
+        }),
+        initiator: None,
+        interface: Some(interface.clone()),
+        offsets: Vec::new(),
+        excerpt: Vec::new(),
+    }
+}
+
+fn encapsulation(tunnel: &TunnelHeader) -> Encapsulation {
+    let protocol = match tunnel.kind {
+        TunnelKind::Gre => TunnelProtocol::Gre,
+        TunnelKind::ErspanII => TunnelProtocol::ErspanII,
+        TunnelKind::ErspanIII => TunnelProtocol::ErspanIII,
+        TunnelKind::Vxlan => TunnelProtocol::Vxlan,
+        TunnelKind::Geneve => TunnelProtocol::Geneve,
+    };
+    Encapsulation {
+        protocol,
+        source: tunnel.source,
+        destination: tunnel.destination,
+        id: tunnel.id,
+    }
+}
+
+/// Summarises a tracked flow for the event model.
+pub fn flow_record(flow: &Flow) -> FlowRecord {
+    let (initiator, responder) = match flow.key.endpoints() {
+        (a, b) if a == flow.initiator => (a, b),
+        (a, b) => (b, a),
+    };
+    FlowRecord {
+        interface: Some(flow.key.interface.clone()),
+        vlan: flow.key.vlan,
+        protocol: TransportProtocol::from(flow.key.protocol),
+        initiator,
+        responder,
+        forward_packets: flow.forward.packets,
+        forward_bytes: flow.forward.bytes,
+        reverse_packets: flow.reverse.packets,
+        reverse_bytes: flow.reverse.bytes,
+        first_seen: flow.first_seen,
+        last_seen: flow.last_seen,
+        application: flow.app_protocol.map(|protocol| match protocol {
+            AppProtocol::Mqtt => ApplicationProtocol::Mqtt,
+            AppProtocol::ModbusTcp => ApplicationProtocol::ModbusTcp,
+            AppProtocol::Coap => ApplicationProtocol::Coap,
+        }),
+    }
+}
+
+/// Builds the addressing fields shared by every event from one packet.
+fn base_event(ip: &IpInfo, transport: Option<Transport>, vlan: Option<u16>) -> NetworkEvent {
+    let (source_port, destination_port) =
+        transport.map_or((0, 0), |t| (t.source_port(), t.destination_port()));
+    let protocol = match transport {
+        Some(Transport::Tcp(_)) => TransportProtocol::Tcp,
+        Some(Transport::Udp(_)) => TransportProtocol::Udp,
+        None => TransportProtocol::from(ip.protocol),
+    };
+    NetworkEvent {
+        source: Some(SocketAddr::new(ip.source, source_port)),
+        destination: Some(SocketAddr::new(ip.destination, destination_port)),
+        protocol: Some(protocol),
+        vlan,
+        ..NetworkEvent::new(0, Bytes::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vakthund_capture::LinkType;
+
+    fn frame(vlan: Option<u16>, dst_port: u16, seq: u32, payload: &[u8]) -> Packet {
+        let mut frame = vec![0; 12];
+        if let Some(vlan) = vlan {
+            frame.extend_from_slice(&[0x81, 0x00]);
+            frame.extend_from_slice(&vlan.to_be_bytes());
+        }
+        frame.extend_from_slice(&[0x08, 0x00]);
+        let total = (20 + 20 + payload.len()) as u16;
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total.to_be_bytes());
+        frame.extend_from_slice(&[0, 1, 0, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&[192, 168, 1, 10, 192, 168, 1, 20]);
+        frame.extend_from_slice(&50000u16.to_be_bytes());
+        frame.extend_from_slice(&dst_port.to_be_bytes());
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 0x50, 0x18, 0xFF, 0xFF, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        Packet::with_timestamp(7, frame.into())
+    }
+
+    fn collect(ingest: &mut PacketIngest, packet: &Packet) -> Vec<NetworkEvent> {
+        let mut events = Vec::new();
+        ingest.process(packet, |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn fills_addressing_and_strips_headers() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let events = collect(&mut ingest, &frame(Some(12), 8080, 1, b"http"));
+        let event = &events[0];
+
+        assert_eq!(event.timestamp, 7);
+        assert_eq!(event.payload.as_ref(), b"http");
+        assert_eq!(event.source, Some("192.168.1.10:50000".parse().unwrap()));
+        assert_eq!(
+            event.destination,
+            Some("192.168.1.20:8080".parse().unwrap())
+        );
+        assert_eq!(event.protocol, Some(TransportProtocol::Tcp));
+        assert_eq!(event.vlan, Some(12));
+        assert_eq!(event.interface.as_deref(), Some("eth0"));
+        assert_eq!(event.initiator, event.source);
+    }
+
+    #[test]
+    fn decodes_the_capture_link_type() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let ethernet = frame(None, 8080, 1, b"http");
+        let raw =
+            Packet::with_timestamp(7, ethernet.data.slice(14..)).with_link_type(LinkType::RawIp);
+        let events = collect(&mut ingest, &raw);
+        assert_eq!(events.len(), 1);
+        assert_eq!(&events[0].payload[..], b"http");
+        assert_eq!(
+            events[0].destination,
+            Some("192.168.1.20:8080".parse().unwrap())
+        );
+
+        // Without the link type the IP header would be misread as Ethernet.
+        assert!(collect(&mut ingest, &Packet::with_timestamp(7, raw.data)).is_empty());
+    }
+
+    #[test]
+    fn accounts_packets_in_flow_table() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        collect(&mut ingest, &frame(Some(12), 1883, 1, b"abc"));
+        collect(&mut ingest, &frame(Some(12), 1883, 4, b"def"));
+
+        let key = FlowKey::new(
+            "eth0".into(),
+            Some(12),
+            6,
+            "192.168.1.20:1883".parse().unwrap(),
+            "192.168.1.10:50000".parse().unwrap(),
+        );
+        let flow = ingest.flows().get(&key).expect("flow should be tracked");
+        assert_eq!(flow.forward.packets, 2);
+        assert_eq!(flow.forward.bytes, 46);
+        assert_eq!(flow.app_protocol, Some(AppProtocol::Mqtt));
+        assert_eq!(
+            flow.tcp_state,
+            Some(vakthund_capture::TcpState::Established)
+        );
+    }
+
+    #[test]
+    fn shared_table_is_swept_once_per_interval() {
+        let mut config = CaptureConfig::default();
+        config.flows.idle_timeout_secs = 1;
+        let flows = new_flow_table(&config);
+        let mut eth0 = PacketIngest::with_flows(&config, flows.clone());
+        config.interface = "eth1".into();
+        let mut eth1 = PacketIngest::with_flows(&config, flows.clone());
+
+        collect(&mut eth0, &frame(None, 1883, 1, b"abc"));
+        let late = |port, timestamp| Packet {
+            timestamp,
+            ..frame(None, port, 1, b"abc")
+        };
+        // The first loop past the interval sweeps the table for both.
+        collect(&mut eth1, &late(8080, 3_000_000_000));
+        assert_eq!(flows.stats().expired, 1);
+        assert_eq!(flows.len(), 1);
+        // Until the interval has passed since that sweep, nobody sweeps again.
+        collect(&mut eth0, &late(502, 3_500_000_000));
+        assert!(flows
+            .expire_due(3_999_999_999, EXPIRY_INTERVAL_NS)
+            .is_empty());
+        assert_eq!(flows.expire_due(4_200_000_000, EXPIRY_INTERVAL_NS).len(), 1);
+        assert_eq!(flows.stats().expired, 2);
+    }
+
+    #[test]
+    fn reports_flow_starts_and_ends() {
+        let mut config = CaptureConfig::default();
+        config.flows.max_flows = 1;
+        config.flows.idle_timeout_secs = 1;
+        let mut ingest = PacketIngest::new(&config);
+        // Flow events as (started, responder port).
+        let drain = |ingest: &mut PacketIngest| -> Vec<(bool, u16)> {
+            ingest
+                .drain_flow_events()
+                .map(|event| match event {
+                    FlowEvent::Started(record) => (true, record.responder.port()),
+                    FlowEvent::Ended(record) => (false, record.responder.port()),
+                })
+                .collect()
+        };
+
+        collect(&mut ingest, &frame(None, 1883, 1, b"abc"));
+        collect(&mut ingest, &frame(None, 1883, 4, b"def"));
+        assert_eq!(drain(&mut ingest), [(true, 1883)]);
+        // The table is full, so the new flow evicts the first one.
+        collect(&mut ingest, &frame(None, 8080, 1, b"abc"));
+        assert_eq!(drain(&mut ingest), [(true, 8080), (false, 1883)]);
+
+        let later = Packet {
+            timestamp: 3_000_000_000,
+            ..frame(None, 1883, 7, b"ghi")
+        };
+        collect(&mut ingest, &later);
+        assert_eq!(drain(&mut ingest), [(false, 8080), (true, 1883)]);
+        assert_eq!(ingest.flows().stats().evicted, 1);
+        assert_eq!(ingest.flows().stats().expired, 1);
+    }
+
+    #[test]
+    fn summarises_flows_from_the_initiator() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        collect(&mut ingest, &frame(None, 1883, 1, b"abc"));
+
+        let record = flow_record(&ingest.flows().snapshot()[0]);
+        assert_eq!(record.initiator, "192.168.1.10:50000".parse().unwrap());
+        assert_eq!(record.responder, "192.168.1.20:1883".parse().unwrap());
+        assert_eq!(record.protocol, TransportProtocol::Tcp);
+        assert_eq!(record.forward_packets, 1);
+        assert_eq!(record.reverse_packets, 0);
+        assert_eq!(record.application, Some(ApplicationProtocol::Mqtt));
+    }
+
+    #[test]
+    fn emits_one_event_per_reassembled_message() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let read_registers = [0, 1, 0, 0, 0, 6, 1, 3, 0, 0, 0, 1];
+
+        // First segment carries half an ADU.
+        assert!(collect(&mut ingest, &frame(None, 502, 100, &read_registers[..7])).is_empty());
+
+        // Second segment completes it and carries a second full ADU.
+        let mut rest = read_registers[7..].to_vec();
+        rest.extend_from_slice(&read_registers);
+        let events = collect(&mut ingest, &frame(None, 502, 107, &rest));
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.payload.as_ref() == read_registers));
+        assert_eq!(
+            events[0].destination,
+            Some("192.168.1.20:502".parse().unwrap())
+        );
+    }
+
+    fn udp_fragment(offset: u16, more_fragments: bool, data: &[u8]) -> Packet {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&[0x08, 0x00, 0x45, 0]);
+        frame.extend_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+        let flags = if more_fragments { 0x2000 } else { 0 } | (offset / 8);
+        frame.extend_from_slice(&[0x12, 0x34]);
+        frame.extend_from_slice(&flags.to_be_bytes());
+        frame.extend_from_slice(&[64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        frame.extend_from_slice(data);
+        Packet::with_timestamp(1, frame.into())
+    }
+
+    #[test]
+    fn reassembles_fragmented_datagrams() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let mut datagram = vec![0x9C, 0x40, 0x16, 0x33, 0, 108, 0, 0];
+        datagram.extend((0..100).map(|i| i as u8));
+
+        assert!(collect(&mut ingest, &udp_fragment(64, false, &datagram[64..])).is_empty());
+        let events = collect(&mut ingest, &udp_fragment(0, true, &datagram[..64]));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload.as_ref(), &datagram[8..]);
+        assert_eq!(ingest.drain_alerts().count(), 0);
+        assert_eq!(
+            events[0].destination,
+            Some("10.0.0.2:5683".parse().unwrap())
+        );
+        assert_eq!(events[0].protocol, Some(TransportProtocol::Udp));
+        assert_eq!(ingest.defrag_stats().reassembled, 1);
+    }
+
+    #[test]
+    fn raises_alerts_for_conflicting_fragments() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        collect(&mut ingest, &udp_fragment(0, true, &[1; 64]));
+        collect(&mut ingest, &udp_fragment(0, true, &[2; 64]));
+
+        let alerts: Vec<_> = ingest.drain_alerts().collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule_id, FRAGMENT_ANOMALY_RULE_BASE);
+        assert_eq!(alerts[0].severity, Severity::High);
+        assert_eq!(alerts[0].flow.unwrap().protocol, 17);
+        assert_eq!(alerts[0].initiator, None);
+        assert_eq!(ingest.drain_alerts().count(), 0);
+    }
+
+    #[test]
+    fn unwraps_vxlan_and_keeps_outer_addressing() {
+        let inner = frame(Some(12), 8080, 1, b"http").data;
+        let mut vxlan = vec![0x08, 0, 0, 0, 0, 0, 0x2A, 0];
+        vxlan.extend_from_slice(&inner);
+        let mut udp = vec![0xC3, 0x50, 0x12, 0xB5];
+        udp.extend_from_slice(&((8 + vxlan.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&vxlan);
+        let mut outer = vec![0; 12];
+        outer.extend_from_slice(&[0x08, 0x00, 0x45, 0]);
+        outer.extend_from_slice(&((20 + udp.len()) as u16).to_be_bytes());
+        outer.extend_from_slice(&[0, 1, 0, 0, 64, 17, 0, 0, 172, 16, 0, 1, 172, 16, 0, 2]);
+        outer.extend_from_slice(&udp);
+
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let events = collect(&mut ingest, &Packet::new(outer));
+        let event = &events[0];
+
+        assert_eq!(event.payload.as_ref(), b"http");
+        assert_eq!(event.source, Some("192.168.1.10:50000".parse().unwrap()));
+        assert_eq!(event.vlan, Some(12));
+        assert_eq!(
+            event.tunnels,
+            [Encapsulation {
+                protocol: TunnelProtocol::Vxlan,
+                source: "172.16.0.1".parse().unwrap(),
+                destination: "172.16.0.2".parse().unwrap(),
+                id: Some(42),
+            }]
+        );
+    }
+
+    #[test]
+    fn inspects_malformed_tunnels_as_plain_udp() {
+        let mut udp = vec![0xC3, 0x50, 0x12, 0xB5, 0, 21, 0, 0];
+        // VXLAN header without the I flag, then the payload.
+        udp.extend_from_slice(&[0; 8]);
+        udp.extend_from_slice(b"hello");
+        let mut outer = vec![0; 12];
+        outer.extend_from_slice(&[0x08, 0x00, 0x45, 0]);
+        outer.extend_from_slice(&((20 + udp.len()) as u16).to_be_bytes());
+        outer.extend_from_slice(&[0, 1, 0, 0, 64, 17, 0, 0, 172, 16, 0, 1, 172, 16, 0, 2]);
+        outer.extend_from_slice(&udp);
+
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let events = collect(&mut ingest, &Packet::new(outer));
+        assert_eq!(events.len(), 1);
+        assert_eq!(&events[0].payload[8..], b"hello");
+        assert_eq!(
+            events[0].destination,
+            Some("172.16.0.2:4789".parse().unwrap())
+        );
+        assert!(events[0].tunnels.is_empty());
+    }
+
+    #[test]
+    fn drops_non_ip_frames() {
+        let mut ingest = PacketIngest::new(&CaptureConfig::default());
+        let mut arp = vec![0; 12];
+        arp.extend_from_slice(&[0x08, 0x06]);
+        arp.extend_from_slice(&[0; 28]);
+        assert!(collect(&mut ingest, &Packet::new(arp)).is_empty());
+        assert!(collect(&mut ingest, &Packet::new(vec![0; 4])).is_empty());
+    }
+}
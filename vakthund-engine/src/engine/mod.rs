@@ -1,12 +1,20 @@
+mod capture_source;
 pub mod default_driver;
 mod diagnostics;
 mod event_processing;
+mod ingest;
+mod pipeline;
 mod runtime;
 mod runtime_trait;
 
 pub use self::{
-    diagnostics::DiagnosticsCollector, event_processing::EventProcessor,
-    runtime::SimulationRuntime, runtime_trait::VakthundRuntime,
+    capture_source::open_capture_source,
+    diagnostics::DiagnosticsCollector,
+    event_processing::EventProcessor,
+    ingest::{PacketIngest, SharedFlowTable},
+    pipeline::{Completion, FlowPipeline, ReorderBuffer, ShardHasher},
+    runtime::SimulationRuntime,
+    runtime_trait::VakthundRuntime,
 };
 
 pub mod prelude {
@@ -0,0 +1,349 @@
+//! Flow-affine event processing across several workers.
+//!
+//! Events are hashed by flow (interface, VLAN, protocol and the unordered
+//! endpoint pair) onto one of N worker queues. Each worker processes its queue
+//! in order, so events of one flow are never reordered while different flows
+//! run in parallel. Events without addresses belong to no flow and are spread
+//! round-robin. Every dispatched event gets a sequence number; workers
+//! report completions tagged with it, and [`ReorderBuffer`] merges them back
+//! into dispatch order where a deterministic result is needed (simulation).
+
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, trace};
+
+use vakthund_core::events::network::NetworkEvent;
+use vakthund_core::SimulationError;
+
+use crate::engine::event_processing::EventProcessor;
+
+/// Events buffered per worker before dispatch waits.
+pub const WORKER_QUEUE_DEPTH: usize = 1024;
+
+/// Outcome of processing one dispatched event.
+#[derive(Debug)]
+pub struct Completion {
+    /// Dispatch order of the event.
+    pub sequence: u64,
+    pub event: NetworkEvent,
+    pub result: Result<(), SimulationError>,
+}
+
+/// Keyed hash mapping flows to workers.
+///
+/// The key is random per run so that crafted traffic cannot pile every flow
+/// onto one worker. Simulation uses [`ShardHasher::from_seed`] to keep the
+/// mapping reproducible.
+#[derive(Debug, Clone)]
+pub struct ShardHasher {
+    key: [u8; 32],
+}
+
+impl ShardHasher {
+    /// A hasher with a fresh random key.
+    pub fn random() -> Self {
+        Self {
+            key: rand::random(),
+        }
+    }
+
+    /// A hasher whose key is derived from `seed`.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            key: blake3::derive_key("vakthund flow shard", &seed.to_le_bytes()),
+        }
+    }
+
+    /// Selects the worker for `event`, dispatched as number `sequence`. Both
+    /// directions of a flow map to the same worker.
+    pub fn shard_for(&self, event: &NetworkEvent, sequence: u64, workers: usize) -> usize {
+        if workers <= 1 {
+            return 0;
+        }
+        if event.source.is_none() && event.destination.is_none() {
+            return (sequence % workers as u64) as usize;
+        }
+        let mut hasher = KeyedHasher(blake3::Hasher::new_keyed(&self.key));
+        event.interface.hash(&mut hasher);
+        event.vlan.hash(&mut hasher);
+        event.protocol.hash(&mut hasher);
+        let (lower, upper) = match (event.source, event.destination) {
+            (Some(a), Some(b)) if b < a => (Some(b), Some(a)),
+            (a, b) => (a, b),
+        };
+        lower.hash(&mut hasher);
+        upper.hash(&mut hasher);
+        (hasher.finish() % workers as u64) as usize
+    }
+}
+
+/// Adapts keyed BLAKE3 to [`Hasher`].
+struct KeyedHasher(blake3::Hasher);
+
+impl Hasher for KeyedHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let hash = self.0.finalize();
+        let mut word = [0; 8];
+        word.copy_from_slice(&hash.as_bytes()[..8]);
+        u64::from_le_bytes(word)
+    }
+}
+
+/// A running set of flow-affine workers.
+pub struct FlowPipeline {
+    queues: Vec<mpsc::Sender<(u64, NetworkEvent)>>,
+    workers: Vec<JoinHandle<Result<u64, SimulationError>>>,
+    shards: ShardHasher,
+    next_sequence: u64,
+}
+
+impl FlowPipeline {
+    /// Spawns `workers` tasks that each run `processor` over their queue.
+    ///
+    /// When `completions` is set, every processed event is reported on it.
+    /// Otherwise a processing error stops the worker and is returned by
+    /// [`FlowPipeline::finish`]. Flows are sharded with a random key unless
+    /// [`FlowPipeline::with_shard_hasher`] sets one.
+    pub fn spawn(
+        processor: Arc<dyn EventProcessor + Send + Sync>,
+        workers: usize,
+        completions: Option<mpsc::UnboundedSender<Completion>>,
+    ) -> Self {
+        let workers = workers.max(1);
+        let (queues, handles) = (0..workers)
+            .map(|worker| {
+                let (sender, receiver) = mpsc::channel(WORKER_QUEUE_DEPTH);
+                let handle = tokio::spawn(run_worker(
+                    worker,
+                    processor.clone(),
+                    receiver,
+                    completions.clone(),
+                ));
+                (sender, handle)
+            })
+            .unzip();
+        Self {
+            queues,
+            workers: handles,
+            shards: ShardHasher::random(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Shards flows with `shards` instead of a randomly keyed hasher.
+    pub fn with_shard_hasher(mut self, shards: ShardHasher) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Number of workers.
+    pub fn workers(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Queues `event` on its flow's worker, waiting while that queue is full.
+    /// Returns the event's sequence number.
+    pub async fn dispatch(&mut self, event: NetworkEvent) -> Result<u64, SimulationError> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        let worker = self.shards.shard_for(&event, sequence, self.queues.len());
+        self.queues[worker]
+            .send((sequence, event))
+            .await
+            .map_err(|_| SimulationError::Processing(format!("Worker {worker} stopped")))?;
+        Ok(sequence)
+    }
+
+    /// Closes the queues and waits for every worker to drain. Returns the
+    /// number of events processed, or the first worker error.
+    pub async fn finish(self) -> Result<u64, SimulationError> {
+        drop(self.queues);
+        let mut processed = 0;
+        let mut first_error = None;
+        for worker in self.workers {
+            match worker.await {
+                Ok(Ok(count)) => processed += count,
+                Ok(Err(e)) => {
+                    first_error.get_or_insert(e);
+                }
+                Err(e) => {
+                    first_error
+                        .get_or_insert(SimulationError::Processing(format!("Worker panic: {e}")));
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(processed),
+        }
+    }
+}
+
+async fn run_worker(
+    worker: usize,
+    processor: Arc<dyn EventProcessor + Send + Sync>,
+    mut queue: mpsc::Receiver<(u64, NetworkEvent)>,
+    completions: Option<mpsc::UnboundedSender<Completion>>,
+) -> Result<u64, SimulationError> {
+    debug!("Worker {worker} started");
+    let mut processed = 0;
+    while let Some((sequence, event)) = queue.recv().await {
+        trace!("Worker {worker} processing event #{sequence}");
+        let result = processor.process(&event).await;
+        processed += 1;
+        match &completions {
+            Some(completions) => {
+                let _ = completions.send(Completion {
+                    sequence,
+                    event,
+                    result,
+                });
+            }
+            None => result?,
+        }
+    }
+    debug!("Worker {worker} finished after {processed} events");
+    Ok(processed)
+}
+
+/// Restores dispatch order from out-of-order completions.
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    next: u64,
+    pending: BTreeMap<u64, T>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Adds the item with sequence number `sequence` and returns every item
+    /// that is now next in order.
+    pub fn push(&mut self, sequence: u64, item: T) -> Vec<T> {
+        self.pending.insert(sequence, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+        ready
+    }
+
+    /// Number of items waiting for an earlier sequence number.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use parking_lot::Mutex;
+
+    fn event(source: &str, destination: &str, timestamp: u64) -> NetworkEvent {
+        NetworkEvent {
+            source: Some(source.parse().unwrap()),
+            destination: Some(destination.parse().unwrap()),
+            ..NetworkEvent::new(timestamp, Bytes::new())
+        }
+    }
+
+    /// Records the order events are processed in.
+    struct Recorder(Mutex<Vec<u64>>);
+
+    #[async_trait::async_trait]
+    impl EventProcessor for Recorder {
+        async fn process(&self, event: &NetworkEvent) -> Result<(), SimulationError> {
+            tokio::task::yield_now().await;
+            self.0.lock().push(event.timestamp);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn both_directions_share_a_shard() {
+        let shards = ShardHasher::random();
+        let forward = event("10.0.0.1:50000", "10.0.0.2:1883", 0);
+        let reverse = event("10.0.0.2:1883", "10.0.0.1:50000", 0);
+        for workers in 1..16 {
+            let shard = shards.shard_for(&forward, 0, workers);
+            assert_eq!(shard, shards.shard_for(&reverse, 7, workers));
+            assert!(shard < workers);
+        }
+    }
+
+    #[test]
+    fn seeded_shards_are_reproducible() {
+        let flows: Vec<_> = (0..32)
+            .map(|i| event(&format!("10.0.0.{i}:50000"), "10.0.0.200:1883", 0))
+            .collect();
+        let shard = |shards: &ShardHasher| -> Vec<_> {
+            flows.iter().map(|e| shards.shard_for(e, 0, 8)).collect()
+        };
+        assert_eq!(
+            shard(&ShardHasher::from_seed(1)),
+            shard(&ShardHasher::from_seed(1))
+        );
+        assert_ne!(
+            shard(&ShardHasher::from_seed(1)),
+            shard(&ShardHasher::from_seed(2))
+        );
+    }
+
+    #[test]
+    fn spreads_events_without_addresses() {
+        let shards = ShardHasher::random();
+        let event = NetworkEvent::new(0, Bytes::new());
+        let used: std::collections::HashSet<_> = (0..4)
+            .map(|sequence| shards.shard_for(&event, sequence, 4))
+            .collect();
+        assert_eq!(used.len(), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn keeps_per_flow_order() {
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let mut pipeline = FlowPipeline::spawn(recorder.clone(), 4, None);
+        for i in 0..400u64 {
+            let client = format!("10.0.0.{}:5000{}", i % 8, i % 8);
+            pipeline
+                .dispatch(event(&client, "10.0.0.200:1883", i))
+                .await
+                .unwrap();
+        }
+        assert_eq!(pipeline.finish().await.unwrap(), 400);
+
+        let seen = recorder.0.lock();
+        assert_eq!(seen.len(), 400);
+        for flow in 0..8 {
+            let order: Vec<_> = seen.iter().filter(|ts| *ts % 8 == flow).collect();
+            assert!(order.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn reorders_completions() {
+        let mut buffer = ReorderBuffer::default();
+        assert!(buffer.push(2, 'c').is_empty());
+        assert!(buffer.push(1, 'b').is_empty());
+        assert_eq!(buffer.pending(), 2);
+        assert_eq!(buffer.push(0, 'a'), ['a', 'b', 'c']);
+        assert_eq!(buffer.push(3, 'd'), ['d']);
+        assert_eq!(buffer.pending(), 0);
+    }
+}
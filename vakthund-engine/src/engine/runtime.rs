@@ -1,41 +1,66 @@
 //! Simulation runtime core - coordinates execution of detection, prevention, and simulation components
-use std::sync::atomic::AtomicBool;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime};
 
-use opentelemetry::KeyValue;
 use parking_lot::Mutex;
-use tokio::task::{spawn_blocking, JoinHandle};
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio::time::sleep;
 use tracing::{debug, error, info, instrument, trace, warn};
 
-use vakthund_config::{SimulatorConfig, VakthundConfig};
-use vakthund_core::events::{bus::EventBus, network::NetworkEvent};
+use vakthund_capture::source::STATS_INTERVAL;
+use vakthund_capture::{run_capture_loop_batched, CaptureStats, Packet};
+use vakthund_config::{CaptureConfig, SimulatorConfig, VakthundConfig};
+use vakthund_core::alloc::PacketPool;
+use vakthund_core::events::bus::{BusMode, BusOptions, EventBus, FullQueueStrategy};
+use vakthund_core::events::network::NetworkEvent;
+use vakthund_core::events::{
+    Alert, ApplicationEvent, ApplicationProtocol, ControlEvent, Envelope, PreventionAction,
+    PreventionEvent, PreventionOutcome, Severity,
+};
 use vakthund_core::SimulationError;
 
 use vakthund_detection::signatures::SignatureEngine;
-use vakthund_prevention::firewall::Firewall;
+use vakthund_detection::{
+    CoapExchangeLimits, CoapExchangeTracker, MqttSessionLimits, MqttSessionTracker,
+};
+use vakthund_prevention::firewall::{block_target, BlockPolicy, Firewall};
 use vakthund_protocols::{AnyParser, CoapParser, ModbusParser, MqttParser};
 use vakthund_simulator::{Scenario, Simulator};
 use vakthund_telemetry::{logging::EventLogger, MetricsRecorder};
 
+use crate::engine::capture_source::open_capture_source;
 use crate::engine::diagnostics::DiagnosticsCollector;
 use crate::engine::event_processing::EventProcessor;
+use crate::engine::ingest::{new_flow_table, PacketIngest, SharedFlowTable};
+use crate::engine::pipeline::{Completion, FlowPipeline, ReorderBuffer, ShardHasher};
 use crate::engine::runtime_trait::SimulationDriver;
 
+/// Events moved from the bus to the workers per bus update.
+const DISPATCH_BATCH_SIZE: usize = 64;
+
+/// Shard key used by simulation runs, so their results are reproducible.
+const SIMULATION_SHARD_SEED: u64 = 0;
+
 /// Coordinates system operations in Vakthund, including event processing, simulation,
 /// fuzz testing, and scenario-based execution.
 pub struct SimulationRuntime<T: SimulationDriver + Send + Sync + 'static> {
     /// System configuration parameters
     config: Arc<VakthundConfig>,
-    /// Event bus for cross-component communication (SPSC)
+    /// Event bus shared by capture threads and workers (always MPMC)
     pub event_bus: Arc<EventBus>,
     /// Metrics collection subsystem
     pub metrics: Arc<MetricsRecorder>,
+    /// Flows seen across all capture interfaces
+    flows: SharedFlowTable,
     /// Diagnostic data collector
     diagnostics: Mutex<DiagnosticsCollector>,
     event_processor: Arc<dyn EventProcessor + Send + Sync>,
-    driver: Arc<Mutex<T>>,
+    /// Severity filtering and response for every alert
+    alerts: Arc<AlertHandler>,
+    driver: Arc<T>,
 }
 
 impl<T: SimulationDriver + Send + Sync + 'static> SimulationRuntime<T> {
@@ -47,165 +72,339 @@ impl<T: SimulationDriver + Send + Sync + 'static> SimulationRuntime<T> {
         info!("Initializing simulation runtime");
         debug!("Core config: {:?}", config.core);
 
+        let bus_config = &config.core.event_bus;
+        let strategy = bus_config.full_queue_strategy.parse().unwrap_or_else(|e| {
+            warn!("{e}, using default");
+            FullQueueStrategy::default()
+        });
+        let mode = match bus_config.mode.parse() {
+            Ok(BusMode::Spsc) => {
+                warn!("The event bus is shared between threads, using mpmc instead of spsc");
+                BusMode::Mpmc
+            }
+            Ok(mode) => mode,
+            Err(e) => {
+                warn!("{e}, using default");
+                BusMode::default()
+            }
+        };
         let event_bus = Arc::new(
-            EventBus::with_capacity(config.core.event_bus.capacity)
-                .expect("Failed to create event bus"),
+            EventBus::with_options(BusOptions {
+                capacity: bus_config.capacity,
+                mode,
+                strategy,
+                priority_capacity: bus_config.priority_capacity,
+                starvation_limit: bus_config.starvation_limit,
+            })
+            .expect("Failed to create event bus"),
         );
 
         // Create shared metrics
         let metrics = Arc::new(MetricsRecorder::new());
 
+        let min_severity = config
+            .monitor
+            .alerts
+            .min_severity
+            .parse()
+            .unwrap_or_else(|e| {
+                warn!("{e}, using default");
+                Severity::default()
+            });
+        let auto_block = &config.prevention.auto_block;
+        let block_policy = BlockPolicy {
+            enabled: auto_block.enabled,
+            min_severity: auto_block.min_severity.parse().unwrap_or_else(|e| {
+                warn!("{e}, blocking only critical alerts");
+                Severity::Critical
+            }),
+            rules: auto_block.rules.clone(),
+            block_udp: auto_block.block_udp,
+            whitelist: config
+                .prevention
+                .firewall
+                .whitelist
+                .iter()
+                .chain(&config.prevention.quarantine.whitelist)
+                .copied()
+                .collect(),
+        };
+        let alerts = Arc::new(AlertHandler::new(
+            min_severity,
+            block_policy,
+            config.prevention.firewall.interface.as_str().into(),
+            metrics.clone(),
+        ));
+
+        let sessions = &config.monitor.mqtt_sessions;
+        let mqtt_limits = MqttSessionLimits {
+            max_sessions: sessions.max_sessions,
+            max_inflight: sessions.max_inflight,
+            ack_timeout_ns: sessions.ack_timeout_secs.saturating_mul(1_000_000_000),
+            idle_timeout_ns: sessions.idle_timeout_secs.saturating_mul(1_000_000_000),
+        };
+        let exchanges = &config.monitor.coap_exchanges;
+        let coap_limits = CoapExchangeLimits {
+            max_endpoints: exchanges.max_endpoints,
+            max_exchanges: exchanges.max_exchanges,
+            max_body_size: exchanges.max_body_size,
+            max_total_buffer: exchanges.max_total_buffer,
+            exchange_timeout_ns: exchanges
+                .exchange_timeout_secs
+                .saturating_mul(1_000_000_000),
+            idle_timeout_ns: exchanges.idle_timeout_secs.saturating_mul(1_000_000_000),
+        };
+
         // Construct the default event processor with shared metrics
-        let default_event_processor = DefaultEventProcessor::new(metrics.clone());
+        let default_event_processor =
+            DefaultEventProcessor::new(metrics.clone(), alerts.clone(), mqtt_limits, coap_limits);
+
+        let flows = new_flow_table(&config.capture);
 
         Self {
             config: Arc::new(config),
             event_bus,
             metrics,
+            flows,
             diagnostics: Mutex::new(DiagnosticsCollector::new()),
             event_processor: Arc::new(default_event_processor),
-            driver: Arc::new(Mutex::new(driver)),
+            alerts,
+            driver: Arc::new(driver),
         }
     }
-    /// Runs in "production mode," capturing live packets from a specified network interface.
-    /// Then it sends them to the event bus and processes them in a background task.
+    /// Returns the flow table populated by production capture.
+    pub fn flows(&self) -> &SharedFlowTable {
+        &self.flows
+    }
+
+    /// Runs in "production mode," capturing live packets from one or more network interfaces.
+    /// Each interface is captured on its own blocking thread; events are sent to the event bus
+    /// and processed in a background task.
     ///
     /// # Arguments
-    /// * `interface` - Network interface name to monitor
-    #[instrument(skip_all, fields(interface = %interface))]
-    pub async fn run_production(self: Arc<Self>, interface: &str) -> Result<(), SimulationError> {
-        info!("Starting production mode on {interface}");
+    /// * `interfaces` - Network interfaces to monitor. When empty, the interfaces from the
+    ///   capture config are used.
+    #[instrument(skip_all, fields(interfaces = ?interfaces))]
+    pub async fn run_production(
+        self: Arc<Self>,
+        interfaces: &[String],
+    ) -> Result<(), SimulationError> {
+        let interfaces = if interfaces.is_empty() {
+            self.config.capture.interface_names()
+        } else {
+            interfaces.to_vec()
+        };
+        info!("Starting production mode on {}", interfaces.join(", "));
         debug!("Using capture config: {:?}", self.config.capture);
 
         let terminate = Arc::new(AtomicBool::new(false));
-        let event_bus = self.event_bus.clone();
+        // Captured frames from every interface are copied into one shared pool.
+        let pool_config = &self.config.core.memory.packet_pool;
+        let packets = PacketPool::new(pool_config.max_packet_size, pool_config.initial_capacity);
 
         // Spawn event processor (drains the bus in the background)
         let processor_self = self.clone();
+        let processor_terminate = terminate.clone();
         let processor = tokio::spawn(async move {
             debug!("Spawning event processor thread");
-            processor_self.spawn_event_processor().await
+            processor_self
+                .spawn_event_processor(processor_terminate)
+                .await
         });
 
-        // Start capture loop on a blocking thread
-        let capture_task = spawn_blocking({
-            let interface = interface.to_string();
-            let event_bus = event_bus.clone();
-            let config = self.config.capture.clone();
-
-            move || {
-                info!("Starting packet capture on {interface}");
-                vakthund_capture::capture::run_capture_loop(
-                    &interface,
-                    config.buffer_size,
-                    config.promiscuous,
-                    &terminate,
-                    |packet| {
-                        trace!("Captured packet: {} bytes", packet.data.len());
-
-                        let timestamp = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .expect("Time went backwards")
-                            .as_nanos() as u64;
-
-                        let event = NetworkEvent {
-                            timestamp,
-                            payload: packet.data.clone(),
-                            source: None,
-                            destination: None,
-                        };
-
-                        debug!("Queueing network event");
-                        if let Err(e) = event_bus.send(event) {
-                            warn!("Failed to queue event: {e}");
-                        }
-                    },
-                )
+        // Start one capture loop per interface on blocking threads
+        let mut captures = JoinSet::new();
+        for interface in &interfaces {
+            let config = self.config.capture.for_interface(interface);
+            let event_bus = self.event_bus.clone();
+            let metrics = self.metrics.clone();
+            let flows = self.flows.clone();
+            let packets = packets.clone();
+            let terminate = terminate.clone();
+            captures.spawn_blocking(move || {
+                let result =
+                    capture_interface(&config, &event_bus, &metrics, flows, packets, &terminate);
+                (config.interface, result)
+            });
+        }
+
+        // Captures are reaped as they finish, so the first failure stops the
+        // others however the interfaces are ordered.
+        info!("Waiting for capture tasks");
+        let mut capture_error = None;
+        while let Some(joined) = captures.join_next().await {
+            let result = match joined {
+                Ok((interface, result)) => {
+                    result.inspect_err(|e| error!("Capture on {interface} failed: {e}"))
+                }
+                Err(e) => {
+                    error!("Capture task failed: {e}");
+                    Err(SimulationError::Processing(format!("Capture failure: {e}")))
+                }
+            };
+            if let Err(e) = result {
+                // One failed interface stops the others so the error is not masked.
+                terminate.store(true, Ordering::Relaxed);
+                capture_error.get_or_insert(e);
             }
-        });
+        }
 
-        info!("Waiting for processor and capture tasks");
-        let (processor_result, capture_result) = tokio::join!(processor, capture_task);
+        // Every source is exhausted or failed; let the processor drain and exit.
+        self.event_bus.close();
 
         // Handle processor task completion
-        let _ = processor_result
+        let _ = processor
+            .await
             .map_err(|e| SimulationError::Processing(e.to_string()))
             .map_err(|e| {
                 error!("Processor task panicked: {e}");
                 SimulationError::Processing(format!("Processor panic: {e}"))
             })?;
 
-        // Handle capture task completion
-        let _ = capture_result.map_err(|e| {
-            error!("Capture task failed: {e}");
-            SimulationError::Processing(format!("Capture failure: {e}"))
-        })?;
+        if packets.fallbacks() > 0 {
+            warn!(
+                "{} captured frames did not fit the packet pool ({} x {} bytes)",
+                packets.fallbacks(),
+                packets.capacity(),
+                packets.slab_size()
+            );
+        }
+
+        if let Some(e) = capture_error {
+            return Err(e);
+        }
 
         info!("Production mode shutdown complete");
         Ok(())
     }
 
-    /// Spawns a dedicated event processor task that continuously calls `recv()`
-    /// on the EventBus. This can have multiple implementations based on use cases.
+    /// Spawns the event processing stage: a dispatcher task drains the EventBus and hands
+    /// each packet event to one of `num_consumers` flow-affine workers. Everything from the
+    /// priority lane is handled by the dispatcher itself; a shutdown command sets
+    /// `terminate`.
     ///
-    /// This loop runs forever unless externally cancelled or aborted.
-    #[instrument(skip(self))]
-    fn spawn_event_processor(&self) -> JoinHandle<Result<(), SimulationError>> {
+    /// This runs until the bus is closed and drained, or the task is aborted.
+    #[instrument(skip_all)]
+    fn spawn_event_processor(
+        &self,
+        terminate: Arc<AtomicBool>,
+    ) -> JoinHandle<Result<(), SimulationError>> {
         let event_bus = self.event_bus.clone();
-        let event_processor = self.event_processor.clone(); // Clone the trait object
+        let alerts = self.alerts.clone();
+        let mut pipeline = FlowPipeline::spawn(self.event_processor.clone(), self.workers(), None);
 
         tokio::spawn(async move {
-            info!("Event processor started");
-            let mut processed_events = 0;
-
-            loop {
-                match event_bus.recv() {
-                    Some(event) => {
-                        processed_events += 1;
-                        trace!("Processing event #{}", processed_events);
+            info!(
+                "Event processor started with {} workers",
+                pipeline.workers()
+            );
 
-                        // Call Event Processor using Trait
-                        event_processor.process(&event).await?;
+            let mut batch = Vec::with_capacity(DISPATCH_BATCH_SIZE);
+            'dispatch: while let Some(envelope) = event_bus.recv_envelope_async().await {
+                // The rest of the batch is pulled through `recv_envelope` too, so
+                // queued control messages are still handled ahead of packet
+                // events and the starvation guard keeps its say.
+                let mut next = Some(envelope);
+                while let Some(envelope) = next {
+                    match envelope {
+                        Envelope::Network(event) => batch.push(event),
+                        Envelope::Control(control) => {
+                            handle_control(control, &alerts, &terminate).await;
+                        }
+                        Envelope::Alert(alert) => alerts.handle(alert).await,
+                        Envelope::Application(message) => {
+                            trace!("{} message on the bus", message.protocol.name());
+                        }
+                        Envelope::Prevention(prevention) => {
+                            EventLogger::log_record("prevention", &prevention).await;
+                        }
+                        Envelope::Flow(flow) => {
+                            EventLogger::log_record("flow", &flow).await;
+                        }
                     }
-                    None => {
-                        // Queue empty, avoid busy-spin
-                        sleep(Duration::from_millis(10)).await;
+                    if batch.len() == DISPATCH_BATCH_SIZE {
+                        break;
+                    }
+                    next = event_bus.recv_envelope();
+                }
+                for event in batch.drain(..) {
+                    // A worker only stops early on error, which `finish` reports.
+                    if pipeline.dispatch(event).await.is_err() {
+                        break 'dispatch;
                     }
                 }
             }
-            // Not expected to return normally unless aborted
+
+            let processed = pipeline.finish().await?;
+            info!("Event bus closed, processed {} events", processed);
+            Ok(())
         })
     }
 
-    /// Runs the simulation by using the concrete driver implementation
+    /// Runs the simulation by using the concrete driver implementation.
+    ///
+    /// Events are processed by the flow-affine workers, and completions are merged back
+    /// into dispatch order before hashing so the result does not depend on scheduling.
     #[instrument(skip(self))]
     pub async fn run_simulation(&self, event_count: usize) -> Result<String, SimulationError> {
         debug!("Starting simulation with {} events", event_count);
 
-        let final_hash = String::new();
+        let (completions, mut completed) = mpsc::unbounded_channel::<Completion>();
+        let mut pipeline = FlowPipeline::spawn(
+            self.event_processor.clone(),
+            self.workers(),
+            Some(completions),
+        )
+        .with_shard_hasher(ShardHasher::from_seed(SIMULATION_SHARD_SEED));
+
+        // Completions are hashed while events are still being dispatched, so
+        // at most the out-of-order ones are held in memory.
+        let hashing = tokio::spawn(async move {
+            let mut order = ReorderBuffer::default();
+            let mut hasher = blake3::Hasher::new();
+            let mut first_error = None;
+            while let Some(completion) = completed.recv().await {
+                for completion in order.push(completion.sequence, completion) {
+                    hasher.update(&completion.sequence.to_le_bytes());
+                    hasher.update(&completion.event.timestamp.to_le_bytes());
+                    hasher.update(&completion.event.payload);
+                    match completion.result {
+                        Ok(()) => hasher.update(b"ok"),
+                        Err(e) => {
+                            hasher.update(first_error.get_or_insert(e).to_string().as_bytes())
+                        }
+                    };
+                }
+            }
+            match first_error {
+                Some(e) => Err(e),
+                None => Ok(hex::encode(hasher.finalize().as_bytes())),
+            }
+        });
+
         for _i in 0..event_count {
-            match self.driver.lock().next_event().await {
-                Ok(Some(event)) => match self.process_event(&event).await {
-                    Ok(_) => {}
-                    Err(e) => return Err(e),
-                },
-                Ok(None) => {
-                    break;
+            match self.driver.next_event().await? {
+                Some(event) => {
+                    pipeline.dispatch(event).await?;
                 }
-                Err(e) => return Err(e),
+                None => break,
             }
         }
-        Ok(final_hash)
+        pipeline.finish().await?;
+
+        hashing
+            .await
+            .map_err(|e| SimulationError::Processing(format!("Hashing task panic: {e}")))?
     }
 
-    async fn process_event(&self, event: &NetworkEvent) -> Result<(), SimulationError> {
-        let event_processor = self.event_processor.clone();
-        event_processor.process(event).await
+    /// Number of flow-affine processing workers.
+    fn workers(&self) -> usize {
+        self.config.core.event_bus.num_consumers.max(1) as usize
     }
 
     /// Validates scenario execution hash against the expected result in the scenario.
     #[instrument(skip(self))]
-    fn validate_scenario_hash(
+    pub fn validate_scenario_hash(
         &self,
         scenario: &Scenario,
         actual_hash: &str,
@@ -282,7 +481,7 @@ impl<T: SimulationDriver + Send + Sync + 'static> SimulationRuntime<T> {
         // Spawn the event processor to drain the bus in the background
         let processor_handle = tokio::spawn({
             let this_arc = self.clone();
-            async move { this_arc.spawn_event_processor().await }
+            async move { this_arc.spawn_event_processor(Arc::default()).await }
         });
 
         let mut current_iteration = 0;
@@ -423,17 +622,117 @@ impl<T: SimulationDriver + Send + Sync + 'static> SimulationRuntime<T> {
     }
 }
 
+/// Captures from the interface named in `config` until the source is exhausted,
+/// fails or `terminate` is set, queueing the resulting events on `event_bus`.
+/// Frames are copied into `packets`.
+fn capture_interface(
+    config: &CaptureConfig,
+    event_bus: &EventBus,
+    metrics: &MetricsRecorder,
+    flows: SharedFlowTable,
+    packets: PacketPool,
+    terminate: &AtomicBool,
+) -> Result<(), SimulationError> {
+    let interface = &config.interface;
+    let mut ingest = PacketIngest::with_flows(config, flows);
+    let mut pending = Vec::new();
+    let queue = |envelope: Envelope| {
+        let kind = envelope.kind();
+        if let Err(e) = event_bus.send_envelope(envelope) {
+            warn!("Failed to queue {kind} event: {e}");
+            metrics.inc_bus_rejected(interface, kind);
+        }
+    };
+    let on_batch = |batch: &[Packet]| {
+        for packet in batch {
+            trace!(
+                "Captured packet on {interface}: {} bytes",
+                packet.data.len()
+            );
+            ingest.process(packet, |event| pending.push(event));
+        }
+        for alert in ingest.drain_alerts() {
+            queue(Envelope::Alert(alert));
+        }
+        for flow in ingest.drain_flow_events() {
+            queue(Envelope::Flow(flow));
+        }
+        if pending.is_empty() {
+            return;
+        }
+
+        // Publish the whole burst with one bus update; whatever does not fit
+        // is dropped, as with single sends.
+        debug!("Queueing {} network events", pending.len());
+        match event_bus.send_batch(&mut pending) {
+            Ok(_) if pending.is_empty() => return,
+            Ok(_) => warn!("Event bus full, dropping {} events", pending.len()),
+            Err(e) => warn!("Failed to queue {} events: {e}", pending.len()),
+        }
+        metrics.inc_bus_rejected_by(interface, "packet", pending.len() as u64);
+        pending.clear();
+    };
+
+    let on_stats = |stats: &CaptureStats| {
+        metrics.observe_capture_totals(interface, stats.received, stats.dropped, stats.if_dropped);
+    };
+
+    let stats =
+        open_capture_source(config, interface, Arc::new(packets)).and_then(|mut source| {
+            info!("Starting {} capture on {interface}", config.mode);
+            run_capture_loop_batched(&mut source, terminate, STATS_INTERVAL, on_stats, on_batch)
+        })?;
+    info!(
+        "Capture on {interface} finished: {} received, {} dropped, {} dropped by interface",
+        stats.received, stats.dropped, stats.if_dropped
+    );
+    let defrag = ingest.defrag_stats();
+    debug!(
+        "IP defragmentation on {interface}: {} fragments, {} reassembled, {} overlaps, {} tiny, {} too fragmented, {} timed out, {} evicted",
+        defrag.fragments,
+        defrag.reassembled,
+        defrag.overlaps,
+        defrag.tiny_fragments,
+        defrag.too_many_fragments,
+        defrag.timed_out,
+        defrag.evicted
+    );
+    let reassembly = ingest.reassembly_stats();
+    debug!(
+        "TCP reassembly on {interface}: {} segments, {} retransmissions, {} out of order, {} dropped, {} streams evicted, {} desyncs, {} gaps skipped",
+        reassembly.segments,
+        reassembly.retransmissions,
+        reassembly.out_of_order,
+        reassembly.dropped_segments,
+        reassembly.evicted_streams,
+        reassembly.desyncs,
+        reassembly.skipped_gaps
+    );
+    Ok(())
+}
+
 /// Default Implementation of EventProcessor
 struct DefaultEventProcessor {
     signature_engine: SignatureEngine,
+    mqtt_sessions: Mutex<MqttSessionTracker>,
+    coap_exchanges: Mutex<CoapExchangeTracker>,
     metrics: Arc<MetricsRecorder>,
+    alerts: Arc<AlertHandler>,
 }
 
 impl DefaultEventProcessor {
-    fn new(metrics: Arc<MetricsRecorder>) -> Self {
+    fn new(
+        metrics: Arc<MetricsRecorder>,
+        alerts: Arc<AlertHandler>,
+        mqtt_limits: MqttSessionLimits,
+        coap_limits: CoapExchangeLimits,
+    ) -> Self {
         Self {
             signature_engine: SignatureEngine::new(),
+            mqtt_sessions: Mutex::new(MqttSessionTracker::new(mqtt_limits)),
+            coap_exchanges: Mutex::new(CoapExchangeTracker::new(coap_limits)),
             metrics,
+            alerts,
         }
     }
 }
@@ -452,48 +751,69 @@ impl EventProcessor for DefaultEventProcessor {
             AnyParser::Modbus(ModbusParser::new()),
         ];
 
+        // Body completed by a CoAP block-wise transfer, and transfers dropped.
+        let mut reassembled = None;
+        let mut dropped = Vec::new();
         for parser in &parsers {
-            match parser {
+            let message = match parser {
                 AnyParser::Mqtt(p) => {
                     trace!("Attempting MQTT parsing");
-                    if let Ok(packet) = p.parse(&event.payload) {
-                        debug!("MQTT packet parsed");
-                        let start_time = SystemTime::now();
-                        let matches = self.signature_engine.buffer_scan(packet.payload());
-
-                        self.metrics
-                            .detection_latency
-                            .observe(start_time.elapsed().unwrap().as_nanos() as f64);
-                        handle_detection_results(matches, "MQTT").await;
+                    if self.process_mqtt(p, event).await {
                         return Ok(());
                     }
+                    None
                 }
                 AnyParser::Coap(p) => {
                     trace!("Attempting CoAP parsing");
-                    if let Ok(packet) = p.parse(&event.payload) {
+                    p.parse(&event.payload).ok().and_then(|packet| {
                         debug!("CoAP packet parsed");
-                        let start_time = SystemTime::now();
-                        let matches = self.signature_engine.buffer_scan(packet.payload());
-                        self.metrics
-                            .detection_latency
-                            .observe(start_time.elapsed().unwrap().as_nanos() as f64);
-                        handle_detection_results(matches, "CoAP").await;
-                        return Ok(());
-                    }
+                        let message = ApplicationEvent::parsed_from(
+                            event,
+                            ApplicationProtocol::Coap,
+                            packet.code,
+                            packet.payload(),
+                        )?;
+                        reassembled = self.coap_exchanges.lock().track(
+                            &packet,
+                            &message.scan_context(),
+                            &mut dropped,
+                        );
+                        Some(message)
+                    })
                 }
                 AnyParser::Modbus(p) => {
                     trace!("Attempting Modbus parsing");
-                    if let Ok(packet) = p.parse(&event.payload) {
+                    p.parse(&event.payload).ok().and_then(|packet| {
                         debug!("Modbus packet parsed");
-                        let start_time = SystemTime::now();
-                        let matches = self.signature_engine.buffer_scan(packet.payload());
-                        self.metrics
-                            .detection_latency
-                            .observe(start_time.elapsed().unwrap().as_nanos() as f64);
-                        handle_detection_results(matches, "Modbus").await;
-                        return Ok(());
-                    }
+                        ApplicationEvent::parsed_from(
+                            event,
+                            ApplicationProtocol::ModbusTcp,
+                            packet.function_code,
+                            packet.payload(),
+                        )
+                    })
+                }
+            };
+            if let Some(message) = message {
+                for alert in dropped.drain(..) {
+                    self.alerts.handle(alert).await;
                 }
+                self.inspect(&message).await;
+                if let Some(body) = reassembled {
+                    debug!(
+                        "Reassembled {} byte CoAP body of {} from {} blocks",
+                        body.data.len(),
+                        body.uri,
+                        body.blocks
+                    );
+                    // Signatures spanning blocks only match the whole body.
+                    let message = ApplicationEvent {
+                        body: body.data.into(),
+                        ..message
+                    };
+                    self.inspect(&message).await;
+                }
+                return Ok(());
             }
         }
 
@@ -502,53 +822,325 @@ impl EventProcessor for DefaultEventProcessor {
     }
 }
 
-/// Handles detection results (e.g., malicious signatures) and triggers prevention actions.
-async fn handle_detection_results(matches: Vec<usize>, protocol: &str) {
-    if matches.is_empty() {
-        return;
+impl DefaultEventProcessor {
+    /// Parses, tracks and inspects every MQTT control packet in `event`, as a
+    /// segment may carry several (e.g. CONNECT and SUBSCRIBE). Returns
+    /// `false` if the payload does not start with one.
+    async fn process_mqtt(&self, parser: &MqttParser, event: &NetworkEvent) -> bool {
+        let flow = event.five_tuple();
+        let mut rest = event.payload.clone();
+        // Session state is read and updated under a single lock per segment,
+        // released before any alert is acted on.
+        let mut frames = Vec::new();
+        {
+            let mut sessions = self.mqtt_sessions.lock();
+            while let Ok(Some(len)) = parser.frame_len(&rest) {
+                let frame = rest.split_to(len);
+                // Packets after CONNECT are decoded with the version it negotiated.
+                let version = flow.map(|flow| sessions.version(&flow));
+                let parser = parser.with_version(version.unwrap_or_default());
+                let Ok(packet) = parser.parse(&frame) else {
+                    break;
+                };
+                debug!("MQTT {} packet parsed", packet.packet_type);
+                let Some(message) = ApplicationEvent::parsed_from(
+                    event,
+                    ApplicationProtocol::Mqtt,
+                    packet.header >> 4,
+                    packet.payload(),
+                ) else {
+                    break;
+                };
+                let anomalies = sessions.observe(&packet, &message.scan_context());
+                frames.push((anomalies, message));
+            }
+        }
+        let parsed = !frames.is_empty();
+        for (anomalies, message) in frames {
+            for alert in anomalies {
+                self.alerts.handle(alert).await;
+            }
+            self.inspect(&message).await;
+        }
+        parsed
     }
 
-    info!(
-        "Detected {} suspicious patterns in {protocol}",
-        matches.len()
-    );
+    /// Scans a parsed message for signatures and acts on the resulting alerts.
+    async fn inspect(&self, message: &ApplicationEvent) {
+        let start_time = SystemTime::now();
+        let alerts = self
+            .signature_engine
+            .detect(&message.body, &message.scan_context());
+        self.metrics
+            .detection_latency
+            .observe(start_time.elapsed().unwrap().as_nanos() as f64);
+        for alert in alerts {
+            self.alerts.handle(alert).await;
+        }
+    }
+}
+
+/// Single entry point for alerts, wherever they are raised. Alerts below
+/// `min_severity` are dropped here; the rest are counted and logged, and
+/// those `block_policy` allows are answered by blocking their client on
+/// `firewall_interface`.
+struct AlertHandler {
+    min_severity: Severity,
+    block_policy: BlockPolicy,
+    firewall_interface: Arc<str>,
+    metrics: Arc<MetricsRecorder>,
+}
+
+impl AlertHandler {
+    fn new(
+        min_severity: Severity,
+        block_policy: BlockPolicy,
+        firewall_interface: Arc<str>,
+        metrics: Arc<MetricsRecorder>,
+    ) -> Self {
+        Self {
+            min_severity,
+            block_policy,
+            firewall_interface,
+            metrics,
+        }
+    }
 
-    let fw = match Firewall::new("eth0") {
-        Ok(fw) => fw,
-        Err(e) => {
-            error!("Firewall initialization failed: {e}");
+    async fn handle(&self, alert: Alert) {
+        if alert.severity < self.min_severity {
+            debug!(
+                "Suppressing {} alert from rule {}",
+                alert.severity, alert.rule_id
+            );
             return;
         }
+
+        info!(
+            "[{}] {} (rule {} rev {}) in {} on {}",
+            alert.severity,
+            alert.message,
+            alert.rule_id,
+            alert.rule_revision,
+            alert.protocol,
+            alert.interface.as_deref().unwrap_or("simulation")
+        );
+        self.metrics.record_alert(&alert);
+        EventLogger::log_record("alert", &alert).await;
+
+        match block_target(&alert, &self.block_policy) {
+            Some(target) => {
+                self.prevent(PreventionAction::Block, target).await;
+            }
+            None => debug!("Alert from rule {} does not block", alert.rule_id),
+        }
+    }
+
+    /// Applies `action` to `target` on the configured firewall interface.
+    async fn prevent(&self, action: PreventionAction, target: IpAddr) -> PreventionEvent {
+        apply_prevention(action, target, self.firewall_interface.clone()).await
+    }
+}
+
+/// Applies a prevention action and records its outcome.
+async fn apply_prevention(
+    action: PreventionAction,
+    target: IpAddr,
+    interface: Arc<str>,
+) -> PreventionEvent {
+    let outcome = match target {
+        IpAddr::V4(ip) => {
+            let result = Firewall::new(&interface).and_then(|mut fw| match action {
+                PreventionAction::Block => fw.block_ip(ip),
+                PreventionAction::Unblock => fw.unblock_ip(ip),
+            });
+            match result {
+                Ok(()) => {
+                    info!("{action:?} applied to {ip} on {interface}");
+                    PreventionOutcome::Applied
+                }
+                Err(e) => {
+                    error!("Firewall {action:?} of {ip} failed: {e}");
+                    PreventionOutcome::Failed(e.to_string())
+                }
+            }
+        }
+        IpAddr::V6(_) => {
+            warn!("Firewall does not support IPv6 rules, ignoring {target}");
+            PreventionOutcome::Unsupported
+        }
     };
 
-    const BLOCK_IP: std::net::Ipv4Addr = std::net::Ipv4Addr::new(127, 0, 0, 1);
+    let prevention = PreventionEvent {
+        timestamp: now_ns(),
+        action,
+        target,
+        interface,
+        outcome,
+    };
+    EventLogger::log_record("prevention", &prevention).await;
+    prevention
+}
+
+/// Wall-clock time in nanoseconds since the Unix epoch.
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
 
-    let result = block_ip_and_log(fw, BLOCK_IP).await;
-    if let Err(e) = result {
-        error!("Firewall block failed: {e}");
+/// Applies a control command from the bus's priority lane.
+async fn handle_control(control: ControlEvent, alerts: &AlertHandler, terminate: &AtomicBool) {
+    info!("Control command: {control:?}");
+    match control {
+        ControlEvent::Shutdown => terminate.store(true, Ordering::Relaxed),
+        ControlEvent::ReloadRules => {
+            warn!("Rule reload requested, but no rule source is configured");
+        }
+        ControlEvent::Block(ip) => {
+            alerts.prevent(PreventionAction::Block, ip).await;
+        }
+        ControlEvent::Unblock(ip) => {
+            alerts.prevent(PreventionAction::Unblock, ip).await;
+        }
     }
 }
 
-async fn block_ip_and_log(mut firewall: Firewall, ip: std::net::Ipv4Addr) -> Result<(), String> {
-    if let Err(e) = firewall.block_ip(ip) {
-        let error_msg = e.to_string();
-        EventLogger::log_event(
-            "firewall_error",
-            vec![
-                KeyValue::new("error", error_msg.clone()),
-                KeyValue::new("action", "block_ip"),
-            ],
-        )
-        .await;
-        return Err(error_msg);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::default_driver::DefaultSimulationDriver;
+    use vakthund_core::events::network::TransportProtocol;
+
+    async fn simulate(workers: u32) -> String {
+        let mut config = VakthundConfig::default();
+        config.core.event_bus.num_consumers = workers;
+        let simulator = Simulator::new(7, false, 1, 0, None);
+        let runtime = SimulationRuntime::new(config, DefaultSimulationDriver::new(simulator, 64));
+        runtime.run_simulation(64).await.unwrap()
     }
 
-    info!("Successfully blocked IP: {ip}");
-    EventLogger::log_event(
-        "firewall_block",
-        vec![KeyValue::new("ip_address", ip.to_string())],
-    )
-    .await;
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn simulation_hash_does_not_depend_on_worker_count() {
+        let serial = simulate(1).await;
+        assert_eq!(serial.len(), 64);
+        assert_eq!(simulate(4).await, serial);
+        assert_eq!(simulate(4).await, serial);
+    }
 
-    Ok(())
+    #[tokio::test]
+    async fn failed_capture_is_reported() {
+        let mut config = VakthundConfig::default();
+        config.capture.mode = "file".into();
+        let simulator = Simulator::new(7, false, 1, 0, None);
+        let runtime = Arc::new(SimulationRuntime::new(
+            config,
+            DefaultSimulationDriver::new(simulator, 1),
+        ));
+        let interfaces = ["eth0".to_string(), "eth1".to_string()];
+        assert!(runtime.run_production(&interfaces).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn alerts_below_min_severity_are_suppressed() {
+        let metrics = Arc::new(MetricsRecorder::new());
+        let handler = AlertHandler::new(
+            Severity::High,
+            BlockPolicy::default(),
+            "eth0".into(),
+            metrics.clone(),
+        );
+        let alert = |severity| Alert {
+            timestamp: 0,
+            rule_id: 1,
+            rule_revision: 1,
+            message: "test".into(),
+            severity,
+            protocol: "MQTT".into(),
+            flow: None,
+            initiator: None,
+            interface: None,
+            offsets: Vec::new(),
+            excerpt: Vec::new(),
+        };
+        handler.handle(alert(Severity::Medium)).await;
+        handler.handle(alert(Severity::Critical)).await;
+
+        let output = metrics.gather_metrics().unwrap();
+        assert!(!output.contains(r#"severity="medium""#));
+        assert!(output.contains(r#"vakthund_alerts_total{protocol="MQTT",severity="critical"} 1"#));
+    }
+
+    #[tokio::test]
+    async fn mqtt_packets_sharing_a_segment_are_all_tracked() {
+        let metrics = Arc::new(MetricsRecorder::new());
+        let alerts = Arc::new(AlertHandler::new(
+            Severity::Low,
+            BlockPolicy::default(),
+            "eth0".into(),
+            metrics.clone(),
+        ));
+        let processor = DefaultEventProcessor::new(
+            metrics.clone(),
+            alerts,
+            MqttSessionLimits::default(),
+            CoapExchangeLimits::default(),
+        );
+        let client = "10.0.0.1:50000".parse().unwrap();
+        let broker = "10.0.0.2:1883".parse().unwrap();
+        let event = |source, destination, payload: &'static [u8]| NetworkEvent {
+            source: Some(source),
+            destination: Some(destination),
+            protocol: Some(TransportProtocol::Tcp),
+            ..NetworkEvent::new(1, bytes::Bytes::from_static(payload))
+        };
+
+        // CONNECT with a clean session and SUBSCRIBE to a/# in one segment.
+        let segment =
+            b"\x10\x0D\x00\x04MQTT\x04\x02\x00\x00\x00\x01c\x82\x08\x00\x01\x00\x03a/#\x00";
+        processor
+            .process(&event(client, broker, segment))
+            .await
+            .unwrap();
+        let publish = b"\x30\x06\x00\x03a/b\x00";
+        processor
+            .process(&event(broker, client, publish))
+            .await
+            .unwrap();
+
+        // The SUBSCRIBE was tracked, so the PUBLISH is not unsolicited.
+        let output = metrics.gather_metrics().unwrap();
+        assert!(!output.contains(r#"vakthund_alerts_total{protocol="MQTT""#));
+        assert_eq!(processor.mqtt_sessions.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn shutdown_command_sets_terminate() {
+        let simulator = Simulator::new(0, false, 0, 0, None);
+        let runtime = SimulationRuntime::new(
+            VakthundConfig::default(),
+            DefaultSimulationDriver::new(simulator, 0),
+        );
+        for ts in 0..16 {
+            let event = NetworkEvent::new(ts, bytes::Bytes::from_static(b"payload"));
+            runtime.event_bus.send(event).unwrap();
+        }
+        runtime
+            .event_bus
+            .send_envelope(ControlEvent::Shutdown.into())
+            .unwrap();
+
+        let terminate = Arc::new(AtomicBool::new(false));
+        let processor = runtime.spawn_event_processor(terminate.clone());
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !terminate.load(Ordering::Relaxed) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("shutdown should be handled");
+
+        runtime.event_bus.close();
+        processor.await.unwrap().unwrap();
+        runtime.event_bus.verify_completion().unwrap();
+    }
 }
@@ -13,7 +13,10 @@
 //!
 //! ### Future:
 //! - P4-programmable data plane integration
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
 use thiserror::Error;
+use vakthund_detection::{Alert, Severity};
 
 #[derive(Debug, Error)]
 pub enum FirewallError {
@@ -21,6 +24,9 @@ pub enum FirewallError {
     NotAvailable,
 }
 
+/// Firewall handle for one interface. No rule backend exists yet, so every
+/// rule change fails with [`FirewallError::NotAvailable`] rather than
+/// reporting an address as blocked or unblocked when it is not.
 pub struct Firewall {}
 
 impl Firewall {
@@ -29,19 +35,78 @@ impl Firewall {
     }
 
     pub fn block_ip(&mut self, _addr: std::net::Ipv4Addr) -> Result<(), FirewallError> {
-        // No-op implementation
-        Ok(())
+        Err(FirewallError::NotAvailable)
+    }
+
+    pub fn unblock_ip(&mut self, _addr: std::net::Ipv4Addr) -> Result<(), FirewallError> {
+        Err(FirewallError::NotAvailable)
     }
 
     pub fn is_ip_blocked(&self, _addr: std::net::Ipv4Addr) -> bool {
-        // No-op implementation
+        // Nothing can be blocked without a rule backend.
         false
     }
 }
 
+/// IP protocol number of UDP.
+const UDP: u8 = 17;
+
+/// Which alerts block their client without an operator's confirmation.
+/// The default blocks nothing.
+#[derive(Clone, Debug)]
+pub struct BlockPolicy {
+    pub enabled: bool,
+    pub min_severity: Severity,
+    /// Rule ids allowed to block; empty allows every rule
+    pub rules: Vec<u32>,
+    /// Block UDP clients, whose source addresses are trivially spoofed
+    pub block_udp: bool,
+    /// Addresses that are never blocked, such as gateways and management hosts
+    pub whitelist: Vec<IpNetwork>,
+}
+
+impl Default for BlockPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_severity: Severity::Critical,
+            rules: Vec::new(),
+            block_udp: false,
+            whitelist: Vec::new(),
+        }
+    }
+}
+
+/// Address to block in response to `alert` under `policy`: the client that
+/// opened the offending connection. Alerts whose client is unknown are
+/// never answered with a block, as blocking the server side would cut off
+/// every legitimate client too. Whitelisted clients are never blocked.
+pub fn block_target(alert: &Alert, policy: &BlockPolicy) -> Option<IpAddr> {
+    if !policy.enabled
+        || alert.severity < policy.min_severity
+        || !(policy.rules.is_empty() || policy.rules.contains(&alert.rule_id))
+    {
+        return None;
+    }
+    let flow = alert.flow?;
+    if flow.protocol == UDP && !policy.block_udp {
+        return None;
+    }
+    let client = alert.initiator?.ip();
+    if policy
+        .whitelist
+        .iter()
+        .any(|network| network.contains(client))
+    {
+        return None;
+    }
+    Some(client)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use vakthund_detection::FiveTuple;
 
     #[test]
     fn test_firewall_init() {
@@ -49,10 +114,98 @@ mod tests {
         // always returns Ok.  More sophisticated tests would be needed
         // if a real implementation was present.
         let interface = "eth0";
-        if let Ok(_fw) = Firewall::new(interface) {
-            assert!(true);
-        } else {
-            assert!(false);
+        assert!(Firewall::new(interface).is_ok());
+    }
+
+    #[test]
+    fn rule_changes_fail_without_a_backend() {
+        let mut firewall = Firewall::new("eth0").unwrap();
+        let addr = "10.0.0.1".parse().unwrap();
+        assert!(matches!(
+            firewall.block_ip(addr),
+            Err(FirewallError::NotAvailable)
+        ));
+        assert!(matches!(
+            firewall.unblock_ip(addr),
+            Err(FirewallError::NotAvailable)
+        ));
+        assert!(!firewall.is_ip_blocked(addr));
+    }
+
+    fn alert(severity: Severity, protocol: u8) -> Alert {
+        Alert {
+            timestamp: 0,
+            rule_id: 7,
+            rule_revision: 1,
+            message: "test".into(),
+            severity,
+            protocol: "MQTT".into(),
+            flow: Some(FiveTuple {
+                source: "10.0.0.2:1883".parse().unwrap(),
+                destination: "10.0.0.1:50000".parse().unwrap(),
+                protocol,
+            }),
+            initiator: Some("10.0.0.1:50000".parse().unwrap()),
+            interface: None,
+            offsets: Vec::new(),
+            excerpt: Vec::new(),
         }
     }
+
+    #[test]
+    fn blocks_the_client_only_when_the_policy_allows() {
+        let policy = BlockPolicy {
+            enabled: true,
+            min_severity: Severity::High,
+            ..BlockPolicy::default()
+        };
+        let client = "10.0.0.1".parse().ok();
+        // The client is blocked even when the server sent the packet.
+        assert_eq!(block_target(&alert(Severity::High, 6), &policy), client);
+        assert_eq!(block_target(&alert(Severity::Medium, 6), &policy), None);
+        assert_eq!(
+            block_target(&alert(Severity::Critical, 6), &BlockPolicy::default()),
+            None
+        );
+
+        let other_rules = BlockPolicy {
+            rules: vec![8],
+            ..policy.clone()
+        };
+        assert_eq!(block_target(&alert(Severity::High, 6), &other_rules), None);
+
+        let mut unknown_client = alert(Severity::High, 6);
+        unknown_client.initiator = None;
+        assert_eq!(block_target(&unknown_client, &policy), None);
+    }
+
+    #[test]
+    fn whitelisted_clients_are_never_blocked() {
+        let policy = BlockPolicy {
+            enabled: true,
+            whitelist: vec!["10.0.0.0/30".parse().unwrap()],
+            ..BlockPolicy::default()
+        };
+        assert_eq!(block_target(&alert(Severity::Critical, 6), &policy), None);
+
+        let policy = BlockPolicy {
+            whitelist: vec!["10.0.1.0/24".parse().unwrap(), "fd00::/8".parse().unwrap()],
+            ..policy
+        };
+        assert!(block_target(&alert(Severity::Critical, 6), &policy).is_some());
+    }
+
+    #[test]
+    fn udp_clients_need_confirmation() {
+        let policy = BlockPolicy {
+            enabled: true,
+            ..BlockPolicy::default()
+        };
+        assert_eq!(block_target(&alert(Severity::Critical, 17), &policy), None);
+        let policy = BlockPolicy {
+            block_udp: true,
+            ..policy
+        };
+        assert!(block_target(&alert(Severity::Critical, 17), &policy).is_some());
+    }
 }
@@ -7,4 +7,4 @@ pub mod firewall;
 // TODO: pub mod quarantine;
 // TODO: pub mod rate_limit;
 
-pub use firewall::Firewall;
+pub use firewall::{block_target, BlockPolicy, Firewall};
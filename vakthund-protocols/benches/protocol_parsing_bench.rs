@@ -9,13 +9,13 @@ use vakthund_protocols::{CoapParser, ModbusParser, MqttParser};
 // Source: https://www.hivemq.com/mqtt-essentials/mqtt-message-format/
 // Example of a complete MQTT Connect package
 const MQTT_DATA: &[u8] = &[
-    0x10, 0x0E, // Connect packet, remaining length
+    0x10, 0x17, // Connect packet, remaining length
     0x00, 0x04, 0x4D, 0x51, 0x54, 0x54, // MQTT
     0x04, // Protocol level
     0x02, // Connect flags
     0x00, 0x3C, // Keepalive
     0x00, 0x0B, // Client ID length
-    0x74, 0x65, 0x73, 0x74, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x31, 0x32,
+    0x74, 0x65, 0x73, 0x74, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74, 0x31,
 ];
 
 // Source: https://datatracker.ietf.org/doc/html/rfc7252#section-3
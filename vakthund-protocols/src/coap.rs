@@ -1,6 +1,20 @@
+//! ## vakthund-protocols::coap
+//! Zero-copy CoAP (RFC 7252) message parser.
+//!
+//! Options are decoded from their delta/length nibbles, including the 13 and
+//! 14 extended forms, so the payload marker is found where the options end
+//! rather than by searching for `0xFF`. Options are validated once and
+//! iterated lazily, with typed accessors for the commonly inspected ones.
+
 use bytes::Bytes;
 use thiserror::Error;
 
+/// Well-known CoAP port (unencrypted).
+pub const COAP_PORT: u16 = 5683;
+
+/// Marker separating options from the payload.
+const PAYLOAD_MARKER: u8 = 0xFF;
+
 /// CoAP-specific errors.
 #[derive(Clone, Debug, PartialEq, Error)]
 pub enum CoapParseError {
@@ -10,7 +24,8 @@ pub enum CoapParseError {
     /// The version field in the header is not supported.
     #[error("Invalid CoAP version")]
     InvalidVersion,
-    /// The option number is invalid.
+    /// An option is malformed: reserved nibble, truncated value, number out
+    /// of range or a value of the wrong length or format.
     #[error("Invalid CoAP option number")]
     InvalidOptionNumber,
     /// The packet is malformed or contains invalid data.
@@ -18,6 +33,226 @@ pub enum CoapParseError {
     MalformedPacket,
 }
 
+/// One option of a CoAP message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CoapOption<'a> {
+    pub number: u16,
+    pub value: &'a [u8],
+}
+
+impl<'a> CoapOption<'a> {
+    pub const IF_MATCH: u16 = 1;
+    pub const URI_HOST: u16 = 3;
+    pub const ETAG: u16 = 4;
+    pub const IF_NONE_MATCH: u16 = 5;
+    pub const OBSERVE: u16 = 6;
+    pub const URI_PORT: u16 = 7;
+    pub const LOCATION_PATH: u16 = 8;
+    pub const URI_PATH: u16 = 11;
+    pub const CONTENT_FORMAT: u16 = 12;
+    pub const MAX_AGE: u16 = 14;
+    pub const URI_QUERY: u16 = 15;
+    pub const ACCEPT: u16 = 17;
+    pub const LOCATION_QUERY: u16 = 20;
+    pub const BLOCK2: u16 = 23;
+    pub const BLOCK1: u16 = 27;
+    pub const SIZE2: u16 = 28;
+    pub const PROXY_URI: u16 = 35;
+    pub const PROXY_SCHEME: u16 = 39;
+    pub const SIZE1: u16 = 60;
+
+    /// Decodes the value as a big-endian unsigned integer, as used by
+    /// `uint` options (an empty value is zero).
+    pub fn uint(&self) -> u32 {
+        self.value
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | u32::from(byte))
+    }
+
+    /// The value of a string option; validated when the message was parsed.
+    fn str(&self) -> &'a str {
+        std::str::from_utf8(self.value).unwrap_or_default()
+    }
+
+    /// Checks the value against the format and length limits of known
+    /// options (RFC 7252 section 5.10, RFC 7641, RFC 7959).
+    fn validate(&self) -> Result<(), CoapParseError> {
+        let (max_len, is_string) = match self.number {
+            Self::URI_HOST => (255, true),
+            Self::LOCATION_PATH | Self::URI_PATH | Self::URI_QUERY | Self::LOCATION_QUERY => {
+                (255, true)
+            }
+            Self::PROXY_URI => (1034, true),
+            Self::PROXY_SCHEME => (255, true),
+            Self::IF_MATCH | Self::ETAG => (8, false),
+            Self::IF_NONE_MATCH => (0, false),
+            Self::URI_PORT | Self::CONTENT_FORMAT | Self::ACCEPT => (2, false),
+            Self::OBSERVE | Self::BLOCK2 | Self::BLOCK1 => (3, false),
+            Self::MAX_AGE | Self::SIZE2 | Self::SIZE1 => (4, false),
+            _ => return Ok(()),
+        };
+        if self.value.len() > max_len || (is_string && std::str::from_utf8(self.value).is_err()) {
+            return Err(CoapParseError::InvalidOptionNumber);
+        }
+        Ok(())
+    }
+}
+
+/// A Block1 or Block2 option value (RFC 7959).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// Block number
+    pub num: u32,
+    /// More blocks follow
+    pub more: bool,
+    /// Size exponent: blocks are `2^(szx + 4)` bytes
+    pub szx: u8,
+}
+
+impl Block {
+    /// Largest valid size exponent; 7 is reserved.
+    pub const MAX_SZX: u8 = 6;
+
+    /// Decodes an option value, or `None` for the reserved `szx` 7.
+    fn from_uint(value: u32) -> Option<Self> {
+        let szx = (value & 0x07) as u8;
+        (szx <= Self::MAX_SZX).then_some(Self {
+            num: value >> 4,
+            more: value & 0x08 != 0,
+            szx,
+        })
+    }
+
+    /// Block size in bytes, from 16 to 1024.
+    pub fn size(&self) -> usize {
+        1 << (usize::from(self.szx) + 4)
+    }
+
+    /// Byte offset of this block within the whole body.
+    pub fn offset(&self) -> usize {
+        self.num as usize * self.size()
+    }
+}
+
+/// The options of a CoAP message, validated at parse time.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CoapOptions<'a>(&'a [u8]);
+
+impl<'a> CoapOptions<'a> {
+    /// The encoded options, as they appear on the wire.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the options in ascending option number order.
+    pub fn iter(&self) -> impl Iterator<Item = CoapOption<'a>> + 'a {
+        let mut data = self.0;
+        let mut number = 0;
+        std::iter::from_fn(move || {
+            let (option, rest) = next_option(data, number).ok()??;
+            data = rest;
+            number = option.number;
+            Some(option)
+        })
+    }
+
+    /// Every option with the given number.
+    pub fn get(&self, number: u16) -> impl Iterator<Item = CoapOption<'a>> + 'a {
+        self.iter().filter(move |option| option.number == number)
+    }
+
+    /// The first option with the given number.
+    pub fn first(&self, number: u16) -> Option<CoapOption<'a>> {
+        self.get(number).next()
+    }
+
+    /// Uri-Path segments in order.
+    pub fn uri_path(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.get(CoapOption::URI_PATH).map(|option| option.str())
+    }
+
+    /// Uri-Query arguments in order.
+    pub fn uri_query(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.get(CoapOption::URI_QUERY).map(|option| option.str())
+    }
+
+    pub fn content_format(&self) -> Option<u16> {
+        self.first(CoapOption::CONTENT_FORMAT)
+            .map(|option| option.uint() as u16)
+    }
+
+    /// Observe registration (0), deregistration (1) or notification sequence
+    /// number.
+    pub fn observe(&self) -> Option<u32> {
+        self.first(CoapOption::OBSERVE).map(|option| option.uint())
+    }
+
+    /// Block1 option, unless absent or using the reserved size exponent.
+    pub fn block1(&self) -> Option<Block> {
+        self.first(CoapOption::BLOCK1)
+            .and_then(|option| Block::from_uint(option.uint()))
+    }
+
+    /// Block2 option, unless absent or using the reserved size exponent.
+    pub fn block2(&self) -> Option<Block> {
+        self.first(CoapOption::BLOCK2)
+            .and_then(|option| Block::from_uint(option.uint()))
+    }
+
+    /// Size of the request body being transferred (Size1).
+    pub fn size1(&self) -> Option<u32> {
+        self.first(CoapOption::SIZE1).map(|option| option.uint())
+    }
+
+    /// Size of the response body being transferred (Size2).
+    pub fn size2(&self) -> Option<u32> {
+        self.first(CoapOption::SIZE2).map(|option| option.uint())
+    }
+}
+
+/// Decodes the option following `previous` at the start of `data`. Returns
+/// `None` at the end of the options (end of data or the payload marker).
+fn next_option(
+    data: &[u8],
+    previous: u16,
+) -> Result<Option<(CoapOption<'_>, &[u8])>, CoapParseError> {
+    let Some((&first, mut rest)) = data.split_first() else {
+        return Ok(None);
+    };
+    if first == PAYLOAD_MARKER {
+        return Ok(None);
+    }
+    let mut extended = |nibble: u8| -> Result<u32, CoapParseError> {
+        let (value, len) = match nibble {
+            0..=12 => return Ok(u32::from(nibble)),
+            13 => (rest.first().map(|&byte| u32::from(byte) + 13), 1),
+            14 => (
+                rest.get(..2)
+                    .map(|bytes| u32::from(u16::from_be_bytes([bytes[0], bytes[1]])) + 269),
+                2,
+            ),
+            // 15 is reserved outside the payload marker.
+            _ => (None, 0),
+        };
+        rest = &rest[len.min(rest.len())..];
+        value.ok_or(CoapParseError::InvalidOptionNumber)
+    };
+    let delta = extended(first >> 4)?;
+    let length = extended(first & 0x0F)? as usize;
+
+    let number = u16::try_from(u32::from(previous) + delta)
+        .map_err(|_| CoapParseError::InvalidOptionNumber)?;
+    if rest.len() < length {
+        return Err(CoapParseError::InvalidOptionNumber);
+    }
+    let (value, rest) = rest.split_at(length);
+    Ok(Some((CoapOption { number, value }, rest)))
+}
+
 /// Represents a CoAP packet with zero-copy slices into the original data.
 #[derive(Debug, Copy, Clone)]
 pub struct CoapPacket<'a> {
@@ -31,17 +266,39 @@ pub struct CoapPacket<'a> {
     pub code: u8,
     /// The message ID (2 bytes).
     pub message_id: u16,
-    /// The options (variable-length bytes after token).
-    pub options: &'a [u8],
+    /// The token (0-8 bytes after the header).
+    pub token: &'a [u8],
+    /// The options (after the token).
+    pub options: CoapOptions<'a>,
     /// The payload (after 0xFF marker).
     pub payload: &'a [u8],
 }
 
 impl<'a> CoapPacket<'a> {
+    pub const CONFIRMABLE: u8 = 0;
+    pub const NON_CONFIRMABLE: u8 = 1;
+    pub const ACKNOWLEDGEMENT: u8 = 2;
+    pub const RESET: u8 = 3;
+
     /// Returns the payload of the packet.
     pub fn payload(&self) -> &'a [u8] {
         self.payload
     }
+
+    /// Class of the code: 0 for requests, 2 to 5 for responses.
+    pub fn code_class(&self) -> u8 {
+        self.code >> 5
+    }
+
+    /// Whether the message is a request (codes 0.01 to 0.31).
+    pub fn is_request(&self) -> bool {
+        self.code_class() == 0 && self.code != 0
+    }
+
+    /// Whether the message is a response (codes 2.00 to 5.31).
+    pub fn is_response(&self) -> bool {
+        (2..=5).contains(&self.code_class())
+    }
 }
 
 /// A simple CoAP parser.
@@ -71,31 +328,38 @@ impl CoapParser {
         if version != 1 {
             return Err(CoapParseError::InvalidVersion);
         }
+        // Token lengths 9-15 are reserved.
+        if token_length > 8 {
+            return Err(CoapParseError::MalformedPacket);
+        }
 
         // Parse remaining header fields
         let code = data[1];
         let message_id = u16::from_be_bytes([data[2], data[3]]);
 
-        // Skip over token bytes if present
-        let mut current_offset = 4;
-        if current_offset + token_length as usize > data.len() {
+        // Token bytes follow the header
+        let options_start = 4 + token_length as usize;
+        if options_start > data.len() {
             return Err(CoapParseError::InsufficientData);
         }
-        current_offset += token_length as usize;
+        let token = &data[4..options_start];
 
-        // Find payload marker (0xFF) if exists
-        let payload_marker = data[current_offset..].iter().position(|&x| x == 0xFF);
+        // Walk the options to find where they end.
+        let mut rest = &data[options_start..];
+        let mut number = 0;
+        while let Some((option, remaining)) = next_option(rest, number)? {
+            option.validate()?;
+            number = option.number;
+            rest = remaining;
+        }
+        let options_end = data.len() - rest.len();
+        let options = CoapOptions(&data[options_start..options_end]);
 
-        let (options, payload) = match payload_marker {
-            Some(pos) => {
-                // Split at payload marker
-                let marker_pos = current_offset + pos;
-                (&data[current_offset..marker_pos], &data[marker_pos + 1..])
-            }
-            None => {
-                // No payload - everything is options
-                (&data[current_offset..], &[] as &[u8])
-            }
+        let payload = match rest.split_first() {
+            // A marker must be followed by a non-empty payload.
+            Some((_, [])) => return Err(CoapParseError::MalformedPacket),
+            Some((_, payload)) => payload,
+            None => &[],
         };
 
         Ok(CoapPacket {
@@ -104,6 +368,7 @@ impl CoapParser {
             token_length,
             code,
             message_id,
+            token,
             options,
             payload,
         })
@@ -153,4 +418,106 @@ mod tests {
         assert_eq!(packet.payload().len(), 0);
         assert!(packet.options.is_empty());
     }
+
+    #[test]
+    fn test_options_with_extended_delta_and_length() {
+        let mut packet = vec![0x42, 0x01, 0x00, 0x01, 0xAB, 0xCD];
+        // Observe (6): register.
+        packet.push(0x60);
+        // Uri-Path (11) "sensors" and "temp", the second with delta 0.
+        packet.push(0x57);
+        packet.extend_from_slice(b"sensors");
+        packet.push(0x04);
+        packet.extend_from_slice(b"temp");
+        // Content-Format (12) 50 (application/json).
+        packet.extend_from_slice(&[0x11, 50]);
+        // Uri-Query (15), 14 bytes long: length nibble 13.
+        packet.extend_from_slice(&[0x3D, 14 - 13]);
+        packet.extend_from_slice(b"unit=celsius&x");
+        // Block2 (23): num 2, more, szx 2 (64 bytes).
+        packet.extend_from_slice(&[0x81, 0x2A]);
+        // Size1 (60), delta 37: delta nibble 13.
+        packet.extend_from_slice(&[0xD2, 37 - 13, 0x01, 0x00]);
+        // Option 400 (unknown, delta 340): delta nibble 14, value 0xFF.
+        packet.push(0xE1);
+        packet.extend_from_slice(&(340u16 - 269).to_be_bytes());
+        packet.push(0xFF);
+        packet.extend_from_slice(&[0xFF, b'h', b'i']);
+        let bytes = Bytes::from(packet);
+
+        let packet = CoapParser::new().parse(&bytes).unwrap();
+        assert_eq!(packet.token, [0xAB, 0xCD]);
+        let options = packet.options;
+        assert_eq!(options.observe(), Some(0));
+        assert_eq!(options.uri_path().collect::<Vec<_>>(), ["sensors", "temp"]);
+        assert_eq!(options.uri_query().collect::<Vec<_>>(), ["unit=celsius&x"]);
+        assert_eq!(options.content_format(), Some(50));
+        assert_eq!(
+            options.block2(),
+            Some(Block {
+                num: 2,
+                more: true,
+                szx: 2
+            })
+        );
+        assert_eq!(options.block2().unwrap().size(), 64);
+
+        // Block1 (27) with the reserved size exponent 7 is not a block.
+        let bytes = Bytes::from_static(&[0x40, 0x03, 0x00, 0x01, 0xD1, 27 - 13, 0x0F]);
+        assert_eq!(
+            CoapParser::new().parse(&bytes).unwrap().options.block1(),
+            None
+        );
+        assert_eq!(options.size1(), Some(256));
+        assert_eq!(options.block1(), None);
+        assert_eq!(
+            options.first(400),
+            Some(CoapOption {
+                number: 400,
+                value: &[0xFF]
+            })
+        );
+        // The 0xFF inside the last option is not mistaken for the marker.
+        assert_eq!(packet.payload(), b"hi");
+    }
+
+    #[test]
+    fn test_malformed_options() {
+        let parser = CoapParser::new();
+        for (options, error) in [
+            // Reserved delta nibble.
+            (&[0xF1, 0x00][..], CoapParseError::InvalidOptionNumber),
+            // Reserved length nibble.
+            (&[0x1F], CoapParseError::InvalidOptionNumber),
+            // Missing extended delta byte.
+            (&[0xD0], CoapParseError::InvalidOptionNumber),
+            // Value runs past the end.
+            (&[0xB4, b'a'], CoapParseError::InvalidOptionNumber),
+            // Option number above 65535.
+            (
+                &[0xE0, 0xFF, 0xFF, 0xE0, 0xFF, 0xFF],
+                CoapParseError::InvalidOptionNumber,
+            ),
+            // Content-Format longer than two bytes.
+            (
+                &[0xC3, 0x00, 0x00, 0x32],
+                CoapParseError::InvalidOptionNumber,
+            ),
+            // Uri-Path that is not UTF-8.
+            (&[0xB1, 0xC3], CoapParseError::InvalidOptionNumber),
+            // Payload marker without a payload.
+            (&[0xFF], CoapParseError::MalformedPacket),
+        ] {
+            let mut packet = vec![0x40, 0x01, 0x00, 0x01];
+            packet.extend_from_slice(options);
+            let bytes = Bytes::from(packet);
+            assert_eq!(parser.parse(&bytes).unwrap_err(), error, "{options:x?}");
+        }
+
+        let bytes = Bytes::from_static(&[0x49, 0x01, 0x00, 0x01]);
+        assert_eq!(
+            parser.parse(&bytes).unwrap_err(),
+            CoapParseError::MalformedPacket
+        );
+    }
 }
@@ -0,0 +1,77 @@
+//! ## vakthund-protocols::framing
+//! Message boundaries for stream (TCP) protocols, so reassembled byte streams
+//! can be cut into one buffer per application message before parsing.
+
+use thiserror::Error;
+
+use crate::{ModbusParseError, ModbusParser, MqttParseError, MqttParser};
+
+/// Well-known MQTT port (unencrypted).
+pub const MQTT_PORT: u16 = 1883;
+/// Well-known Modbus/TCP port.
+pub const MODBUS_TCP_PORT: u16 = 502;
+
+/// Errors raised while delimiting messages in a stream.
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum FramingError {
+    #[error("MQTT framing error: {0}")]
+    Mqtt(#[from] MqttParseError),
+    #[error("Modbus framing error: {0}")]
+    Modbus(#[from] ModbusParseError),
+}
+
+/// TCP protocols whose streams are reassembled and framed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamProtocol {
+    Mqtt,
+    ModbusTcp,
+}
+
+impl StreamProtocol {
+    /// Selects a protocol from a connection's ports (either direction).
+    pub fn from_ports(source: u16, destination: u16) -> Option<Self> {
+        [destination, source]
+            .into_iter()
+            .find_map(|port| match port {
+                MQTT_PORT => Some(StreamProtocol::Mqtt),
+                MODBUS_TCP_PORT => Some(StreamProtocol::ModbusTcp),
+                _ => None,
+            })
+    }
+
+    /// Returns the length of the first complete message in `data`, or `None`
+    /// if more data is needed.
+    pub fn frame_len(self, data: &[u8]) -> Result<Option<usize>, FramingError> {
+        match self {
+            StreamProtocol::Mqtt => Ok(MqttParser::new().frame_len(data)?),
+            StreamProtocol::ModbusTcp => Ok(ModbusParser::new().frame_len(data)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_protocol_by_port() {
+        assert_eq!(
+            StreamProtocol::from_ports(50000, 1883),
+            Some(StreamProtocol::Mqtt)
+        );
+        assert_eq!(
+            StreamProtocol::from_ports(502, 50000),
+            Some(StreamProtocol::ModbusTcp)
+        );
+        assert_eq!(StreamProtocol::from_ports(50000, 80), None);
+    }
+
+    #[test]
+    fn frames_back_to_back_messages() {
+        let stream = [0xE0, 0x00, 0xC0, 0x00];
+        assert_eq!(StreamProtocol::Mqtt.frame_len(&stream), Ok(Some(2)));
+        assert!(StreamProtocol::ModbusTcp
+            .frame_len(&[0, 0, 0, 1, 0, 2])
+            .is_err());
+    }
+}
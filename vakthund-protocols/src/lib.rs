@@ -3,12 +3,14 @@
 use std::fmt::Debug;
 
 pub mod coap;
+pub mod framing;
 pub mod modbus;
 pub mod mqtt;
 
 pub use coap::{CoapPacket, CoapParseError, CoapParser};
+pub use framing::{FramingError, StreamProtocol};
 pub use modbus::{ModbusPacket, ModbusParseError, ModbusParser};
-pub use mqtt::{MqttPacket, MqttParseError, MqttParser};
+pub use mqtt::{ControlPacket, MqttPacket, MqttParseError, MqttParser, ProtocolVersion};
 
 /// A trait for a protocol-specific packet.
 pub trait ProtocolPacket<'a> {
@@ -52,6 +52,22 @@ impl ModbusParser {
         Self
     }
 
+    /// Returns the length of the first complete Modbus/TCP ADU in a byte
+    /// stream, or `None` if more data is needed to delimit it.
+    pub fn frame_len(&self, data: &[u8]) -> Result<Option<usize>, ModbusParseError> {
+        if data.len() < 6 {
+            return Ok(None);
+        }
+        let protocol_id = u16::from_be_bytes([data[2], data[3]]);
+        let length = usize::from(u16::from_be_bytes([data[4], data[5]]));
+        // The length covers the unit id, function code and at most 252 data bytes.
+        if protocol_id != 0 || !(2..=254).contains(&length) {
+            return Err(ModbusParseError::MalformedPacket);
+        }
+        let total = 6 + length;
+        Ok((data.len() >= total).then_some(total))
+    }
+
     /// Parses a Modbus packet from a Bytes slice.
     pub fn parse<'a>(&self, data: &'a Bytes) -> Result<ModbusPacket<'a>, ModbusParseError> {
         if data.len() < 8 {
@@ -70,6 +86,10 @@ impl ModbusParser {
             // We expect the protocol ID to be 0.
             return Err(ModbusParseError::MalformedPacket);
         }
+        if !(2..=254).contains(&length) {
+            // The length covers the unit id, function code and at most 252 data bytes.
+            return Err(ModbusParseError::MalformedPacket);
+        }
         if data.len() < 6 + length as usize {
             // Make sure length is sufficient to read all data.
             return Err(ModbusParseError::InsufficientData);
@@ -145,6 +165,37 @@ mod tests {
         assert!(matches!(result, Err(ModbusParseError::MalformedPacket)));
     }
 
+    #[test]
+    fn test_length_below_header() {
+        let parser = ModbusParser::new();
+        for length in [0, 1] {
+            let packet_bytes = Bytes::from(vec![0x00, 0x00, 0x00, 0x00, 0x00, length, 0x00, 0x00]);
+            let result = parser.parse(&packet_bytes);
+            assert!(matches!(result, Err(ModbusParseError::MalformedPacket)));
+        }
+    }
+
+    #[test]
+    fn test_frame_len() {
+        let parser = ModbusParser::new();
+        let adu = [
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x00, 0x00, 0x01,
+        ];
+        assert_eq!(parser.frame_len(&adu[..5]), Ok(None));
+        assert_eq!(parser.frame_len(&adu[..11]), Ok(None));
+        assert_eq!(parser.frame_len(&adu), Ok(Some(12)));
+
+        let mut stream = adu.to_vec();
+        stream.extend_from_slice(&adu[..4]);
+        assert_eq!(parser.frame_len(&stream), Ok(Some(12)));
+
+        let bad_protocol = [0x00, 0x01, 0x00, 0x01, 0x00, 0x06];
+        assert_eq!(
+            parser.frame_len(&bad_protocol),
+            Err(ModbusParseError::MalformedPacket)
+        );
+    }
+
     #[test]
     fn test_invalid_data_length() {
         let packet_bytes = Bytes::from(vec![
@@ -0,0 +1,1265 @@
+//! ## vakthund-protocols::mqtt
+//! Zero-copy decoder for MQTT 3.1, 3.1.1 and 5 control packets.
+//!
+//! The fixed header is validated (packet type, per-type flags, variable-length
+//! remaining length) and the variable header and payload are decoded into a
+//! [`ControlPacket`] whose strings and binary fields borrow from the input.
+//! Topic filter lists and MQTT 5 [`Properties`] are validated up front and
+//! iterated lazily.
+//!
+//! CONNECT carries its own protocol level. Every other packet is decoded
+//! according to the version the parser was built for, which callers take from
+//! the CONNECT that opened the connection.
+
+mod properties;
+
+pub use properties::{Properties, Property};
+
+use bytes::Bytes;
+use hex;
+use std::fmt;
+use thiserror::Error;
+
+/// Errors that can occur while parsing an MQTT packet.
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum MqttParseError {
+    #[error("Insufficient data to parse MQTT packet")]
+    InsufficientData,
+    #[error("Invalid MQTT header")]
+    InvalidHeader,
+    #[error("Malformed remaining length field")]
+    RemainingLengthMalformed,
+    #[error("Incomplete MQTT packet")]
+    PacketIncomplete,
+    #[error("Invalid fixed header flags for {0}")]
+    InvalidFlags(PacketType),
+    #[error("Unsupported MQTT protocol level {0}")]
+    UnsupportedProtocolLevel(u8),
+    #[error("Invalid UTF-8 string in MQTT packet")]
+    InvalidUtf8,
+    #[error("Malformed {0} packet")]
+    MalformedPacket(PacketType),
+    #[error("Invalid or repeated MQTT property {0:#04x}")]
+    InvalidProperty(u32),
+}
+
+/// MQTT protocol version, as announced by the CONNECT protocol level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolVersion {
+    V3_1 = 3,
+    #[default]
+    V3_1_1 = 4,
+    V5 = 5,
+}
+
+impl ProtocolVersion {
+    /// Maps a CONNECT protocol level to a version.
+    pub fn from_level(level: u8) -> Option<Self> {
+        match level {
+            3 => Some(ProtocolVersion::V3_1),
+            4 => Some(ProtocolVersion::V3_1_1),
+            5 => Some(ProtocolVersion::V5),
+            _ => None,
+        }
+    }
+
+    /// The CONNECT protocol level of this version.
+    pub fn level(self) -> u8 {
+        self as u8
+    }
+}
+
+/// MQTT control packet type, the upper nibble of the fixed header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketType {
+    Connect = 1,
+    Connack,
+    Publish,
+    Puback,
+    Pubrec,
+    Pubrel,
+    Pubcomp,
+    Subscribe,
+    Suback,
+    Unsubscribe,
+    Unsuback,
+    Pingreq,
+    Pingresp,
+    Disconnect,
+    /// MQTT 5 only
+    Auth,
+}
+
+impl PacketType {
+    /// Decodes the packet type from a fixed header byte. Type 0 is reserved.
+    pub fn from_header(header: u8) -> Option<Self> {
+        Some(match header >> 4 {
+            1 => PacketType::Connect,
+            2 => PacketType::Connack,
+            3 => PacketType::Publish,
+            4 => PacketType::Puback,
+            5 => PacketType::Pubrec,
+            6 => PacketType::Pubrel,
+            7 => PacketType::Pubcomp,
+            8 => PacketType::Subscribe,
+            9 => PacketType::Suback,
+            10 => PacketType::Unsubscribe,
+            11 => PacketType::Unsuback,
+            12 => PacketType::Pingreq,
+            13 => PacketType::Pingresp,
+            14 => PacketType::Disconnect,
+            15 => PacketType::Auth,
+            _ => return None,
+        })
+    }
+
+    /// Upper-case name as used in the specification, e.g. `PUBLISH`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PacketType::Connect => "CONNECT",
+            PacketType::Connack => "CONNACK",
+            PacketType::Publish => "PUBLISH",
+            PacketType::Puback => "PUBACK",
+            PacketType::Pubrec => "PUBREC",
+            PacketType::Pubrel => "PUBREL",
+            PacketType::Pubcomp => "PUBCOMP",
+            PacketType::Subscribe => "SUBSCRIBE",
+            PacketType::Suback => "SUBACK",
+            PacketType::Unsubscribe => "UNSUBSCRIBE",
+            PacketType::Unsuback => "UNSUBACK",
+            PacketType::Pingreq => "PINGREQ",
+            PacketType::Pingresp => "PINGRESP",
+            PacketType::Disconnect => "DISCONNECT",
+            PacketType::Auth => "AUTH",
+        }
+    }
+
+    /// Fixed header flags every packet of this type must carry, or `None` for
+    /// PUBLISH, whose flags are DUP, QoS and RETAIN.
+    fn required_flags(self) -> Option<u8> {
+        match self {
+            PacketType::Publish => None,
+            PacketType::Pubrel | PacketType::Subscribe | PacketType::Unsubscribe => Some(0b0010),
+            _ => Some(0),
+        }
+    }
+}
+
+impl fmt::Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Message delivery guarantee.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl QoS {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(QoS::AtMostOnce),
+            1 => Some(QoS::AtLeastOnce),
+            2 => Some(QoS::ExactlyOnce),
+            _ => None,
+        }
+    }
+}
+
+/// Will message registered by a CONNECT.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub message: &'a [u8],
+    pub qos: QoS,
+    pub retain: bool,
+    pub properties: Properties<'a>,
+}
+
+/// CONNECT: a client opening a session.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Connect<'a> {
+    /// `MQTT` for 3.1.1 and 5, `MQIsdp` for 3.1
+    pub protocol_name: &'a str,
+    pub version: ProtocolVersion,
+    /// Raw connect flags byte
+    pub flags: u8,
+    /// Clean session (3.x) or clean start (5)
+    pub clean_session: bool,
+    /// Keep alive interval in seconds (0 disables it)
+    pub keep_alive: u16,
+    pub properties: Properties<'a>,
+    pub client_id: &'a str,
+    pub will: Option<Will<'a>>,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+}
+
+/// PUBLISH: an application message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Publish<'a> {
+    pub dup: bool,
+    pub qos: QoS,
+    pub retain: bool,
+    /// Empty when an MQTT 5 topic alias stands in for the topic
+    pub topic: &'a str,
+    /// Present for QoS 1 and 2 only
+    pub packet_id: Option<u16>,
+    pub properties: Properties<'a>,
+    pub message: &'a [u8],
+}
+
+/// PUBACK, PUBREC, PUBREL or PUBCOMP.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ack<'a> {
+    pub packet_id: u16,
+    /// Always success (0) before MQTT 5
+    pub reason_code: u8,
+    pub properties: Properties<'a>,
+}
+
+/// One topic filter of a SUBSCRIBE with its subscription options.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Subscription<'a> {
+    pub filter: &'a str,
+    /// Maximum QoS requested
+    pub qos: QoS,
+    /// MQTT 5: do not forward the subscriber's own publications
+    pub no_local: bool,
+    /// MQTT 5: keep the RETAIN flag when forwarding
+    pub retain_as_published: bool,
+    /// MQTT 5: when to send retained messages (0-2)
+    pub retain_handling: u8,
+}
+
+impl<'a> Subscription<'a> {
+    /// Decodes a subscription options byte; bits reserved in `version` must
+    /// be clear.
+    fn new(filter: &'a str, options: u8, version: ProtocolVersion) -> Option<Self> {
+        let reserved = if version == ProtocolVersion::V5 {
+            0xC0
+        } else {
+            0xFC
+        };
+        let retain_handling = (options >> 4) & 0x03;
+        if options & reserved != 0 || retain_handling == 3 {
+            return None;
+        }
+        Some(Self {
+            filter,
+            qos: QoS::from_bits(options & 0x03)?,
+            no_local: options & 0x04 != 0,
+            retain_as_published: options & 0x08 != 0,
+            retain_handling,
+        })
+    }
+}
+
+/// Topic filters and options of a SUBSCRIBE, validated at parse time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Subscriptions<'a> {
+    data: &'a [u8],
+    version: ProtocolVersion,
+}
+
+impl<'a> Subscriptions<'a> {
+    /// Iterates over the requested subscriptions.
+    pub fn iter(&self) -> impl Iterator<Item = Subscription<'a>> + 'a {
+        let mut reader = Reader::new(self.data, PacketType::Subscribe);
+        let version = self.version;
+        std::iter::from_fn(move || {
+            let filter = reader.string().ok()?;
+            Subscription::new(filter, reader.u8().ok()?, version)
+        })
+    }
+}
+
+/// Topic filters of an UNSUBSCRIBE, validated at parse time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TopicFilters<'a>(&'a [u8]);
+
+impl<'a> TopicFilters<'a> {
+    /// Iterates over the topic filters.
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let mut reader = Reader::new(self.0, PacketType::Unsubscribe);
+        std::iter::from_fn(move || reader.string().ok())
+    }
+}
+
+/// Decoded variable header and payload of an MQTT control packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControlPacket<'a> {
+    Connect(Connect<'a>),
+    Connack {
+        session_present: bool,
+        /// Connect return code (3.x) or reason code (5)
+        reason_code: u8,
+        properties: Properties<'a>,
+    },
+    Publish(Publish<'a>),
+    Puback(Ack<'a>),
+    Pubrec(Ack<'a>),
+    Pubrel(Ack<'a>),
+    Pubcomp(Ack<'a>),
+    Subscribe {
+        packet_id: u16,
+        properties: Properties<'a>,
+        subscriptions: Subscriptions<'a>,
+    },
+    Suback {
+        packet_id: u16,
+        properties: Properties<'a>,
+        /// Granted QoS per filter, or a failure code (`0x80` and up)
+        reason_codes: &'a [u8],
+    },
+    Unsubscribe {
+        packet_id: u16,
+        properties: Properties<'a>,
+        filters: TopicFilters<'a>,
+    },
+    Unsuback {
+        packet_id: u16,
+        properties: Properties<'a>,
+        /// One per filter in MQTT 5; empty before
+        reason_codes: &'a [u8],
+    },
+    Pingreq,
+    Pingresp,
+    Disconnect {
+        /// Always normal disconnection (0) before MQTT 5
+        reason_code: u8,
+        properties: Properties<'a>,
+    },
+    Auth {
+        reason_code: u8,
+        properties: Properties<'a>,
+    },
+}
+
+/// Represents an MQTT packet as zero‑copy slices into the original data.
+#[derive(Debug, Copy, Clone)]
+pub struct MqttPacket<'a> {
+    pub header: u8,
+    pub packet_type: PacketType,
+    /// Version the packet was decoded as
+    pub version: ProtocolVersion,
+    pub control: ControlPacket<'a>,
+    /// The application message for PUBLISH; otherwise the whole variable
+    /// header and payload.
+    pub payload: &'a [u8],
+}
+
+impl<'a> MqttPacket<'a> {
+    /// Generates a rule ID string based on the packet contents.
+    /// For PUBLISH, it produces "MQTT_{hex‑encoded topic}",
+    /// otherwise "MQTT_{packet type}", e.g. "MQTT_CONNECT".
+    pub fn rule_id(&self) -> String {
+        match self.topic() {
+            Some(topic) => format!("MQTT_{}", hex::encode(topic)),
+            None => format!("MQTT_{}", self.packet_type),
+        }
+    }
+
+    /// Returns the topic of a PUBLISH packet.
+    pub fn topic(&self) -> Option<&'a str> {
+        match self.control {
+            ControlPacket::Publish(publish) => Some(publish.topic),
+            _ => None,
+        }
+    }
+
+    /// Returns the reason codes the packet carries: one for CONNACK, the
+    /// PUBLISH acknowledgements, DISCONNECT and AUTH, one per filter for
+    /// SUBACK and UNSUBACK, and none for other packets. Codes of `0x80` and
+    /// above signal failure.
+    pub fn reason_codes(&self) -> &[u8] {
+        match &self.control {
+            ControlPacket::Connack { reason_code, .. }
+            | ControlPacket::Disconnect { reason_code, .. }
+            | ControlPacket::Auth { reason_code, .. }
+            | ControlPacket::Puback(Ack { reason_code, .. })
+            | ControlPacket::Pubrec(Ack { reason_code, .. })
+            | ControlPacket::Pubrel(Ack { reason_code, .. })
+            | ControlPacket::Pubcomp(Ack { reason_code, .. }) => std::slice::from_ref(reason_code),
+            ControlPacket::Suback { reason_codes, .. }
+            | ControlPacket::Unsuback { reason_codes, .. } => reason_codes,
+            _ => &[],
+        }
+    }
+
+    /// Returns the MQTT 5 properties of the packet (empty before MQTT 5).
+    pub fn properties(&self) -> Properties<'a> {
+        match self.control {
+            ControlPacket::Connect(Connect { properties, .. })
+            | ControlPacket::Publish(Publish { properties, .. })
+            | ControlPacket::Puback(Ack { properties, .. })
+            | ControlPacket::Pubrec(Ack { properties, .. })
+            | ControlPacket::Pubrel(Ack { properties, .. })
+            | ControlPacket::Pubcomp(Ack { properties, .. })
+            | ControlPacket::Connack { properties, .. }
+            | ControlPacket::Subscribe { properties, .. }
+            | ControlPacket::Suback { properties, .. }
+            | ControlPacket::Unsubscribe { properties, .. }
+            | ControlPacket::Unsuback { properties, .. }
+            | ControlPacket::Disconnect { properties, .. }
+            | ControlPacket::Auth { properties, .. } => properties,
+            ControlPacket::Pingreq | ControlPacket::Pingresp => Properties::default(),
+        }
+    }
+
+    /// Returns the payload of the packet.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+}
+
+/// Bounds-checked cursor over the body of one control packet.
+struct Reader<'a> {
+    data: &'a [u8],
+    packet_type: PacketType,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], packet_type: PacketType) -> Self {
+        Self { data, packet_type }
+    }
+
+    fn malformed(&self) -> MqttParseError {
+        MqttParseError::MalformedPacket(self.packet_type)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MqttParseError> {
+        if self.data.len() < len {
+            return Err(self.malformed());
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, MqttParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MqttParseError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MqttParseError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Variable byte integer of at most four bytes.
+    fn variable_int(&mut self) -> Result<u32, MqttParseError> {
+        let (value, len) =
+            MqttParser::decode_remaining_length(self.data).map_err(|_| self.malformed())?;
+        self.take(len)?;
+        Ok(value)
+    }
+
+    /// Two-byte length prefixed binary data.
+    fn binary(&mut self) -> Result<&'a [u8], MqttParseError> {
+        let len = self.u16()?;
+        self.take(usize::from(len))
+    }
+
+    /// Two-byte length prefixed UTF-8 string.
+    fn string(&mut self) -> Result<&'a str, MqttParseError> {
+        let bytes = self.binary()?;
+        std::str::from_utf8(bytes).map_err(|_| MqttParseError::InvalidUtf8)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fails unless the whole body has been consumed.
+    fn finish(&self) -> Result<(), MqttParseError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self.malformed())
+        }
+    }
+
+    /// Reads a property block if `version` has them, or returns an empty one.
+    fn properties_for(
+        &mut self,
+        version: ProtocolVersion,
+    ) -> Result<Properties<'a>, MqttParseError> {
+        if version == ProtocolVersion::V5 {
+            self.properties()
+        } else {
+            Ok(Properties::default())
+        }
+    }
+
+    /// Reads the optional MQTT 5 reason code and property block that end
+    /// acknowledgements, DISCONNECT and AUTH. Both may be omitted when the
+    /// reason code is 0 and there are no properties.
+    fn reason_and_properties(
+        &mut self,
+        version: ProtocolVersion,
+    ) -> Result<(u8, Properties<'a>), MqttParseError> {
+        if version != ProtocolVersion::V5 || self.is_empty() {
+            return Ok((0, Properties::default()));
+        }
+        let reason_code = self.u8()?;
+        if self.is_empty() {
+            return Ok((reason_code, Properties::default()));
+        }
+        Ok((reason_code, self.properties()?))
+    }
+}
+
+/// A simple MQTT parser that works on zero‑copy data.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct MqttParser {
+    version: ProtocolVersion,
+}
+
+impl MqttParser {
+    /// Creates a parser for MQTT 3.1.1 connections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes packets other than CONNECT as `version`.
+    pub fn with_version(mut self, version: ProtocolVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Version packets other than CONNECT are decoded as.
+    pub fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    /// Decodes MQTT’s variable‑length “remaining length” field.
+    ///
+    /// Returns a tuple of (decoded_value, number_of_bytes_used).
+    fn decode_remaining_length(input: &[u8]) -> Result<(u32, usize), MqttParseError> {
+        let mut value: u32 = 0;
+        // The MQTT spec limits the length field to 4 bytes.
+        for (i, byte) in input.iter().take(4).enumerate() {
+            value |= u32::from(byte & 0x7F) << (7 * i);
+            if (byte & 0x80) == 0 {
+                return Ok((value, i + 1));
+            }
+        }
+        Err(MqttParseError::RemainingLengthMalformed)
+    }
+
+    /// Returns the length of the first complete MQTT control packet in a
+    /// byte stream, or `None` if more data is needed to delimit it.
+    pub fn frame_len(&self, data: &[u8]) -> Result<Option<usize>, MqttParseError> {
+        let Some(&header) = data.first() else {
+            return Ok(None);
+        };
+        // Packet type 0 is reserved.
+        if header >> 4 == 0 {
+            return Err(MqttParseError::InvalidHeader);
+        }
+
+        let mut remaining_length = 0usize;
+        for (i, byte) in data[1..].iter().take(4).enumerate() {
+            remaining_length |= usize::from(byte & 0x7F) << (7 * i);
+            if byte & 0x80 == 0 {
+                let total = 1 + (i + 1) + remaining_length;
+                return Ok((data.len() >= total).then_some(total));
+            }
+        }
+        if data.len() > 4 {
+            // Four continuation bytes: the field is longer than MQTT allows.
+            return Err(MqttParseError::RemainingLengthMalformed);
+        }
+        Ok(None)
+    }
+
+    /// Parses an MQTT packet from a Bytes slice.
+    pub fn parse<'a>(&self, data: &'a Bytes) -> Result<MqttPacket<'a>, MqttParseError> {
+        if data.len() < 2 {
+            return Err(MqttParseError::InsufficientData);
+        }
+        let header = data[0];
+        let packet_type = PacketType::from_header(header)
+            .filter(|&packet_type| {
+                packet_type != PacketType::Auth || self.version == ProtocolVersion::V5
+            })
+            .ok_or(MqttParseError::InvalidHeader)?;
+        if let Some(flags) = packet_type.required_flags() {
+            if header & 0x0F != flags {
+                return Err(MqttParseError::InvalidFlags(packet_type));
+            }
+        }
+
+        // Decode the remaining length field (which can be 1-4 bytes).
+        let (remaining_length, length_field_size) = Self::decode_remaining_length(&data[1..])?;
+        let fixed_header_length = 1 + length_field_size;
+
+        // Check that the total packet is present.
+        if data.len() < fixed_header_length + (remaining_length as usize) {
+            return Err(MqttParseError::PacketIncomplete);
+        }
+        let body = &data[fixed_header_length..fixed_header_length + (remaining_length as usize)];
+
+        let control = self.decode_body(header, packet_type, body)?;
+        let (version, payload) = match control {
+            ControlPacket::Connect(connect) => (connect.version, body),
+            ControlPacket::Publish(publish) => (self.version, publish.message),
+            _ => (self.version, body),
+        };
+        Ok(MqttPacket {
+            header,
+            packet_type,
+            version,
+            control,
+            payload,
+        })
+    }
+
+    /// Decodes the variable header and payload following the fixed header.
+    fn decode_body<'a>(
+        &self,
+        header: u8,
+        packet_type: PacketType,
+        body: &'a [u8],
+    ) -> Result<ControlPacket<'a>, MqttParseError> {
+        let version = self.version;
+        let mut reader = Reader::new(body, packet_type);
+        let control = match packet_type {
+            PacketType::Connect => ControlPacket::Connect(Self::decode_connect(&mut reader)?),
+            PacketType::Connack => {
+                let flags = reader.u8()?;
+                if flags & !0x01 != 0 {
+                    return Err(reader.malformed());
+                }
+                ControlPacket::Connack {
+                    session_present: flags & 0x01 != 0,
+                    reason_code: reader.u8()?,
+                    properties: reader.properties_for(version)?,
+                }
+            }
+            PacketType::Publish => {
+                ControlPacket::Publish(Self::decode_publish(header, version, &mut reader)?)
+            }
+            PacketType::Puback => ControlPacket::Puback(Self::decode_ack(version, &mut reader)?),
+            PacketType::Pubrec => ControlPacket::Pubrec(Self::decode_ack(version, &mut reader)?),
+            PacketType::Pubrel => ControlPacket::Pubrel(Self::decode_ack(version, &mut reader)?),
+            PacketType::Pubcomp => ControlPacket::Pubcomp(Self::decode_ack(version, &mut reader)?),
+            PacketType::Subscribe => {
+                let packet_id = reader.u16()?;
+                let properties = reader.properties_for(version)?;
+                let list = reader.rest();
+                let mut filters = Reader::new(list, packet_type);
+                while !filters.is_empty() {
+                    let filter = filters.string()?;
+                    Subscription::new(filter, filters.u8()?, version)
+                        .ok_or_else(|| filters.malformed())?;
+                }
+                if list.is_empty() {
+                    return Err(reader.malformed());
+                }
+                ControlPacket::Subscribe {
+                    packet_id,
+                    properties,
+                    subscriptions: Subscriptions {
+                        data: list,
+                        version,
+                    },
+                }
+            }
+            PacketType::Suback => {
+                let packet_id = reader.u16()?;
+                let properties = reader.properties_for(version)?;
+                let reason_codes = reader.rest();
+                // Before MQTT 5 the only failure code is 0x80.
+                let valid = |code: u8| match version {
+                    ProtocolVersion::V5 => code <= 2 || code >= 0x80,
+                    _ => code <= 2 || code == 0x80,
+                };
+                if reason_codes.is_empty() || !reason_codes.iter().all(|&code| valid(code)) {
+                    return Err(reader.malformed());
+                }
+                ControlPacket::Suback {
+                    packet_id,
+                    properties,
+                    reason_codes,
+                }
+            }
+            PacketType::Unsubscribe => {
+                let packet_id = reader.u16()?;
+                let properties = reader.properties_for(version)?;
+                let list = reader.rest();
+                let mut filters = Reader::new(list, packet_type);
+                while !filters.is_empty() {
+                    filters.string()?;
+                }
+                if list.is_empty() {
+                    return Err(reader.malformed());
+                }
+                ControlPacket::Unsubscribe {
+                    packet_id,
+                    properties,
+                    filters: TopicFilters(list),
+                }
+            }
+            PacketType::Unsuback => {
+                let packet_id = reader.u16()?;
+                let properties = reader.properties_for(version)?;
+                let reason_codes = reader.rest();
+                if version == ProtocolVersion::V5 && reason_codes.is_empty() {
+                    return Err(reader.malformed());
+                }
+                ControlPacket::Unsuback {
+                    packet_id,
+                    properties,
+                    reason_codes,
+                }
+            }
+            PacketType::Pingreq => ControlPacket::Pingreq,
+            PacketType::Pingresp => ControlPacket::Pingresp,
+            PacketType::Disconnect => {
+                let (reason_code, properties) = reader.reason_and_properties(version)?;
+                ControlPacket::Disconnect {
+                    reason_code,
+                    properties,
+                }
+            }
+            PacketType::Auth => {
+                let (reason_code, properties) = reader.reason_and_properties(version)?;
+                ControlPacket::Auth {
+                    reason_code,
+                    properties,
+                }
+            }
+        };
+        reader.finish()?;
+        Ok(control)
+    }
+
+    fn decode_connect<'a>(reader: &mut Reader<'a>) -> Result<Connect<'a>, MqttParseError> {
+        let protocol_name = reader.string()?;
+        let protocol_level = reader.u8()?;
+        let version = ProtocolVersion::from_level(protocol_level)
+            .ok_or(MqttParseError::UnsupportedProtocolLevel(protocol_level))?;
+        let flags = reader.u8()?;
+        let will_flag = flags & 0x04 != 0;
+        let will_qos = QoS::from_bits((flags >> 3) & 0x03).ok_or_else(|| reader.malformed())?;
+        let will_retain = flags & 0x20 != 0;
+        let has_password = flags & 0x40 != 0;
+        let has_username = flags & 0x80 != 0;
+        // The reserved bit must be clear, will QoS and retain require a will,
+        // and before MQTT 5 a password requires a username.
+        if flags & 0x01 != 0
+            || (!will_flag && (will_qos != QoS::AtMostOnce || will_retain))
+            || (has_password && !has_username && version != ProtocolVersion::V5)
+        {
+            return Err(reader.malformed());
+        }
+        let keep_alive = reader.u16()?;
+        let properties = reader.properties_for(version)?;
+
+        let client_id = reader.string()?;
+        let will = if will_flag {
+            let properties = reader.properties_for(version)?;
+            Some(Will {
+                topic: reader.string()?,
+                message: reader.binary()?,
+                qos: will_qos,
+                retain: will_retain,
+                properties,
+            })
+        } else {
+            None
+        };
+        let username = has_username.then(|| reader.string()).transpose()?;
+        let password = has_password.then(|| reader.binary()).transpose()?;
+
+        Ok(Connect {
+            protocol_name,
+            version,
+            flags,
+            clean_session: flags & 0x02 != 0,
+            keep_alive,
+            properties,
+            client_id,
+            will,
+            username,
+            password,
+        })
+    }
+
+    fn decode_publish<'a>(
+        header: u8,
+        version: ProtocolVersion,
+        reader: &mut Reader<'a>,
+    ) -> Result<Publish<'a>, MqttParseError> {
+        let dup = header & 0x08 != 0;
+        let qos = QoS::from_bits((header >> 1) & 0x03)
+            .ok_or(MqttParseError::InvalidFlags(PacketType::Publish))?;
+        // DUP is meaningless, and forbidden, for QoS 0 deliveries.
+        if dup && qos == QoS::AtMostOnce {
+            return Err(MqttParseError::InvalidFlags(PacketType::Publish));
+        }
+        let topic = reader.string()?;
+        if topic.contains(['+', '#']) {
+            return Err(reader.malformed());
+        }
+        let packet_id = match qos {
+            QoS::AtMostOnce => None,
+            _ => Some(reader.u16()?),
+        };
+        let properties = reader.properties_for(version)?;
+        // An MQTT 5 topic may only be empty when a topic alias replaces it.
+        if version == ProtocolVersion::V5 && topic.is_empty() && properties.topic_alias().is_none()
+        {
+            return Err(reader.malformed());
+        }
+        Ok(Publish {
+            dup,
+            qos,
+            retain: header & 0x01 != 0,
+            topic,
+            packet_id,
+            properties,
+            message: reader.rest(),
+        })
+    }
+
+    fn decode_ack<'a>(
+        version: ProtocolVersion,
+        reader: &mut Reader<'a>,
+    ) -> Result<Ack<'a>, MqttParseError> {
+        let packet_id = reader.u16()?;
+        let (reason_code, properties) = reader.reason_and_properties(version)?;
+        Ok(Ack {
+            packet_id,
+            reason_code,
+            properties,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    /// Prefixes `body` with a fixed header.
+    fn packet(header: u8, body: &[u8]) -> Bytes {
+        let mut packet = vec![header, body.len() as u8];
+        packet.extend_from_slice(body);
+        Bytes::from(packet)
+    }
+
+    #[test]
+    fn test_connect_packet() {
+        let mut body = vec![0x00, 0x04];
+        body.extend_from_slice(b"MQTT");
+        // Level 4; username, password, will retain, will QoS 1, will, clean session.
+        body.extend_from_slice(&[0x04, 0b1110_1110, 0x00, 0x3C]);
+        for field in [&b"sensor-1"[..], b"status", b"offline", b"admin", b"secret"] {
+            body.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            body.extend_from_slice(field);
+        }
+        let bytes = packet(0x10, &body);
+        let mqtt_packet = MqttParser::new().parse(&bytes).unwrap();
+        assert_eq!(mqtt_packet.packet_type, PacketType::Connect);
+        assert_eq!(mqtt_packet.rule_id(), "MQTT_CONNECT");
+        let ControlPacket::Connect(connect) = mqtt_packet.control else {
+            panic!("expected CONNECT, got {:?}", mqtt_packet.control);
+        };
+        assert_eq!(connect.protocol_name, "MQTT");
+        assert_eq!(connect.version, ProtocolVersion::V3_1_1);
+        assert!(connect.properties.is_empty());
+        assert!(connect.clean_session);
+        assert_eq!(connect.keep_alive, 60);
+        assert_eq!(connect.client_id, "sensor-1");
+        assert_eq!(
+            connect.will,
+            Some(Will {
+                topic: "status",
+                message: b"offline",
+                qos: QoS::AtLeastOnce,
+                retain: true,
+                properties: Properties::default(),
+            })
+        );
+        assert_eq!(connect.username, Some("admin"));
+        assert_eq!(connect.password, Some(&b"secret"[..]));
+    }
+
+    #[test]
+    fn test_connect_rejects_invalid_flags() {
+        let mut body = vec![0x00, 0x04];
+        body.extend_from_slice(b"MQTT");
+        // Password without a username.
+        body.extend_from_slice(&[0x04, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let bytes = packet(0x10, &body);
+        assert_eq!(
+            MqttParser::new().parse(&bytes).unwrap_err(),
+            MqttParseError::MalformedPacket(PacketType::Connect)
+        );
+
+        body[6] = 6;
+        let bytes = packet(0x10, &body);
+        assert_eq!(
+            MqttParser::new().parse(&bytes).unwrap_err(),
+            MqttParseError::UnsupportedProtocolLevel(6)
+        );
+    }
+
+    #[test]
+    fn test_publish_packet() {
+        let parser = MqttParser::new();
+        let bytes = packet(0x30, b"\x00\x04testabc");
+        let mqtt_packet = parser.parse(&bytes).unwrap();
+        assert_eq!(mqtt_packet.topic(), Some("test"));
+        assert_eq!(mqtt_packet.payload, b"abc");
+        assert_eq!(mqtt_packet.rule_id(), "MQTT_74657374");
+
+        // DUP, QoS 2, retain, with a packet identifier.
+        let bytes = packet(0x3D, b"\x00\x03a/b\x12\x34on");
+        let ControlPacket::Publish(publish) = parser.parse(&bytes).unwrap().control else {
+            panic!("expected PUBLISH");
+        };
+        assert!(publish.dup && publish.retain);
+        assert_eq!(publish.qos, QoS::ExactlyOnce);
+        assert_eq!(publish.topic, "a/b");
+        assert_eq!(publish.packet_id, Some(0x1234));
+        assert_eq!(publish.message, b"on");
+    }
+
+    #[test]
+    fn test_publish_rejects_invalid_packets() {
+        let parser = MqttParser::new();
+        for (header, body, error) in [
+            (
+                0x36,
+                &b"\x00\x01a\x00\x01"[..],
+                MqttParseError::InvalidFlags(PacketType::Publish),
+            ),
+            (
+                0x38,
+                b"\x00\x01a",
+                MqttParseError::InvalidFlags(PacketType::Publish),
+            ),
+            (
+                0x30,
+                b"\x00\x03a/#",
+                MqttParseError::MalformedPacket(PacketType::Publish),
+            ),
+            (0x30, b"\x00\x02\xC3\x28", MqttParseError::InvalidUtf8),
+            (
+                0x32,
+                b"\x00\x01a\x00",
+                MqttParseError::MalformedPacket(PacketType::Publish),
+            ),
+        ] {
+            let bytes = packet(header, body);
+            assert_eq!(parser.parse(&bytes).unwrap_err(), error, "{header:#x}");
+        }
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe_packets() {
+        let parser = MqttParser::new();
+        let bytes = packet(0x82, b"\x00\x0A\x00\x03a/+\x01\x00\x01#\x02");
+        let mqtt_packet = parser.parse(&bytes).unwrap();
+        let ControlPacket::Subscribe {
+            packet_id,
+            subscriptions,
+            ..
+        } = mqtt_packet.control
+        else {
+            panic!("expected SUBSCRIBE");
+        };
+        assert_eq!(packet_id, 10);
+        assert_eq!(
+            subscriptions
+                .iter()
+                .map(|subscription| (subscription.filter, subscription.qos))
+                .collect::<Vec<_>>(),
+            [("a/+", QoS::AtLeastOnce), ("#", QoS::ExactlyOnce)]
+        );
+
+        let bytes = packet(0xA2, b"\x00\x0B\x00\x03a/+\x00\x01#");
+        let ControlPacket::Unsubscribe {
+            packet_id, filters, ..
+        } = parser.parse(&bytes).unwrap().control
+        else {
+            panic!("expected UNSUBSCRIBE");
+        };
+        assert_eq!(packet_id, 11);
+        assert_eq!(filters.iter().collect::<Vec<_>>(), ["a/+", "#"]);
+
+        // Flags must be 0b0010, requested QoS at most 2, and the list non-empty.
+        for (header, body) in [
+            (0x80, &b"\x00\x0A\x00\x01#\x00"[..]),
+            (0x82, b"\x00\x0A\x00\x01#\x03"),
+            (0x82, b"\x00\x0A"),
+            (0xA2, b"\x00\x0B\x00\x05a"),
+        ] {
+            let bytes = packet(header, body);
+            assert!(parser.parse(&bytes).is_err(), "{header:#x} {body:?}");
+        }
+    }
+
+    #[test]
+    fn test_acknowledgements() {
+        let parser = MqttParser::new();
+        let ack = Ack {
+            packet_id: 7,
+            reason_code: 0,
+            properties: Properties::default(),
+        };
+        let cases = [
+            (
+                0x20,
+                &b"\x01\x05"[..],
+                ControlPacket::Connack {
+                    session_present: true,
+                    reason_code: 5,
+                    properties: Properties::default(),
+                },
+            ),
+            (0x40, b"\x00\x07", ControlPacket::Puback(ack)),
+            (0x50, b"\x00\x07", ControlPacket::Pubrec(ack)),
+            (0x62, b"\x00\x07", ControlPacket::Pubrel(ack)),
+            (0x70, b"\x00\x07", ControlPacket::Pubcomp(ack)),
+            (
+                0x90,
+                b"\x00\x07\x01\x80",
+                ControlPacket::Suback {
+                    packet_id: 7,
+                    properties: Properties::default(),
+                    reason_codes: &[0x01, 0x80],
+                },
+            ),
+            (
+                0xB0,
+                b"\x00\x07",
+                ControlPacket::Unsuback {
+                    packet_id: 7,
+                    properties: Properties::default(),
+                    reason_codes: &[],
+                },
+            ),
+            (0xC0, b"", ControlPacket::Pingreq),
+            (0xD0, b"", ControlPacket::Pingresp),
+            (
+                0xE0,
+                b"",
+                ControlPacket::Disconnect {
+                    reason_code: 0,
+                    properties: Properties::default(),
+                },
+            ),
+        ];
+        for (header, body, expected) in cases {
+            let bytes = packet(header, body);
+            let mqtt_packet = parser.parse(&bytes).unwrap();
+            assert_eq!(mqtt_packet.control, expected);
+            assert_eq!(mqtt_packet.payload, body);
+        }
+
+        // Trailing bytes, wrong flags and reserved packet types are rejected.
+        for header_and_body in [
+            &b"\x40\x03\x00\x07\x00"[..],
+            b"\xC1\x00",
+            b"\x60\x02\x00\x07",
+            b"\xF0\x00",
+        ] {
+            let bytes = Bytes::copy_from_slice(header_and_body);
+            assert!(parser.parse(&bytes).is_err(), "{header_and_body:?}");
+        }
+    }
+
+    #[test]
+    fn test_mqtt5_connect() {
+        let mut body = vec![0x00, 0x04];
+        body.extend_from_slice(b"MQTT");
+        // Level 5; password without username, will, clean start.
+        body.extend_from_slice(&[0x05, 0b0100_0110, 0x00, 0x1E]);
+        // Session expiry 60s, authentication method "SCRAM-SHA-1" with data.
+        body.extend_from_slice(b"\x17\x11\x00\x00\x00\x3C\x15\x00\x0BSCRAM-SHA-1\x16\x00\x01\x2A");
+        body.extend_from_slice(b"\x00\x02id");
+        // Will properties: delay 5s.
+        body.extend_from_slice(b"\x05\x18\x00\x00\x00\x05\x00\x01w\x00\x00");
+        body.extend_from_slice(b"\x00\x02pw");
+        let bytes = packet(0x10, &body);
+
+        let mqtt_packet = MqttParser::new().parse(&bytes).unwrap();
+        assert_eq!(mqtt_packet.version, ProtocolVersion::V5);
+        let ControlPacket::Connect(connect) = mqtt_packet.control else {
+            panic!("expected CONNECT");
+        };
+        assert_eq!(connect.properties.session_expiry_interval(), Some(60));
+        assert_eq!(
+            connect.properties.authentication_method(),
+            Some("SCRAM-SHA-1")
+        );
+        assert_eq!(connect.properties.authentication_data(), Some(&[0x2A][..]));
+        assert_eq!(connect.client_id, "id");
+        let will = connect.will.unwrap();
+        assert_eq!(will.topic, "w");
+        assert_eq!(
+            will.properties.iter().collect::<Vec<_>>(),
+            [Property::WillDelayInterval(5)]
+        );
+        assert_eq!(connect.username, None);
+        assert_eq!(connect.password, Some(&b"pw"[..]));
+    }
+
+    #[test]
+    fn test_mqtt5_publish_and_subscribe() {
+        let parser = MqttParser::new().with_version(ProtocolVersion::V5);
+
+        // QoS 1, empty topic replaced by alias 3, one user property.
+        let bytes = packet(
+            0x32,
+            b"\x00\x00\x00\x09\x0B\x23\x00\x03\x26\x00\x01k\x00\x02v1data",
+        );
+        let mqtt_packet = parser.parse(&bytes).unwrap();
+        let properties = mqtt_packet.properties();
+        assert_eq!(properties.topic_alias(), Some(3));
+        assert_eq!(
+            properties.user_properties().collect::<Vec<_>>(),
+            [("k", "v1")]
+        );
+        assert_eq!(mqtt_packet.payload, b"data");
+
+        // Without an alias the topic may not be empty.
+        let bytes = packet(0x30, b"\x00\x00\x00data");
+        assert!(parser.parse(&bytes).is_err());
+
+        // No local, retain as published, retain handling 2, QoS 1.
+        let bytes = packet(0x82, b"\x00\x01\x02\x0B\x07\x00\x01#\x2D");
+        let ControlPacket::Subscribe {
+            properties,
+            subscriptions,
+            ..
+        } = parser.parse(&bytes).unwrap().control
+        else {
+            panic!("expected SUBSCRIBE");
+        };
+        assert_eq!(
+            properties.iter().collect::<Vec<_>>(),
+            [Property::SubscriptionIdentifier(7)]
+        );
+        assert_eq!(
+            subscriptions.iter().collect::<Vec<_>>(),
+            [Subscription {
+                filter: "#",
+                qos: QoS::AtLeastOnce,
+                no_local: true,
+                retain_as_published: true,
+                retain_handling: 2,
+            }]
+        );
+        // The same options are reserved bits before MQTT 5.
+        assert!(MqttParser::new().parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_mqtt5_reason_codes() {
+        let parser = MqttParser::new().with_version(ProtocolVersion::V5);
+        for (header_and_body, reason_codes) in [
+            // PUBACK with "no matching subscribers" and no properties.
+            (&b"\x40\x03\x00\x07\x10"[..], &[0x10][..]),
+            // PUBACK with the reason code omitted.
+            (b"\x40\x02\x00\x07", &[0x00]),
+            (b"\x20\x03\x00\x87\x00", &[0x87]),
+            (b"\x90\x05\x00\x07\x00\x01\x97", &[0x01, 0x97]),
+            (b"\xB0\x04\x00\x07\x00\x11", &[0x11]),
+            (b"\xE0\x00", &[0x00]),
+            (b"\xF0\x02\x18\x00", &[0x18]),
+        ] {
+            let bytes = Bytes::copy_from_slice(header_and_body);
+            let mqtt_packet = parser.parse(&bytes).unwrap();
+            assert_eq!(
+                mqtt_packet.reason_codes(),
+                reason_codes,
+                "{header_and_body:?}"
+            );
+        }
+
+        // DISCONNECT "keep alive timeout" with a reason string.
+        let bytes = packet(0xE0, b"\x8D\x06\x1F\x00\x03t/o");
+        let mqtt_packet = parser.parse(&bytes).unwrap();
+        assert_eq!(mqtt_packet.reason_codes(), [0x8D]);
+        assert_eq!(mqtt_packet.properties().reason_string(), Some("t/o"));
+
+        // AUTH is unknown before MQTT 5.
+        let bytes = Bytes::from_static(b"\xF0\x00");
+        assert_eq!(
+            MqttParser::new().parse(&bytes).unwrap_err(),
+            MqttParseError::InvalidHeader
+        );
+    }
+
+    #[test]
+    fn test_incomplete_packet() {
+        // A packet that claims to have more bytes than are provided.
+        let packet = vec![0x10, 0x07, b'a'];
+        let bytes = Bytes::from(packet);
+        let parser = MqttParser::new();
+        let result = parser.parse(&bytes);
+        assert!(matches!(result, Err(MqttParseError::PacketIncomplete)));
+    }
+
+    #[test]
+    fn test_frame_len() {
+        let parser = MqttParser::new();
+        assert_eq!(parser.frame_len(&[]), Ok(None));
+        assert_eq!(parser.frame_len(&[0x30, 0x03, b'a']), Ok(None));
+        assert_eq!(
+            parser.frame_len(&[0x30, 0x02, b'a', b'b', 0xC0]),
+            Ok(Some(4))
+        );
+        // Two-byte remaining length (200), split before the second byte.
+        assert_eq!(parser.frame_len(&[0x30, 0xC8]), Ok(None));
+        let mut packet = vec![0x30, 0xC8, 0x01];
+        packet.resize(203, 0);
+        assert_eq!(parser.frame_len(&packet), Ok(Some(203)));
+        assert_eq!(
+            parser.frame_len(&[0x30, 0xFF, 0xFF, 0xFF, 0xFF]),
+            Err(MqttParseError::RemainingLengthMalformed)
+        );
+        assert_eq!(
+            parser.frame_len(&[0x00, 0x00]),
+            Err(MqttParseError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn test_malformed_remaining_length() {
+        // A packet with a remaining length field that does not terminate.
+        let packet = vec![0x10, 0xFF, 0xFF, 0xFF, 0xFF];
+        let bytes = Bytes::from(packet);
+        let parser = MqttParser::new();
+        let result = parser.parse(&bytes);
+        assert!(matches!(
+            result,
+            Err(MqttParseError::RemainingLengthMalformed)
+        ));
+
+        // A fifth length byte is rejected rather than overflowing.
+        let bytes = Bytes::from_static(&[0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F]);
+        assert_eq!(
+            parser.parse(&bytes).unwrap_err(),
+            MqttParseError::RemainingLengthMalformed
+        );
+    }
+}
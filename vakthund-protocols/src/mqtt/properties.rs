@@ -0,0 +1,219 @@
+//! ## vakthund-protocols::mqtt::properties
+//! MQTT 5 property blocks.
+//!
+//! A property block is a variable byte integer length followed by
+//! identifier/value pairs. Blocks are validated once when their packet is
+//! parsed (known identifiers, well-formed values, no repeated single-valued
+//! properties) and then iterated lazily as zero-copy [`Property`] values.
+
+use super::{MqttParseError, Reader};
+
+/// A single MQTT 5 property.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Property<'a> {
+    PayloadFormatIndicator(u8),
+    MessageExpiryInterval(u32),
+    ContentType(&'a str),
+    ResponseTopic(&'a str),
+    CorrelationData(&'a [u8]),
+    SubscriptionIdentifier(u32),
+    SessionExpiryInterval(u32),
+    AssignedClientIdentifier(&'a str),
+    ServerKeepAlive(u16),
+    AuthenticationMethod(&'a str),
+    AuthenticationData(&'a [u8]),
+    RequestProblemInformation(u8),
+    WillDelayInterval(u32),
+    RequestResponseInformation(u8),
+    ResponseInformation(&'a str),
+    ServerReference(&'a str),
+    ReasonString(&'a str),
+    ReceiveMaximum(u16),
+    TopicAliasMaximum(u16),
+    TopicAlias(u16),
+    MaximumQos(u8),
+    RetainAvailable(u8),
+    UserProperty(&'a str, &'a str),
+    MaximumPacketSize(u32),
+    WildcardSubscriptionAvailable(u8),
+    SubscriptionIdentifierAvailable(u8),
+    SharedSubscriptionAvailable(u8),
+}
+
+impl Property<'_> {
+    /// Whether the property may appear more than once in one block.
+    fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Property::UserProperty(..) | Property::SubscriptionIdentifier(_)
+        )
+    }
+}
+
+/// A validated MQTT 5 property block. Empty for MQTT 3.x packets.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Properties<'a>(&'a [u8]);
+
+impl<'a> Properties<'a> {
+    /// Returns `true` if the block holds no properties.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the properties in wire order.
+    pub fn iter(&self) -> impl Iterator<Item = Property<'a>> + 'a {
+        // The block was validated when parsed, so the packet type reported in
+        // errors is never seen.
+        let mut reader = Reader::new(self.0, super::PacketType::Connect);
+        std::iter::from_fn(move || reader.property().ok().map(|(_, property)| property))
+    }
+
+    /// Name/value pairs of every user property.
+    pub fn user_properties(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.iter().filter_map(|property| match property {
+            Property::UserProperty(name, value) => Some((name, value)),
+            _ => None,
+        })
+    }
+
+    pub fn topic_alias(&self) -> Option<u16> {
+        self.iter().find_map(|property| match property {
+            Property::TopicAlias(alias) => Some(alias),
+            _ => None,
+        })
+    }
+
+    /// Session expiry interval in seconds.
+    pub fn session_expiry_interval(&self) -> Option<u32> {
+        self.iter().find_map(|property| match property {
+            Property::SessionExpiryInterval(interval) => Some(interval),
+            _ => None,
+        })
+    }
+
+    pub fn authentication_method(&self) -> Option<&'a str> {
+        self.iter().find_map(|property| match property {
+            Property::AuthenticationMethod(method) => Some(method),
+            _ => None,
+        })
+    }
+
+    pub fn authentication_data(&self) -> Option<&'a [u8]> {
+        self.iter().find_map(|property| match property {
+            Property::AuthenticationData(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    pub fn reason_string(&self) -> Option<&'a str> {
+        self.iter().find_map(|property| match property {
+            Property::ReasonString(reason) => Some(reason),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> Reader<'a> {
+    /// Reads and validates a length-prefixed property block.
+    pub(super) fn properties(&mut self) -> Result<Properties<'a>, MqttParseError> {
+        let len = self.variable_int()?;
+        let block = self.take(len as usize)?;
+
+        let mut reader = Reader::new(block, self.packet_type);
+        // Property identifiers are all below 64, so one bit per identifier.
+        let mut seen = 0u64;
+        while !reader.is_empty() {
+            let (id, property) = reader.property()?;
+            let bit = 1u64 << id;
+            if seen & bit != 0 && !property.is_repeatable() {
+                return Err(MqttParseError::InvalidProperty(id));
+            }
+            seen |= bit;
+        }
+        Ok(Properties(block))
+    }
+
+    /// Reads one identifier/value pair.
+    fn property(&mut self) -> Result<(u32, Property<'a>), MqttParseError> {
+        let id = self.variable_int()?;
+        let property = match id {
+            0x01 => Property::PayloadFormatIndicator(self.u8()?),
+            0x02 => Property::MessageExpiryInterval(self.u32()?),
+            0x03 => Property::ContentType(self.string()?),
+            0x08 => Property::ResponseTopic(self.string()?),
+            0x09 => Property::CorrelationData(self.binary()?),
+            0x0B => Property::SubscriptionIdentifier(self.variable_int()?),
+            0x11 => Property::SessionExpiryInterval(self.u32()?),
+            0x12 => Property::AssignedClientIdentifier(self.string()?),
+            0x13 => Property::ServerKeepAlive(self.u16()?),
+            0x15 => Property::AuthenticationMethod(self.string()?),
+            0x16 => Property::AuthenticationData(self.binary()?),
+            0x17 => Property::RequestProblemInformation(self.u8()?),
+            0x18 => Property::WillDelayInterval(self.u32()?),
+            0x19 => Property::RequestResponseInformation(self.u8()?),
+            0x1A => Property::ResponseInformation(self.string()?),
+            0x1C => Property::ServerReference(self.string()?),
+            0x1F => Property::ReasonString(self.string()?),
+            0x21 => Property::ReceiveMaximum(self.u16()?),
+            0x22 => Property::TopicAliasMaximum(self.u16()?),
+            0x23 => Property::TopicAlias(self.u16()?),
+            0x24 => Property::MaximumQos(self.u8()?),
+            0x25 => Property::RetainAvailable(self.u8()?),
+            0x26 => Property::UserProperty(self.string()?, self.string()?),
+            0x27 => Property::MaximumPacketSize(self.u32()?),
+            0x28 => Property::WildcardSubscriptionAvailable(self.u8()?),
+            0x29 => Property::SubscriptionIdentifierAvailable(self.u8()?),
+            0x2A => Property::SharedSubscriptionAvailable(self.u8()?),
+            _ => return Err(MqttParseError::InvalidProperty(id)),
+        };
+        Ok((id, property))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PacketType;
+    use super::*;
+
+    fn block(properties: &[u8]) -> Vec<u8> {
+        let mut block = vec![properties.len() as u8];
+        block.extend_from_slice(properties);
+        block
+    }
+
+    #[test]
+    fn decodes_properties() {
+        let data = block(
+            b"\x11\x00\x00\x0E\x10\x26\x00\x04site\x00\x03lab\x23\x00\x05\x26\x00\x01k\x00\x01v",
+        );
+        let mut reader = Reader::new(&data, PacketType::Connect);
+        let properties = reader.properties().unwrap();
+        assert!(reader.is_empty());
+
+        assert_eq!(properties.session_expiry_interval(), Some(3600));
+        assert_eq!(properties.topic_alias(), Some(5));
+        assert_eq!(
+            properties.user_properties().collect::<Vec<_>>(),
+            [("site", "lab"), ("k", "v")]
+        );
+        assert_eq!(properties.authentication_method(), None);
+        assert_eq!(properties.iter().count(), 4);
+    }
+
+    #[test]
+    fn rejects_unknown_and_repeated_properties() {
+        for properties in [
+            &b"\x05\x00"[..],
+            b"\x23\x00\x01\x23\x00\x02",
+            b"\x15\x00\x05ab",
+        ] {
+            let data = block(properties);
+            assert!(
+                Reader::new(&data, PacketType::Connect)
+                    .properties()
+                    .is_err(),
+                "{properties:?}"
+            );
+        }
+    }
+}
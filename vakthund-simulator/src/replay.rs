@@ -51,8 +51,7 @@ impl Scenario {
     }
 
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
-        let serialized = serde_yaml::to_string(self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let serialized = serde_yaml::to_string(self).map_err(std::io::Error::other)?;
         std::fs::write(path, serialized)
     }
 }
@@ -104,21 +103,11 @@ mod tests {
             events: vec![
                 ScenarioEvent::NetworkEvent {
                     delay_ns: 1_000,
-                    event: NetworkEvent {
-                        timestamp: 0,
-                        payload: Bytes::from("dummy"),
-                        source: None,
-                        destination: None,
-                    },
+                    event: NetworkEvent::new(0, Bytes::from("dummy")),
                 },
                 ScenarioEvent::NetworkEvent {
                     delay_ns: 2_000,
-                    event: NetworkEvent {
-                        timestamp: 0,
-                        payload: Bytes::from("dummy"),
-                        source: None,
-                        destination: None,
-                    },
+                    event: NetworkEvent::new(0, Bytes::from("dummy")),
                 },
             ],
         }
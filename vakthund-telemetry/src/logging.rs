@@ -18,6 +18,7 @@
 //! Structured logging with tracing and OpenTelemetry
 
 use opentelemetry::KeyValue;
+use serde::Serialize;
 use tracing::{info_span, Instrument};
 use tracing_subscriber::fmt::format::FmtSpan;
 
@@ -60,6 +61,29 @@ impl EventLogger {
         .instrument(span)
         .await
     }
+
+    /// Logs a serializable event record, such as an alert or prevention
+    /// action, as JSON under `event_type`.
+    pub async fn log_record<T: Serialize + ?Sized>(event_type: &str, record: &T) {
+        let record = match serde_json::to_string(record) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!("Failed to serialize {event_type} record: {e}");
+                return;
+            }
+        };
+        let span = info_span!(
+            "security_event",
+            event_type = event_type,
+            otel.kind = "INTERNAL"
+        );
+
+        async {
+            tracing::info!(record = %record, "Security event occurred");
+        }
+        .instrument(span)
+        .await
+    }
 }
 
 #[cfg(test)]
@@ -78,4 +102,21 @@ mod tests {
             )); // Block on future
         assert!(logs_contain("Security event occurred"));
     }
+
+    #[traced_test]
+    #[test]
+    fn logs_records_as_json() {
+        #[derive(Serialize)]
+        struct Record {
+            target: &'static str,
+        }
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(EventLogger::log_record(
+                "prevention",
+                &Record { target: "10.0.0.1" },
+            ));
+        assert!(logs_contain(r#"record={"target":"10.0.0.1"}"#));
+    }
 }
@@ -15,13 +15,25 @@
 //! - eBPF-based performance monitoring
 //! - Anomaly detection on telemetry data
 
-use prometheus::{Counter, Histogram, HistogramOpts, Registry};
+use prometheus::{Counter, Histogram, HistogramOpts, IntCounterVec, Opts, Registry};
+use vakthund_detection::Alert;
 
 #[derive(Debug, Clone)]
 pub struct MetricsRecorder {
     pub registry: prometheus::Registry,
     pub processed_events: prometheus::Counter,
     pub detection_latency: prometheus::Histogram,
+    /// Packets delivered by the capture source, per interface.
+    pub capture_received: IntCounterVec,
+    /// Packets dropped because the capture buffer was full, per interface.
+    pub capture_dropped: IntCounterVec,
+    /// Packets dropped by the interface or driver, per interface.
+    pub capture_if_dropped: IntCounterVec,
+    /// Events rejected by the event bus (queue full or closed), per interface
+    /// and message kind.
+    pub bus_rejected: IntCounterVec,
+    /// Alerts raised, per severity and application protocol.
+    pub alerts: IntCounterVec,
 }
 
 impl Default for MetricsRecorder {
@@ -45,17 +57,63 @@ impl MetricsRecorder {
         )
         .unwrap();
 
+        let per_interface = |name: &str, help: &str| {
+            IntCounterVec::new(Opts::new(name, help), &["interface"]).unwrap()
+        };
+        let capture_received = per_interface(
+            "vakthund_capture_received_total",
+            "Packets received by the capture source",
+        );
+        let capture_dropped = per_interface(
+            "vakthund_capture_dropped_total",
+            "Packets dropped because the capture buffer was full",
+        );
+        let capture_if_dropped = per_interface(
+            "vakthund_capture_if_dropped_total",
+            "Packets dropped by the network interface or driver",
+        );
+        let bus_rejected = IntCounterVec::new(
+            Opts::new(
+                "vakthund_event_bus_rejected_total",
+                "Captured events rejected by the event bus",
+            ),
+            &["interface", "kind"],
+        )
+        .unwrap();
+
         registry
             .register(Box::new(processed_events.clone()))
             .unwrap();
         registry
             .register(Box::new(detection_latency.clone()))
             .unwrap();
+        registry
+            .register(Box::new(capture_received.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(capture_dropped.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(capture_if_dropped.clone()))
+            .unwrap();
+        let alerts = IntCounterVec::new(
+            Opts::new("vakthund_alerts_total", "Alerts raised by detection"),
+            &["severity", "protocol"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(bus_rejected.clone())).unwrap();
+        registry.register(Box::new(alerts.clone())).unwrap();
 
         Self {
             registry,
             processed_events,
             detection_latency,
+            capture_received,
+            capture_dropped,
+            capture_if_dropped,
+            bus_rejected,
+            alerts,
         }
     }
 
@@ -70,4 +128,92 @@ impl MetricsRecorder {
     pub fn inc_processed_events(&self) {
         self.processed_events.inc();
     }
+
+    /// Records cumulative capture counters sampled from the source on
+    /// `interface`. Only the increase since the previous sample is added.
+    pub fn observe_capture_totals(
+        &self,
+        interface: &str,
+        received: u64,
+        dropped: u64,
+        if_dropped: u64,
+    ) {
+        for (counter, total) in [
+            (&self.capture_received, received),
+            (&self.capture_dropped, dropped),
+            (&self.capture_if_dropped, if_dropped),
+        ] {
+            let counter = counter.with_label_values(&[interface]);
+            counter.inc_by(total.saturating_sub(counter.get()));
+        }
+    }
+
+    /// Counts one `kind` message the bus refused for `interface`.
+    pub fn inc_bus_rejected(&self, interface: &str, kind: &str) {
+        self.bus_rejected
+            .with_label_values(&[interface, kind])
+            .inc();
+    }
+
+    /// Counts `count` `kind` messages the bus refused for `interface`.
+    pub fn inc_bus_rejected_by(&self, interface: &str, kind: &str, count: u64) {
+        self.bus_rejected
+            .with_label_values(&[interface, kind])
+            .inc_by(count);
+    }
+
+    /// Counts an alert that passed severity filtering.
+    pub fn record_alert(&self, alert: &Alert) {
+        self.alerts
+            .with_label_values(&[alert.severity.as_str(), &alert.protocol])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_totals_are_exported_per_interface() {
+        let metrics = MetricsRecorder::new();
+        metrics.observe_capture_totals("eth0", 10, 1, 0);
+        metrics.observe_capture_totals("eth0", 25, 3, 0);
+        metrics.observe_capture_totals("eth1", 5, 0, 2);
+        metrics.inc_bus_rejected("eth0", "alert");
+        metrics.inc_bus_rejected_by("eth0", "packet", 4);
+        metrics.inc_bus_rejected_by("eth0", "packet", 1);
+
+        let output = metrics.gather_metrics().unwrap();
+        assert!(output.contains(r#"vakthund_capture_received_total{interface="eth0"} 25"#));
+        assert!(output.contains(r#"vakthund_capture_dropped_total{interface="eth0"} 3"#));
+        assert!(output.contains(r#"vakthund_capture_if_dropped_total{interface="eth1"} 2"#));
+        assert!(output
+            .contains(r#"vakthund_event_bus_rejected_total{interface="eth0",kind="alert"} 1"#));
+        assert!(output
+            .contains(r#"vakthund_event_bus_rejected_total{interface="eth0",kind="packet"} 5"#));
+    }
+
+    #[test]
+    fn alerts_are_counted_per_severity() {
+        let metrics = MetricsRecorder::new();
+        let alert = Alert {
+            timestamp: 0,
+            rule_id: 1,
+            rule_revision: 1,
+            message: "test".into(),
+            severity: vakthund_detection::Severity::High,
+            protocol: "MQTT".into(),
+            flow: None,
+            initiator: None,
+            interface: None,
+            offsets: Vec::new(),
+            excerpt: b"test".to_vec(),
+        };
+        metrics.record_alert(&alert);
+        metrics.record_alert(&alert);
+
+        let output = metrics.gather_metrics().unwrap();
+        assert!(output.contains(r#"vakthund_alerts_total{protocol="MQTT",severity="high"} 2"#));
+    }
 }